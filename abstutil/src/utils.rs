@@ -44,6 +44,14 @@ pub fn abbreviated_format(x: usize) -> String {
     }
 }
 
+/// Escapes text for use in XML attributes or elements.
+pub fn escape_xml(x: &str) -> String {
+    x.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 pub fn basename<I: AsRef<str>>(path: I) -> String {
     std::path::Path::new(path.as_ref())
         .file_stem()
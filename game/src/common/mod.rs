@@ -371,9 +371,9 @@ pub fn cmp_duration_shorter(app: &App, after: Duration, before: Duration) -> Vec
 pub fn color_for_mode(app: &App, m: TripMode) -> Color {
     match m {
         TripMode::Walk => app.cs.unzoomed_pedestrian,
-        TripMode::Bike => app.cs.unzoomed_bike,
+        TripMode::Bike | TripMode::BikeShare => app.cs.unzoomed_bike,
         TripMode::Transit => app.cs.unzoomed_bus,
-        TripMode::Drive => app.cs.unzoomed_car,
+        TripMode::Drive | TripMode::RideHail => app.cs.unzoomed_car,
     }
 }
 
@@ -395,6 +395,9 @@ pub fn color_for_trip_phase(app: &App, tpt: TripPhaseType) -> Color {
         TripPhaseType::Parking => app.cs.parking_trip,
         TripPhaseType::WaitingForBus(_, _) => app.cs.bus_layer,
         TripPhaseType::RidingBus(_, _, _) => app.cs.bus_trip,
+        TripPhaseType::WaitingForRideHail => app.cs.bus_layer,
+        TripPhaseType::RidingRideHail(_) => app.cs.unzoomed_car,
+        TripPhaseType::RidingSharedVehicle => app.cs.bike_trip,
         TripPhaseType::Cancelled | TripPhaseType::Finished => unreachable!(),
         TripPhaseType::DelayedStart => Color::YELLOW,
     }
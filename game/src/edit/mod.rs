@@ -6,7 +6,7 @@ use map_gui::options::OptionsPanel;
 use map_gui::render::DrawMap;
 use map_gui::tools::{grey_out_map, ChooseSomething, ColorLegend, PopupMsg};
 use map_gui::ID;
-use map_model::{EditCmd, IntersectionID, LaneID, LaneType, MapEdits};
+use map_model::{EditCmd, IntersectionID, LaneID, LaneType, MapEdits, ParkingPlace};
 use widgetry::{
     lctrl, Choice, Color, ControlState, Drawable, EventCtx, GfxCtx, HorizontalAlignment, Image,
     Key, Line, Menu, Outcome, Panel, State, Text, TextBox, TextExt, VerticalAlignment, Widget,
@@ -871,6 +871,11 @@ fn cmd_to_id(cmd: &EditCmd) -> Option<ID> {
         EditCmd::ChangeRoad { r, .. } => Some(ID::Road(*r)),
         EditCmd::ChangeIntersection { i, .. } => Some(ID::Intersection(*i)),
         EditCmd::ChangeRouteSchedule { .. } => None,
+        EditCmd::ChangeParkingPolicy { place, .. } => Some(match place {
+            ParkingPlace::Road(r) => ID::Road(*r),
+            ParkingPlace::Building(b) => ID::Building(*b),
+            ParkingPlace::ParkingLot(pl) => ID::ParkingLot(*pl),
+        }),
     }
 }
 
@@ -55,11 +55,23 @@ fn stop_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusStopID
 
     let mut boardings: Counter<BusRouteID> = Counter::new();
     let mut alightings: Counter<BusRouteID> = Counter::new();
+    let mut left_behind: Counter<BusRouteID> = Counter::new();
     if let Some(list) = app.primary.sim.get_analytics().passengers_boarding.get(&id) {
         for (_, r, _) in list {
             boardings.inc(*r);
         }
     }
+    if let Some(list) = app
+        .primary
+        .sim
+        .get_analytics()
+        .passengers_left_behind
+        .get(&id)
+    {
+        for (_, r) in list {
+            left_behind.inc(*r);
+        }
+    }
     if let Some(list) = app
         .primary
         .sim
@@ -75,9 +87,10 @@ fn stop_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusStopID
     txt.add_line("Total");
     txt.append(
         Line(format!(
-            ": {} boardings, {} alightings",
+            ": {} boardings, {} alightings, {} left behind by full vehicles",
             prettyprint_usize(boardings.sum()),
-            prettyprint_usize(alightings.sum())
+            prettyprint_usize(alightings.sum()),
+            prettyprint_usize(left_behind.sum())
         ))
         .secondary(),
     );
@@ -85,9 +98,10 @@ fn stop_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusStopID
         txt.add_line(format!("Route {}", r.short_name));
         txt.append(
             Line(format!(
-                ": {} boardings, {} alightings",
+                ": {} boardings, {} alightings, {} left behind",
                 prettyprint_usize(boardings.get(r.id)),
-                prettyprint_usize(alightings.get(r.id))
+                prettyprint_usize(alightings.get(r.id)),
+                prettyprint_usize(left_behind.get(r.id))
             ))
             .secondary(),
         );
@@ -129,13 +143,27 @@ fn bus_status_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: Car
         Tab::BusRoute(route.id),
     );
 
+    let passengers = app.primary.sim.num_transit_passengers(id);
+    let capacity = app.primary.sim.transit_capacity(id);
     rows.push(
         Line(format!(
-            "Currently has {} passengers",
-            app.primary.sim.num_transit_passengers(id),
+            "Currently has {} passengers, out of {} seats and room for {} standing",
+            prettyprint_usize(passengers),
+            prettyprint_usize(capacity.seated),
+            prettyprint_usize(capacity.standing),
         ))
         .into_widget(ctx),
     );
+    if capacity.is_crowded(passengers) {
+        rows.push(
+            Line(format!(
+                "{} passengers are standing",
+                prettyprint_usize(passengers - capacity.seated)
+            ))
+            .secondary()
+            .into_widget(ctx),
+        );
+    }
 
     Widget::col(rows)
 }
@@ -228,6 +256,7 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusRoute
     let mut boardings: Counter<BusStopID> = Counter::new();
     let mut alightings: Counter<BusStopID> = Counter::new();
     let mut waiting: Counter<BusStopID> = Counter::new();
+    let mut left_behind: Counter<BusStopID> = Counter::new();
     for bs in &route.stops {
         if let Some(list) = app.primary.sim.get_analytics().passengers_boarding.get(bs) {
             for (_, r, _) in list {
@@ -244,6 +273,14 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusRoute
             }
         }
 
+        if let Some(list) = app.primary.sim.get_analytics().passengers_left_behind.get(bs) {
+            for (_, r) in list {
+                if *r == id {
+                    left_behind.inc(*bs);
+                }
+            }
+        }
+
         for (_, r, _, _) in app.primary.sim.get_people_waiting_at_stop(*bs) {
             if *r == id {
                 waiting.inc(*bs);
@@ -255,16 +292,44 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusRoute
         Text::from_all(vec![
             Line("Total"),
             Line(format!(
-                ": {} boardings, {} alightings, {} currently waiting",
+                ": {} boardings, {} alightings, {} currently waiting, {} left behind",
                 prettyprint_usize(boardings.sum()),
                 prettyprint_usize(alightings.sum()),
-                prettyprint_usize(waiting.sum())
+                prettyprint_usize(waiting.sum()),
+                prettyprint_usize(left_behind.sum())
             ))
             .secondary(),
         ])
         .into_widget(ctx),
     );
 
+    {
+        let analytics = app.primary.sim.get_analytics();
+        let mut txt = Text::new();
+        if let Some(otp) = analytics.on_time_performance(id) {
+            txt.add_line(Line("On-time performance"));
+            txt.append(Line(format!(": {:.1}% of arrivals", otp * 100.0)).secondary());
+        }
+        if let Some(cv) = analytics.headway_regularity(id) {
+            txt.add_line(Line("Headway variation"));
+            txt.append(Line(format!(": {:.2}", cv)).secondary());
+        }
+        let bunching = analytics
+            .transit_bunching
+            .iter()
+            .filter(|(_, _, r, _)| *r == id)
+            .count();
+        txt.add_line(Line("Bunching"));
+        txt.append(
+            Line(format!(
+                ": {} times a vehicle caught up to the one ahead",
+                prettyprint_usize(bunching)
+            ))
+            .secondary(),
+        );
+        rows.push(txt.into_widget(ctx));
+    }
+
     rows.push(format!("{} stops", route.stops.len()).text_widget(ctx));
     {
         let i = map.get_i(map.get_l(route.start).src_i);
@@ -279,25 +344,38 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusRoute
         ]));
         details.warpers.insert(name, ID::Intersection(i.id));
     }
+    let load_profile = app.primary.sim.get_analytics().transit_load_profile(route);
     for (idx, bs) in route.stops.iter().enumerate() {
         let bs = map.get_bs(*bs);
         let name = format!("Stop {}: {}", idx + 1, bs.name);
+        let mut txt = Text::from_all(vec![
+            Line(&bs.name),
+            Line(format!(
+                ": {} boardings, {} alightings, {} currently waiting, {} left behind",
+                prettyprint_usize(boardings.get(bs.id)),
+                prettyprint_usize(alightings.get(bs.id)),
+                prettyprint_usize(waiting.get(bs.id)),
+                prettyprint_usize(left_behind.get(bs.id))
+            ))
+            .secondary(),
+        ]);
+        // The load between this stop and the next
+        if let Some((avg, max)) = load_profile[idx].1 {
+            txt.add_line(
+                Line(format!(
+                    "  Leaving with {:.1} passengers on average, {} at most",
+                    avg,
+                    prettyprint_usize(max)
+                ))
+                .secondary(),
+            );
+        }
         rows.push(Widget::row(vec![
             ctx.style()
                 .btn_plain
                 .icon("system/assets/tools/pin.svg")
                 .build_widget(ctx, &name),
-            Text::from_all(vec![
-                Line(&bs.name),
-                Line(format!(
-                    ": {} boardings, {} alightings, {} currently waiting",
-                    prettyprint_usize(boardings.get(bs.id)),
-                    prettyprint_usize(alightings.get(bs.id)),
-                    prettyprint_usize(waiting.get(bs.id))
-                ))
-                .secondary(),
-            ])
-            .into_widget(ctx),
+            txt.into_widget(ctx),
         ]));
         details.warpers.insert(name, ID::BusStop(bs.id));
     }
@@ -437,7 +437,7 @@ fn describe_problems(
                 txt.into_widget(ctx),
             ])
         }
-        TripMode::Bike => {
+        TripMode::Bike | TripMode::BikeShare => {
             let mut count_complex_intersections = 0;
             let mut count_overtakes = 0;
             let empty = Vec::new();
@@ -697,6 +697,11 @@ fn make_timeline(
                         "system/assets/timeline/waiting_for_bus.svg"
                     }
                     TripPhaseType::RidingBus(_, _, _) => "system/assets/timeline/riding_bus.svg",
+                    TripPhaseType::WaitingForRideHail => {
+                        "system/assets/timeline/waiting_for_bus.svg"
+                    }
+                    TripPhaseType::RidingRideHail(_) => "system/assets/timeline/driving.svg",
+                    TripPhaseType::RidingSharedVehicle => "system/assets/timeline/biking.svg",
                     TripPhaseType::Cancelled | TripPhaseType::Finished => unreachable!(),
                     TripPhaseType::DelayedStart => "system/assets/timeline/delayed_start.svg",
                 },
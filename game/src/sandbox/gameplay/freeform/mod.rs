@@ -382,6 +382,7 @@ pub fn spawn_agents_around(i: IntersectionID, app: &mut App) {
                         TripEndpoint::Bldg(map.all_buildings().choose(&mut rng).unwrap().id),
                         mode,
                     )],
+                    behavior: None,
                 });
             }
         } else if lane.is_walkable() {
@@ -398,6 +399,7 @@ pub fn spawn_agents_around(i: IntersectionID, app: &mut App) {
                         TripEndpoint::Bldg(map.all_buildings().choose(&mut rng).unwrap().id),
                         TripMode::Walk,
                     )],
+                    behavior: None,
                 });
             }
         }
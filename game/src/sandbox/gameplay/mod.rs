@@ -190,7 +190,7 @@ impl GameplayMode {
                     }
                     _ => {}
                 },
-                EditCmd::ChangeRouteSchedule { .. } => {}
+                EditCmd::ChangeRouteSchedule { .. } | EditCmd::ChangeParkingPolicy { .. } => {}
             }
         }
         true
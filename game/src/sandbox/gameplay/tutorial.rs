@@ -1087,6 +1087,7 @@ impl TutorialState {
                             TripEndpoint::Bldg(goal_bldg),
                             TripMode::Drive,
                         )],
+                        behavior: None,
                     });
                     // Will definitely get there first
                     for _ in 0..map.get_b(goal_bldg).num_parking_spots() {
@@ -1102,6 +1103,7 @@ impl TutorialState {
                                 TripEndpoint::Bldg(goal_bldg),
                                 TripMode::Drive,
                             )],
+                            behavior: None,
                         });
                     }
                     let mut rng = app.primary.current_flags.sim_flags.make_rng();
@@ -10,8 +10,11 @@ use crate::{Distance, GPSBounds, Pt2D};
 
 /// Represents a (longitude, latitude) point.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub struct LonLat {
+    #[cfg_attr(feature = "json_schema", schemars(with = "f64"))]
     longitude: NotNan<f64>,
+    #[cfg_attr(feature = "json_schema", schemars(with = "f64"))]
     latitude: NotNan<f64>,
 }
 
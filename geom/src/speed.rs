@@ -4,10 +4,13 @@ use serde::{Deserialize, Serialize};
 
 use crate::{deserialize_f64, serialize_f64, trim_f64, Distance, Duration, UnitFmt};
 
-/// In meters per second. Can be negative.
+/// In meters per second. Can be negative. Serialized as an integer, multiplied by 10,000.
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub struct Speed(
-    #[serde(serialize_with = "serialize_f64", deserialize_with = "deserialize_f64")] f64,
+    #[serde(serialize_with = "serialize_f64", deserialize_with = "deserialize_f64")]
+    #[cfg_attr(feature = "json_schema", schemars(with = "i32"))]
+    f64,
 );
 
 // By construction, Speed is a finite f64 with trimmed precision.
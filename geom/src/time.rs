@@ -6,10 +6,13 @@ use serde::{Deserialize, Serialize};
 
 use crate::{deserialize_f64, serialize_f64, trim_f64, Duration};
 
-/// In seconds since midnight. Can't be negative.
+/// In seconds since midnight. Can't be negative. Serialized as an integer, multiplied by 10,000.
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub struct Time(
-    #[serde(serialize_with = "serialize_f64", deserialize_with = "deserialize_f64")] f64,
+    #[serde(serialize_with = "serialize_f64", deserialize_with = "deserialize_f64")]
+    #[cfg_attr(feature = "json_schema", schemars(with = "i32"))]
+    f64,
 );
 
 // By construction, Time is a finite f64 with trimmed precision.
@@ -96,6 +99,18 @@ impl Time {
         )
     }
 
+    /// Like `25:05:00`, rounded to the nearest second. Hours keep counting past midnight, the way
+    /// GTFS and MATSim write times.
+    pub fn as_hhmmss(self) -> String {
+        let seconds = self.0.round() as usize;
+        format!(
+            "{:02}:{:02}:{:02}",
+            seconds / 3600,
+            (seconds % 3600) / 60,
+            seconds % 60
+        )
+    }
+
     pub fn parse(string: &str) -> Result<Time> {
         let parts: Vec<&str> = string.split(':').collect();
         if parts.is_empty() {
@@ -211,6 +226,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn as_hhmmss() {
+        assert_eq!(Time::START_OF_DAY.as_hhmmss(), "00:00:00");
+        assert_eq!(
+            (Time::START_OF_DAY + Duration::hours(7) + Duration::seconds(62.6)).as_hhmmss(),
+            "07:01:03"
+        );
+        assert_eq!(
+            (Time::START_OF_DAY + Duration::hours(25) + Duration::minutes(5)).as_hhmmss(),
+            "25:05:00"
+        );
+    }
+
     #[test]
     fn get_hours() {
         assert_eq!((Time::START_OF_DAY + Duration::hours(6)).get_hours(), 6);
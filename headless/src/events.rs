@@ -0,0 +1,260 @@
+//! Streams simulation events to long-lived HTTP clients as JSON lines.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use anyhow::Result;
+use hyper::{Body, Response};
+use tokio::sync::mpsc;
+
+use headless_client::{SessionID, StreamedEvent};
+use map_model::{IntersectionID, Map, RoadID, Traversable};
+use sim::{AlertLocation, Event, ParkingSpot, Problem, Sim};
+
+lazy_static::lazy_static! {
+    static ref SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+}
+
+struct Subscriber {
+    session: SessionID,
+    filter: EventFilter,
+    tx: mpsc::UnboundedSender<String>,
+}
+
+/// Decides which events a subscriber sees. Every specified condition must match.
+struct EventFilter {
+    /// Names of Event variants, like "TripFinished". If empty, all kinds are sent.
+    kinds: BTreeSet<String>,
+    /// If non-empty, only events happening at one of these intersections or roads are sent.
+    /// Events without any location, like TripFinished, never match.
+    intersections: BTreeSet<IntersectionID>,
+    roads: BTreeSet<RoadID>,
+}
+
+/// Starts a streaming response. The body stays open until the client disconnects, and receives
+/// one JSON object per line every time `broadcast` is called for the session.
+pub fn subscribe(session: SessionID, params: &HashMap<String, String>) -> Result<Response<Body>> {
+    let filter = EventFilter {
+        kinds: params
+            .get("kinds")
+            .map(|x| x.split(',').map(|k| k.to_string()).collect())
+            .unwrap_or_default(),
+        intersections: parse_ids(params.get("intersections"))?
+            .into_iter()
+            .map(IntersectionID)
+            .collect(),
+        roads: parse_ids(params.get("roads"))?
+            .into_iter()
+            .map(RoadID)
+            .collect(),
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        while let Some(line) = rx.recv().await {
+            if sender.send_data(line.into()).await.is_err() {
+                // The client disconnected. Dropping rx will unregister the subscriber the next
+                // time something is broadcast.
+                break;
+            }
+        }
+    });
+    SUBSCRIBERS.lock().unwrap().push(Subscriber {
+        session,
+        filter,
+        tx,
+    });
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/x-ndjson")
+        .body(body)
+        .unwrap())
+}
+
+/// Closes the streams of every subscriber to a deleted session.
+pub fn unsubscribe_all(session: SessionID) {
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .retain(|sub| sub.session != session);
+}
+
+/// Sends every event captured by the session's simulation since the last call to all of its
+/// subscribers.
+pub fn broadcast(session: SessionID, sim: &mut Sim, map: &Map) {
+    let events = sim.drain_captured_events();
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    if events.is_empty() || subscribers.iter().all(|sub| sub.session != session) {
+        return;
+    }
+
+    for (time, event) in events {
+        let kind = event_kind(&event);
+        let (intersections, roads) = event_locations(&event, map);
+        let ev = StreamedEvent { time, event };
+        let mut line = None;
+        subscribers.retain(|sub| {
+            if sub.session != session || !sub.filter.matches(kind, &intersections, &roads) {
+                return true;
+            }
+            let line = line.get_or_insert_with(|| format!("{}\n", abstutil::to_json_terse(&ev)));
+            sub.tx.send(line.clone()).is_ok()
+        });
+    }
+}
+
+impl EventFilter {
+    fn matches(
+        &self,
+        kind: &str,
+        intersections: &BTreeSet<IntersectionID>,
+        roads: &BTreeSet<RoadID>,
+    ) -> bool {
+        if !self.kinds.is_empty() && !self.kinds.contains(kind) {
+            return false;
+        }
+        if self.intersections.is_empty() && self.roads.is_empty() {
+            return true;
+        }
+        !self.intersections.is_disjoint(intersections) || !self.roads.is_disjoint(roads)
+    }
+}
+
+fn parse_ids(list: Option<&String>) -> Result<Vec<usize>> {
+    let mut ids = Vec::new();
+    if let Some(list) = list {
+        for x in list.split(',') {
+            ids.push(x.parse::<usize>()?);
+        }
+    }
+    Ok(ids)
+}
+
+/// The name of the Event variant, matching how it's serialized.
+fn event_kind(ev: &Event) -> &'static str {
+    match ev {
+        Event::CarReachedParkingSpot(..) => "CarReachedParkingSpot",
+        Event::CarLeftParkingSpot(..) => "CarLeftParkingSpot",
+        Event::BusArrivedAtStop(..) => "BusArrivedAtStop",
+        Event::BusDepartedFromStop(..) => "BusDepartedFromStop",
+        Event::PassengerBoardsTransit(..) => "PassengerBoardsTransit",
+        Event::PassengerAlightsTransit(..) => "PassengerAlightsTransit",
+        Event::TransitScheduleAdherence { .. } => "TransitScheduleAdherence",
+        Event::TransitBunching { .. } => "TransitBunching",
+        Event::TransitVehicleHeld { .. } => "TransitVehicleHeld",
+        Event::PassengerLeftBehind(..) => "PassengerLeftBehind",
+        Event::TransitVehicleLoad { .. } => "TransitVehicleLoad",
+        Event::PersonEntersBuilding(..) => "PersonEntersBuilding",
+        Event::PersonLeavesBuilding(..) => "PersonLeavesBuilding",
+        Event::PersonLeavesMap(..) => "PersonLeavesMap",
+        Event::PersonEntersMap(..) => "PersonEntersMap",
+        Event::PedReachedParkingSpot(..) => "PedReachedParkingSpot",
+        Event::DeliveryMade(..) => "DeliveryMade",
+        Event::DeliveryTourFinished { .. } => "DeliveryTourFinished",
+        Event::RideHailPickup(..) => "RideHailPickup",
+        Event::RideHailDropoff { .. } => "RideHailDropoff",
+        Event::RideHailVehicleMoved { .. } => "RideHailVehicleMoved",
+        Event::BikeStoppedAtSidewalk(..) => "BikeStoppedAtSidewalk",
+        Event::DockOccupancyChanged { .. } => "DockOccupancyChanged",
+        Event::BikeShareUnavailable(..) => "BikeShareUnavailable",
+        Event::EnergyUsed { .. } => "EnergyUsed",
+        Event::ChargerOccupancyChanged { .. } => "ChargerOccupancyChanged",
+        Event::BatteryCharged { .. } => "BatteryCharged",
+        Event::ChargingDetour(..) => "ChargingDetour",
+        Event::ParkingFeePaid { .. } => "ParkingFeePaid",
+        Event::ParkingTooExpensive(..) => "ParkingTooExpensive",
+        Event::ProblemEncountered(..) => "ProblemEncountered",
+        Event::AgentEntersTraversable(..) => "AgentEntersTraversable",
+        Event::IntersectionDelayMeasured(..) => "IntersectionDelayMeasured",
+        Event::TripFinished { .. } => "TripFinished",
+        Event::TripCancelled(..) => "TripCancelled",
+        Event::TripPhaseStarting(..) => "TripPhaseStarting",
+        Event::PathAmended(..) => "PathAmended",
+        Event::Alert(..) => "Alert",
+    }
+}
+
+/// Everywhere an event happens.
+fn event_locations(ev: &Event, map: &Map) -> (BTreeSet<IntersectionID>, BTreeSet<RoadID>) {
+    let mut intersections = BTreeSet::new();
+    let mut on = Vec::new();
+    match ev {
+        Event::CarReachedParkingSpot(_, spot)
+        | Event::CarLeftParkingSpot(_, spot)
+        | Event::PedReachedParkingSpot(_, spot) => {
+            if let ParkingSpot::Onstreet(l, _) = spot {
+                on.push(Traversable::Lane(*l));
+            }
+        }
+        Event::BusArrivedAtStop(_, _, bs)
+        | Event::BusDepartedFromStop(_, _, bs)
+        | Event::PassengerBoardsTransit(_, _, _, bs, _)
+        | Event::PassengerAlightsTransit(_, _, _, bs)
+        | Event::PassengerLeftBehind(_, _, _, bs)
+        | Event::TransitVehicleLoad { departing: bs, .. }
+        | Event::TransitScheduleAdherence { stop: bs, .. }
+        | Event::TransitBunching { stop: bs, .. }
+        | Event::TransitVehicleHeld { stop: bs, .. } => {
+            on.push(Traversable::Lane(map.get_bs(*bs).driving_pos.lane()));
+        }
+        Event::PersonLeavesMap(_, _, i) | Event::PersonEntersMap(_, _, i) => {
+            intersections.insert(*i);
+        }
+        Event::BikeStoppedAtSidewalk(_, l) | Event::DeliveryMade(_, _, l, _) => {
+            on.push(Traversable::Lane(*l));
+        }
+        Event::ProblemEncountered(_, problem) => match problem {
+            Problem::IntersectionDelay(i, _) | Problem::ComplexIntersectionCrossing(i) => {
+                intersections.insert(*i);
+            }
+            Problem::ArterialIntersectionCrossing(t) => {
+                on.push(Traversable::Turn(*t));
+            }
+            Problem::OvertakeDesired(t) => {
+                on.push(*t);
+            }
+        },
+        Event::AgentEntersTraversable(_, _, t, _) => {
+            on.push(*t);
+        }
+        Event::IntersectionDelayMeasured(_, t, _, _) => {
+            on.push(Traversable::Turn(*t));
+        }
+        Event::Alert(AlertLocation::Intersection(i), _) => {
+            intersections.insert(*i);
+        }
+        Event::PersonEntersBuilding(_, _)
+        | Event::PersonLeavesBuilding(_, _)
+        | Event::TripFinished { .. }
+        | Event::DeliveryTourFinished { .. }
+        | Event::RideHailPickup(_, _, _)
+        | Event::RideHailDropoff { .. }
+        | Event::RideHailVehicleMoved { .. }
+        | Event::DockOccupancyChanged { .. }
+        | Event::BikeShareUnavailable(_, _)
+        | Event::EnergyUsed { .. }
+        | Event::ChargerOccupancyChanged { .. }
+        | Event::BatteryCharged { .. }
+        | Event::ChargingDetour(_, _)
+        | Event::ParkingFeePaid { .. }
+        | Event::ParkingTooExpensive(_, _)
+        | Event::TripCancelled(_, _)
+        | Event::TripPhaseStarting(_, _, _, _)
+        | Event::PathAmended(_)
+        | Event::Alert(_, _) => {}
+    }
+
+    let mut roads = BTreeSet::new();
+    for t in on {
+        match t {
+            Traversable::Lane(l) => {
+                roads.insert(map.get_l(l).parent);
+            }
+            Traversable::Turn(t) => {
+                intersections.insert(t.parent);
+            }
+        }
+    }
+    (intersections, roads)
+}
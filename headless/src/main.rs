@@ -8,47 +8,88 @@
 // it's now 01:01:00.0
 // > curl http://localhost:1234/data/get-road-thruput
 // ... huge JSON blob
+// > curl -N http://localhost:1234/sim/stream-events?kinds=TripFinished,Alert
+// ... one JSON event per line, as other requests advance the sim
+//
+// To run independent simulations in the same process, create a session and pass its ID to any of
+// the other commands:
+// > curl http://localhost:1234/session/create -d '{"scenario": "path/to/scenario.bin"}'
+// 1
+// > curl http://localhost:1234/sim/goto-time?session=1&t=01:01:00
+//
+// The state of a simulation can be saved and restored later, or forked into a new session:
+// > curl http://localhost:1234/sim/snapshot
+// 0
+// > curl http://localhost:1234/sim/restore?id=0
+// > curl http://localhost:1234/sim/fork?id=0
+// 2
+// > curl http://localhost:1234/sim/snapshot-bytes > savestate.bin
+// > curl http://localhost:1234/sim/restore --data-binary @savestate.bin
+//
+// Map edits can be applied to a running simulation. Any trips crossing something that changed are
+// cancelled and reported.
+// > curl http://localhost:1234/map/get-edit-road-command?id=123 > cmd.json
+// ... modify the "new" part of cmd.json
+// > curl http://localhost:1234/map/apply-edit-commands -d "[$(cat cmd.json)]"
+//
+// Traffic signals can be controlled by the client. When a signal needs to pick its next stage,
+// goto-time stops early.
+// > curl http://localhost:1234/traffic-signals/set-controller?id=42&controller=external
+// > curl http://localhost:1234/sim/goto-time?t=07:00:00
+// it's now 00:00:30.0
+// > curl http://localhost:1234/traffic-signals/get-pending-decisions
+// > curl http://localhost:1234/traffic-signals/choose-stage?id=42&stage=1&duration=20
+//
+// Virtual detectors count vehicles passing a point. Readings are grouped into time bins.
+// > curl http://localhost:1234/detectors/add -d '{"name": "loop1", "pos": {"lane": 42, \
+//   "dist_along": 300000}, "length": 20000, "bin_width": 9000000}'
+// 0
+// > curl http://localhost:1234/detectors/export-csv > counts.csv
+//
+// An OpenAPI description of every command is served at /openapi.json. Rust programs can use the
+// typed client in the headless_client crate instead.
 
 #[macro_use]
 extern crate anyhow;
 #[macro_use]
 extern crate log;
 
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
-use std::sync::RwLock;
+use std::sync::Arc;
 
 use anyhow::Result;
 use hyper::{Body, Request, Response, Server, StatusCode};
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
-use serde::{Deserialize, Serialize};
 
 use abstio::MapName;
-use abstutil::{serialize_btreemap, CmdArgs, Timer};
-use geom::{Distance, Duration, LonLat, Time};
+use abstutil::{CmdArgs, Timer};
+use geom::{Distance, Duration, Time};
 use map_model::{
     CompressedMovementID, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, Map,
-    MovementID, PermanentMapEdits, RoadID, TurnID,
+    MapEdits, MovementID, PermanentEditCmd, PermanentMapEdits, RoadID,
 };
 use sim::{
-    AgentID, AgentType, DelayCause, ExternalPerson, PersonID, Scenario, ScenarioModifier, Sim,
-    SimFlags, SimOptions, TripID, TripMode, VehicleType,
+    Detector, DetectorID, ExternalPerson, Scenario, ScenarioModifier, Sim, SimFlags, SimOptions,
+    TripID,
 };
 
-lazy_static::lazy_static! {
-    static ref MAP: RwLock<Map> = RwLock::new(Map::blank());
-    static ref SIM: RwLock<Sim> = RwLock::new(Sim::new(&Map::blank(), SimOptions::new("tmp")));
-    static ref LOAD: RwLock<LoadSim> = RwLock::new({
-        LoadSim {
-            scenario: abstio::path_scenario(&MapName::seattle("montlake"), "weekday"),
-            modifiers: Vec::new(),
-            edits: None,
-            rng_seed: SimFlags::RNG_SEED,
-            opts: SimOptions::default(),
-        }
-    });
-}
+use headless_client::{
+    AffectedByEdits, AgentPosition, AgentPositions, BlockedByGraph, CancelledByEdits, Delays,
+    FinishedTrip, LoadScenario, RoadThroughput, SessionID, SnapshotID, Throughput,
+    TrafficSignalState,
+};
+
+use crate::sessions::{Session, DEFAULT_SESSION};
+
+mod events;
+mod openapi;
+mod sessions;
+mod signals;
+
+/// While advancing time, send captured events to subscribers at least this often.
+const STREAM_EVENTS_FREQUENCY: Duration = Duration::const_seconds(60.0);
 
 #[tokio::main]
 async fn main() {
@@ -59,17 +100,22 @@ async fn main() {
         .unwrap_or(SimFlags::RNG_SEED);
     let opts = SimOptions::from_args(&mut args, rng_seed);
     let port = args.required("--port").parse::<u16>().unwrap();
+    // Sessions unused for this long are deleted. The default session never expires.
+    let idle_session_timeout = args.optional_parse("--idle_session_timeout", Duration::parse);
     args.done();
 
-    {
-        let mut load = LOAD.write().unwrap();
-        load.rng_seed = rng_seed;
-        load.opts = opts;
-
-        let (map, sim) = load.setup(&mut timer);
-        *MAP.write().unwrap() = map;
-        *SIM.write().unwrap() = sim;
-    }
+    sessions::initialize(
+        LoadSim {
+            scenario: abstio::path_scenario(&MapName::seattle("montlake"), "weekday"),
+            modifiers: Vec::new(),
+            edits: None,
+            rng_seed,
+            opts,
+        },
+        idle_session_timeout,
+        &mut timer,
+    )
+    .unwrap();
 
     let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
     info!("Listening on http://{}", addr);
@@ -92,35 +138,68 @@ async fn serve_req(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
             .collect();
     let body = hyper::body::to_bytes(req).await?.to_vec();
     info!("Handling {}", path);
-    Ok(
-        match handle_command(
-            &path,
-            &params,
-            &body,
-            &mut SIM.write().unwrap(),
-            &mut MAP.write().unwrap(),
-            &mut LOAD.write().unwrap(),
-        ) {
-            Ok(resp) => Response::new(Body::from(resp)),
-            Err(err) => {
-                error!("{}: {}", path, err);
-                Response::builder()
-                    .status(StatusCode::BAD_REQUEST)
-                    .body(Body::from(format!("Bad command {}: {}", path, err)))
-                    .unwrap()
-            }
-        },
-    )
+
+    let result = if path == "/openapi.json" {
+        Ok(Response::new(Body::from(abstutil::to_json(
+            &openapi::describe(),
+        ))))
+    } else if path.starts_with("/session/") {
+        sessions::handle_command(&path, &params, &body).map(|resp| Response::new(Body::from(resp)))
+    } else {
+        params
+            .get("session")
+            .map(|x| x.parse::<SessionID>())
+            .unwrap_or(Ok(DEFAULT_SESSION))
+            .map_err(anyhow::Error::from)
+            .and_then(|id| {
+                let session = sessions::get(id)?;
+                if path == "/sim/stream-events" {
+                    // This one keeps the connection open, so it doesn't fit into handle_command
+                    return events::subscribe(id, &params);
+                }
+                let mut session = session.lock().unwrap();
+                if path == "/sim/snapshot-bytes" {
+                    // The response is binary, not a string
+                    return Ok(Response::new(Body::from(session.sim.save_to_bytes())));
+                }
+                let result = handle_command(&path, &params, &body, &mut session)
+                    .map(|resp| Response::new(Body::from(resp)));
+                // Many commands besides goto-time can produce events
+                let Session { sim, map, .. } = &mut *session;
+                events::broadcast(id, sim, map);
+                result
+            })
+    };
+
+    Ok(match result {
+        Ok(resp) => resp,
+        Err(err) => {
+            error!("{}: {}", path, err);
+            Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Bad command {}: {}", path, err)))
+                .unwrap()
+        }
+    })
 }
 
 fn handle_command(
     path: &str,
     params: &HashMap<String, String>,
     body: &[u8],
-    sim: &mut Sim,
-    map: &mut Map,
-    load: &mut LoadSim,
+    session: &mut Session,
 ) -> Result<String> {
+    if let Some(result) = handle_snapshot_command(path, params, body, session) {
+        return result;
+    }
+
+    let Session {
+        id: session_id,
+        sim,
+        map,
+        load,
+        ..
+    } = session;
     let get = |key: &str| {
         params
             .get(key)
@@ -130,20 +209,22 @@ fn handle_command(
     match path {
         // Controlling the simulation
         "/sim/reset" => {
-            let (new_map, new_sim) = load.setup(&mut Timer::new("reset sim"));
+            let (new_map, new_sim) = load.setup(&mut Timer::new("reset sim"))?;
             *map = new_map;
             *sim = new_sim;
             Ok("sim reloaded".to_string())
         }
         "/sim/load" => {
-            let args: LoadSim = abstutil::from_json(body)?;
+            let args: LoadScenario = abstutil::from_json(body)?;
 
-            load.scenario = args.scenario;
-            load.modifiers = args.modifiers;
-            load.edits = args.edits;
+            let mut new_load = load.clone();
+            new_load.scenario = args.scenario;
+            new_load.modifiers = args.modifiers;
+            new_load.edits = args.edits;
 
-            // Also reset
-            let (new_map, new_sim) = load.setup(&mut Timer::new("reset sim"));
+            // Also reset. If the new flags don't work, keep the old ones.
+            let (new_map, new_sim) = new_load.setup(&mut Timer::new("reset sim"))?;
+            *load = new_load;
             *map = new_map;
             *sim = new_sim;
 
@@ -155,9 +236,19 @@ fn handle_command(
             if t <= sim.time() {
                 bail!("{} is in the past. call /sim/reset first?", t)
             } else {
-                let dt = t - sim.time();
-                sim.timed_step(map, dt, &mut None, &mut Timer::new("goto-time"));
-                Ok(format!("it's now {}", t))
+                // Advance in pieces, so anybody streaming events sees them as they happen
+                let mut timer = Timer::new("goto-time");
+                while sim.time() < t {
+                    let dt = std::cmp::min(t - sim.time(), STREAM_EVENTS_FREQUENCY);
+                    let before = sim.time();
+                    sim.timed_step(map, dt, &mut None, &mut timer);
+                    events::broadcast(*session_id, sim, map);
+                    // An alert might have blocked the sim
+                    if sim.time() < before + dt {
+                        break;
+                    }
+                }
+                Ok(format!("it's now {}", sim.time()))
             }
         }
         "/sim/new-person" => {
@@ -202,6 +293,7 @@ fn handle_command(
                 old: map.get_i_edit(id),
                 new: EditIntersection::TrafficSignal(ts.export(map)),
             });
+            let map = sessions::map_mut(map);
             map.must_apply_edits(edits);
             map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
 
@@ -285,6 +377,25 @@ fn handle_command(
             }
             Ok(abstutil::to_json(&all_state))
         }
+        "/traffic-signals/set-controller" => {
+            let i = IntersectionID(get("id")?.parse::<usize>()?);
+            signals::set_controller(sim, map, i, get("controller")?)?;
+            Ok(format!(
+                "{} now uses the {} controller",
+                i,
+                get("controller")?
+            ))
+        }
+        "/traffic-signals/get-pending-decisions" => {
+            Ok(abstutil::to_json(&signals::pending_decisions(sim, map)))
+        }
+        "/traffic-signals/choose-stage" => {
+            let i = IntersectionID(get("id")?.parse::<usize>()?);
+            let stage = get("stage")?.parse::<usize>()?;
+            let duration = Duration::seconds(get("duration")?.parse::<f64>()?);
+            signals::choose_stage(sim, map, i, stage, duration)?;
+            Ok(format!("{} will run stage {} for {}", i, stage, duration))
+        }
         // Querying data
         "/data/get-finished-trips" => {
             let mut trips = Vec::new();
@@ -363,6 +474,19 @@ fn handle_command(
                 .collect();
             Ok(abstutil::to_json(&results))
         }
+        // Detectors
+        "/detectors/add" => {
+            let detector: Detector = abstutil::from_json(body)?;
+            Ok(abstutil::to_json(&sim.add_detector(detector, map)?))
+        }
+        "/detectors/get-readings" => {
+            let id = DetectorID(get("id")?.parse::<usize>()?);
+            if id.0 >= sim.all_detectors().len() {
+                bail!("no detector {}", id.0);
+            }
+            Ok(abstutil::to_json(&sim.get_detector_readings(id)))
+        }
+        "/detectors/export-csv" => Ok(sim.export_detector_readings()),
         // Controlling the map
         "/map/get-edits" => {
             let mut edits = map.get_edits().clone();
@@ -376,6 +500,19 @@ fn handle_command(
                 &map.edit_road_cmd(r, |_| {}).to_perma(map),
             ))
         }
+        "/map/set-edits" => {
+            let perma: PermanentMapEdits = abstutil::from_json(body)?;
+            let edits = perma.into_edits(map)?;
+            Ok(abstutil::to_json(&apply_live_edits(sim, map, edits)))
+        }
+        "/map/apply-edit-commands" => {
+            let cmds: Vec<PermanentEditCmd> = abstutil::from_json(body)?;
+            let mut edits = map.get_edits().clone();
+            for cmd in cmds {
+                edits.commands.push(cmd.into_cmd(map)?);
+            }
+            Ok(abstutil::to_json(&apply_live_edits(sim, map, edits)))
+        }
         "/map/get-intersection-geometry" => {
             let i = IntersectionID(get("id")?.parse::<usize>()?);
             Ok(abstutil::to_json(&export_geometry(map, i)))
@@ -385,109 +522,78 @@ fn handle_command(
     }
 }
 
-// TODO I think specifying the API with protobufs or similar will be a better idea.
-
-#[derive(Serialize)]
-struct FinishedTrip {
-    id: TripID,
-    person: PersonID,
-    duration: Option<Duration>,
-    distance_crossed: Distance,
-    mode: TripMode,
-    capped: bool,
-}
-
-#[derive(Serialize)]
-struct Delays {
-    #[serde(serialize_with = "serialize_btreemap")]
-    per_direction: BTreeMap<MovementID, Vec<Duration>>,
-}
-
-#[derive(Serialize)]
-struct Throughput {
-    #[serde(serialize_with = "serialize_btreemap")]
-    per_direction: BTreeMap<MovementID, usize>,
-}
-
-#[derive(Serialize)]
-struct AgentPositions {
-    agents: Vec<AgentPosition>,
-}
-
-#[derive(Serialize)]
-struct AgentPosition {
-    /// The agent's ID
-    id: AgentID,
-    /// None for buses
-    trip: Option<TripID>,
-    /// None for buses
-    person: Option<PersonID>,
-    /// None for pedestrians
-    vehicle_type: Option<VehicleType>,
-    /// The agent's current position. For pedestrians, this is their center. For vehicles, this
-    /// represents the front of the vehicle.
-    pos: LonLat,
-    /// The distance crossed so far by the agent, in meters. There are some caveats to this value:
-    /// - The distance along driveways between buildings/parking lots and the road doesn't count
-    ///   here.
-    /// - The distance only represents the current leg of the trip. If somebody walks to a car, the
-    ///   distance will reset when they begin driving, and also vehicle_type will change.
-    /// - No meaning for bus passengers currently.
-    /// - For buses and trains, the value will reset every time the vehicle reaches the next
-    ///   transit stop.
-    /// - The value might be slightly undercounted or overcounted if the path crosses into or out
-    ///   of an access-restricted or capped zone.
-    /// - At the very end of a driving trip, the agent may wind up crossing slightly more or less
-    ///   than the total path length, due to where they park along that last road.
-    distance_crossed: Distance,
-}
-
-#[derive(Serialize)]
-struct RoadThroughput {
-    // (road, agent type, hour since midnight, throughput for that one hour period)
-    counts: Vec<(RoadID, AgentType, usize, usize)>,
+/// Changes the map and updates the simulation in-place, without resetting it.
+fn apply_live_edits(sim: &mut Sim, map: &mut Arc<Map>, edits: MapEdits) -> AffectedByEdits {
+    let map = sessions::map_mut(map);
+    map.must_apply_edits(edits);
+    map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+    sim.handle_live_edited_traffic_signals(map);
+    let (affected, parked_cars_displaced) = sim.handle_live_edits_listing_affected(map);
+    AffectedByEdits {
+        cancelled: affected
+            .into_iter()
+            .map(|(agent, trip)| CancelledByEdits {
+                agent,
+                trip,
+                person: sim.trip_to_person(trip),
+            })
+            .collect(),
+        parked_cars_displaced,
+    }
 }
 
-#[derive(Serialize)]
-struct TrafficSignalState {
-    current_stage_idx: usize,
-    remaining_time: Duration,
-    accepted: BTreeSet<AgentID>,
-    // Some agent has been waiting to start a turn since some time
-    waiting: Vec<(AgentID, TurnID, Time)>,
-}
+/// Saving, restoring, and forking the simulation need the entire session, so they're handled
+/// separately. Returns None for any other command.
+fn handle_snapshot_command(
+    path: &str,
+    params: &HashMap<String, String>,
+    body: &[u8],
+    session: &mut Session,
+) -> Option<Result<String>> {
+    let snapshot_id = || -> Result<Option<SnapshotID>> {
+        match params.get("id") {
+            Some(x) => Ok(Some(x.parse::<SnapshotID>()?)),
+            None => Ok(None),
+        }
+    };
 
-#[derive(Serialize)]
-struct BlockedByGraph {
-    /// Each entry indicates that some agent has been stuck in one place for some amount of time,
-    /// due to being blocked by another agent or because they're waiting at an intersection. Unless
-    /// the agent is a bus, then the TripID and PersonID will also be filled out.
-    #[serde(serialize_with = "serialize_btreemap")]
-    blocked_by: BTreeMap<AgentID, (Duration, DelayCause, Option<TripID>, Option<PersonID>)>,
+    let result = match path {
+        "/sim/snapshot" => Ok(abstutil::to_json(&session.snapshot())),
+        "/sim/restore" => snapshot_id()
+            .and_then(|id| session.restore(id, body))
+            .map(|_| format!("restored to {}", session.sim.time())),
+        "/sim/fork" => snapshot_id()
+            .and_then(|id| session.fork(id))
+            .map(|id| abstutil::to_json(&id)),
+        "/sim/delete-snapshot" => snapshot_id().and_then(|id| {
+            let id = id.ok_or_else(|| anyhow!("missing GET parameter id"))?;
+            if session.snapshots.remove(&id).is_none() {
+                bail!("no snapshot {}", id);
+            }
+            Ok(format!("snapshot {} deleted", id))
+        }),
+        _ => {
+            return None;
+        }
+    };
+    Some(result)
 }
 
-#[derive(Deserialize)]
-struct LoadSim {
+#[derive(Clone)]
+pub struct LoadSim {
     scenario: String,
     modifiers: Vec<ScenarioModifier>,
     edits: Option<PermanentMapEdits>,
-    // These are fixed from the initial command line flags
-    #[serde(skip_deserializing)]
+    // These are fixed when the session is created
     rng_seed: u64,
-    #[serde(skip_deserializing)]
     opts: SimOptions,
 }
 
 impl LoadSim {
-    fn setup(&self, timer: &mut Timer) -> (Map, Sim) {
-        let mut scenario: Scenario = abstio::must_read_object(self.scenario.clone(), timer);
+    fn setup(&self, timer: &mut Timer) -> Result<(Arc<Map>, Sim)> {
+        let mut scenario: Scenario = abstio::read_object(self.scenario.clone(), timer)?;
 
-        let mut map = Map::load_synchronously(scenario.map_name.path(), timer);
-        if let Some(perma) = self.edits.clone() {
-            let edits = perma.into_edits(&map).unwrap();
-            map.must_apply_edits(edits);
-            map.recalculate_pathfinding_after_edits(timer);
-        }
+        let map = sessions::load_map(&scenario.map_name, self.edits.clone(), timer)?;
 
         for m in &self.modifiers {
             scenario = m.apply(&map, scenario);
@@ -495,9 +601,10 @@ impl LoadSim {
 
         let mut rng = XorShiftRng::seed_from_u64(self.rng_seed);
         let mut sim = Sim::new(&map, self.opts.clone());
+        sim.capture_events();
         scenario.instantiate(&mut sim, &map, &mut rng, timer);
 
-        (map, sim)
+        Ok((map, sim))
     }
 }
 
@@ -0,0 +1,367 @@
+//! Describes the API in the OpenAPI 3 format. Request and response schemas come from the types in
+//! the headless_client crate, which the server uses for serialization, so they stay accurate.
+//! When adding a command to handle_command, describe it here too.
+
+use std::collections::BTreeMap;
+
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use serde_json::{json, Value};
+
+use geom::Duration;
+use headless_client::schema::opaque;
+use headless_client::{
+    AffectedByEdits, AgentPositions, BlockedByGraph, CreateSession, Delays, FinishedTrip,
+    LoadScenario, PendingStageDecision, RoadThroughput, SessionID, SessionInfo, SnapshotID,
+    StreamedEvent, Throughput, TrafficSignalState,
+};
+use sim::{Detector, DetectorReading};
+
+struct Endpoint {
+    path: &'static str,
+    summary: &'static str,
+    /// (name, description) of GET parameters. All of them are required, unless the description
+    /// starts with "Optional".
+    params: Vec<(&'static str, &'static str)>,
+    body: Option<Body>,
+    response: Body,
+}
+
+struct Body {
+    content_type: &'static str,
+    schema: Value,
+}
+
+/// Produces the entire OpenAPI document.
+pub fn describe() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+
+    let mut paths = serde_json::Map::new();
+    for endpoint in endpoints(&mut gen) {
+        paths.insert(endpoint.path.to_string(), endpoint.to_json());
+    }
+
+    json!({
+        "openapi": "3.0.0",
+        "info": {
+            "title": "A/B Street headless API",
+            "version": "0.1.0",
+        },
+        "paths": paths,
+        "components": {
+            "schemas": gen.take_definitions(),
+        },
+    })
+}
+
+fn endpoints(gen: &mut SchemaGenerator) -> Vec<Endpoint> {
+    let id = |what: &'static str| ("id", what);
+    let time = |name: &'static str| (name, "A time, like 07:30:00");
+
+    vec![
+        // Sessions
+        Endpoint::new("/session/create", "Start a new independent simulation")
+            .body(json_body::<CreateSession>(gen))
+            .response(json_body::<SessionID>(gen)),
+        Endpoint::new("/session/list", "Describe all sessions")
+            .response(json_body::<Vec<SessionInfo>>(gen)),
+        Endpoint::new("/session/delete", "Delete a session")
+            .param("session", "The session to delete"),
+        // Controlling the simulation
+        Endpoint::new("/sim/reset", "Restart the simulation from midnight"),
+        Endpoint::new("/sim/load", "Change the scenario or edits, and reset")
+            .body(json_body::<LoadScenario>(gen)),
+        Endpoint::new("/sim/get-time", "The current simulation time"),
+        Endpoint::new("/sim/goto-time", "Run the simulation until some time").param_pair(time("t")),
+        Endpoint::new(
+            "/sim/new-person",
+            "Add a person with some trips to the simulation",
+        )
+        .body(opaque_body("A sim::ExternalPerson")),
+        Endpoint::new(
+            "/sim/stream-events",
+            "Keep the connection open, receiving one event per line as the simulation advances",
+        )
+        .param(
+            "kinds",
+            "Optional. A comma-separated list of sim::Event variants, like TripFinished",
+        )
+        .param(
+            "intersections",
+            "Optional. A comma-separated list of IntersectionIDs. Only events happening at these \
+             intersections or roads are sent.",
+        )
+        .param(
+            "roads",
+            "Optional. A comma-separated list of RoadIDs. Only events happening at these \
+             intersections or roads are sent.",
+        )
+        .response(Body {
+            content_type: "application/x-ndjson",
+            schema: schema_for::<StreamedEvent>(gen),
+        }),
+        // Snapshots
+        Endpoint::new(
+            "/sim/snapshot",
+            "Remember the current state of the simulation",
+        )
+        .response(json_body::<SnapshotID>(gen)),
+        Endpoint::new(
+            "/sim/snapshot-bytes",
+            "Serialize the current state of the simulation",
+        )
+        .response(binary_body()),
+        Endpoint::new(
+            "/sim/restore",
+            "Roll back to a snapshot, or to serialized bytes passed in the body",
+        )
+        .param_pair(id("Optional. The snapshot to restore"))
+        .body(binary_body()),
+        Endpoint::new(
+            "/sim/fork",
+            "Create a new session from the current state or a snapshot",
+        )
+        .param_pair(id("Optional. The snapshot to start from"))
+        .response(json_body::<SessionID>(gen)),
+        Endpoint::new("/sim/delete-snapshot", "Forget a snapshot")
+            .param_pair(id("The snapshot to delete")),
+        // Traffic signals
+        Endpoint::new("/traffic-signals/get", "Describe a traffic signal")
+            .param_pair(id("An IntersectionID"))
+            .response(opaque_body("A map_model::ControlTrafficSignal")),
+        Endpoint::new("/traffic-signals/set", "Change a traffic signal")
+            .body(opaque_body("A map_model::ControlTrafficSignal")),
+        Endpoint::new(
+            "/traffic-signals/get-delays",
+            "Delays experienced at a traffic signal between two times",
+        )
+        .param_pair(id("An IntersectionID"))
+        .param_pair(time("t1"))
+        .param_pair(time("t2"))
+        .response(json_body::<Delays>(gen)),
+        Endpoint::new(
+            "/traffic-signals/get-cumulative-thruput",
+            "How many agents have done each movement through a traffic signal",
+        )
+        .param_pair(id("An IntersectionID"))
+        .response(json_body::<Throughput>(gen)),
+        Endpoint::new(
+            "/traffic-signals/get-all-current-state",
+            "The current state of every traffic signal, keyed by IntersectionID",
+        )
+        .response(json_body::<BTreeMap<String, TrafficSignalState>>(gen)),
+        Endpoint::new(
+            "/traffic-signals/set-controller",
+            "Change how a traffic signal decides its next stage, until the simulation is reset",
+        )
+        .param_pair(id("An IntersectionID"))
+        .param("controller", "default, external, max-pressure, or actuated"),
+        Endpoint::new(
+            "/traffic-signals/get-pending-decisions",
+            "Externally controlled signals waiting for a decision. When /sim/goto-time returns \
+             early, check here.",
+        )
+        .response(json_body::<Vec<PendingStageDecision>>(gen)),
+        Endpoint::new(
+            "/traffic-signals/choose-stage",
+            "Tell an externally controlled signal what stage to run next, and for how long",
+        )
+        .param_pair(id("An IntersectionID"))
+        .param("stage", "The stage index, zero based")
+        .param("duration", "How long to run the stage, in seconds"),
+        // Querying data
+        Endpoint::new(
+            "/data/get-finished-trips",
+            "Every trip finished or cancelled so far",
+        )
+        .response(json_body::<Vec<FinishedTrip>>(gen)),
+        Endpoint::new(
+            "/data/get-agent-positions",
+            "Where every agent is right now",
+        )
+        .response(json_body::<AgentPositions>(gen)),
+        Endpoint::new(
+            "/data/get-road-thruput",
+            "How many agents crossed each road per hour",
+        )
+        .response(json_body::<RoadThroughput>(gen)),
+        Endpoint::new(
+            "/data/get-blocked-by-graph",
+            "Which agents are stuck, and what's blocking them",
+        )
+        .response(json_body::<BlockedByGraph>(gen)),
+        Endpoint::new(
+            "/data/trip-time-lower-bound",
+            "The fastest a trip could possibly be, in seconds",
+        )
+        .param_pair(id("A TripID")),
+        Endpoint::new(
+            "/data/all-trip-time-lower-bounds",
+            "The fastest each trip could possibly be, keyed by TripID",
+        )
+        .response(json_body::<BTreeMap<String, Duration>>(gen)),
+        // Detectors
+        Endpoint::new(
+            "/detectors/add",
+            "Start counting vehicles passing some point. Returns the detector's ID.",
+        )
+        .body(json_body::<Detector>(gen))
+        .response(json_body::<usize>(gen)),
+        Endpoint::new(
+            "/detectors/get-readings",
+            "Everything one detector measured so far",
+        )
+        .param_pair(id("A detector ID"))
+        .response(json_body::<Vec<DetectorReading>>(gen)),
+        Endpoint::new(
+            "/detectors/export-csv",
+            "Everything every detector measured so far, as CSV",
+        )
+        .response(Body {
+            content_type: "text/csv",
+            schema: json!({ "type": "string" }),
+        }),
+        // Controlling the map
+        Endpoint::new("/map/get-edits", "The current map edits")
+            .response(opaque_body("A map_model::PermanentMapEdits")),
+        Endpoint::new(
+            "/map/get-edit-road-command",
+            "A command to change a road. Modify the new part, then pass it to \
+             /map/apply-edit-commands.",
+        )
+        .param_pair(id("A RoadID"))
+        .response(opaque_body("A map_model::PermanentEditCmd")),
+        Endpoint::new(
+            "/map/set-edits",
+            "Replace all map edits, without resetting the simulation",
+        )
+        .body(opaque_body("A map_model::PermanentMapEdits"))
+        .response(json_body::<AffectedByEdits>(gen)),
+        Endpoint::new(
+            "/map/apply-edit-commands",
+            "Add to the current map edits, without resetting the simulation",
+        )
+        .body(opaque_body("A list of map_model::PermanentEditCmd"))
+        .response(json_body::<AffectedByEdits>(gen)),
+        Endpoint::new(
+            "/map/get-intersection-geometry",
+            "GeoJSON of an intersection and its roads, in meters relative to its center",
+        )
+        .param_pair(id("An IntersectionID"))
+        .response(opaque_body("GeoJSON")),
+        Endpoint::new(
+            "/map/get-all-geometry",
+            "GeoJSON of every intersection and road",
+        )
+        .response(opaque_body("GeoJSON")),
+    ]
+}
+
+impl Endpoint {
+    fn new(path: &'static str, summary: &'static str) -> Endpoint {
+        Endpoint {
+            path,
+            summary,
+            params: Vec::new(),
+            body: None,
+            response: Body {
+                content_type: "text/plain",
+                schema: json!({ "type": "string" }),
+            },
+        }
+    }
+
+    fn param(mut self, name: &'static str, description: &'static str) -> Endpoint {
+        self.params.push((name, description));
+        self
+    }
+
+    fn param_pair(self, (name, description): (&'static str, &'static str)) -> Endpoint {
+        self.param(name, description)
+    }
+
+    fn body(mut self, body: Body) -> Endpoint {
+        self.body = Some(body);
+        self
+    }
+
+    fn response(mut self, response: Body) -> Endpoint {
+        self.response = response;
+        self
+    }
+
+    fn to_json(self) -> Value {
+        let mut params: Vec<Value> = self
+            .params
+            .into_iter()
+            .map(|(name, description)| {
+                json!({
+                    "name": name,
+                    "in": "query",
+                    "description": description,
+                    "required": !description.starts_with("Optional"),
+                    "schema": { "type": "string" },
+                })
+            })
+            .collect();
+        if !self.path.starts_with("/session/") {
+            params.push(json!({
+                "name": "session",
+                "in": "query",
+                "description": "Optional. Which session to use. If missing, use the default one.",
+                "required": false,
+                "schema": { "type": "integer" },
+            }));
+        }
+
+        let mut operation = json!({
+            "summary": self.summary,
+            "parameters": params,
+            "responses": {
+                "200": {
+                    "description": "Success",
+                    "content": { self.response.content_type: { "schema": self.response.schema } },
+                },
+                "400": {
+                    "description": "Something went wrong",
+                    "content": { "text/plain": { "schema": { "type": "string" } } },
+                },
+            },
+        });
+        // The server doesn't care about the method, but clients sending a body should use POST.
+        let method = if let Some(body) = self.body {
+            operation["requestBody"] = json!({
+                "content": { body.content_type: { "schema": body.schema } },
+            });
+            "post"
+        } else {
+            "get"
+        };
+        json!({ method: operation })
+    }
+}
+
+fn schema_for<T: JsonSchema>(gen: &mut SchemaGenerator) -> Value {
+    serde_json::to_value(gen.subschema_for::<T>()).unwrap()
+}
+
+fn json_body<T: JsonSchema>(gen: &mut SchemaGenerator) -> Body {
+    Body {
+        content_type: "application/json",
+        schema: schema_for::<T>(gen),
+    }
+}
+
+fn opaque_body(description: &str) -> Body {
+    Body {
+        content_type: "application/json",
+        schema: serde_json::to_value(opaque(description)).unwrap(),
+    }
+}
+
+fn binary_body() -> Body {
+    Body {
+        content_type: "application/octet-stream",
+        schema: json!({ "type": "string", "format": "binary" }),
+    }
+}
@@ -0,0 +1,305 @@
+//! The headless server can run many independent simulations at once. Each one lives in a
+//! `Session`, identified by the `session` GET parameter. Requests without that parameter use the
+//! default session, configured from the command line.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
+
+use anyhow::Result;
+
+use abstio::MapName;
+use abstutil::Timer;
+use geom::Duration;
+use headless_client::{CreateSession, SessionID, SessionInfo, SnapshotID};
+use map_model::{Map, PermanentMapEdits};
+use sim::{Sim, SimOptions};
+
+use crate::{events, LoadSim};
+
+/// Used when a request doesn't specify a session. It never expires and can't be deleted.
+pub const DEFAULT_SESSION: SessionID = 0;
+
+lazy_static::lazy_static! {
+    static ref SESSIONS: Mutex<Sessions> = Mutex::new(Sessions {
+        sessions: BTreeMap::new(),
+        next_id: DEFAULT_SESSION,
+        idle_timeout: None,
+        default_rng_seed: sim::SimFlags::RNG_SEED,
+        default_opts: SimOptions::default(),
+    });
+    /// Unedited maps are shared between all sessions using them. Only weak references are kept
+    /// here, so a map is freed once no session uses it anymore.
+    static ref MAPS: Mutex<BTreeMap<MapName, Weak<Map>>> = Mutex::new(BTreeMap::new());
+}
+
+pub struct Session {
+    pub id: SessionID,
+    pub sim: Sim,
+    /// Possibly shared with other sessions. Use `map_mut` before modifying it.
+    pub map: Arc<Map>,
+    pub load: LoadSim,
+    /// Saved states of this session's simulation, which can be restored or forked later.
+    pub snapshots: BTreeMap<SnapshotID, Snapshot>,
+    next_snapshot: SnapshotID,
+    last_used: Instant,
+}
+
+/// The map is kept alongside the simulation, in case the map is edited after the snapshot.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub sim: Sim,
+    pub map: Arc<Map>,
+}
+
+struct Sessions {
+    sessions: BTreeMap<SessionID, Arc<Mutex<Session>>>,
+    next_id: SessionID,
+    idle_timeout: Option<Duration>,
+    // Sessions not specifying these use the command line flags
+    default_rng_seed: u64,
+    default_opts: SimOptions,
+}
+
+/// Sets up the default session and the policy for expiring idle sessions.
+pub fn initialize(load: LoadSim, idle_timeout: Option<Duration>, timer: &mut Timer) -> Result<()> {
+    let mut sessions = SESSIONS.lock().unwrap();
+    sessions.idle_timeout = idle_timeout;
+    sessions.default_rng_seed = load.rng_seed;
+    sessions.default_opts = load.opts.clone();
+    let id = sessions.next_id;
+    sessions.next_id += 1;
+    let session = Session::new(id, load, timer)?;
+    sessions.sessions.insert(id, Arc::new(Mutex::new(session)));
+    Ok(())
+}
+
+/// Finds a session, marking it as recently used.
+pub fn get(id: SessionID) -> Result<Arc<Mutex<Session>>> {
+    expire_idle_sessions();
+    let session = SESSIONS
+        .lock()
+        .unwrap()
+        .sessions
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| anyhow!("no session {}", id))?;
+    session.lock().unwrap().last_used = Instant::now();
+    Ok(session)
+}
+
+/// Handles everything under /session/
+pub fn handle_command(path: &str, params: &HashMap<String, String>, body: &[u8]) -> Result<String> {
+    expire_idle_sessions();
+
+    match path {
+        "/session/create" => {
+            let args: CreateSession = abstutil::from_json(body)?;
+            let (id, load) = {
+                let mut sessions = SESSIONS.lock().unwrap();
+                let id = sessions.next_id;
+                sessions.next_id += 1;
+                (
+                    id,
+                    LoadSim {
+                        scenario: args.scenario,
+                        modifiers: args.modifiers,
+                        edits: args.edits,
+                        rng_seed: args.rng_seed.unwrap_or(sessions.default_rng_seed),
+                        opts: sessions.default_opts.clone(),
+                    },
+                )
+            };
+            // Don't block other sessions while loading
+            let session = Session::new(id, load, &mut Timer::new("create session"))?;
+            SESSIONS
+                .lock()
+                .unwrap()
+                .sessions
+                .insert(id, Arc::new(Mutex::new(session)));
+            Ok(abstutil::to_json(&id))
+        }
+        "/session/list" => {
+            let sessions: Vec<Arc<Mutex<Session>>> = SESSIONS
+                .lock()
+                .unwrap()
+                .sessions
+                .values()
+                .cloned()
+                .collect();
+            let mut list = Vec::new();
+            for session in sessions {
+                let session = session.lock().unwrap();
+                list.push(SessionInfo {
+                    id: session.id,
+                    scenario: session.load.scenario.clone(),
+                    time: session.sim.time(),
+                    idle: Duration::realtime_elapsed(session.last_used),
+                });
+            }
+            Ok(abstutil::to_json(&list))
+        }
+        "/session/delete" => {
+            let id = params
+                .get("session")
+                .ok_or_else(|| anyhow!("missing GET parameter session"))?
+                .parse::<SessionID>()?;
+            if id == DEFAULT_SESSION {
+                bail!("the default session can't be deleted");
+            }
+            if SESSIONS.lock().unwrap().sessions.remove(&id).is_none() {
+                bail!("no session {}", id);
+            }
+            events::unsubscribe_all(id);
+            Ok(format!("session {} deleted", id))
+        }
+        _ => Err(anyhow!("Unknown command")),
+    }
+}
+
+impl Session {
+    fn new(id: SessionID, load: LoadSim, timer: &mut Timer) -> Result<Session> {
+        let (map, sim) = load.setup(timer)?;
+        Ok(Session {
+            id,
+            sim,
+            map,
+            load,
+            snapshots: BTreeMap::new(),
+            next_snapshot: 0,
+            last_used: Instant::now(),
+        })
+    }
+
+    /// Remembers the current state of the simulation, returning a handle to it.
+    pub fn snapshot(&mut self) -> SnapshotID {
+        let mut sim = self.sim.clone();
+        // Anything buffered belongs to the present, not the snapshot
+        sim.drain_captured_events();
+        let id = self.next_snapshot;
+        self.next_snapshot += 1;
+        self.snapshots.insert(
+            id,
+            Snapshot {
+                sim,
+                map: self.map.clone(),
+            },
+        );
+        id
+    }
+
+    /// Rolls back to one of the snapshots. If none is specified, instead restores from the output
+    /// of `Sim::save_to_bytes`, which must've been produced using the same map.
+    pub fn restore(&mut self, snapshot: Option<SnapshotID>, bytes: &[u8]) -> Result<()> {
+        if let Some(id) = snapshot {
+            let snapshot = self
+                .snapshots
+                .get(&id)
+                .ok_or_else(|| anyhow!("no snapshot {}", id))?;
+            self.sim = snapshot.sim.clone();
+            self.map = snapshot.map.clone();
+        } else {
+            self.sim = Sim::load_savestate_from_bytes(bytes, &self.map)?;
+            self.sim.capture_events();
+        }
+        Ok(())
+    }
+
+    /// Creates a new session starting from the current state, or from one of the snapshots. The
+    /// new session shares the map, until either one edits it.
+    pub fn fork(&self, snapshot: Option<SnapshotID>) -> Result<SessionID> {
+        let Snapshot { sim, map } = match snapshot {
+            Some(id) => self
+                .snapshots
+                .get(&id)
+                .cloned()
+                .ok_or_else(|| anyhow!("no snapshot {}", id))?,
+            None => Snapshot {
+                sim: self.sim.clone(),
+                map: self.map.clone(),
+            },
+        };
+
+        let mut sessions = SESSIONS.lock().unwrap();
+        let id = sessions.next_id;
+        sessions.next_id += 1;
+        let mut session = Session {
+            id,
+            sim,
+            map,
+            load: self.load.clone(),
+            snapshots: BTreeMap::new(),
+            next_snapshot: 0,
+            last_used: Instant::now(),
+        };
+        // Events that happened before the fork were already sent to this session's subscribers
+        session.sim.drain_captured_events();
+        sessions.sessions.insert(id, Arc::new(Mutex::new(session)));
+        Ok(id)
+    }
+}
+
+/// Loads a map, sharing it with other sessions if there are no edits. Fails if the edits don't
+/// match the map.
+pub fn load_map(
+    name: &MapName,
+    edits: Option<PermanentMapEdits>,
+    timer: &mut Timer,
+) -> Result<Arc<Map>> {
+    if let Some(perma) = edits {
+        let mut map = Map::load_synchronously(name.path(), timer);
+        let edits = perma.into_edits(&map)?;
+        map.must_apply_edits(edits);
+        map.recalculate_pathfinding_after_edits(timer);
+        return Ok(Arc::new(map));
+    }
+
+    let mut maps = MAPS.lock().unwrap();
+    if let Some(map) = maps.get(name).and_then(|map| map.upgrade()) {
+        return Ok(map);
+    }
+    let map = Arc::new(Map::load_synchronously(name.path(), timer));
+    maps.insert(name.clone(), Arc::downgrade(&map));
+    // Clean up maps that aren't used anymore
+    maps.retain(|_, map| map.strong_count() > 0);
+    Ok(map)
+}
+
+/// Returns a map that only this session uses and can modify. If the map is shared, this copies it
+/// first, preserving any edits.
+pub fn map_mut(map: &mut Arc<Map>) -> &mut Map {
+    Arc::make_mut(map)
+}
+
+fn expire_idle_sessions() {
+    let mut sessions = SESSIONS.lock().unwrap();
+    let timeout = if let Some(dt) = sessions.idle_timeout {
+        dt
+    } else {
+        return;
+    };
+    let mut expired = Vec::new();
+    sessions.sessions.retain(|id, session| {
+        if *id == DEFAULT_SESSION {
+            return true;
+        }
+        // If somebody's using it right now, it's not idle
+        let keep = match session.try_lock() {
+            Ok(session) => Duration::realtime_elapsed(session.last_used) < timeout,
+            Err(_) => true,
+        };
+        if !keep {
+            info!(
+                "Session {} has been idle for over {}, deleting it",
+                id, timeout
+            );
+            expired.push(*id);
+        }
+        keep
+    });
+    drop(sessions);
+
+    for id in expired {
+        events::unsubscribe_all(id);
+    }
+}
@@ -0,0 +1,124 @@
+//! Lets API clients act as the controller for some traffic signals. When one of these signals
+//! reaches the end of a stage and nobody has chosen the next one yet, the simulation halts, so
+//! /sim/goto-time returns early. The client looks at /traffic-signals/get-pending-decisions,
+//! calls /traffic-signals/choose-stage, and then continues advancing time.
+
+use anyhow::Result;
+
+use geom::Duration;
+use map_model::{IntersectionID, Map};
+use sim::{Actuated, MaxPressure, SignalController, SignalControllerInput, Sim, StageDecision};
+
+use headless_client::{MovementDemand, PendingStageDecision};
+
+#[derive(Clone, Default)]
+struct ExternalController {
+    /// Filled out when the simulation is waiting for a decision
+    pending: Option<PendingStageDecision>,
+    /// Used the next time the signal asks, even if it isn't waiting yet
+    chosen: Option<(usize, Duration)>,
+}
+
+impl SignalController for ExternalController {
+    fn next_stage(&mut self, input: &SignalControllerInput) -> StageDecision {
+        if let Some((stage, duration)) = self.chosen.take() {
+            self.pending = None;
+            return StageDecision::Stage(stage, duration);
+        }
+        self.pending = Some(PendingStageDecision {
+            id: input.id,
+            time: input.now,
+            current_stage: input.current_stage,
+            num_stages: input.signal.stages.len(),
+            movements: input
+                .movements
+                .iter()
+                .map(|(id, m)| MovementDemand {
+                    id: *id,
+                    queue_length: m.queue_length,
+                    downstream_queue_length: m.downstream_queue_length,
+                    waiting: m.waiting.clone(),
+                    detectors: m
+                        .detectors
+                        .iter()
+                        .map(|d| (d.id, d.occupied, d.last_passage))
+                        .collect(),
+                })
+                .collect(),
+        });
+        StageDecision::Defer
+    }
+
+    fn clone_box(&self) -> Box<dyn SignalController> {
+        Box::new(self.clone())
+    }
+}
+
+/// Changes how one traffic signal is controlled, until the simulation is reset.
+pub fn set_controller(sim: &mut Sim, map: &Map, i: IntersectionID, kind: &str) -> Result<()> {
+    let controller: Option<Box<dyn SignalController>> = match kind {
+        "default" => None,
+        "external" => Some(Box::new(ExternalController::default())),
+        "max-pressure" => Some(Box::new(MaxPressure {
+            decision_interval: Duration::seconds(10.0),
+        })),
+        // This only makes sense after adding detectors
+        "actuated" => Some(Box::new(Actuated::new(
+            Duration::seconds(10.0),
+            Duration::seconds(60.0),
+            Duration::seconds(3.0),
+        ))),
+        _ => bail!(
+            "unknown controller {}; use default, external, max-pressure, or actuated",
+            kind
+        ),
+    };
+    sim.set_signal_controller(i, controller, map)
+}
+
+pub fn pending_decisions(sim: &mut Sim, map: &Map) -> Vec<PendingStageDecision> {
+    let mut results = Vec::new();
+    for i in map.all_intersections() {
+        if let Some(controller) = get_external(sim, i.id) {
+            if let Some(ref pending) = controller.pending {
+                results.push(pending.clone());
+            }
+        }
+    }
+    results
+}
+
+/// The signal will run this stage for some duration, then ask again.
+pub fn choose_stage(
+    sim: &mut Sim,
+    map: &Map,
+    i: IntersectionID,
+    stage: usize,
+    duration: Duration,
+) -> Result<()> {
+    let num_stages = if let Some(ts) = map.maybe_get_traffic_signal(i) {
+        ts.stages.len()
+    } else {
+        bail!("{} isn't a traffic signal", i);
+    };
+    if stage >= num_stages {
+        bail!("{} only has {} stages", i, num_stages);
+    }
+    if duration <= Duration::ZERO {
+        bail!("the stage must last for some time, not {}", duration);
+    }
+    if let Some(controller) = get_external(sim, i) {
+        controller.chosen = Some((stage, duration));
+        Ok(())
+    } else {
+        bail!(
+            "{} isn't controlled externally; call /traffic-signals/set-controller first",
+            i
+        )
+    }
+}
+
+fn get_external(sim: &mut Sim, i: IntersectionID) -> Option<&mut ExternalController> {
+    sim.get_signal_controller_mut(i)?
+        .downcast_mut::<ExternalController>()
+}
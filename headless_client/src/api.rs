@@ -0,0 +1,208 @@
+//! Request and response types for the headless API. The server and client both use these, so they
+//! can't drift apart.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, LonLat, Time};
+use map_model::{IntersectionID, MovementID, PermanentMapEdits, RoadID, TurnID};
+use sim::{
+    AgentID, AgentType, DelayCause, DetectorID, Event, PersonID, ScenarioModifier, TripID,
+    TripMode, VehicleType,
+};
+
+/// Identifies an independent simulation running in the server. Most commands take an optional
+/// `session` parameter; if it's missing, the default session is used.
+pub type SessionID = usize;
+/// Identifies a saved state of one session's simulation.
+pub type SnapshotID = usize;
+
+/// The body of /sim/load
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct LoadScenario {
+    /// The path to a scenario file
+    pub scenario: String,
+    /// A list of sim::ScenarioModifier to transform the scenario before running it
+    #[serde(default)]
+    #[schemars(with = "Vec<serde_json::Value>")]
+    pub modifiers: Vec<ScenarioModifier>,
+    /// A map_model::PermanentMapEdits to apply before running the scenario
+    #[serde(default)]
+    #[schemars(with = "Option<serde_json::Value>")]
+    pub edits: Option<PermanentMapEdits>,
+}
+
+/// The body of /session/create
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct CreateSession {
+    /// The path to a scenario file
+    pub scenario: String,
+    /// A list of sim::ScenarioModifier to transform the scenario before running it
+    #[serde(default)]
+    #[schemars(with = "Vec<serde_json::Value>")]
+    pub modifiers: Vec<ScenarioModifier>,
+    /// A map_model::PermanentMapEdits to apply before running the scenario
+    #[serde(default)]
+    #[schemars(with = "Option<serde_json::Value>")]
+    pub edits: Option<PermanentMapEdits>,
+    /// If missing, use the server's --rng_seed
+    #[serde(default)]
+    pub rng_seed: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SessionInfo {
+    pub id: SessionID,
+    pub scenario: String,
+    pub time: Time,
+    /// How long since this session was last used, in real time
+    pub idle: Duration,
+}
+
+/// One line of the /sim/stream-events response
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct StreamedEvent {
+    pub time: Time,
+    /// A sim::Event
+    #[schemars(with = "serde_json::Value")]
+    pub event: Event,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct FinishedTrip {
+    pub id: TripID,
+    pub person: PersonID,
+    /// None if the trip was cancelled
+    pub duration: Option<Duration>,
+    pub distance_crossed: Distance,
+    pub mode: TripMode,
+    pub capped: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Delays {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    #[schemars(with = "Vec<(MovementID, Vec<Duration>)>")]
+    pub per_direction: BTreeMap<MovementID, Vec<Duration>>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Throughput {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    #[schemars(with = "Vec<(MovementID, usize)>")]
+    pub per_direction: BTreeMap<MovementID, usize>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AgentPositions {
+    pub agents: Vec<AgentPosition>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AgentPosition {
+    /// The agent's ID
+    pub id: AgentID,
+    /// None for buses
+    pub trip: Option<TripID>,
+    /// None for buses
+    pub person: Option<PersonID>,
+    /// None for pedestrians
+    pub vehicle_type: Option<VehicleType>,
+    /// The agent's current position. For pedestrians, this is their center. For vehicles, this
+    /// represents the front of the vehicle.
+    pub pos: LonLat,
+    /// The distance crossed so far by the agent, in meters. There are some caveats to this value:
+    /// - The distance along driveways between buildings/parking lots and the road doesn't count
+    ///   here.
+    /// - The distance only represents the current leg of the trip. If somebody walks to a car, the
+    ///   distance will reset when they begin driving, and also vehicle_type will change.
+    /// - No meaning for bus passengers currently.
+    /// - For buses and trains, the value will reset every time the vehicle reaches the next
+    ///   transit stop.
+    /// - The value might be slightly undercounted or overcounted if the path crosses into or out
+    ///   of an access-restricted or capped zone.
+    /// - At the very end of a driving trip, the agent may wind up crossing slightly more or less
+    ///   than the total path length, due to where they park along that last road.
+    pub distance_crossed: Distance,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct RoadThroughput {
+    /// (road, agent type, hour since midnight, throughput for that one hour period)
+    pub counts: Vec<(RoadID, AgentType, usize, usize)>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct TrafficSignalState {
+    pub current_stage_idx: usize,
+    pub remaining_time: Duration,
+    pub accepted: BTreeSet<AgentID>,
+    /// Some agent has been waiting to start a turn since some time
+    pub waiting: Vec<(AgentID, TurnID, Time)>,
+}
+
+/// An externally controlled traffic signal has finished a stage, and the simulation is waiting
+/// for /traffic-signals/choose-stage.
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PendingStageDecision {
+    pub id: IntersectionID,
+    /// When the decision was needed
+    pub time: Time,
+    /// The stage that just ended, zero based
+    pub current_stage: usize,
+    pub num_stages: usize,
+    /// Demand for every movement through the signal
+    pub movements: Vec<MovementDemand>,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MovementDemand {
+    pub id: MovementID,
+    /// How many vehicles are on the lanes leading to this movement. Lanes feeding more than one
+    /// movement count towards each of them.
+    pub queue_length: usize,
+    /// How many vehicles are on the lanes this movement leads to
+    pub downstream_queue_length: usize,
+    /// Agents waiting to start this movement, and since when
+    pub waiting: Vec<(AgentID, Time)>,
+    /// Detectors on the lanes leading to this movement: (detector, is some vehicle over it right
+    /// now, when the last vehicle passed)
+    pub detectors: Vec<(DetectorID, bool, Option<Time>)>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct BlockedByGraph {
+    /// Each entry indicates that some agent has been stuck in one place for some amount of time,
+    /// due to being blocked by another agent or because they're waiting at an intersection. Unless
+    /// the agent is a bus, then the TripID and PersonID will also be filled out.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    #[schemars(with = "Vec<(AgentID, (Duration, DelayCause, Option<TripID>, \
+                       Option<PersonID>))>")]
+    pub blocked_by: BTreeMap<AgentID, (Duration, DelayCause, Option<TripID>, Option<PersonID>)>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AffectedByEdits {
+    /// Every agent whose trip crossed something edited. These trips are cancelled.
+    pub cancelled: Vec<CancelledByEdits>,
+    pub parked_cars_displaced: usize,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct CancelledByEdits {
+    pub agent: AgentID,
+    pub trip: TripID,
+    pub person: Option<PersonID>,
+}
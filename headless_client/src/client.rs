@@ -0,0 +1,372 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader};
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use geom::{Duration, Time};
+use map_model::{
+    ControlTrafficSignal, IntersectionID, PermanentEditCmd, PermanentMapEdits, RoadID,
+};
+use sim::{Detector, DetectorID, DetectorReading, ExternalPerson, TripID};
+
+use crate::{
+    AffectedByEdits, AgentPositions, BlockedByGraph, CreateSession, Delays, FinishedTrip,
+    LoadScenario, PendingStageDecision, RoadThroughput, SessionID, SessionInfo, SnapshotID,
+    StreamedEvent, Throughput, TrafficSignalState,
+};
+
+/// Talks to a running headless server. Each method corresponds to one endpoint; see the server's
+/// documentation for details.
+pub struct Client {
+    base_url: String,
+    session: Option<SessionID>,
+    http: reqwest::blocking::Client,
+}
+
+impl Client {
+    /// Connects to a server, like "http://localhost:1234". Commands use the default session.
+    pub fn new(base_url: &str) -> Client {
+        Client {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            session: None,
+            http: reqwest::blocking::Client::builder()
+                // Advancing the simulation can take arbitrarily long
+                .timeout(None)
+                .build()
+                .unwrap(),
+        }
+    }
+
+    /// Returns a client sending all commands to a different session.
+    pub fn for_session(&self, session: SessionID) -> Client {
+        Client {
+            base_url: self.base_url.clone(),
+            session: Some(session),
+            http: self.http.clone(),
+        }
+    }
+
+    // Sessions
+
+    pub fn create_session(&self, args: &CreateSession) -> Result<SessionID> {
+        self.post_json("/session/create", &[], args)
+    }
+
+    pub fn list_sessions(&self) -> Result<Vec<SessionInfo>> {
+        self.get_json("/session/list", &[])
+    }
+
+    pub fn delete_session(&self, session: SessionID) -> Result<()> {
+        self.request("/session/delete", &[("session", session.to_string())], None)?;
+        Ok(())
+    }
+
+    // Controlling the simulation
+
+    pub fn reset(&self) -> Result<()> {
+        self.get_text("/sim/reset", &[])?;
+        Ok(())
+    }
+
+    pub fn load(&self, args: &LoadScenario) -> Result<()> {
+        self.request("/sim/load", &[], Some(abstutil::to_json(args).into_bytes()))?;
+        Ok(())
+    }
+
+    pub fn get_time(&self) -> Result<Time> {
+        Time::parse(&self.get_text("/sim/get-time", &[])?)
+    }
+
+    /// Returns the time actually reached, which may be earlier if an alert paused the simulation.
+    pub fn goto_time(&self, t: Time) -> Result<Time> {
+        let resp = self.get_text("/sim/goto-time", &[("t", t.to_string())])?;
+        Time::parse(resp.trim_start_matches("it's now "))
+    }
+
+    /// Returns a description of the person created.
+    pub fn new_person(&self, person: &ExternalPerson) -> Result<String> {
+        Ok(self
+            .request(
+                "/sim/new-person",
+                &[],
+                Some(abstutil::to_json(person).into_bytes()),
+            )?
+            .text()?)
+    }
+
+    /// Yields events as the simulation advances, until the server goes away. Other clients should
+    /// advance time meanwhile. Parameters that're empty don't filter anything.
+    pub fn stream_events(
+        &self,
+        kinds: &[&str],
+        intersections: &[IntersectionID],
+        roads: &[RoadID],
+    ) -> Result<impl Iterator<Item = Result<StreamedEvent>>> {
+        let mut params = Vec::new();
+        if !kinds.is_empty() {
+            params.push(("kinds", kinds.join(",")));
+        }
+        if !intersections.is_empty() {
+            params.push((
+                "intersections",
+                intersections
+                    .iter()
+                    .map(|i| i.0.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ));
+        }
+        if !roads.is_empty() {
+            params.push((
+                "roads",
+                roads
+                    .iter()
+                    .map(|r| r.0.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ));
+        }
+        let resp = self.request("/sim/stream-events", &params, None)?;
+        Ok(BufReader::new(resp).lines().map(|line| {
+            let line = line?;
+            abstutil::from_json(line.as_bytes())
+        }))
+    }
+
+    // Snapshots
+
+    pub fn snapshot(&self) -> Result<SnapshotID> {
+        self.get_json("/sim/snapshot", &[])
+    }
+
+    /// Serializes the entire simulation state. Pass this to `restore_from_bytes` later.
+    pub fn snapshot_bytes(&self) -> Result<Vec<u8>> {
+        Ok(self
+            .request("/sim/snapshot-bytes", &[], None)?
+            .bytes()?
+            .to_vec())
+    }
+
+    pub fn restore(&self, snapshot: SnapshotID) -> Result<()> {
+        self.get_text("/sim/restore", &[("id", snapshot.to_string())])?;
+        Ok(())
+    }
+
+    pub fn restore_from_bytes(&self, bytes: Vec<u8>) -> Result<()> {
+        self.request("/sim/restore", &[], Some(bytes))?;
+        Ok(())
+    }
+
+    /// Creates a new session from the current state, or a snapshot of this session.
+    pub fn fork(&self, snapshot: Option<SnapshotID>) -> Result<SessionID> {
+        let params: Vec<(&str, String)> = snapshot
+            .into_iter()
+            .map(|id| ("id", id.to_string()))
+            .collect();
+        self.get_json("/sim/fork", &params)
+    }
+
+    pub fn delete_snapshot(&self, snapshot: SnapshotID) -> Result<()> {
+        self.get_text("/sim/delete-snapshot", &[("id", snapshot.to_string())])?;
+        Ok(())
+    }
+
+    // Traffic signals
+
+    pub fn get_traffic_signal(&self, i: IntersectionID) -> Result<ControlTrafficSignal> {
+        self.get_json("/traffic-signals/get", &[("id", i.0.to_string())])
+    }
+
+    pub fn set_traffic_signal(&self, ts: &ControlTrafficSignal) -> Result<()> {
+        self.request(
+            "/traffic-signals/set",
+            &[],
+            Some(abstutil::to_json(ts).into_bytes()),
+        )?;
+        Ok(())
+    }
+
+    pub fn get_traffic_signal_delays(
+        &self,
+        i: IntersectionID,
+        t1: Time,
+        t2: Time,
+    ) -> Result<Delays> {
+        self.get_json(
+            "/traffic-signals/get-delays",
+            &[
+                ("id", i.0.to_string()),
+                ("t1", t1.to_string()),
+                ("t2", t2.to_string()),
+            ],
+        )
+    }
+
+    pub fn get_traffic_signal_cumulative_thruput(&self, i: IntersectionID) -> Result<Throughput> {
+        self.get_json(
+            "/traffic-signals/get-cumulative-thruput",
+            &[("id", i.0.to_string())],
+        )
+    }
+
+    pub fn get_all_traffic_signal_states(
+        &self,
+    ) -> Result<BTreeMap<IntersectionID, TrafficSignalState>> {
+        self.get_json("/traffic-signals/get-all-current-state", &[])
+    }
+
+    /// Changes how a traffic signal is controlled: "default", "external", "max-pressure", or
+    /// "actuated".
+    pub fn set_traffic_signal_controller(&self, i: IntersectionID, controller: &str) -> Result<()> {
+        self.get_text(
+            "/traffic-signals/set-controller",
+            &[
+                ("id", i.0.to_string()),
+                ("controller", controller.to_string()),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Externally controlled signals waiting for `choose_traffic_signal_stage`. When
+    /// `goto_time` returns early, check here.
+    pub fn get_pending_stage_decisions(&self) -> Result<Vec<PendingStageDecision>> {
+        self.get_json("/traffic-signals/get-pending-decisions", &[])
+    }
+
+    /// Tells an externally controlled signal what stage to run next, and for how long.
+    pub fn choose_traffic_signal_stage(
+        &self,
+        i: IntersectionID,
+        stage: usize,
+        duration: Duration,
+    ) -> Result<()> {
+        self.get_text(
+            "/traffic-signals/choose-stage",
+            &[
+                ("id", i.0.to_string()),
+                ("stage", stage.to_string()),
+                ("duration", duration.inner_seconds().to_string()),
+            ],
+        )?;
+        Ok(())
+    }
+
+    // Querying data
+
+    pub fn get_finished_trips(&self) -> Result<Vec<FinishedTrip>> {
+        self.get_json("/data/get-finished-trips", &[])
+    }
+
+    pub fn get_agent_positions(&self) -> Result<AgentPositions> {
+        self.get_json("/data/get-agent-positions", &[])
+    }
+
+    pub fn get_road_thruput(&self) -> Result<RoadThroughput> {
+        self.get_json("/data/get-road-thruput", &[])
+    }
+
+    pub fn get_blocked_by_graph(&self) -> Result<BlockedByGraph> {
+        self.get_json("/data/get-blocked-by-graph", &[])
+    }
+
+    pub fn trip_time_lower_bound(&self, trip: TripID) -> Result<Duration> {
+        let secs = self
+            .get_text("/data/trip-time-lower-bound", &[("id", trip.0.to_string())])?
+            .parse::<f64>()?;
+        Ok(Duration::seconds(secs))
+    }
+
+    pub fn all_trip_time_lower_bounds(&self) -> Result<BTreeMap<TripID, Duration>> {
+        self.get_json("/data/all-trip-time-lower-bounds", &[])
+    }
+
+    // Detectors
+
+    pub fn add_detector(&self, detector: &Detector) -> Result<DetectorID> {
+        self.post_json("/detectors/add", &[], detector)
+    }
+
+    pub fn get_detector_readings(&self, id: DetectorID) -> Result<Vec<DetectorReading>> {
+        self.get_json("/detectors/get-readings", &[("id", id.0.to_string())])
+    }
+
+    /// Every reading from every detector so far, as CSV
+    pub fn export_detector_readings(&self) -> Result<String> {
+        self.get_text("/detectors/export-csv", &[])
+    }
+
+    // Controlling the map
+
+    pub fn get_edits(&self) -> Result<PermanentMapEdits> {
+        self.get_json("/map/get-edits", &[])
+    }
+
+    /// Returns a command that doesn't change anything yet. Modify the `new` part of it, then pass
+    /// it to `apply_edit_commands`.
+    pub fn get_edit_road_command(&self, r: RoadID) -> Result<PermanentEditCmd> {
+        self.get_json("/map/get-edit-road-command", &[("id", r.0.to_string())])
+    }
+
+    pub fn set_edits(&self, edits: &PermanentMapEdits) -> Result<AffectedByEdits> {
+        self.post_json("/map/set-edits", &[], edits)
+    }
+
+    pub fn apply_edit_commands(&self, cmds: &[PermanentEditCmd]) -> Result<AffectedByEdits> {
+        self.post_json("/map/apply-edit-commands", &[], &cmds)
+    }
+
+    pub fn get_intersection_geometry(&self, i: IntersectionID) -> Result<geojson::GeoJson> {
+        self.get_json("/map/get-intersection-geometry", &[("id", i.0.to_string())])
+    }
+
+    pub fn get_all_geometry(&self) -> Result<geojson::GeoJson> {
+        self.get_json("/map/get-all-geometry", &[])
+    }
+}
+
+// Plumbing
+impl Client {
+    fn request(
+        &self,
+        path: &str,
+        params: &[(&str, String)],
+        body: Option<Vec<u8>>,
+    ) -> Result<reqwest::blocking::Response> {
+        let mut query: Vec<(&str, String)> = params.to_vec();
+        if let Some(session) = self.session {
+            query.push(("session", session.to_string()));
+        }
+        let url = format!("{}{}", self.base_url, path);
+        let req = if let Some(body) = body {
+            self.http.post(&url).body(body)
+        } else {
+            self.http.get(&url)
+        };
+        let resp = req.query(&query).send()?;
+        if !resp.status().is_success() {
+            bail!("{} failed: {}", path, resp.text()?);
+        }
+        Ok(resp)
+    }
+
+    fn get_text(&self, path: &str, params: &[(&str, String)]) -> Result<String> {
+        Ok(self.request(path, params, None)?.text()?)
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, path: &str, params: &[(&str, String)]) -> Result<T> {
+        abstutil::from_json(&self.request(path, params, None)?.bytes()?)
+    }
+
+    fn post_json<I: Serialize, O: DeserializeOwned>(
+        &self,
+        path: &str,
+        params: &[(&str, String)],
+        body: &I,
+    ) -> Result<O> {
+        let body = abstutil::to_json(body).into_bytes();
+        abstutil::from_json(&self.request(path, params, Some(body))?.bytes()?)
+    }
+}
@@ -0,0 +1,23 @@
+//! Types and a typed client for the API served by the `headless` binary. The server publishes an
+//! OpenAPI description at /openapi.json, generated from the types here.
+//!
+//! ```no_run
+//! let client = headless_client::Client::new("http://localhost:1234");
+//! client.goto_time(geom::Time::START_OF_DAY + geom::Duration::hours(1))?;
+//! for agent in client.get_agent_positions()?.agents {
+//!     println!("{} is at {}", agent.id, agent.pos);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+#[macro_use]
+extern crate anyhow;
+
+pub use self::api::*;
+#[cfg(feature = "client")]
+pub use self::client::Client;
+
+mod api;
+#[cfg(feature = "client")]
+mod client;
+pub mod schema;
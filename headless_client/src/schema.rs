@@ -0,0 +1,16 @@
+//! Helpers for describing the API. Types from geom, map_model, and sim derive their own schemas
+//! through the `json_schema` feature, so they always match how they're serialized.
+
+use schemars::schema::{Metadata, Schema, SchemaObject};
+
+/// A schema accepting anything, for complex types that're easier to document by pointing to the
+/// Rust definition.
+pub fn opaque(description: &str) -> Schema {
+    Schema::Object(SchemaObject {
+        metadata: Some(Box::new(Metadata {
+            description: Some(description.to_string()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    })
+}
@@ -0,0 +1,282 @@
+//! Dynamic traffic assignment. Normally every driver picks their route once, assuming roads can be
+//! crossed at the speed limit. This tool repeatedly simulates a scenario, letting some drivers
+//! switch to routes that would've been faster given the congestion they just experienced, until
+//! nobody can do much better by changing routes.
+//!
+//! Each iteration:
+//! 1) Simulate the day. Every driving trip follows the route it's been assigned. Trips without one
+//!    yet use the best route at the time they start.
+//! 2) Measure how long cars took to cross each road, including waiting to turn at the end, and use
+//!    that as the routing cost for cars.
+//! 3) Calculate the relative gap: the total cost of the routes drivers took, compared to the best
+//!    routes using the new costs. 0 means equilibrium.
+//! 4) Switch a shrinking fraction of drivers (the method of successive averages) to their new best
+//!    route. Everybody else keeps the route they just took.
+//!
+//! `--input`: The binary scenario to start from. It isn't modified.
+//! `--max_iterations`: Defaults to 10.
+//! `--target_gap`: Stop once the relative gap is below this. Defaults to 0.01.
+//! `--stats`: Where to write convergence statistics for each iteration, as CSV. Defaults to
+//!            `assignment_stats.csv`.
+//!
+//! Saves a new scenario with `_assigned` appended to the name. Every driving trip in it stores the
+//! route it took in the last iteration, so simulating the new scenario reproduces that.
+
+#[macro_use]
+extern crate log;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+use abstutil::{prettyprint_usize, CmdArgs, Timer};
+use geom::{Duration, Time};
+use map_model::{DirectedRoadID, Map, PathRequest, PathStepV2, Traversable};
+use sim::{
+    AgentID, AgentType, AlertHandler, Event, Scenario, Sim, SimOptions, TripID, TripPhaseType,
+};
+
+fn main() {
+    let mut args = CmdArgs::new();
+    let input = args.required("--input");
+    let max_iterations: usize = args
+        .optional_parse("--max_iterations", |s| s.parse())
+        .unwrap_or(10);
+    let target_gap: f64 = args
+        .optional_parse("--target_gap", |s| s.parse())
+        .unwrap_or(0.01);
+    let stats_path = args
+        .optional("--stats")
+        .unwrap_or_else(|| "assignment_stats.csv".to_string());
+    let rng_seed: u64 = args
+        .optional_parse("--rng_seed", |s| s.parse())
+        .unwrap_or(42);
+    args.done();
+
+    let mut rng = XorShiftRng::seed_from_u64(rng_seed);
+    let mut timer = Timer::new("assign traffic");
+
+    let mut scenario: Scenario = abstio::must_read_object(input, &mut timer);
+    let mut map = Map::load_synchronously(scenario.map_name.path(), &mut timer);
+
+    let mut stats = File::create(&stats_path).unwrap();
+    writeln!(
+        stats,
+        "iteration,relative_gap,driving_trips,trips_switched,total_driving_hours"
+    )
+    .unwrap();
+
+    for iteration in 1..=max_iterations {
+        let drives = simulate(&scenario, &mut map, rng_seed, &mut timer);
+
+        // Evaluate the routes just taken using the costs just measured
+        let mut total_cost = Duration::ZERO;
+        let mut total_best_cost = Duration::ZERO;
+        let mut total_driving = Duration::ZERO;
+        let mut evaluated = Vec::new();
+        for drive in drives.values() {
+            let roads = match drive.route(&map) {
+                Some(roads) => roads,
+                // Didn't finish driving, or went somewhere unexpected
+                None => continue,
+            };
+            let cost = match map.pathfind_along_roads(drive.req.clone(), roads.clone()) {
+                Ok(path) => path.get_cost(),
+                Err(_) => continue,
+            };
+            let best = match map.pathfind_v2(drive.req.clone()) {
+                Ok(path) => path,
+                Err(_) => continue,
+            };
+            total_cost += cost;
+            total_best_cost += std::cmp::min(cost, best.get_cost());
+            total_driving += drive.duration.unwrap();
+            evaluated.push((drive.spec, roads, cost, best));
+        }
+        let gap = if total_cost > Duration::ZERO {
+            (total_cost - total_best_cost) / total_cost
+        } else {
+            0.0
+        };
+        let done = gap < target_gap || iteration == max_iterations;
+
+        let step = 1.0 / (iteration + 1) as f64;
+        let mut switched = 0;
+        for ((p, t), roads, cost, best) in evaluated.iter() {
+            let spec = &mut scenario.people[*p].trips[*t];
+            if !done && best.get_cost() < *cost && rng.gen_bool(step) {
+                spec.route = Some(
+                    best.get_steps()
+                        .iter()
+                        .filter_map(|x| match x {
+                            PathStepV2::Along(dr) => Some(*dr),
+                            _ => None,
+                        })
+                        .collect(),
+                );
+                switched += 1;
+            } else {
+                spec.route = Some(roads.clone());
+            }
+        }
+
+        info!(
+            "Iteration {}: relative gap is {:.4}. Switched {} of {} drivers to a new route",
+            iteration,
+            gap,
+            prettyprint_usize(switched),
+            prettyprint_usize(evaluated.len())
+        );
+        writeln!(
+            stats,
+            "{},{},{},{},{}",
+            iteration,
+            gap,
+            evaluated.len(),
+            switched,
+            total_driving.inner_seconds() / 3600.0
+        )
+        .unwrap();
+
+        if done {
+            break;
+        }
+    }
+
+    scenario.scenario_name = format!("{}_assigned", scenario.scenario_name);
+    scenario.save();
+    info!("Wrote {}", stats_path);
+}
+
+/// One trip's driving phase
+struct Drive {
+    /// Indices into the scenario's people and that person's trips
+    spec: (usize, usize),
+    req: PathRequest,
+    roads: Vec<DirectedRoadID>,
+    started: Time,
+    duration: Option<Duration>,
+}
+
+impl Drive {
+    /// The roads used to reach the requested destination. Cars searching for parking may keep
+    /// driving past it, so trim that off.
+    fn route(&self, map: &Map) -> Option<Vec<DirectedRoadID>> {
+        self.duration?;
+        let end = map.get_l(self.req.end.lane()).get_directed_parent();
+        let idx = self.roads.iter().position(|dr| *dr == end)?;
+        Some(self.roads[0..=idx].to_vec())
+    }
+}
+
+/// Runs the scenario for the full day, then changes the map's routing costs to match the travel
+/// times observed. Returns every driving trip.
+fn simulate(
+    scenario: &Scenario,
+    map: &mut Map,
+    rng_seed: u64,
+    timer: &mut Timer,
+) -> BTreeMap<TripID, Drive> {
+    let mut opts = SimOptions::new("assignment");
+    opts.alerts = AlertHandler::Silence;
+    let mut sim = Sim::new(map, opts);
+    let mut rng = XorShiftRng::seed_from_u64(rng_seed);
+    scenario.instantiate(&mut sim, map, &mut rng, timer);
+    sim.capture_events();
+
+    // Each person in the simulation comes from the scenario, in order. Match trips by their ID,
+    // since they may not be numbered in scenario order.
+    let mut trip_specs: BTreeMap<TripID, (usize, usize)> = BTreeMap::new();
+    for (p, person) in sim.get_all_people().iter().enumerate() {
+        for (t, trip) in person.trips.iter().enumerate() {
+            trip_specs.insert(*trip, (p, t));
+        }
+    }
+    assert_eq!(sim.get_all_people().len(), scenario.people.len());
+
+    let mut drives: BTreeMap<TripID, Drive> = BTreeMap::new();
+    // Per road, the total time spent crossing it and the number of cars doing so
+    let mut road_times: BTreeMap<DirectedRoadID, (Duration, usize)> = BTreeMap::new();
+    // Per car, the road it's on and when it entered
+    let mut entered: BTreeMap<AgentID, (DirectedRoadID, Time)> = BTreeMap::new();
+
+    // Leave time for trips starting late in the day to finish
+    let end = sim.get_end_of_day() + Duration::hours(3);
+    while sim.time() < end {
+        let before = sim.time();
+        // Step an hour at a time, so the captured events don't pile up
+        sim.timed_step(map, Duration::hours(1), &mut None, timer);
+        for (time, ev) in sim.drain_captured_events() {
+            match ev {
+                Event::TripPhaseStarting(trip, _, maybe_req, phase) => {
+                    if let Some(drive) = drives.get_mut(&trip) {
+                        if drive.duration.is_none() {
+                            drive.duration = Some(time - drive.started);
+                        }
+                    }
+                    if let (TripPhaseType::Driving, Some(req), Some(spec)) =
+                        (phase, maybe_req, trip_specs.get(&trip))
+                    {
+                        let start = map.get_l(req.start.lane()).get_directed_parent();
+                        drives.insert(
+                            trip,
+                            Drive {
+                                spec: *spec,
+                                req,
+                                roads: vec![start],
+                                started: time,
+                                duration: None,
+                            },
+                        );
+                    }
+                }
+                Event::TripFinished { trip, .. } | Event::TripCancelled(trip, _) => {
+                    if let Some(drive) = drives.get_mut(&trip) {
+                        if drive.duration.is_none() {
+                            drive.duration = Some(time - drive.started);
+                        }
+                    }
+                }
+                Event::AgentEntersTraversable(agent, Some(trip), on, _)
+                    if agent.to_type() == AgentType::Car =>
+                {
+                    match on {
+                        Traversable::Lane(l) => {
+                            let dr = map.get_l(l).get_directed_parent();
+                            entered.insert(agent, (dr, time));
+                            if let Some(drive) = drives.get_mut(&trip) {
+                                if drive.roads.last() != Some(&dr) {
+                                    drive.roads.push(dr);
+                                }
+                            }
+                        }
+                        Traversable::Turn(_) => {
+                            if let Some((dr, t)) = entered.remove(&agent) {
+                                let entry = road_times.entry(dr).or_insert((Duration::ZERO, 0));
+                                entry.0 += time - t;
+                                entry.1 += 1;
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if sim.time() == before {
+            warn!("The simulation stopped advancing at {}", sim.time());
+            break;
+        }
+    }
+
+    let mut params = map.routing_params().clone();
+    params.road_travel_times = road_times
+        .into_iter()
+        .map(|(dr, (total, count))| (dr, total / count as f64))
+        .collect();
+    map.hack_override_routing_params(params, timer);
+
+    drives
+}
@@ -0,0 +1,652 @@
+//! A tool to adjust a scenario so simulated traffic matches observed counts, similar to
+//! origin-destination matrix estimation.
+//!
+//! `--input`: The binary scenario to start from. It isn't modified.
+//! `--counts`: A CSV file with observed counts. Each row has these columns:
+//!   - `site`: Any name, just used in the report
+//!   - `osm_way_id` or `osm_node_id`: Exactly one must be filled out. A way is compared against
+//!     the average throughput of all roads belonging to it, counting both directions.
+//!   - `start_time` and `end_time`: Optional, like `07:00:00`. Simulated counts are only grouped
+//!     by hour, so these get rounded to the hour. If missing, the whole day is used.
+//!   - `count`: How many vehicles passed during that time
+//! `--max_iterations`: How many times to simulate and adjust the scenario. Defaults to 5.
+//! `--target`: Stop early once this fraction of sites has a GEH statistic under 5. Defaults to
+//!             0.85.
+//! `--agent_types`: Which simulated agents to compare against the counts, like `Car,Bus`.
+//!                  Defaults to `Car`.
+//! `--report`: Where to write the goodness-of-fit for each site, as CSV. Defaults to
+//!             `calibration_report.csv`.
+//!
+//! Each iteration simulates the scenario for a full day, then compares counts. Every person with
+//! a trip through a count site is randomly dropped or duplicated, based on how far off the sites
+//! they pass through are. Sites that no simulated trip passes through at all get new driving trips
+//! starting just upstream. The result is saved as a new scenario, with `_calibrated` appended to
+//! the name.
+
+#[macro_use]
+extern crate anyhow;
+#[macro_use]
+extern crate log;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Write;
+
+use anyhow::Result;
+use rand::prelude::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use serde::Deserialize;
+
+use abstutil::{prettyprint_usize, CmdArgs, Timer};
+use geom::{Duration, Time};
+use map_model::{
+    osm, BuildingID, IntersectionID, LaneID, LaneType, Map, PathConstraints, Position, RoadID,
+    Traversable,
+};
+use sim::{
+    AgentType, AlertHandler, Event, IndividTrip, PersonSpec, Scenario, Sim, SimOptions,
+    TripEndpoint, TripMode, TripPurpose,
+};
+
+/// Don't change the amount of traffic through a site by more than this factor in one iteration.
+/// The simulated counts react non-linearly, so smaller steps converge more reliably.
+const MAX_STEP: f64 = 2.0;
+
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let input = args.required("--input");
+    let counts = args.required("--counts");
+    let max_iterations: usize = args
+        .optional_parse("--max_iterations", |s| s.parse())
+        .unwrap_or(5);
+    let target: f64 = args
+        .optional_parse("--target", |s| s.parse())
+        .unwrap_or(0.85);
+    let agent_types: Vec<AgentType> = args
+        .optional_parse("--agent_types", |s| {
+            s.split(',').map(parse_agent_type).collect::<Result<_>>()
+        })
+        .unwrap_or_else(|| vec![AgentType::Car]);
+    let report = args
+        .optional("--report")
+        .unwrap_or_else(|| "calibration_report.csv".to_string());
+    let rng_seed: u64 = args
+        .optional_parse("--rng_seed", |s| s.parse())
+        .unwrap_or(42);
+    args.done();
+
+    let mut rng = XorShiftRng::seed_from_u64(rng_seed);
+    let mut timer = Timer::new("calibrate scenario");
+
+    let mut scenario: Scenario = abstio::must_read_object(input, &mut timer);
+    let map = Map::load_synchronously(scenario.map_name.path(), &mut timer);
+    let sites = read_sites(&counts, &map)?;
+    if sites.is_empty() {
+        bail!("{} doesn't have any count sites matching this map", counts);
+    }
+    let buildings_per_road = index_buildings(&map);
+
+    let mut iteration = 0;
+    loop {
+        let results = simulate(&scenario, &map, &sites, &agent_types, rng_seed, &mut timer);
+        let passing = sites
+            .iter()
+            .zip(results.modeled.iter())
+            .filter(|(site, modeled)| geh(**modeled, site.observed) < 5.0)
+            .count();
+        info!(
+            "After {} iterations, {} people, and {}/{} sites have GEH < 5",
+            iteration,
+            prettyprint_usize(scenario.people.len()),
+            passing,
+            sites.len()
+        );
+
+        if passing as f64 / sites.len() as f64 >= target || iteration == max_iterations {
+            write_report(&report, &sites, &results.modeled)?;
+            info!("Wrote {}", report);
+            break;
+        }
+
+        adjust(
+            &mut scenario,
+            &sites,
+            &results,
+            &map,
+            &buildings_per_road,
+            &mut rng,
+        );
+        iteration += 1;
+    }
+
+    scenario.scenario_name = format!("{}_calibrated", scenario.scenario_name);
+    scenario.save();
+    Ok(())
+}
+
+/// One place with an observed count
+struct Site {
+    name: String,
+    location: Location,
+    /// Inclusive
+    start_hour: usize,
+    /// Exclusive
+    end_hour: usize,
+    /// Per hour
+    observed: f64,
+}
+
+enum Location {
+    Way(osm::WayID, Vec<RoadID>),
+    Node(osm::NodeID, IntersectionID),
+}
+
+#[derive(Deserialize)]
+struct Record {
+    site: String,
+    osm_way_id: Option<i64>,
+    osm_node_id: Option<i64>,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    count: f64,
+}
+
+fn read_sites(path: &str, map: &Map) -> Result<Vec<Site>> {
+    let mut roads_per_way: BTreeMap<osm::WayID, Vec<RoadID>> = BTreeMap::new();
+    for r in map.all_roads() {
+        roads_per_way
+            .entry(r.orig_id.osm_way_id)
+            .or_insert_with(Vec::new)
+            .push(r.id);
+    }
+
+    let mut sites = Vec::new();
+    for rec in csv::Reader::from_reader(File::open(path)?).deserialize() {
+        let rec: Record = rec?;
+        let location = match (rec.osm_way_id, rec.osm_node_id) {
+            (Some(id), None) => {
+                let id = osm::WayID(id);
+                if let Some(roads) = roads_per_way.get(&id) {
+                    Location::Way(id, roads.clone())
+                } else {
+                    warn!("Skipping site {}; {} isn't in this map", rec.site, id);
+                    continue;
+                }
+            }
+            (None, Some(id)) => {
+                let id = osm::NodeID(id);
+                if let Ok(i) = map.find_i_by_osm_id(id) {
+                    Location::Node(id, i)
+                } else {
+                    warn!("Skipping site {}; {} isn't in this map", rec.site, id);
+                    continue;
+                }
+            }
+            _ => bail!(
+                "Site {} needs exactly one of osm_way_id or osm_node_id",
+                rec.site
+            ),
+        };
+
+        let (start_hour, end_hour) =
+            match hour_range(rec.start_time.as_deref(), rec.end_time.as_deref())? {
+                Some(range) => range,
+                None => bail!("Site {} has an empty time range", rec.site),
+            };
+        sites.push(Site {
+            name: rec.site,
+            location,
+            start_hour,
+            end_hour,
+            observed: rec.count / (end_hour - start_hour) as f64,
+        });
+    }
+    Ok(sites)
+}
+
+/// Rounds a count's time range out to whole hours, since simulated counts are grouped by hour.
+/// The end is exclusive. None if the range is empty.
+fn hour_range(start_time: Option<&str>, end_time: Option<&str>) -> Result<Option<(usize, usize)>> {
+    let start_hour = match start_time {
+        Some(t) => Time::parse(t)?.get_hours(),
+        None => 0,
+    };
+    let end_hour = match end_time {
+        // Round up partial hours
+        Some(t) => ((Time::parse(t)? - Time::START_OF_DAY) / Duration::hours(1)).ceil() as usize,
+        None => 24,
+    };
+    if end_hour <= start_hour {
+        return Ok(None);
+    }
+    Ok(Some((start_hour, end_hour)))
+}
+
+fn parse_agent_type(x: &str) -> Result<AgentType> {
+    for t in AgentType::all() {
+        if format!("{:?}", t) == x {
+            return Ok(t);
+        }
+    }
+    bail!("Unknown agent type {}", x)
+}
+
+/// The outcome of simulating one iteration
+struct Results {
+    /// Per site, the average simulated count per hour
+    modeled: Vec<f64>,
+    /// Per person in the scenario, every site their trips passed, weighted by how much each
+    /// passage contributed to the site's simulated count.
+    passages: BTreeMap<usize, Vec<(usize, f64)>>,
+}
+
+fn simulate(
+    scenario: &Scenario,
+    map: &Map,
+    sites: &[Site],
+    agent_types: &[AgentType],
+    rng_seed: u64,
+    timer: &mut Timer,
+) -> Results {
+    let mut sites_per_road: BTreeMap<RoadID, Vec<(usize, f64)>> = BTreeMap::new();
+    let mut sites_per_intersection: BTreeMap<IntersectionID, Vec<usize>> = BTreeMap::new();
+    for (idx, site) in sites.iter().enumerate() {
+        match site.location {
+            Location::Way(_, ref roads) => {
+                // The site's count is averaged over all of the roads, so crossing one road only
+                // counts partly.
+                let weight = 1.0 / roads.len() as f64;
+                for r in roads {
+                    sites_per_road
+                        .entry(*r)
+                        .or_insert_with(Vec::new)
+                        .push((idx, weight));
+                }
+            }
+            Location::Node(_, i) => {
+                sites_per_intersection
+                    .entry(i)
+                    .or_insert_with(Vec::new)
+                    .push(idx);
+            }
+        }
+    }
+
+    let mut opts = SimOptions::new("calibration");
+    opts.alerts = AlertHandler::Silence;
+    let mut sim = Sim::new(map, opts);
+    // Use the same seed every iteration, so differences come from the scenario changes
+    let mut rng = XorShiftRng::seed_from_u64(rng_seed);
+    scenario.instantiate(&mut sim, map, &mut rng, timer);
+    sim.capture_events();
+
+    let mut passages: BTreeMap<usize, Vec<(usize, f64)>> = BTreeMap::new();
+    let end = Time::START_OF_DAY + Duration::hours(24);
+    while sim.time() < end {
+        let before = sim.time();
+        // Step an hour at a time, so the captured events don't pile up
+        sim.timed_step(map, Duration::hours(1), &mut None, timer);
+        for (time, ev) in sim.drain_captured_events() {
+            if let Event::AgentEntersTraversable(agent, Some(trip), on, _) = ev {
+                if !agent_types.contains(&agent.to_type()) {
+                    continue;
+                }
+                let hour = time.get_hours();
+                let matches: Vec<(usize, f64)> = match on {
+                    Traversable::Lane(l) => sites_per_road
+                        .get(&map.get_l(l).parent)
+                        .cloned()
+                        .unwrap_or_default(),
+                    Traversable::Turn(t) => sites_per_intersection
+                        .get(&t.parent)
+                        .map(|list| list.iter().map(|idx| (*idx, 1.0)).collect())
+                        .unwrap_or_default(),
+                };
+                for (idx, weight) in matches {
+                    if hour < sites[idx].start_hour || hour >= sites[idx].end_hour {
+                        continue;
+                    }
+                    // People in the scenario become PersonIDs in order
+                    if let Some(person) = sim.trip_to_person(trip) {
+                        passages
+                            .entry(person.0)
+                            .or_insert_with(Vec::new)
+                            .push((idx, weight));
+                    }
+                }
+            }
+        }
+        if sim.time() == before {
+            warn!("The simulation stopped advancing at {}", sim.time());
+            break;
+        }
+    }
+
+    let analytics = sim.get_analytics();
+    let modeled = sites
+        .iter()
+        .map(|site| {
+            let mut total = 0.0;
+            for hour in site.start_hour..site.end_hour {
+                for agent_type in agent_types {
+                    match site.location {
+                        Location::Way(_, ref roads) => {
+                            for r in roads {
+                                total += *analytics
+                                    .road_thruput
+                                    .counts
+                                    .get(&(*r, *agent_type, hour))
+                                    .unwrap_or(&0) as f64
+                                    / roads.len() as f64;
+                            }
+                        }
+                        Location::Node(_, i) => {
+                            total += *analytics
+                                .intersection_thruput
+                                .counts
+                                .get(&(i, *agent_type, hour))
+                                .unwrap_or(&0) as f64;
+                        }
+                    }
+                }
+            }
+            total / (site.end_hour - site.start_hour) as f64
+        })
+        .collect();
+
+    Results { modeled, passages }
+}
+
+fn adjust(
+    scenario: &mut Scenario,
+    sites: &[Site],
+    results: &Results,
+    map: &Map,
+    buildings_per_road: &BTreeMap<RoadID, Vec<BuildingID>>,
+    rng: &mut XorShiftRng,
+) {
+    let ratios: Vec<Option<f64>> = sites
+        .iter()
+        .zip(results.modeled.iter())
+        .map(|(site, modeled)| scaling_ratio(site.observed, *modeled))
+        .collect();
+
+    // Each person passing through sites is randomly dropped or duplicated to match their scaling
+    // factor on average.
+    let orig_people = scenario.people.len();
+    let mut people = Vec::new();
+    for (idx, person) in std::mem::take(&mut scenario.people).into_iter().enumerate() {
+        let passages = results
+            .passages
+            .get(&idx)
+            .map(|x| x.as_slice())
+            .unwrap_or(&[]);
+        let copies = num_copies(person_factor(passages, &ratios), rng);
+        for copy in 0..copies {
+            let mut person = person.clone();
+            if copy > 0 {
+                person.orig_id = None;
+            }
+            people.push(person);
+        }
+    }
+    scenario.people = people;
+
+    let mut added = 0;
+    for (site, modeled) in sites.iter().zip(results.modeled.iter()) {
+        if *modeled == 0.0 && site.observed > 0.0 {
+            let new_people = new_trips_through(site, map, buildings_per_road, rng);
+            added += new_people.len();
+            scenario.people.extend(new_people);
+        }
+    }
+
+    info!(
+        "Went from {} to {} people, including {} new people for sites without any traffic",
+        prettyprint_usize(orig_people),
+        prettyprint_usize(scenario.people.len()),
+        prettyprint_usize(added)
+    );
+}
+
+/// How much to scale the traffic through a site. Sites without any simulated traffic can't be
+/// scaled.
+fn scaling_ratio(observed: f64, modeled: f64) -> Option<f64> {
+    if modeled > 0.0 {
+        Some((observed / modeled).max(1.0 / MAX_STEP).min(MAX_STEP))
+    } else {
+        None
+    }
+}
+
+/// The weighted geometric mean of the ratios of every site a person passes through. People not
+/// passing any site that can be scaled are kept as-is.
+fn person_factor(passages: &[(usize, f64)], ratios: &[Option<f64>]) -> f64 {
+    let mut sum_logs = 0.0;
+    let mut sum_weights = 0.0;
+    for (site, weight) in passages {
+        if let Some(ratio) = ratios[*site] {
+            sum_logs += weight * ratio.ln();
+            sum_weights += weight;
+        }
+    }
+    if sum_weights > 0.0 {
+        (sum_logs / sum_weights).exp()
+    } else {
+        1.0
+    }
+}
+
+/// Randomly rounds a scaling factor to a whole number of copies, so that it matches on average.
+fn num_copies(factor: f64, rng: &mut XorShiftRng) -> usize {
+    let mut copies = factor.floor() as usize;
+    if rng.gen_bool(factor.fract()) {
+        copies += 1;
+    }
+    copies
+}
+
+/// Creates enough new driving trips through a site to match its observed count. They appear just
+/// upstream of the site and go to some building just past it.
+fn new_trips_through(
+    site: &Site,
+    map: &Map,
+    buildings_per_road: &BTreeMap<RoadID, Vec<BuildingID>>,
+    rng: &mut XorShiftRng,
+) -> Vec<PersonSpec> {
+    // (Where to start, the road containing the destination)
+    let mut candidates: Vec<(LaneID, RoadID)> = Vec::new();
+    match site.location {
+        Location::Way(_, ref roads) => {
+            for r in roads {
+                for (l, _, lt) in map.get_r(*r).lanes_ltr() {
+                    if lt != LaneType::Driving {
+                        continue;
+                    }
+                    for turn in map.get_turns_to_lane(l) {
+                        candidates.push((turn.id.src, *r));
+                    }
+                }
+            }
+        }
+        Location::Node(_, i) => {
+            let i = map.get_i(i);
+            let dst_roads: BTreeSet<RoadID> = i
+                .get_outgoing_lanes(map, PathConstraints::Car)
+                .into_iter()
+                .map(|l| map.get_l(l).parent)
+                .collect();
+            for src in i.get_incoming_lanes(map, PathConstraints::Car) {
+                for r in &dst_roads {
+                    candidates.push((src, *r));
+                }
+            }
+        }
+    }
+    candidates.retain(|(_, r)| buildings_per_road.contains_key(r));
+    if candidates.is_empty() {
+        warn!(
+            "Can't add trips through site {}; there are no buildings just past it",
+            site.name
+        );
+        return Vec::new();
+    }
+
+    let mut people = Vec::new();
+    for hour in site.start_hour..site.end_hour {
+        for _ in 0..site.observed.round() as usize {
+            let (src, dst_road) = *candidates.choose(rng).unwrap();
+            let b = *buildings_per_road[&dst_road].choose(rng).unwrap();
+            let depart = Time::START_OF_DAY
+                + Duration::hours(hour)
+                + Duration::seconds(rng.gen_range(0.0..3600.0));
+            people.push(PersonSpec {
+                orig_id: None,
+                trips: vec![IndividTrip::new(
+                    depart,
+                    TripPurpose::Shopping,
+                    TripEndpoint::SuddenlyAppear(Position::start(src)),
+                    TripEndpoint::Bldg(b),
+                    TripMode::Drive,
+                )],
+                behavior: None,
+            });
+        }
+    }
+    people
+}
+
+/// Buildings that vehicles can reach, grouped by the road they connect to
+fn index_buildings(map: &Map) -> BTreeMap<RoadID, Vec<BuildingID>> {
+    let mut result = BTreeMap::new();
+    for b in map.all_buildings() {
+        if b.driving_connection(map).is_some() {
+            result
+                .entry(map.building_to_road(b.id).id)
+                .or_insert_with(Vec::new)
+                .push(b.id);
+        }
+    }
+    result
+}
+
+/// The GEH statistic compares hourly traffic volumes. Under 5 is usually considered a good fit.
+fn geh(modeled: f64, observed: f64) -> f64 {
+    if modeled + observed == 0.0 {
+        return 0.0;
+    }
+    (2.0 * (modeled - observed).powi(2) / (modeled + observed)).sqrt()
+}
+
+fn write_report(path: &str, sites: &[Site], modeled: &[f64]) -> Result<()> {
+    let mut f = File::create(path)?;
+    writeln!(
+        f,
+        "site,osm_way_id,osm_node_id,start_hour,end_hour,observed_per_hour,modeled_per_hour,geh"
+    )?;
+    for (site, modeled) in sites.iter().zip(modeled.iter()) {
+        let (way, node) = match site.location {
+            Location::Way(id, _) => (id.0.to_string(), String::new()),
+            Location::Node(id, _) => (String::new(), id.0.to_string()),
+        };
+        writeln!(
+            f,
+            "{},{},{},{},{},{},{},{}",
+            site.name,
+            way,
+            node,
+            site.start_hour,
+            site.end_hour,
+            site.observed,
+            modeled,
+            geh(*modeled, site.observed)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx_eq(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn test_geh() {
+        assert!(approx_eq(geh(0.0, 0.0), 0.0));
+        assert!(approx_eq(geh(100.0, 100.0), 0.0));
+        // sqrt(2 * 50^2 / 250)
+        assert!(approx_eq(geh(150.0, 100.0), 20.0_f64.sqrt()));
+        // Symmetric
+        assert!(approx_eq(geh(100.0, 150.0), geh(150.0, 100.0)));
+        // The same absolute difference matters less for busier sites
+        assert!(geh(1100.0, 1000.0) < geh(200.0, 100.0));
+        assert!(geh(1100.0, 1000.0) < 5.0);
+        assert!(geh(200.0, 100.0) > 5.0);
+    }
+
+    #[test]
+    fn test_scaling_ratio() {
+        assert_eq!(scaling_ratio(10.0, 0.0), None);
+        assert!(approx_eq(scaling_ratio(150.0, 100.0).unwrap(), 1.5));
+        // Clamped to MAX_STEP in either direction
+        assert!(approx_eq(scaling_ratio(1000.0, 100.0).unwrap(), MAX_STEP));
+        assert!(approx_eq(
+            scaling_ratio(0.0, 100.0).unwrap(),
+            1.0 / MAX_STEP
+        ));
+    }
+
+    #[test]
+    fn test_person_factor() {
+        let ratios = vec![Some(2.0), Some(0.5), None];
+        // Nobody passing any site stays the same
+        assert!(approx_eq(person_factor(&[], &ratios), 1.0));
+        assert!(approx_eq(person_factor(&[(2, 1.0)], &ratios), 1.0));
+        assert!(approx_eq(person_factor(&[(0, 1.0)], &ratios), 2.0));
+        // Opposite ratios with equal weights cancel out
+        assert!(approx_eq(
+            person_factor(&[(0, 1.0), (1, 1.0)], &ratios),
+            1.0
+        ));
+        // The geometric mean, weighted: 2^(3/4) * 0.5^(1/4)
+        assert!(approx_eq(
+            person_factor(&[(0, 0.75), (1, 0.25)], &ratios),
+            2.0_f64.powf(0.5)
+        ));
+    }
+
+    #[test]
+    fn test_num_copies() {
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        assert_eq!(num_copies(2.0, &mut rng), 2);
+        assert_eq!(num_copies(0.0, &mut rng), 0);
+
+        let n = 10_000;
+        let total: usize = (0..n).map(|_| num_copies(1.25, &mut rng)).sum();
+        let mean = total as f64 / n as f64;
+        assert!((mean - 1.25).abs() < 0.02, "mean was {}", mean);
+    }
+
+    #[test]
+    fn test_hour_range() {
+        assert_eq!(hour_range(None, None).unwrap(), Some((0, 24)));
+        assert_eq!(
+            hour_range(Some("07:00:00"), Some("09:00:00")).unwrap(),
+            Some((7, 9))
+        );
+        // Partial hours are rounded outwards
+        assert_eq!(
+            hour_range(Some("07:30:00"), Some("08:15:00")).unwrap(),
+            Some((7, 9))
+        );
+        assert_eq!(
+            hour_range(Some("09:00:00"), Some("09:00:00")).unwrap(),
+            None
+        );
+        assert!(hour_range(Some("garbage"), None).is_err());
+    }
+}
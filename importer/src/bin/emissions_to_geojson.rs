@@ -0,0 +1,120 @@
+//! Turns emissions and noise estimates recorded by `run_scenario --record_emissions` into GeoJSON,
+//! with one LineString per road and hour.
+//!
+//! `--map`: The map the simulation ran on. Edits don't change road geometry, so use the unedited
+//!          map even if the run had edits.
+//! `--input`: The recorded `Emissions`.
+//! `--baseline`: Optionally, `Emissions` recorded from another run, maybe with different map
+//!               edits. Each feature then also has the baseline values and the change from them.
+//! `--output`: Defaults to `emissions.geojson`.
+
+use std::collections::BTreeSet;
+
+use geojson::{Feature, FeatureCollection, GeoJson};
+
+use abstutil::{prettyprint_usize, CmdArgs, Timer};
+use map_model::Map;
+use sim::{Emissions, RoadEmissions};
+
+fn main() {
+    let mut args = CmdArgs::new();
+    let map_path = args.required("--map");
+    let input = args.required("--input");
+    let baseline_path = args.optional("--baseline");
+    let output = args
+        .optional("--output")
+        .unwrap_or_else(|| "emissions.geojson".to_string());
+    args.done();
+
+    let mut timer = Timer::new("export emissions");
+    let map = Map::load_synchronously(map_path, &mut timer);
+    let emissions: Emissions = abstio::must_read_object(input, &mut timer);
+    let baseline: Option<Emissions> =
+        baseline_path.map(|path| abstio::must_read_object(path, &mut timer));
+
+    let mut keys: BTreeSet<_> = emissions.per_road.keys().cloned().collect();
+    if let Some(ref baseline) = baseline {
+        keys.extend(baseline.per_road.keys().cloned());
+    }
+
+    let mut features = Vec::new();
+    for (r, hour) in keys {
+        let mut properties = serde_json::Map::new();
+        properties.insert("road".to_string(), r.0.into());
+        properties.insert(
+            "osm_way_id".to_string(),
+            map.get_r(r).orig_id.osm_way_id.0.into(),
+        );
+        properties.insert("hour".to_string(), hour.into());
+
+        let current = emissions.per_road.get(&(r, hour));
+        add_properties(&mut properties, "", current);
+        if let Some(ref baseline) = baseline {
+            let before = baseline.per_road.get(&(r, hour));
+            add_properties(&mut properties, "baseline_", before);
+            for ((key, after), (_, before)) in summarize(current).into_iter().zip(summarize(before))
+            {
+                if let (Some(after), Some(before)) = (after, before) {
+                    properties.insert(format!("{}_change", key), (after - before).into());
+                }
+            }
+        }
+
+        features.push(Feature {
+            bbox: None,
+            geometry: Some(
+                map.get_r(r)
+                    .center_pts
+                    .to_geojson(Some(map.get_gps_bounds())),
+            ),
+            id: None,
+            properties: Some(properties),
+            foreign_members: None,
+        });
+    }
+
+    println!(
+        "Writing {} features to {}",
+        prettyprint_usize(features.len()),
+        output
+    );
+    let geojson = GeoJson::from(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    });
+    abstio::write_json(output, &geojson);
+}
+
+/// Every value worth exporting. Nothing recorded means no traffic, so no emissions, but there's no
+/// speed or noise level to speak of.
+fn summarize(x: Option<&RoadEmissions>) -> Vec<(&'static str, Option<f64>)> {
+    vec![
+        ("co2_grams", Some(x.map_or(0.0, |x| x.co2))),
+        ("nox_grams", Some(x.map_or(0.0, |x| x.nox))),
+        ("pm_grams", Some(x.map_or(0.0, |x| x.pm))),
+        ("vehicles", Some(x.map_or(0.0, |x| x.vehicles as f64))),
+        (
+            "heavy_vehicles",
+            Some(x.map_or(0.0, |x| x.heavy_vehicles as f64)),
+        ),
+        (
+            "average_speed_kmh",
+            x.and_then(|x| x.average_speed())
+                .map(|s| s.inner_meters_per_second() * 3.6),
+        ),
+        ("noise_dba", x.and_then(|x| x.noise_level())),
+    ]
+}
+
+fn add_properties(
+    properties: &mut serde_json::Map<String, serde_json::Value>,
+    prefix: &str,
+    x: Option<&RoadEmissions>,
+) {
+    for (key, value) in summarize(x) {
+        if let Some(value) = value {
+            properties.insert(format!("{}{}", prefix, key), value.into());
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! Exports a map as a MATSim `network.xml`, and optionally a scenario as a MATSim `plans.xml`, so
+//! the same demand can run in both simulators. `import_matsim` reads the plans back.
+//!
+//! `--map`: The map to export.
+//! `--scenario`: Optionally, a scenario for the map to export as plans.
+//! `--network_output`: Defaults to `network.xml`.
+//! `--plans_output`: Defaults to `plans.xml`.
+//! `--coords`: How to write x and y. `local` (the default) means meters from the southwest corner
+//!             of the map, which MATSim needs to calculate distances by itself. `wgs84` means
+//!             longitude and latitude, and the output declares that coordinate reference system.
+//!
+//! Every direction of a road with any vehicle lanes becomes a link, named like `123_fwd`.
+//! Intersections become nodes with the same IDs as the map. Each person's trips become one
+//! selected plan, with activities at the trip endpoints.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use anyhow::Result;
+
+use abstutil::{escape_xml, prettyprint_usize, CmdArgs, Timer};
+use geom::Pt2D;
+use importer::MatsimCoordinates;
+use map_model::{Direction, LaneType, Map};
+use sim::{Scenario, TripEndpoint, TripMode, TripPurpose};
+
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let map_path = args.required("--map");
+    let scenario_path = args.optional("--scenario");
+    let network_output = args
+        .optional("--network_output")
+        .unwrap_or_else(|| "network.xml".to_string());
+    let plans_output = args
+        .optional("--plans_output")
+        .unwrap_or_else(|| "plans.xml".to_string());
+    let coords_arg = args.optional("--coords");
+    args.done();
+
+    let mut timer = Timer::new("export to MATSim");
+    let map = Map::load_synchronously(map_path, &mut timer);
+    let coords = MatsimCoordinates::from_arg(&map, coords_arg)?;
+
+    timer.start("write network");
+    write_network(&map, &coords, &network_output)?;
+    timer.stop("write network");
+
+    if let Some(path) = scenario_path {
+        let scenario: Scenario = abstio::read_binary(path, &mut timer);
+        timer.start("write plans");
+        write_plans(&map, &scenario, &coords, &plans_output)?;
+        timer.stop("write plans");
+    }
+    Ok(())
+}
+
+fn write_network(map: &Map, coords: &MatsimCoordinates, path: &str) -> Result<()> {
+    let mut f = BufWriter::new(File::create(path)?);
+    writeln!(f, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(
+        f,
+        "<!DOCTYPE network SYSTEM \"http://www.matsim.org/files/dtd/network_v2.dtd\">"
+    )?;
+    writeln!(
+        f,
+        "<network name=\"{}\">",
+        escape_xml(&map.get_name().describe())
+    )?;
+    write_crs(&mut f, coords)?;
+
+    writeln!(f, "  <nodes>")?;
+    for i in map.all_intersections() {
+        writeln!(
+            f,
+            "    <node id=\"{}\" {}/>",
+            i.id.0,
+            coords.xy(i.polygon.center())
+        )?;
+    }
+    writeln!(f, "  </nodes>")?;
+
+    writeln!(
+        f,
+        "  <links capperiod=\"01:00:00\" effectivecellsize=\"7.5\" effectivelanewidth=\"3.75\">"
+    )?;
+    let mut num_links = 0;
+    for r in map.all_roads() {
+        for dr in r.id.both_directions() {
+            let lane_types: Vec<LaneType> = r
+                .lanes_ltr()
+                .into_iter()
+                .filter(|(_, dir, _)| *dir == dr.dir)
+                .map(|(_, _, lt)| lt)
+                .collect();
+            let driving = lane_types
+                .iter()
+                .filter(|lt| **lt == LaneType::Driving)
+                .count();
+            let mut modes = Vec::new();
+            if driving > 0 {
+                modes.push("car");
+            }
+            if lane_types.contains(&LaneType::Bus) || driving > 0 {
+                modes.push("pt");
+            }
+            if lane_types.contains(&LaneType::Biking) || driving > 0 {
+                modes.push("bike");
+            }
+            if modes.is_empty() {
+                continue;
+            }
+            let permlanes = driving.max(1);
+            writeln!(
+                f,
+                "    <link id=\"{}\" from=\"{}\" to=\"{}\" length=\"{:.2}\" freespeed=\"{:.2}\" \
+                 capacity=\"{}\" permlanes=\"{}\" oneway=\"1\" modes=\"{}\"/>",
+                link_id(r.id.0, dr.dir),
+                dr.src_i(map).0,
+                dr.dst_i(map).0,
+                r.center_pts.length().inner_meters(),
+                r.speed_limit.inner_meters_per_second(),
+                // A typical saturation flow per lane
+                1800 * permlanes,
+                permlanes,
+                modes.join(",")
+            )?;
+            num_links += 1;
+        }
+    }
+    writeln!(f, "  </links>")?;
+    writeln!(f, "</network>")?;
+    f.flush()?;
+
+    println!(
+        "Wrote {} nodes and {} links to {}",
+        prettyprint_usize(map.all_intersections().len()),
+        prettyprint_usize(num_links),
+        path
+    );
+    Ok(())
+}
+
+fn write_plans(
+    map: &Map,
+    scenario: &Scenario,
+    coords: &MatsimCoordinates,
+    path: &str,
+) -> Result<()> {
+    let mut f = BufWriter::new(File::create(path)?);
+    writeln!(f, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(
+        f,
+        "<!DOCTYPE population SYSTEM \"http://www.matsim.org/files/dtd/population_v6.dtd\">"
+    )?;
+    writeln!(f, "<population>")?;
+    write_crs(&mut f, coords)?;
+
+    let mut num_people = 0;
+    for (idx, person) in scenario.people.iter().enumerate() {
+        let trips: Vec<_> = person.trips.iter().filter(|t| !t.cancelled).collect();
+        if trips.is_empty() {
+            continue;
+        }
+        num_people += 1;
+        writeln!(f, "  <person id=\"{}\">", idx)?;
+        writeln!(f, "    <plan selected=\"yes\">")?;
+        // Assume everybody starts the day at home
+        let mut act_type = activity_type(TripPurpose::Home);
+        for trip in &trips {
+            // Place the activity where the trip starts. That's usually the previous trip's
+            // destination, unless the person left and came back in through different borders.
+            writeln!(
+                f,
+                "      <act type=\"{}\" {} end_time=\"{}\"/>",
+                act_type,
+                coords.xy(endpoint_pt(trip.origin, map)),
+                trip.depart.as_hhmmss()
+            )?;
+            writeln!(
+                f,
+                "      <leg mode=\"{}\" dep_time=\"{}\"/>",
+                leg_mode(trip.mode),
+                trip.depart.as_hhmmss()
+            )?;
+            act_type = activity_type(trip.purpose);
+        }
+        writeln!(
+            f,
+            "      <act type=\"{}\" {}/>",
+            act_type,
+            coords.xy(endpoint_pt(trips.last().unwrap().destination, map))
+        )?;
+        writeln!(f, "    </plan>")?;
+        writeln!(f, "  </person>")?;
+    }
+    writeln!(f, "</population>")?;
+    f.flush()?;
+
+    println!("Wrote {} people to {}", prettyprint_usize(num_people), path);
+    Ok(())
+}
+
+/// MATSim assumes coordinates are already in the scenario's system unless the file says otherwise.
+fn write_crs<W: Write>(f: &mut W, coords: &MatsimCoordinates) -> Result<()> {
+    if let Some(crs) = coords.crs() {
+        writeln!(f, "  <attributes>")?;
+        writeln!(
+            f,
+            "    <attribute name=\"coordinateReferenceSystem\" class=\"java.lang.String\">{}</attribute>",
+            crs
+        )?;
+        writeln!(f, "  </attributes>")?;
+    }
+    Ok(())
+}
+
+fn link_id(road: usize, dir: Direction) -> String {
+    match dir {
+        Direction::Fwd => format!("{}_fwd", road),
+        Direction::Back => format!("{}_back", road),
+    }
+}
+
+fn endpoint_pt(endpt: TripEndpoint, map: &Map) -> Pt2D {
+    match endpt {
+        TripEndpoint::Bldg(b) => map.get_b(b).label_center,
+        TripEndpoint::Border(i) => map.get_i(i).polygon.center(),
+        TripEndpoint::SuddenlyAppear(pos) => pos.pt(map),
+    }
+}
+
+/// `import_matsim` understands all of these
+fn activity_type(purpose: TripPurpose) -> &'static str {
+    match purpose {
+        TripPurpose::Home => "home",
+        TripPurpose::Work => "work",
+        TripPurpose::School => "education",
+        TripPurpose::Escort => "escort",
+        TripPurpose::PersonalBusiness | TripPurpose::ParkAndRideTransfer => "other",
+        TripPurpose::Shopping => "shop",
+        TripPurpose::Meal => "eat",
+        TripPurpose::Social => "social",
+        TripPurpose::Recreation => "leisure",
+        TripPurpose::Medical => "medical",
+    }
+}
+
+fn leg_mode(mode: TripMode) -> &'static str {
+    match mode {
+        TripMode::Walk => "walk",
+        TripMode::Bike | TripMode::BikeShare => "bike",
+        TripMode::Transit => "pt",
+        TripMode::Drive => "car",
+        TripMode::RideHail => "taxi",
+    }
+}
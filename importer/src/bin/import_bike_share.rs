@@ -0,0 +1,148 @@
+//! Adds bike-share or scooter-share docking stations to an existing scenario.
+//!
+//! By default, every building tagged `amenity=bicycle_rental` in OpenStreetMap gets a dock. With
+//! `--geojson`, docks come from the points in that file instead, each snapped to the closest
+//! building. Features may set `capacity`, `vehicles`, and `type` (`bike` or `scooter`)
+//! properties; otherwise `--capacity` and `--fill` decide how big each dock is and how full it
+//! starts the day.
+//!
+//! It modifies the given `--input` binary scenario in-place, replacing any docks already there.
+
+#[macro_use]
+extern crate log;
+
+use anyhow::Result;
+use geojson::{GeoJson, Value};
+
+use abstutil::{prettyprint_usize, CmdArgs, Timer};
+use geom::{Distance, FindClosest, LonLat};
+use map_model::{BuildingID, Map};
+use sim::{BikeShareDock, Scenario, SharedVehicleType};
+
+fn main() {
+    let mut args = CmdArgs::new();
+    let input = args.required("--input");
+    let geojson_path = args.optional("--geojson");
+    let capacity: usize = args
+        .optional_parse("--capacity", |s| s.parse())
+        .unwrap_or(15);
+    let fill: f64 = args.optional_parse("--fill", |s| s.parse()).unwrap_or(0.5);
+    args.done();
+
+    let mut timer = Timer::new("import bike-share docks");
+    let mut scenario: Scenario = abstio::must_read_object(input, &mut timer);
+    let map = Map::load_synchronously(scenario.map_name.path(), &mut timer);
+
+    let defaults = DockDefaults { capacity, fill };
+    scenario.bike_share_docks = if let Some(path) = geojson_path {
+        docks_from_geojson(&path, &map, &defaults).unwrap()
+    } else {
+        docks_from_osm(&map, &defaults)
+    };
+    info!(
+        "Added {} bike-share docks",
+        prettyprint_usize(scenario.bike_share_docks.len())
+    );
+
+    scenario.save();
+}
+
+struct DockDefaults {
+    capacity: usize,
+    fill: f64,
+}
+
+impl DockDefaults {
+    fn make(
+        &self,
+        building: BuildingID,
+        vehicle_type: SharedVehicleType,
+        capacity: Option<usize>,
+        vehicles: Option<usize>,
+    ) -> BikeShareDock {
+        let capacity = capacity.unwrap_or(self.capacity);
+        BikeShareDock {
+            building,
+            vehicle_type,
+            capacity,
+            vehicles: vehicles
+                .unwrap_or_else(|| (self.fill * capacity as f64).round() as usize)
+                .min(capacity),
+        }
+    }
+}
+
+fn docks_from_osm(map: &Map, defaults: &DockDefaults) -> Vec<BikeShareDock> {
+    let mut docks = Vec::new();
+    for b in map.all_buildings() {
+        // Depending on import options, the amenity's tags may be empty. Then we just use the
+        // defaults.
+        if let Some(amenity) = b
+            .amenities
+            .iter()
+            .find(|a| a.amenity_type == "bicycle_rental")
+        {
+            let capacity = amenity
+                .osm_tags
+                .get("capacity")
+                .and_then(|x| x.parse::<usize>().ok());
+            docks.push(defaults.make(b.id, SharedVehicleType::Bike, capacity, None));
+        }
+    }
+    docks
+}
+
+fn docks_from_geojson(
+    path: &str,
+    map: &Map,
+    defaults: &DockDefaults,
+) -> Result<Vec<BikeShareDock>> {
+    let geojson = String::from_utf8(abstio::slurp_file(path)?)?.parse::<GeoJson>()?;
+    let features = match geojson {
+        GeoJson::Feature(feature) => vec![feature],
+        GeoJson::FeatureCollection(collection) => collection.features,
+        _ => anyhow::bail!("Unexpected geojson: {:?}", geojson),
+    };
+
+    let mut closest: FindClosest<BuildingID> = FindClosest::new(map.get_bounds());
+    for b in map.all_buildings() {
+        closest.add(b.id, b.polygon.points());
+    }
+
+    let mut docks = Vec::new();
+    for feature in features {
+        let pt = match feature.geometry.as_ref().map(|g| &g.value) {
+            Some(Value::Point(pt)) => LonLat::new(pt[0], pt[1]),
+            _ => {
+                continue;
+            }
+        };
+        if !map.get_gps_bounds().contains(pt) {
+            continue;
+        }
+        let building =
+            match closest.closest_pt(pt.to_pt(map.get_gps_bounds()), Distance::meters(50.0)) {
+                Some((b, _)) => b,
+                None => {
+                    warn!("No building near the dock at {}", pt);
+                    continue;
+                }
+            };
+
+        let property = |key: &str| {
+            feature
+                .properties
+                .as_ref()
+                .and_then(|props| props.get(key))
+                .cloned()
+        };
+        let capacity = property("capacity").and_then(|x| x.as_u64().map(|n| n as usize));
+        let vehicles = property("vehicles").and_then(|x| x.as_u64().map(|n| n as usize));
+        let vehicle_type = match property("type").as_ref().and_then(|x| x.as_str()) {
+            Some("scooter") => SharedVehicleType::Scooter,
+            _ => SharedVehicleType::Bike,
+        };
+        docks.push(defaults.make(building, vehicle_type, capacity, vehicles));
+    }
+    Ok(docks)
+}
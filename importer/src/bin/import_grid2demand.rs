@@ -61,6 +61,7 @@ fn parse_trips(csv_path: String) -> Result<Vec<ExternalPerson>> {
                 mode,
                 purpose: TripPurpose::Work,
             }],
+            behavior: None,
         });
     }
     Ok(people)
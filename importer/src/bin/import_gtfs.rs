@@ -0,0 +1,596 @@
+//! Adds transit routes and their real schedules from a GTFS feed to an existing map.
+//!
+//! `--map`: The map to modify in-place.
+//! `--gtfs`: A directory with an unzipped feed: `stops.txt`, `routes.txt`, `trips.txt`, and
+//!           `stop_times.txt`, plus `calendar.txt`, `calendar_dates.txt`, or both. `shapes.txt`
+//!           is used if it's present.
+//! `--date`: The service day to import, like `20210614`.
+//! `--replace`: Remove every route and stop from OpenStreetMap first. Use this when the GTFS feed
+//!              covers all transit in the area.
+//!
+//! Only the part of each trip inside the map is kept. Trips serving the same stops in the same
+//! order become one route, with one vehicle spawning per trip.
+
+#[macro_use]
+extern crate log;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File;
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+
+use abstutil::{prettyprint_usize, CmdArgs, Timer};
+use geom::{Duration, LonLat, Pt2D, Time};
+use map_model::{Map, ScheduledRoute};
+
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let map_path = args.required("--map");
+    let gtfs_dir = args.required("--gtfs");
+    let date = args.required("--date");
+    let replace_existing = args.enabled("--replace");
+    args.done();
+
+    let mut timer = Timer::new("import GTFS");
+    let mut map = Map::load_synchronously(map_path, &mut timer);
+
+    timer.start("read GTFS");
+    let feed = Feed::load(&gtfs_dir, &date)?;
+    timer.stop("read GTFS");
+
+    let routes = feed.scheduled_routes(&map);
+    let num_routes = routes.len();
+    let added = map.hack_add_scheduled_routes(routes, replace_existing, &mut timer);
+    println!(
+        "Added {}/{} routes, serving {} stops",
+        prettyprint_usize(added),
+        prettyprint_usize(num_routes),
+        prettyprint_usize(map.all_bus_stops().len())
+    );
+    map.save();
+
+    Ok(())
+}
+
+struct Feed {
+    stops: HashMap<String, StopRecord>,
+    routes: HashMap<String, RouteRecord>,
+    /// Only the trips running on the service day
+    trips: HashMap<String, TripRecord>,
+    /// Per trip, in order
+    stop_times: HashMap<String, Vec<StopTimeRecord>>,
+    shapes: HashMap<String, Vec<LonLat>>,
+}
+
+impl Feed {
+    fn load(dir: &str, date: &str) -> Result<Feed> {
+        let services = active_services(
+            date,
+            read_optional_csv(dir, "calendar.txt")?,
+            read_optional_csv(dir, "calendar_dates.txt")?,
+        )?;
+        if services.is_empty() {
+            bail!("No service runs on {}", date);
+        }
+
+        let mut stops = HashMap::new();
+        for rec in read_csv::<StopRecord>(dir, "stops.txt")? {
+            stops.insert(rec.stop_id.clone(), rec);
+        }
+        let mut routes = HashMap::new();
+        for rec in read_csv::<RouteRecord>(dir, "routes.txt")? {
+            routes.insert(rec.route_id.clone(), rec);
+        }
+        let mut trips = HashMap::new();
+        for rec in read_csv::<TripRecord>(dir, "trips.txt")? {
+            if services.contains(&rec.service_id) {
+                trips.insert(rec.trip_id.clone(), rec);
+            }
+        }
+
+        let mut stop_times: HashMap<String, Vec<StopTimeRecord>> = HashMap::new();
+        for rec in read_csv::<StopTimeRecord>(dir, "stop_times.txt")? {
+            if trips.contains_key(&rec.trip_id) {
+                stop_times
+                    .entry(rec.trip_id.clone())
+                    .or_insert_with(Vec::new)
+                    .push(rec);
+            }
+        }
+        for list in stop_times.values_mut() {
+            list.sort_by_key(|rec| rec.stop_sequence);
+        }
+
+        let mut shapes = HashMap::new();
+        let mut pts: HashMap<String, Vec<(usize, LonLat)>> = HashMap::new();
+        for rec in read_optional_csv::<ShapeRecord>(dir, "shapes.txt")? {
+            pts.entry(rec.shape_id).or_insert_with(Vec::new).push((
+                rec.shape_pt_sequence,
+                LonLat::new(rec.shape_pt_lon, rec.shape_pt_lat),
+            ));
+        }
+        for (id, mut list) in pts {
+            list.sort_by_key(|(seq, _)| *seq);
+            shapes.insert(id, list.into_iter().map(|(_, pt)| pt).collect());
+        }
+
+        info!("{} trips run on {}", prettyprint_usize(trips.len()), date);
+        Ok(Feed {
+            stops,
+            routes,
+            trips,
+            stop_times,
+            shapes,
+        })
+    }
+
+    /// Groups trips serving the same stops in the same order into routes.
+    fn scheduled_routes(&self, map: &Map) -> Vec<ScheduledRoute> {
+        let boundary = map.get_boundary_polygon();
+        let gps_bounds = map.get_gps_bounds();
+
+        // (route, shape, stops) => every trip's departure, arrival offsets, and timepoints
+        let mut patterns: BTreeMap<
+            (String, Option<String>, Vec<String>),
+            Vec<(Time, Vec<Duration>, BTreeSet<usize>)>,
+        > = BTreeMap::new();
+        for (trip_id, stop_times) in &self.stop_times {
+            let trip = &self.trips[trip_id];
+            let times = match interpolate_times(stop_times) {
+                Ok(times) => times,
+                Err(err) => {
+                    warn!("Skipping trip {}: {}", trip_id, err);
+                    continue;
+                }
+            };
+
+            // Only keep the longest stretch of the trip inside the map
+            let mut best: Option<(usize, usize)> = None;
+            let mut current_start = None;
+            for (idx, rec) in stop_times.iter().enumerate() {
+                let inside = self
+                    .stops
+                    .get(&rec.stop_id)
+                    .map(|stop| {
+                        let pt = LonLat::new(stop.stop_lon, stop.stop_lat);
+                        gps_bounds.contains(pt) && boundary.contains_pt(pt.to_pt(gps_bounds))
+                    })
+                    .unwrap_or(false);
+                if inside {
+                    let start = *current_start.get_or_insert(idx);
+                    if best.map(|(s, e)| idx - start > e - s).unwrap_or(true) {
+                        best = Some((start, idx));
+                    }
+                } else {
+                    current_start = None;
+                }
+            }
+            let (start, end) = match best {
+                Some((start, end)) if end > start => (start, end),
+                _ => {
+                    continue;
+                }
+            };
+
+            let departure = times[start].1;
+            let offsets = (start..=end)
+                .map(|idx| {
+                    if idx == start {
+                        Duration::ZERO
+                    } else {
+                        times[idx].0 - departure
+                    }
+                })
+                .collect();
+            let timepoints = (start..=end)
+                .filter(|idx| stop_times[*idx].timepoint == Some(1))
+                .map(|idx| idx - start)
+                .collect();
+            let stop_ids = stop_times[start..=end]
+                .iter()
+                .map(|rec| rec.stop_id.clone())
+                .collect();
+            patterns
+                .entry((trip.route_id.clone(), trip.shape_id.clone(), stop_ids))
+                .or_insert_with(Vec::new)
+                .push((departure, offsets, timepoints));
+        }
+
+        let mut results = Vec::new();
+        for ((route_id, shape_id, stop_ids), mut trips) in patterns {
+            trips.sort_by_key(|(t, _, _)| *t);
+            let route = match self.routes.get(&route_id) {
+                Some(route) => route,
+                None => {
+                    warn!("Skipping trips of unknown route {}", route_id);
+                    continue;
+                }
+            };
+            let is_bus = match route.route_type {
+                3 | 11 | 700..=799 | 800..=899 => true,
+                0 | 1 | 2 | 12 | 100..=199 | 400..=499 | 900..=999 => false,
+                // Ferries, cable cars, and so on
+                _ => {
+                    continue;
+                }
+            };
+
+            let stops: Vec<(String, Pt2D)> = stop_ids
+                .iter()
+                .map(|id| {
+                    let stop = &self.stops[id];
+                    (
+                        stop.stop_name.clone().unwrap_or_else(|| id.clone()),
+                        LonLat::new(stop.stop_lon, stop.stop_lat).to_pt(gps_bounds),
+                    )
+                })
+                .collect();
+            // Running times vary through the day, but a route only has one schedule, so average
+            // them
+            let stop_offsets = (0..stops.len())
+                .map(|idx| {
+                    let total: f64 = trips
+                        .iter()
+                        .map(|(_, offsets, _)| offsets[idx].inner_seconds())
+                        .sum();
+                    Duration::seconds(total / (trips.len() as f64))
+                })
+                .collect();
+            let short_name = route
+                .route_short_name
+                .clone()
+                .filter(|x| !x.is_empty())
+                .or_else(|| route.route_long_name.clone())
+                .unwrap_or_else(|| route_id.clone());
+            let full_name = format!(
+                "{} to {}",
+                route
+                    .route_long_name
+                    .clone()
+                    .filter(|x| !x.is_empty())
+                    .unwrap_or_else(|| short_name.clone()),
+                stops.last().unwrap().0
+            );
+
+            results.push(ScheduledRoute {
+                full_name,
+                short_name,
+                gtfs_trip_marker: Some(shape_id.clone().unwrap_or_else(|| route_id.clone())),
+                is_bus,
+                stops,
+                shape: shape_id
+                    .and_then(|id| self.shapes.get(&id))
+                    .map(|pts| gps_bounds.convert(pts)),
+                departures: trips.iter().map(|(t, _, _)| *t).collect(),
+                stop_offsets,
+                // Assume the first trip of the day has the same timepoints as every other
+                timepoints: trips[0].2.clone(),
+            });
+        }
+        results
+    }
+}
+
+/// Returns every (arrival, departure) time along a trip. Stops without times get them
+/// interpolated between the closest stops with times, spacing them evenly.
+fn interpolate_times(stop_times: &[StopTimeRecord]) -> Result<Vec<(Time, Time)>> {
+    let mut known: Vec<Option<(Time, Time)>> = Vec::new();
+    for rec in stop_times {
+        let arrival = parse_time(&rec.arrival_time)?;
+        let departure = parse_time(&rec.departure_time)?;
+        known.push(match (arrival, departure) {
+            (Some(a), Some(d)) => Some((a, d)),
+            (Some(t), None) | (None, Some(t)) => Some((t, t)),
+            (None, None) => None,
+        });
+    }
+    if known.first().cloned().flatten().is_none() || known.last().cloned().flatten().is_none() {
+        bail!("the first and last stops need times");
+    }
+
+    let mut results = Vec::new();
+    let mut prev = 0;
+    for (idx, times) in known.iter().enumerate() {
+        if let Some(times) = times {
+            results.push(*times);
+            prev = idx;
+            continue;
+        }
+        let next = (idx..known.len()).find(|i| known[*i].is_some()).unwrap();
+        let t1 = known[prev].unwrap().1;
+        let t2 = known[next].unwrap().0;
+        let t = t1 + ((idx - prev) as f64) / ((next - prev) as f64) * (t2 - t1);
+        results.push((t, t));
+    }
+    Ok(results)
+}
+
+/// GTFS times can go past 24:00:00 for trips running after midnight.
+fn parse_time(x: &Option<String>) -> Result<Option<Time>> {
+    match x.as_ref().map(|x| x.trim()) {
+        Some(x) if !x.is_empty() => Ok(Some(Time::parse(x)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Which services run on a date, given as YYYYMMDD. Feeds may describe services with a weekly
+/// calendar, exceptions to it, or only a list of dates.
+fn active_services(
+    date: &str,
+    calendar: Vec<CalendarRecord>,
+    calendar_dates: Vec<CalendarDateRecord>,
+) -> Result<HashSet<String>> {
+    let date_num = date
+        .parse::<u32>()
+        .map_err(|_| anyhow!("--date={} should look like 20210614", date))?;
+    let weekday = day_of_week(date_num / 10000, (date_num / 100) % 100, date_num % 100);
+
+    let mut services = HashSet::new();
+    for rec in calendar {
+        let runs = [
+            rec.sunday,
+            rec.monday,
+            rec.tuesday,
+            rec.wednesday,
+            rec.thursday,
+            rec.friday,
+            rec.saturday,
+        ][weekday]
+            == 1;
+        if runs && rec.start_date <= date_num && date_num <= rec.end_date {
+            services.insert(rec.service_id);
+        }
+    }
+    for rec in calendar_dates {
+        if rec.date != date_num {
+            continue;
+        }
+        match rec.exception_type {
+            1 => {
+                services.insert(rec.service_id);
+            }
+            2 => {
+                services.remove(&rec.service_id);
+            }
+            x => warn!("Unknown exception_type {} for {}", x, rec.service_id),
+        }
+    }
+    Ok(services)
+}
+
+/// 0 is Sunday. This is Sakamoto's method.
+fn day_of_week(year: u32, month: u32, day: u32) -> usize {
+    let offsets = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+    let year = if month < 3 { year - 1 } else { year };
+    ((year + year / 4 - year / 100 + year / 400 + offsets[(month - 1) as usize] + day) % 7) as usize
+}
+
+fn read_csv<T: serde::de::DeserializeOwned>(dir: &str, file: &str) -> Result<Vec<T>> {
+    let path = format!("{}/{}", dir, file);
+    let mut results = Vec::new();
+    for rec in csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(File::open(&path).map_err(|err| anyhow!("{}: {}", path, err))?)
+        .deserialize()
+    {
+        results.push(rec.map_err(|err| anyhow!("{}: {}", path, err))?);
+    }
+    Ok(results)
+}
+
+/// Like `read_csv`, but a missing file has no records.
+fn read_optional_csv<T: serde::de::DeserializeOwned>(dir: &str, file: &str) -> Result<Vec<T>> {
+    if std::path::Path::new(&format!("{}/{}", dir, file)).exists() {
+        read_csv(dir, file)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+#[derive(Deserialize)]
+struct StopRecord {
+    stop_id: String,
+    stop_name: Option<String>,
+    stop_lat: f64,
+    stop_lon: f64,
+}
+
+#[derive(Deserialize)]
+struct RouteRecord {
+    route_id: String,
+    route_short_name: Option<String>,
+    route_long_name: Option<String>,
+    route_type: usize,
+}
+
+#[derive(Deserialize)]
+struct TripRecord {
+    route_id: String,
+    service_id: String,
+    trip_id: String,
+    shape_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StopTimeRecord {
+    trip_id: String,
+    arrival_time: Option<String>,
+    departure_time: Option<String>,
+    stop_id: String,
+    stop_sequence: usize,
+    timepoint: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct ShapeRecord {
+    shape_id: String,
+    shape_pt_lat: f64,
+    shape_pt_lon: f64,
+    shape_pt_sequence: usize,
+}
+
+#[derive(Deserialize)]
+struct CalendarRecord {
+    service_id: String,
+    monday: usize,
+    tuesday: usize,
+    wednesday: usize,
+    thursday: usize,
+    friday: usize,
+    saturday: usize,
+    sunday: usize,
+    start_date: u32,
+    end_date: u32,
+}
+
+#[derive(Deserialize)]
+struct CalendarDateRecord {
+    service_id: String,
+    date: u32,
+    exception_type: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: &str) -> Time {
+        Time::parse(x).unwrap()
+    }
+
+    fn stop_time(arrival: &str, departure: &str) -> StopTimeRecord {
+        let time = |x: &str| {
+            if x.is_empty() {
+                None
+            } else {
+                Some(x.to_string())
+            }
+        };
+        StopTimeRecord {
+            trip_id: "trip".to_string(),
+            arrival_time: time(arrival),
+            departure_time: time(departure),
+            stop_id: "stop".to_string(),
+            stop_sequence: 0,
+            timepoint: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(
+            parse_time(&Some("08:30:00".to_string())).unwrap(),
+            Some(Time::START_OF_DAY + Duration::hours(8) + Duration::minutes(30))
+        );
+        // Trips running past midnight belong to the service day they started on
+        assert_eq!(
+            parse_time(&Some("25:10:00".to_string())).unwrap(),
+            Some(Time::START_OF_DAY + Duration::hours(25) + Duration::minutes(10))
+        );
+        assert_eq!(parse_time(&Some(" ".to_string())).unwrap(), None);
+        assert_eq!(parse_time(&None).unwrap(), None);
+        assert!(parse_time(&Some("noon".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_interpolate_times() {
+        let times = interpolate_times(&[
+            stop_time("08:00:00", "08:01:00"),
+            stop_time("", ""),
+            stop_time("", ""),
+            stop_time("08:10:00", ""),
+        ])
+        .unwrap();
+        assert_eq!(
+            times,
+            vec![
+                (at("08:00:00"), at("08:01:00")),
+                (at("08:04:00"), at("08:04:00")),
+                (at("08:07:00"), at("08:07:00")),
+                (at("08:10:00"), at("08:10:00")),
+            ]
+        );
+
+        assert!(interpolate_times(&[stop_time("", ""), stop_time("08:10:00", "")]).is_err());
+        assert!(interpolate_times(&[stop_time("08:00:00", ""), stop_time("", "")]).is_err());
+    }
+
+    #[test]
+    fn test_day_of_week() {
+        assert_eq!(day_of_week(2021, 6, 14), 1);
+        assert_eq!(day_of_week(2000, 1, 1), 6);
+        assert_eq!(day_of_week(2024, 2, 29), 4);
+        assert_eq!(day_of_week(2023, 12, 31), 0);
+    }
+
+    fn calendar(service_id: &str, weekdays: bool) -> CalendarRecord {
+        let weekday = if weekdays { 1 } else { 0 };
+        CalendarRecord {
+            service_id: service_id.to_string(),
+            monday: weekday,
+            tuesday: weekday,
+            wednesday: weekday,
+            thursday: weekday,
+            friday: weekday,
+            saturday: 1 - weekday,
+            sunday: 1 - weekday,
+            start_date: 20210101,
+            end_date: 20211231,
+        }
+    }
+
+    fn exception(service_id: &str, date: u32, exception_type: usize) -> CalendarDateRecord {
+        CalendarDateRecord {
+            service_id: service_id.to_string(),
+            date,
+            exception_type,
+        }
+    }
+
+    #[test]
+    fn test_active_services() {
+        let services = |date, calendar_dates| {
+            let mut list: Vec<String> = active_services(
+                date,
+                vec![calendar("weekday", true), calendar("weekend", false)],
+                calendar_dates,
+            )
+            .unwrap()
+            .into_iter()
+            .collect();
+            list.sort();
+            list
+        };
+
+        // A Tuesday and a Saturday
+        assert_eq!(services("20210615", Vec::new()), vec!["weekday"]);
+        assert_eq!(services("20210619", Vec::new()), vec!["weekend"]);
+        // Outside the calendar's dates
+        assert!(services("20220111", Vec::new()).is_empty());
+        // A holiday on a Monday
+        assert_eq!(
+            services(
+                "20210614",
+                vec![
+                    exception("weekday", 20210614, 2),
+                    exception("holiday", 20210614, 1),
+                    exception("weekday", 20210615, 2),
+                ]
+            ),
+            vec!["holiday"]
+        );
+
+        // Some feeds only list dates, without calendar.txt
+        let services = active_services(
+            "20210614",
+            Vec::new(),
+            vec![exception("special", 20210614, 1)],
+        )
+        .unwrap();
+        assert!(services.contains("special"));
+        assert_eq!(services.len(), 1);
+
+        assert!(active_services("June 14", Vec::new(), Vec::new()).is_err());
+    }
+}
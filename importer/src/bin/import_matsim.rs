@@ -0,0 +1,416 @@
+//! Imports a scenario from a MATSim population file (`plans.xml`). Only each person's selected
+//! plan is used. Every leg between two activities becomes a trip; legs broken up by stage
+//! activities like `pt interaction` are merged into one trip.
+//!
+//! `--map`: The map to snap activities to.
+//! `--input`: The MATSim `plans.xml`.
+//! `--facilities`: A MATSim `facilities.xml`, needed if activities only refer to a facility.
+//! `--network`: A MATSim `network.xml`, needed if activities only refer to a link. The activity is
+//!              placed at the middle of the link.
+//! `--coords`: How to interpret x and y. `local` (the default) means meters from the southwest
+//!             corner of the map, like what `export_matsim` produces. `wgs84` means longitude and
+//!             latitude. Files in any other coordinate system must be reprojected first.
+//! `--scenario_name`: Defaults to `matsim`.
+
+#[macro_use]
+extern crate log;
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+
+use abstutil::{prettyprint_usize, CmdArgs, Timer};
+use geom::{Duration, LonLat, Time};
+use importer::MatsimCoordinates;
+use map_model::Map;
+use sim::{ExternalPerson, ExternalTrip, ExternalTripEndpoint, Scenario, TripMode, TripPurpose};
+
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let map = args.required("--map");
+    let input = args.required("--input");
+    let facilities_path = args.optional("--facilities");
+    let network_path = args.optional("--network");
+    let coords_arg = args.optional("--coords");
+    let scenario_name = args
+        .optional("--scenario_name")
+        .unwrap_or_else(|| "matsim".to_string());
+    args.done();
+
+    let mut timer = Timer::new("import MATSim plans");
+    let map = Map::load_synchronously(map, &mut timer);
+    let coords = MatsimCoordinates::from_arg(&map, coords_arg)?;
+
+    timer.start("read facilities and network");
+    let facilities = match facilities_path {
+        Some(path) => read_facilities(&path, &coords)?,
+        None => HashMap::new(),
+    };
+    let links = match network_path {
+        Some(path) => read_link_midpoints(&path, &coords)?,
+        None => HashMap::new(),
+    };
+    timer.stop("read facilities and network");
+
+    timer.start("parse plans");
+    let locations = Locations {
+        coords,
+        facilities,
+        links,
+    };
+    let text = std::fs::read_to_string(&input).map_err(|err| anyhow!("{}: {}", input, err))?;
+    let doc = roxmltree::Document::parse(&text)?;
+    let mut people = Vec::new();
+    let mut orig_num = 0;
+    for person in doc
+        .root_element()
+        .children()
+        .filter(|n| n.has_tag_name("person"))
+    {
+        orig_num += 1;
+        match parse_person(person, &locations) {
+            Ok(Some(p)) => {
+                people.push(p);
+            }
+            // Somebody staying at home all day
+            Ok(None) => {}
+            Err(err) => {
+                warn!(
+                    "Skipping person {}: {}",
+                    person.attribute("id").unwrap_or("?"),
+                    err
+                );
+            }
+        }
+    }
+    timer.stop("parse plans");
+
+    let mut s = Scenario::empty(&map, &scenario_name);
+    // Include all buses/trains
+    s.only_seed_buses = None;
+    let skip_problems = true;
+    s.people = ExternalPerson::import(&map, people, skip_problems)?;
+    // Always clean up people with no-op trips (going between the same buildings)
+    s = s.remove_weird_schedules();
+    println!(
+        "Imported {}/{} people",
+        prettyprint_usize(s.people.len()),
+        prettyprint_usize(orig_num)
+    );
+    s.save();
+
+    Ok(())
+}
+
+struct Locations<'a> {
+    coords: MatsimCoordinates<'a>,
+    facilities: HashMap<String, LonLat>,
+    links: HashMap<String, LonLat>,
+}
+
+impl<'a> Locations<'a> {
+    /// Coordinates take priority over facilities, then links
+    fn find(&self, act: roxmltree::Node) -> Result<LonLat> {
+        if let Some(pt) = self.coords.parse(act)? {
+            return Ok(pt);
+        }
+        if let Some(facility) = act.attribute("facility") {
+            return self
+                .facilities
+                .get(facility)
+                .cloned()
+                .ok_or_else(|| anyhow!("unknown facility {}; pass --facilities", facility));
+        }
+        if let Some(link) = act.attribute("link") {
+            return self
+                .links
+                .get(link)
+                .cloned()
+                .ok_or_else(|| anyhow!("unknown link {}; pass --network", link));
+        }
+        bail!("activity has no location")
+    }
+}
+
+fn read_facilities(path: &str, coords: &MatsimCoordinates) -> Result<HashMap<String, LonLat>> {
+    let text = std::fs::read_to_string(path).map_err(|err| anyhow!("{}: {}", path, err))?;
+    let doc = roxmltree::Document::parse(&text)?;
+    let mut facilities = HashMap::new();
+    for node in doc.descendants().filter(|n| n.has_tag_name("facility")) {
+        if let (Some(id), Some(pt)) = (node.attribute("id"), coords.parse(node)?) {
+            facilities.insert(id.to_string(), pt);
+        }
+    }
+    Ok(facilities)
+}
+
+fn read_link_midpoints(path: &str, coords: &MatsimCoordinates) -> Result<HashMap<String, LonLat>> {
+    let text = std::fs::read_to_string(path).map_err(|err| anyhow!("{}: {}", path, err))?;
+    let doc = roxmltree::Document::parse(&text)?;
+    let mut nodes = HashMap::new();
+    for node in doc.descendants().filter(|n| n.has_tag_name("node")) {
+        if let (Some(id), Some(pt)) = (node.attribute("id"), coords.parse(node)?) {
+            nodes.insert(id, pt);
+        }
+    }
+    let mut links = HashMap::new();
+    for link in doc.descendants().filter(|n| n.has_tag_name("link")) {
+        let id = link
+            .attribute("id")
+            .ok_or_else(|| anyhow!("link without an id"))?;
+        let from = link.attribute("from").and_then(|n| nodes.get(n));
+        let to = link.attribute("to").and_then(|n| nodes.get(n));
+        if let (Some(from), Some(to)) = (from, to) {
+            links.insert(
+                id.to_string(),
+                LonLat::new((from.x() + to.x()) / 2.0, (from.y() + to.y()) / 2.0),
+            );
+        }
+    }
+    Ok(links)
+}
+
+/// Returns None if the person doesn't go anywhere.
+fn parse_person(person: roxmltree::Node, locations: &Locations) -> Result<Option<ExternalPerson>> {
+    let plans: Vec<roxmltree::Node> = person
+        .children()
+        .filter(|n| n.has_tag_name("plan"))
+        .collect();
+    let plan = match plans
+        .iter()
+        .find(|p| p.attribute("selected") == Some("yes"))
+        .or_else(|| plans.first())
+    {
+        Some(plan) => plan,
+        None => {
+            return Ok(None);
+        }
+    };
+
+    let mut trips = Vec::new();
+    // The last activity that wasn't just a transfer
+    let mut origin: Option<LonLat> = None;
+    // When the current trip started
+    let mut departure: Option<Time> = None;
+    // The modes of every leg in the current trip
+    let mut modes: Vec<&str> = Vec::new();
+    // Keep track of the time, since activities may only say how long they last
+    let mut clock = Time::START_OF_DAY;
+    for node in plan.children().filter(|n| n.is_element()) {
+        match node.tag_name().name() {
+            "act" | "activity" => {
+                let act_type = node.attribute("type").unwrap_or("");
+                // Transfers between legs of one trip
+                if act_type.ends_with(" interaction") {
+                    continue;
+                }
+                let pos = locations.find(node)?;
+                if let Some(from) = origin {
+                    if modes.is_empty() {
+                        bail!("two activities in a row without a leg");
+                    }
+                    trips.push(ExternalTrip {
+                        departure: departure.unwrap_or(clock),
+                        origin: ExternalTripEndpoint::Position(from),
+                        destination: ExternalTripEndpoint::Position(pos),
+                        mode: main_mode(&modes)?,
+                        purpose: purpose(act_type),
+                    });
+                }
+                origin = Some(pos);
+                departure = None;
+                modes.clear();
+
+                if let Some(end) = node.attribute("end_time") {
+                    clock = Time::parse(end)?;
+                } else if let Some(dur) =
+                    node.attribute("max_dur").or_else(|| node.attribute("dur"))
+                {
+                    clock += Duration::parse(dur)?;
+                } else {
+                    // The last activity of the day
+                    break;
+                }
+            }
+            "leg" => {
+                if let Some(dep) = node.attribute("dep_time") {
+                    clock = Time::parse(dep)?;
+                }
+                // Only the first leg of a trip determines when it starts
+                if modes.is_empty() {
+                    departure = Some(clock);
+                }
+                if let Some(dt) = node.attribute("trav_time") {
+                    clock += Duration::parse(dt)?;
+                }
+                modes.push(node.attribute("mode").unwrap_or("walk"));
+            }
+            _ => {}
+        }
+    }
+
+    if trips.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(ExternalPerson {
+        trips,
+        behavior: None,
+    }))
+}
+
+/// Picks one mode for a trip made of many legs. Transit legs win, then anything besides walking.
+fn main_mode(modes: &[&str]) -> Result<TripMode> {
+    let mut result = TripMode::Walk;
+    for mode in modes {
+        let mode = match *mode {
+            "pt" | "bus" | "train" | "rail" | "tram" | "subway" => {
+                return Ok(TripMode::Transit);
+            }
+            "car" => TripMode::Drive,
+            "bike" | "bicycle" => TripMode::Bike,
+            // A car passenger, not a taxi
+            "ride" => TripMode::Drive,
+            "taxi" | "drt" => TripMode::RideHail,
+            "walk" | "transit_walk" | "non_network_walk" | "access_walk" | "egress_walk"
+            | "walk_main" => TripMode::Walk,
+            x => bail!("unknown mode {}", x),
+        };
+        if result == TripMode::Walk {
+            result = mode;
+        }
+    }
+    Ok(result)
+}
+
+/// MATSim activity types are freeform, but usually some variation of these. They sometimes have a
+/// typical duration at the end, like `work_3600`.
+fn purpose(act_type: &str) -> TripPurpose {
+    let act_type = act_type.to_lowercase();
+    let base = act_type
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .trim_end_matches('_');
+    match base {
+        "home" | "h" => TripPurpose::Home,
+        "work" | "w" => TripPurpose::Work,
+        "education" | "school" | "university" | "edu" | "e" => TripPurpose::School,
+        "shop" | "shopping" | "s" => TripPurpose::Shopping,
+        "leisure" | "l" => TripPurpose::Recreation,
+        "eat" | "meal" | "restaurant" => TripPurpose::Meal,
+        "social" | "visit" => TripPurpose::Social,
+        "medical" | "doctor" => TripPurpose::Medical,
+        "escort" | "pick_up" | "drop_off" => TripPurpose::Escort,
+        _ => TripPurpose::PersonalBusiness,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(xml: &str) -> Result<Option<ExternalPerson>> {
+        let map = Map::blank();
+        let locations = Locations {
+            coords: MatsimCoordinates::from_arg(&map, Some("wgs84".to_string()))?,
+            facilities: HashMap::new(),
+            links: vec![("7".to_string(), LonLat::new(-122.3, 47.6))]
+                .into_iter()
+                .collect(),
+        };
+        let doc = roxmltree::Document::parse(xml)?;
+        parse_person(doc.root_element(), &locations)
+    }
+
+    #[test]
+    fn test_parse_person() {
+        let person = parse(
+            r#"<person id="1">
+                <plan selected="no">
+                    <act type="home" x="0" y="0" end_time="01:00:00"/>
+                </plan>
+                <plan selected="yes">
+                    <act type="home" x="-122.31" y="47.61" end_time="08:00:00"/>
+                    <leg mode="walk" trav_time="00:05:00"/>
+                    <act type="pt interaction" x="-122.32" y="47.62" max_dur="00:00:00"/>
+                    <leg mode="pt" trav_time="00:20:00"/>
+                    <act type="work_3600" link="7" dur="09:00:00"/>
+                    <leg mode="ride"/>
+                    <act type="h" x="-122.31" y="47.61"/>
+                </plan>
+            </person>"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(person.trips.len(), 2);
+
+        let commute = &person.trips[0];
+        assert_eq!(commute.departure, Time::START_OF_DAY + Duration::hours(8));
+        assert!(matches!(
+            commute.origin,
+            ExternalTripEndpoint::Position(pt) if pt == LonLat::new(-122.31, 47.61)
+        ));
+        // The transfer is skipped, and the link's midpoint is used
+        assert!(matches!(
+            commute.destination,
+            ExternalTripEndpoint::Position(pt) if pt == LonLat::new(-122.3, 47.6)
+        ));
+        assert_eq!(commute.mode, TripMode::Transit);
+        assert!(matches!(commute.purpose, TripPurpose::Work));
+
+        // The clock advances through both legs and the activity's duration
+        let home = &person.trips[1];
+        assert_eq!(
+            home.departure,
+            Time::START_OF_DAY + Duration::hours(17) + Duration::minutes(25)
+        );
+        assert_eq!(home.mode, TripMode::Drive);
+        assert!(matches!(home.purpose, TripPurpose::Home));
+    }
+
+    #[test]
+    fn test_parse_person_without_trips() {
+        assert!(
+            parse(r#"<person id="1"><plan><act type="home" x="0" y="0"/></plan></person>"#)
+                .unwrap()
+                .is_none()
+        );
+        assert!(parse(r#"<person id="1"/>"#).unwrap().is_none());
+        assert!(parse(
+            r#"<person id="1"><plan>
+                <act type="home" x="0" y="0" end_time="08:00:00"/>
+                <leg mode="car"/>
+                <act type="work" link="unknown"/>
+            </plan></person>"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_main_mode() {
+        assert_eq!(main_mode(&["walk"]).unwrap(), TripMode::Walk);
+        assert_eq!(
+            main_mode(&["walk", "car", "walk"]).unwrap(),
+            TripMode::Drive
+        );
+        assert_eq!(
+            main_mode(&["access_walk", "bike", "pt", "egress_walk"]).unwrap(),
+            TripMode::Transit
+        );
+        assert_eq!(main_mode(&["ride"]).unwrap(), TripMode::Drive);
+        assert_eq!(main_mode(&["taxi"]).unwrap(), TripMode::RideHail);
+        assert_eq!(main_mode(&["drt"]).unwrap(), TripMode::RideHail);
+        // The first non-walking leg wins
+        assert_eq!(main_mode(&["bike", "car"]).unwrap(), TripMode::Bike);
+        assert!(main_mode(&["hovercraft"]).is_err());
+    }
+
+    #[test]
+    fn test_purpose() {
+        assert!(matches!(purpose("home"), TripPurpose::Home));
+        assert!(matches!(purpose("Work"), TripPurpose::Work));
+        assert!(matches!(purpose("work_3600"), TripPurpose::Work));
+        assert!(matches!(purpose("s"), TripPurpose::Shopping));
+        assert!(matches!(purpose("education"), TripPurpose::School));
+        assert!(matches!(purpose("leisure"), TripPurpose::Recreation));
+        assert!(matches!(purpose("other"), TripPurpose::PersonalBusiness));
+    }
+}
@@ -0,0 +1,330 @@
+//! Exports what transit actually did during a simulation, as recorded by
+//! `run_scenario --record_transit_log`. This writes a GTFS feed with the simulated arrival and
+//! departure times at every stop, plus an AVL-style log of every vehicle arriving at and
+//! departing from stops.
+//!
+//! `--map`: The map the simulation ran on. Edits don't change transit routes or stops, so use the
+//!          unedited map even if the run had edits, like new bus lanes.
+//! `--input`: The recorded `TransitLog`.
+//! `--output`: A directory to write `agency.txt`, `stops.txt`, `routes.txt`, `trips.txt`,
+//!             `stop_times.txt`, `calendar.txt`, and `avl.csv` to. Defaults to `transit_gtfs`.
+//! `--date`: The service day to write in `calendar.txt`, like `20210614`. Defaults to `20200101`.
+//!
+//! Each simulated vehicle becomes one GTFS trip. Trip IDs come from the route and scheduled start
+//! time, so the same trip has the same ID in runs with different edits. Vehicles still driving
+//! when the simulation ended have partial trips.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use abstutil::{prettyprint_usize, CmdArgs, Timer};
+use map_model::{BusStopID, Map, PathConstraints};
+use sim::{CarID, StopVisit, TransitLog};
+
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let map_path = args.required("--map");
+    let input = args.required("--input");
+    let output = args
+        .optional("--output")
+        .unwrap_or_else(|| "transit_gtfs".to_string());
+    let date = args
+        .optional("--date")
+        .unwrap_or_else(|| "20200101".to_string());
+    args.done();
+
+    let mut timer = Timer::new("export transit");
+    let map = Map::load_synchronously(map_path, &mut timer);
+    let log: TransitLog = abstio::must_read_object(input, &mut timer);
+    std::fs::create_dir_all(&output)?;
+
+    // BusStopIDs aren't meaningful outside of this map, so just number the stops
+    let stop_ids: BTreeMap<BusStopID, String> = map
+        .all_bus_stops()
+        .keys()
+        .enumerate()
+        .map(|(idx, id)| (*id, format!("stop{}", idx)))
+        .collect();
+
+    write_csv(
+        &output,
+        "agency.txt",
+        vec![AgencyRecord {
+            agency_id: "sim",
+            agency_name: format!("Simulated transit on {}", map.get_name().describe()),
+            agency_url: "https://abstreet.org",
+            agency_timezone: "UTC",
+        }],
+    )?;
+    write_csv(
+        &output,
+        "calendar.txt",
+        vec![CalendarRecord {
+            service_id: "sim",
+            monday: 1,
+            tuesday: 1,
+            wednesday: 1,
+            thursday: 1,
+            friday: 1,
+            saturday: 1,
+            sunday: 1,
+            start_date: date.clone(),
+            end_date: date,
+        }],
+    )?;
+    write_csv(
+        &output,
+        "stops.txt",
+        map.all_bus_stops()
+            .values()
+            .map(|stop| {
+                let gps = stop.sidewalk_pos.pt(&map).to_gps(map.get_gps_bounds());
+                StopRecord {
+                    stop_id: stop_ids[&stop.id].clone(),
+                    stop_name: stop.name.clone(),
+                    stop_lat: gps.y(),
+                    stop_lon: gps.x(),
+                }
+            })
+            .collect(),
+    )?;
+    write_csv(
+        &output,
+        "routes.txt",
+        map.all_bus_routes()
+            .iter()
+            .map(|route| RouteRecord {
+                route_id: route_id(route.id),
+                agency_id: "sim",
+                route_short_name: route.short_name.clone(),
+                route_long_name: route.full_name.clone(),
+                // Trains are light rail
+                route_type: if route.route_type == PathConstraints::Train {
+                    0
+                } else {
+                    3
+                },
+            })
+            .collect(),
+    )?;
+
+    // Group the log by vehicle, preserving the order each one served stops
+    let mut per_vehicle: BTreeMap<CarID, Vec<&StopVisit>> = BTreeMap::new();
+    for visit in &log.visits {
+        per_vehicle.entry(visit.vehicle).or_default().push(visit);
+    }
+
+    let mut trips = Vec::new();
+    let mut stop_times = Vec::new();
+    let mut avl = Vec::new();
+    for (vehicle, visits) in per_vehicle {
+        let trip_id = trip_id(vehicle, &visits);
+        trips.push(TripRecord {
+            route_id: route_id(visits[0].route),
+            service_id: "sim",
+            trip_id: trip_id.clone(),
+            trip_headsign: map.get_br(visits[0].route).full_name.clone(),
+        });
+        for visit in visits {
+            let stop_id = stop_ids[&visit.stop].clone();
+            stop_times.push(StopTimeRecord {
+                trip_id: trip_id.clone(),
+                arrival_time: visit.arrived.as_hhmmss(),
+                departure_time: visit.departed.unwrap_or(visit.arrived).as_hhmmss(),
+                stop_id: stop_id.clone(),
+                stop_sequence: visit.stop_sequence,
+            });
+
+            avl.push(AvlRecord {
+                time: visit.arrived.as_hhmmss(),
+                event: "arrival",
+                vehicle_id: vehicle.id,
+                trip_id: trip_id.clone(),
+                route_id: route_id(visit.route),
+                stop_id: stop_id.clone(),
+                stop_sequence: visit.stop_sequence,
+                scheduled_time: Some(visit.scheduled.as_hhmmss()),
+                delay_seconds: Some(
+                    (visit.arrived - visit.scheduled).inner_seconds().round() as isize
+                ),
+                boarded: None,
+                alighted: None,
+                load: None,
+            });
+            if let Some(departed) = visit.departed {
+                avl.push(AvlRecord {
+                    time: departed.as_hhmmss(),
+                    event: "departure",
+                    vehicle_id: vehicle.id,
+                    trip_id: trip_id.clone(),
+                    route_id: route_id(visit.route),
+                    stop_id,
+                    stop_sequence: visit.stop_sequence,
+                    scheduled_time: None,
+                    delay_seconds: None,
+                    boarded: Some(visit.boarded),
+                    alighted: Some(visit.alighted),
+                    load: visit.departing_load,
+                });
+            }
+        }
+    }
+    // Like a real AVL feed, the log is ordered by time
+    avl.sort_by(|a, b| {
+        (&a.time, a.vehicle_id, a.stop_sequence).cmp(&(&b.time, b.vehicle_id, b.stop_sequence))
+    });
+
+    println!(
+        "Writing {} trips and {} AVL events to {}",
+        prettyprint_usize(trips.len()),
+        prettyprint_usize(avl.len()),
+        output
+    );
+    write_csv(&output, "trips.txt", trips)?;
+    write_csv(&output, "stop_times.txt", stop_times)?;
+    write_csv(&output, "avl.csv", avl)?;
+    Ok(())
+}
+
+fn route_id(route: map_model::BusRouteID) -> String {
+    format!("route{}", route.0)
+}
+
+/// Vehicle IDs depend on everything else spawned in the simulation, so instead name the trip by
+/// its route and scheduled start.
+fn trip_id(vehicle: CarID, visits: &[&StopVisit]) -> String {
+    let first = visits[0];
+    if first.stop_sequence == 0 {
+        format!(
+            "{}_{}",
+            route_id(first.route),
+            first.scheduled.as_hhmmss().replace(':', "")
+        )
+    } else {
+        format!("{}_vehicle{}", route_id(first.route), vehicle.id)
+    }
+}
+
+fn write_csv<T: Serialize>(dir: &str, file: &str, records: Vec<T>) -> Result<()> {
+    let mut writer = csv::Writer::from_path(format!("{}/{}", dir, file))?;
+    for rec in records {
+        writer.serialize(rec)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct AgencyRecord {
+    agency_id: &'static str,
+    agency_name: String,
+    agency_url: &'static str,
+    agency_timezone: &'static str,
+}
+
+#[derive(Serialize)]
+struct CalendarRecord {
+    service_id: &'static str,
+    monday: usize,
+    tuesday: usize,
+    wednesday: usize,
+    thursday: usize,
+    friday: usize,
+    saturday: usize,
+    sunday: usize,
+    start_date: String,
+    end_date: String,
+}
+
+#[derive(Serialize)]
+struct StopRecord {
+    stop_id: String,
+    stop_name: String,
+    stop_lat: f64,
+    stop_lon: f64,
+}
+
+#[derive(Serialize)]
+struct RouteRecord {
+    route_id: String,
+    agency_id: &'static str,
+    route_short_name: String,
+    route_long_name: String,
+    route_type: usize,
+}
+
+#[derive(Serialize)]
+struct TripRecord {
+    route_id: String,
+    service_id: &'static str,
+    trip_id: String,
+    trip_headsign: String,
+}
+
+#[derive(Serialize)]
+struct StopTimeRecord {
+    trip_id: String,
+    arrival_time: String,
+    departure_time: String,
+    stop_id: String,
+    stop_sequence: usize,
+}
+
+/// One vehicle arriving at or departing from a stop
+#[derive(Serialize)]
+struct AvlRecord {
+    time: String,
+    /// "arrival" or "departure"
+    event: &'static str,
+    vehicle_id: usize,
+    trip_id: String,
+    route_id: String,
+    stop_id: String,
+    stop_sequence: usize,
+    /// Only for arrivals
+    scheduled_time: Option<String>,
+    /// Positive when the vehicle was late
+    delay_seconds: Option<isize>,
+    /// Only for departures
+    boarded: Option<usize>,
+    alighted: Option<usize>,
+    load: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use geom::{Duration, Time};
+    use map_model::BusRouteID;
+    use sim::VehicleType;
+
+    use super::*;
+
+    #[test]
+    fn test_trip_id() {
+        let vehicle = CarID {
+            id: 7,
+            vehicle_type: VehicleType::Bus,
+        };
+        let stop: BusStopID =
+            abstutil::from_json("{\"sidewalk\": 1, \"idx\": 0}".as_bytes()).unwrap();
+        let visit = |stop_sequence| StopVisit {
+            vehicle,
+            route: BusRouteID(3),
+            stop,
+            stop_sequence,
+            arrived: Time::START_OF_DAY + Duration::hours(8),
+            departed: None,
+            scheduled: Time::START_OF_DAY + Duration::hours(8) + Duration::minutes(2),
+            boarded: 0,
+            alighted: 0,
+            departing_load: None,
+        };
+
+        // Named by the scheduled start, regardless of the vehicle
+        assert_eq!(trip_id(vehicle, &[&visit(0), &visit(1)]), "route3_080200");
+        // Vehicles that started partway along the route don't have a scheduled start
+        assert_eq!(trip_id(vehicle, &[&visit(4)]), "route3_vehicle7");
+    }
+}
@@ -0,0 +1,5 @@
+//! Pieces shared by the importer's standalone tools in `src/bin`.
+
+pub use self::matsim::MatsimCoordinates;
+
+mod matsim;
@@ -0,0 +1,65 @@
+use anyhow::{bail, Result};
+
+use geom::{LonLat, Pt2D};
+use map_model::Map;
+
+/// How x and y are written in MATSim files. MATSim needs a projected coordinate system to measure
+/// distances itself, so the default is local coordinates: meters from the southwest corner of the
+/// map. Alternatively, longitude and latitude.
+pub struct MatsimCoordinates<'a> {
+    map: &'a Map,
+    local: bool,
+}
+
+impl<'a> MatsimCoordinates<'a> {
+    /// Understands the `--coords` flag: `local` (the default) or `wgs84`.
+    pub fn from_arg(map: &'a Map, arg: Option<String>) -> Result<MatsimCoordinates<'a>> {
+        let local = match arg.as_deref() {
+            None | Some("local") => true,
+            Some("wgs84") => false,
+            Some(x) => bail!("Unknown --coords {}", x),
+        };
+        Ok(MatsimCoordinates { map, local })
+    }
+
+    /// The value of the `coordinateReferenceSystem` attribute. Local coordinates don't have a
+    /// standard name.
+    pub fn crs(&self) -> Option<&'static str> {
+        if self.local {
+            None
+        } else {
+            Some("EPSG:4326")
+        }
+    }
+
+    /// Formatted as attributes
+    pub fn xy(&self, pt: Pt2D) -> String {
+        if self.local {
+            // The map's own coordinates have Y pointing down
+            format!(
+                "x=\"{:.2}\" y=\"{:.2}\"",
+                pt.x(),
+                self.map.get_bounds().max_y - pt.y()
+            )
+        } else {
+            let gps = pt.to_gps(self.map.get_gps_bounds());
+            format!("x=\"{:.7}\" y=\"{:.7}\"", gps.x(), gps.y())
+        }
+    }
+
+    pub fn to_gps(&self, x: f64, y: f64) -> LonLat {
+        if self.local {
+            Pt2D::new(x, self.map.get_bounds().max_y - y).to_gps(self.map.get_gps_bounds())
+        } else {
+            LonLat::new(x, y)
+        }
+    }
+
+    /// Reads the x and y attributes of an element, if it has them.
+    pub fn parse(&self, node: roxmltree::Node) -> Result<Option<LonLat>> {
+        match (node.attribute("x"), node.attribute("y")) {
+            (Some(x), Some(y)) => Ok(Some(self.to_gps(x.parse()?, y.parse()?))),
+            _ => Ok(None),
+        }
+    }
+}
@@ -188,8 +188,10 @@ fn clip_trips(map: &Map, popdat: &PopDat, huge_map: &Map, timer: &mut Timer) ->
                 &osm_id_to_bldg,
                 borders.for_mode(orig.mode),
                 match orig.mode {
-                    TripMode::Walk | TripMode::Transit => PathConstraints::Pedestrian,
-                    TripMode::Drive => PathConstraints::Car,
+                    TripMode::Walk | TripMode::Transit | TripMode::BikeShare => {
+                        PathConstraints::Pedestrian
+                    }
+                    TripMode::Drive | TripMode::RideHail => PathConstraints::Car,
                     TripMode::Bike => PathConstraints::Bike,
                 },
                 maybe_huge_map.as_ref(),
@@ -263,6 +265,7 @@ pub fn make_weekday_scenario(
         people.push(PersonSpec {
             orig_id: Some(orig_id),
             trips,
+            behavior: None,
         });
     }
     for maybe_t in individ_trips {
@@ -276,6 +279,11 @@ pub fn make_weekday_scenario(
         map_name: map.get_name().clone(),
         people,
         only_seed_buses: None,
+        deliveries: Vec::new(),
+        ride_hail_fleet: Vec::new(),
+        bike_share_docks: Vec::new(),
+        electric_vehicles: None,
+        chargers: Vec::new(),
     }
     .remove_weird_schedules()
 }
@@ -70,6 +70,10 @@ impl MapLoader {
             ctx,
             name.path(),
             Box::new(move |ctx, app, timer, map| {
+                // Maps written before some fields were added can still be loaded, more slowly
+                let map = map.or_else(|err| {
+                    map_model::Map::load_old_format(name.path(), timer).map_err(|_| err)
+                });
                 match map {
                     Ok(mut map) => {
                         // Kind of a hack. We can't generically call Map::new with the FileLoader.
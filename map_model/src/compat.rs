@@ -0,0 +1,122 @@
+//! Maps are bincoded without any versioning, so a map written before some field was added can't
+//! be deserialized directly anymore. The old layouts still in use are kept here, along with how to
+//! fill out what they're missing.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use abstio::MapName;
+use abstutil::{deserialize_btreemap, Timer};
+use geom::{Bounds, GPSBounds, Polygon, Time};
+
+use crate::make::{estimate_schedule, parking_policies_from_osm};
+use crate::pathfind::Pathfinder;
+use crate::{
+    osm, Area, Building, BusRoute, BusRouteID, BusStop, BusStopID, ControlStopSign,
+    ControlTrafficSignal, Intersection, IntersectionID, Lane, LaneID, Map, MapConfig, MapEdits,
+    ParkingLot, PathConstraints, Road, RoutingParams, Zone,
+};
+
+/// Before parking policies and transit schedules
+#[derive(Deserialize)]
+struct MapV0 {
+    roads: Vec<Road>,
+    lanes: BTreeMap<LaneID, Lane>,
+    lane_id_counter: usize,
+    intersections: Vec<Intersection>,
+    buildings: Vec<Building>,
+    #[serde(deserialize_with = "deserialize_btreemap")]
+    bus_stops: BTreeMap<BusStopID, BusStop>,
+    bus_routes: Vec<BusRouteV0>,
+    areas: Vec<Area>,
+    parking_lots: Vec<ParkingLot>,
+    boundary_polygon: Polygon,
+    stop_signs: BTreeMap<IntersectionID, ControlStopSign>,
+    traffic_signals: BTreeMap<IntersectionID, ControlTrafficSignal>,
+    gps_bounds: GPSBounds,
+    bounds: Bounds,
+    config: MapConfig,
+    pathfinder: Pathfinder,
+    pathfinder_dirty: bool,
+    routing_params: RoutingParams,
+    zones: Vec<Zone>,
+    name: MapName,
+}
+
+#[derive(Deserialize)]
+struct BusRouteV0 {
+    id: BusRouteID,
+    full_name: String,
+    short_name: String,
+    gtfs_trip_marker: Option<String>,
+    osm_rel_id: osm::RelationID,
+    stops: Vec<BusStopID>,
+    start: LaneID,
+    end_border: Option<LaneID>,
+    route_type: PathConstraints,
+    spawn_times: Vec<Time>,
+    orig_spawn_times: Vec<Time>,
+}
+
+impl Map {
+    /// Loads a map written in an older format. Parking policies come from the OSM tags that are
+    /// still in the map, so parking lots don't get any. Transit schedules are estimated assuming
+    /// no traffic. Like after deserializing directly, call `map_loaded_directly` after.
+    pub fn load_old_format(path: String, timer: &mut Timer) -> Result<Map> {
+        let old: MapV0 = abstio::maybe_read_binary(path, timer)?;
+        let mut map = Map {
+            roads: old.roads,
+            lanes: old.lanes,
+            lane_id_counter: old.lane_id_counter,
+            intersections: old.intersections,
+            buildings: old.buildings,
+            bus_stops: old.bus_stops,
+            bus_routes: Vec::new(),
+            areas: old.areas,
+            parking_lots: old.parking_lots,
+            parking_policies: BTreeMap::new(),
+            orig_parking_policies: BTreeMap::new(),
+            boundary_polygon: old.boundary_polygon,
+            stop_signs: old.stop_signs,
+            traffic_signals: old.traffic_signals,
+            gps_bounds: old.gps_bounds,
+            bounds: old.bounds,
+            config: old.config,
+            pathfinder: old.pathfinder,
+            pathfinder_dirty: old.pathfinder_dirty,
+            routing_params: old.routing_params,
+            travel_times: None,
+            zones: old.zones,
+            name: old.name,
+            edits: MapEdits::new(),
+        };
+
+        map.parking_policies = parking_policies_from_osm(&map, &BTreeMap::new());
+        map.orig_parking_policies = map.parking_policies.clone();
+
+        for r in old.bus_routes {
+            let mut route = BusRoute {
+                id: r.id,
+                full_name: r.full_name,
+                short_name: r.short_name,
+                gtfs_trip_marker: r.gtfs_trip_marker,
+                osm_rel_id: r.osm_rel_id,
+                stops: r.stops,
+                start: r.start,
+                end_border: r.end_border,
+                route_type: r.route_type,
+                spawn_times: r.spawn_times,
+                orig_spawn_times: r.orig_spawn_times,
+                stop_schedule: Vec::new(),
+                timepoints: BTreeSet::new(),
+            };
+            estimate_schedule(&map, &mut route)
+                .map_err(|err| anyhow!("can't schedule {}: {}", route.full_name, err))?;
+            map.bus_routes.push(route);
+        }
+
+        Ok(map)
+    }
+}
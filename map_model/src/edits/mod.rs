@@ -10,13 +10,14 @@ use serde::{Deserialize, Serialize};
 use abstutil::Timer;
 use geom::{Distance, HashablePt2D, Line, Speed, Time};
 
-pub use self::perma::PermanentMapEdits;
+pub use self::perma::{PermanentEditCmd, PermanentMapEdits, PermanentParkingPlace};
 use crate::make::initial::lane_specs::get_lane_specs_ltr;
 use crate::make::{match_points_to_lanes, snap_driveway, trim_path};
 use crate::{
     connectivity, AccessRestrictions, BuildingID, BusRouteID, ControlStopSign,
     ControlTrafficSignal, IntersectionID, IntersectionType, LaneID, LaneSpec, Map, MapConfig,
-    ParkingLotID, PathConstraints, Pathfinder, Road, RoadID, TurnID, Zone,
+    ParkingLotID, ParkingPlace, ParkingPolicy, PathConstraints, Pathfinder, Road, RoadID, TurnID,
+    Zone,
 };
 
 mod compat;
@@ -39,6 +40,7 @@ pub struct MapEdits {
     pub changed_roads: BTreeSet<RoadID>,
     pub original_intersections: BTreeMap<IntersectionID, EditIntersection>,
     pub changed_routes: BTreeSet<BusRouteID>,
+    pub changed_parking_policies: BTreeSet<ParkingPlace>,
 
     /// Some edits are included in the game by default, in data/system/proposals, as "community
     /// proposals." They require a description and may have a link to a write-up.
@@ -131,6 +133,12 @@ pub enum EditCmd {
         old: Vec<Time>,
         new: Vec<Time>,
     },
+    /// None means free parking, with no time limit
+    ChangeParkingPolicy {
+        place: ParkingPlace,
+        old: Option<ParkingPolicy>,
+        new: Option<ParkingPolicy>,
+    },
 }
 
 pub struct EditEffects {
@@ -155,6 +163,7 @@ impl MapEdits {
             changed_roads: BTreeSet::new(),
             original_intersections: BTreeMap::new(),
             changed_routes: BTreeSet::new(),
+            changed_parking_policies: BTreeSet::new(),
         }
     }
 
@@ -188,6 +197,7 @@ impl MapEdits {
         self.changed_roads.clear();
         self.original_intersections.clear();
         self.changed_routes.clear();
+        self.changed_parking_policies.clear();
 
         for cmd in &self.commands {
             match cmd {
@@ -202,6 +212,9 @@ impl MapEdits {
                 EditCmd::ChangeRouteSchedule { id, .. } => {
                     self.changed_routes.insert(*id);
                 }
+                EditCmd::ChangeParkingPolicy { place, .. } => {
+                    self.changed_parking_policies.insert(*place);
+                }
             }
         }
 
@@ -214,6 +227,8 @@ impl MapEdits {
             let r = map.get_br(*br);
             r.spawn_times != r.orig_spawn_times
         });
+        self.changed_parking_policies
+            .retain(|place| map.get_parking_policy(*place) != map.orig_parking_policies.get(place));
     }
 
     /// Assumes update_derived has been called.
@@ -240,6 +255,13 @@ impl MapEdits {
                 old: r.orig_spawn_times.clone(),
             });
         }
+        for place in &self.changed_parking_policies {
+            self.commands.push(EditCmd::ChangeParkingPolicy {
+                place: *place,
+                old: map.orig_parking_policies.get(place).cloned(),
+                new: map.get_parking_policy(*place).cloned(),
+            });
+        }
     }
 
     /// Pick apart changed_roads and figure out if an entire road was edited, or just a few lanes.
@@ -298,6 +320,10 @@ impl EditCmd {
             EditCmd::ChangeRouteSchedule { id, .. } => {
                 format!("reschedule route {}", map.get_br(*id).short_name)
             }
+            EditCmd::ChangeParkingPolicy { place, new, .. } => {
+                details = describe_parking_policy(new);
+                format!("parking policy for {}", place)
+            }
         };
         (summary, details)
     }
@@ -374,6 +400,19 @@ impl EditCmd {
             EditCmd::ChangeRouteSchedule { id, new, .. } => {
                 map.bus_routes[id.0].spawn_times = new.clone();
             }
+            EditCmd::ChangeParkingPolicy { place, new, .. } => match new {
+                Some(policy) => match policy.clone().validate() {
+                    Ok(policy) => {
+                        map.parking_policies.insert(*place, policy);
+                    }
+                    Err(err) => {
+                        warn!("Not changing the parking policy for {}: {}", place, err);
+                    }
+                },
+                None => {
+                    map.parking_policies.remove(place);
+                }
+            },
         }
     }
 
@@ -394,8 +433,35 @@ impl EditCmd {
                 old: new,
                 new: old,
             },
+            EditCmd::ChangeParkingPolicy { place, old, new } => EditCmd::ChangeParkingPolicy {
+                place,
+                old: new,
+                new: old,
+            },
+        }
+    }
+}
+
+fn describe_parking_policy(policy: &Option<ParkingPolicy>) -> Vec<String> {
+    let policy = match policy {
+        Some(policy) => policy,
+        None => {
+            return vec!["free, with no time limit".to_string()];
+        }
+    };
+    let mut details = vec![format!("zone {}", policy.zone)];
+    for rate in &policy.rates {
+        let mut line = format!(
+            "from {}, ${:.2} per hour",
+            rate.start.ampm_tostring(),
+            rate.hourly_price
+        );
+        if let Some(max) = rate.max_stay {
+            line = format!("{}, {} limit", line, max);
         }
+        details.push(line);
     }
+    details
 }
 
 // This clobbers previously set traffic signal overrides.
@@ -737,6 +803,18 @@ impl Map {
         EditCmd::ChangeRoad { r, old, new }
     }
 
+    pub fn edit_parking_policy_cmd(
+        &self,
+        place: ParkingPlace,
+        new: Option<ParkingPolicy>,
+    ) -> Result<EditCmd> {
+        Ok(EditCmd::ChangeParkingPolicy {
+            place,
+            old: self.get_parking_policy(place).cloned(),
+            new: new.map(|policy| policy.validate()).transpose()?,
+        })
+    }
+
     /// Panics on borders
     pub fn get_i_edit(&self, i: IntersectionID) -> EditIntersection {
         match self.get_i(i).intersection_type {
@@ -875,6 +953,10 @@ impl Map {
         let mut pathfinder = std::mem::replace(&mut self.pathfinder, Pathfinder::Dijkstra);
         pathfinder.apply_edits(self, timer);
         self.pathfinder = pathfinder;
+        if let Some(mut travel_times) = self.travel_times.take() {
+            travel_times.recalculate(self, timer);
+            self.travel_times = Some(travel_times);
+        }
 
         // Also recompute blackholes. This is cheap enough to do from scratch.
         timer.start("recompute blackholes");
@@ -9,7 +9,7 @@ use geom::Time;
 
 use crate::edits::{EditCmd, EditIntersection, EditRoad, MapEdits};
 use crate::raw::OriginalRoad;
-use crate::{osm, ControlStopSign, IntersectionID, Map};
+use crate::{osm, ControlStopSign, IntersectionID, Map, ParkingPlace, ParkingPolicy};
 
 /// MapEdits are converted to this before serializing. Referencing things like LaneID in a Map won't
 /// work if the basemap is rebuilt from new OSM data, so instead we use stabler OSM IDs that're less
@@ -61,6 +61,18 @@ pub enum PermanentEditCmd {
         old: Vec<Time>,
         new: Vec<Time>,
     },
+    ChangeParkingPolicy {
+        place: PermanentParkingPlace,
+        old: Option<ParkingPolicy>,
+        new: Option<ParkingPolicy>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum PermanentParkingPlace {
+    Road(OriginalRoad),
+    Building(osm::OsmID),
+    ParkingLot(osm::OsmID),
 }
 
 impl EditCmd {
@@ -83,6 +95,21 @@ impl EditCmd {
                     new: new.clone(),
                 }
             }
+            EditCmd::ChangeParkingPolicy { place, old, new } => {
+                PermanentEditCmd::ChangeParkingPolicy {
+                    place: match place {
+                        ParkingPlace::Road(r) => PermanentParkingPlace::Road(map.get_r(*r).orig_id),
+                        ParkingPlace::Building(b) => {
+                            PermanentParkingPlace::Building(map.get_b(*b).orig_id)
+                        }
+                        ParkingPlace::ParkingLot(pl) => {
+                            PermanentParkingPlace::ParkingLot(map.get_pl(*pl).osm_id)
+                        }
+                    },
+                    old: old.clone(),
+                    new: new.clone(),
+                }
+            }
         }
     }
 }
@@ -127,6 +154,28 @@ impl PermanentEditCmd {
                     .ok_or_else(|| anyhow!("can't find {}", osm_rel_id))?;
                 Ok(EditCmd::ChangeRouteSchedule { id, old, new })
             }
+            PermanentEditCmd::ChangeParkingPolicy { place, old, new } => {
+                let place = match place {
+                    PermanentParkingPlace::Road(r) => ParkingPlace::Road(map.find_r_by_osm_id(r)?),
+                    PermanentParkingPlace::Building(id) => ParkingPlace::Building(
+                        map.find_b_by_osm_id(id)
+                            .ok_or_else(|| anyhow!("can't find building {}", id))?,
+                    ),
+                    PermanentParkingPlace::ParkingLot(id) => ParkingPlace::ParkingLot(
+                        map.find_pl_by_osm_id(id)
+                            .ok_or_else(|| anyhow!("can't find parking lot {}", id))?,
+                    ),
+                };
+                let old = old
+                    .map(|policy| policy.validate())
+                    .transpose()
+                    .with_context(|| format!("old ChangeParkingPolicy of {} invalid", place))?;
+                let new = new
+                    .map(|policy| policy.validate())
+                    .transpose()
+                    .with_context(|| format!("new ChangeParkingPolicy of {} invalid", place))?;
+                Ok(EditCmd::ChangeParkingPolicy { place, old, new })
+            }
         }
     }
 }
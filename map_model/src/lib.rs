@@ -38,9 +38,10 @@ use geom::{Bounds, GPSBounds, Polygon};
 
 pub use crate::city::City;
 pub use crate::edits::{
-    EditCmd, EditEffects, EditIntersection, EditRoad, MapEdits, PermanentMapEdits,
+    EditCmd, EditEffects, EditIntersection, EditRoad, MapEdits, PermanentEditCmd,
+    PermanentMapEdits, PermanentParkingPlace,
 };
-pub use crate::make::RawToMapOptions;
+pub use crate::make::{RawToMapOptions, ScheduledRoute};
 pub use crate::map::{DrivingSide, MapConfig};
 pub use crate::objects::area::{Area, AreaID, AreaType};
 pub use crate::objects::building::{
@@ -53,6 +54,7 @@ pub use crate::objects::lane::{
     SIDEWALK_THICKNESS,
 };
 pub use crate::objects::parking_lot::{ParkingLot, ParkingLotID};
+pub use crate::objects::parking_policy::{ParkingPlace, ParkingPolicy, ParkingRate};
 pub use crate::objects::road::{DirectedRoadID, Direction, Road, RoadID};
 pub use crate::objects::stop_signs::{ControlStopSign, RoadWithStopSign};
 pub use crate::objects::traffic_signals::{ControlTrafficSignal, Stage, StageType};
@@ -61,13 +63,15 @@ pub use crate::objects::turn::{
 };
 pub use crate::objects::zone::{AccessRestrictions, Zone};
 pub use crate::pathfind::uber_turns::{IntersectionCluster, UberTurn};
-use crate::pathfind::Pathfinder;
 pub use crate::pathfind::{
     Path, PathConstraints, PathRequest, PathStep, PathStepV2, PathV2, RoutingParams,
+    TravelTimeProfile,
 };
+use crate::pathfind::{Pathfinder, TimeDependentPathfinder};
 pub use crate::traversable::{Position, Traversable, MAX_BIKE_SPEED, MAX_WALKING_SPEED};
 
 mod city;
+mod compat;
 pub mod connectivity;
 mod edits;
 mod make;
@@ -80,7 +84,12 @@ mod traversable;
 
 // The map used by the simulation and UI. This struct is declared here so that the rest of the
 // crate can reach into private fields.
-#[derive(Serialize, Deserialize)]
+//
+// The binary format isn't versioned; it's tied to the current code. After adding a field here or
+// to anything nested, either regenerate every map with data/regen.sh and upload the result for the
+// updater, or keep the previous layout loadable in `compat`. #[serde(default)] doesn't help,
+// because bincode ignores it.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Map {
     roads: Vec<Road>,
     lanes: BTreeMap<LaneID, Lane>,
@@ -95,6 +104,17 @@ pub struct Map {
     bus_routes: Vec<BusRoute>,
     areas: Vec<Area>,
     parking_lots: Vec<ParkingLot>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    parking_policies: BTreeMap<ParkingPlace, ParkingPolicy>,
+    /// Before any edits, from OSM
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    orig_parking_policies: BTreeMap<ParkingPlace, ParkingPolicy>,
     boundary_polygon: Polygon,
 
     // Note that border nodes belong in neither!
@@ -108,6 +128,8 @@ pub struct Map {
     pathfinder: Pathfinder,
     pathfinder_dirty: bool,
     routing_params: RoutingParams,
+    #[serde(skip_serializing, skip_deserializing)]
+    travel_times: Option<TimeDependentPathfinder>,
     // Not the source of truth, just cached.
     zones: Vec<Zone>,
 
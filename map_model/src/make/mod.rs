@@ -10,12 +10,15 @@ use geom::{
 };
 
 pub use self::parking_lots::snap_driveway;
+pub(crate) use self::transit::estimate_schedule;
+pub use self::transit::{add_scheduled_routes, ScheduledRoute};
 use crate::pathfind::Pathfinder;
 use crate::raw::{OriginalRoad, RawMap};
 use crate::{
     connectivity, osm, AccessRestrictions, Area, AreaID, AreaType, ControlStopSign,
-    ControlTrafficSignal, Intersection, IntersectionID, IntersectionType, Lane, LaneID, Map,
-    MapEdits, Movement, PathConstraints, Position, Road, RoadID, RoutingParams, Zone,
+    ControlTrafficSignal, Intersection, IntersectionID, IntersectionType, Lane, LaneID, LaneType,
+    Map, MapEdits, Movement, OffstreetParking, ParkingPlace, ParkingPolicy, PathConstraints,
+    Position, Road, RoadID, RoutingParams, Zone,
 };
 
 mod bridges;
@@ -83,6 +86,8 @@ impl Map {
             bus_routes: Vec::new(),
             areas: Vec::new(),
             parking_lots: Vec::new(),
+            parking_policies: BTreeMap::new(),
+            orig_parking_policies: BTreeMap::new(),
             zones: Vec::new(),
             boundary_polygon: raw.boundary_polygon.clone(),
             stop_signs: BTreeMap::new(),
@@ -93,6 +98,7 @@ impl Map {
             pathfinder: Pathfinder::Dijkstra,
             pathfinder_dirty: false,
             routing_params: RoutingParams::default(),
+            travel_times: None,
             name: raw.name.clone(),
             edits: MapEdits::new(),
         };
@@ -268,6 +274,14 @@ impl Map {
             timer,
         );
 
+        let lot_tags: BTreeMap<osm::OsmID, &Tags> = raw
+            .parking_lots
+            .iter()
+            .map(|pl| (pl.osm_id, &pl.osm_tags))
+            .collect();
+        map.parking_policies = parking_policies_from_osm(&map, &lot_tags);
+        map.orig_parking_policies = map.parking_policies.clone();
+
         map.zones = Zone::make_all(&map);
 
         // Create medians first, so they wind up rendering underneath areas from OSM. Sometimes
@@ -453,3 +467,48 @@ pub fn trim_path(poly: &Polygon, path: Line) -> Line {
     // Just give up
     path
 }
+
+/// Prices and time limits, whenever OSM has them. The map doesn't keep tags for parking lots, so
+/// those are passed in.
+pub(crate) fn parking_policies_from_osm(
+    map: &Map,
+    lot_tags: &BTreeMap<osm::OsmID, &Tags>,
+) -> BTreeMap<ParkingPlace, ParkingPolicy> {
+    let mut policies = BTreeMap::new();
+    for r in &map.roads {
+        if r.lanes_ltr
+            .iter()
+            .any(|(_, _, lt)| *lt == LaneType::Parking)
+        {
+            if let Some(policy) = ParkingPolicy::from_osm(
+                &r.osm_tags,
+                &[
+                    "parking:both:",
+                    "parking:left:",
+                    "parking:right:",
+                    "parking:condition:both:",
+                    "parking:condition:left:",
+                    "parking:condition:right:",
+                ],
+            ) {
+                policies.insert(ParkingPlace::Road(r.id), policy);
+            }
+        }
+    }
+    for b in &map.buildings {
+        if let OffstreetParking::PublicGarage(_, _) = b.parking {
+            if let Some(policy) = ParkingPolicy::from_osm(&b.osm_tags, &[""]) {
+                policies.insert(ParkingPlace::Building(b.id), policy);
+            }
+        }
+    }
+    for pl in &map.parking_lots {
+        if let Some(policy) = lot_tags
+            .get(&pl.osm_id)
+            .and_then(|tags| ParkingPolicy::from_osm(tags, &[""]))
+        {
+            policies.insert(ParkingPlace::ParkingLot(pl.id), policy);
+        }
+    }
+    policies
+}
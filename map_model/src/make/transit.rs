@@ -1,16 +1,25 @@
-use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 
 use anyhow::Result;
 
 use abstutil::Timer;
-use geom::{Distance, Duration, FindClosest, HashablePt2D, Time};
+use geom::{Angle, Distance, Duration, FindClosest, HashablePt2D, PolyLine, Pt2D, Time};
 
 use crate::make::match_points_to_lanes;
 use crate::raw::{RawBusRoute, RawBusStop};
 use crate::{
-    BusRoute, BusRouteID, BusStop, BusStopID, LaneID, LaneType, Map, PathConstraints, Position,
+    osm, BusRoute, BusRouteID, BusStop, BusStopID, LaneID, LaneType, Map, PathConstraints, Position,
 };
 
+/// Allowed for boarding and alighting at each stop when estimating the schedule
+const SCHEDULED_DWELL_TIME: Duration = Duration::const_seconds(20.0);
+const TIMEPOINT_EVERY_N_STOPS: usize = 5;
+/// How far from a lane a stop from some other source may be
+const MAX_STOP_DIST: Distance = Distance::const_meters(30.0);
+/// Warn when the route between two stops is this much longer than the given shape
+const SHAPE_DETOUR_FACTOR: f64 = 1.5;
+const SHAPE_DETOUR_SLACK: Distance = Distance::const_meters(100.0);
+
 /// Construct the final model of bus/train stops and routes. This is quite broken currently, so not
 /// going to describe how it works.
 pub fn make_stops_and_routes(map: &mut Map, raw_routes: &[RawBusRoute], timer: &mut Timer) {
@@ -25,7 +34,13 @@ pub fn make_stops_and_routes(map: &mut Map, raw_routes: &[RawBusRoute], timer: &
         }
     }
 
-    // Remove orphaned bus stops. This messes up the BusStopID indexing.
+    remove_orphaned_stops(map);
+
+    timer.stop("make transit stops and routes");
+}
+
+/// Remove bus stops no route serves. This messes up the BusStopID indexing.
+fn remove_orphaned_stops(map: &mut Map) {
     for id in map
         .bus_stops
         .keys()
@@ -40,8 +55,6 @@ pub fn make_stops_and_routes(map: &mut Map, raw_routes: &[RawBusRoute], timer: &
             .bus_stops
             .remove(&id);
     }
-
-    timer.stop("make transit stops and routes");
 }
 
 fn make_route(
@@ -60,33 +73,14 @@ fn make_route(
     for stop in &r.stops {
         match matcher.lookup(route_type, stop, map) {
             Ok((sidewalk_pos, driving_pos)) => {
-                // Create a new bus stop if needed.
-                let stop_id = if let Some(id) = pt_to_stop.get(&(sidewalk_pos, driving_pos)) {
-                    *id
-                } else {
-                    let id = BusStopID {
-                        sidewalk: sidewalk_pos.lane(),
-                        idx: map.get_l(sidewalk_pos.lane()).bus_stops.len(),
-                    };
-                    pt_to_stop.insert((sidewalk_pos, driving_pos), id);
-                    map.lanes
-                        .get_mut(&sidewalk_pos.lane())
-                        .unwrap()
-                        .bus_stops
-                        .insert(id);
-                    map.bus_stops.insert(
-                        id,
-                        BusStop {
-                            id,
-                            name: stop.name.clone(),
-                            driving_pos,
-                            sidewalk_pos,
-                            is_train_stop: !r.is_bus,
-                        },
-                    );
-                    id
-                };
-                stops.push(stop_id);
+                stops.push(get_or_create_stop(
+                    map,
+                    pt_to_stop,
+                    &stop.name,
+                    sidewalk_pos,
+                    driving_pos,
+                    !r.is_bus,
+                ));
             }
             Err(err) => {
                 bail!("couldn't match stop {}: {}", stop.name, err);
@@ -137,7 +131,7 @@ fn make_route(
         }
     }
 
-    let route = BusRoute {
+    let mut route = BusRoute {
         id: BusRouteID(map.bus_routes.len()),
         full_name: r.full_name.clone(),
         short_name: r.short_name.clone(),
@@ -149,8 +143,19 @@ fn make_route(
         end_border,
         spawn_times: default_spawn_times(),
         orig_spawn_times: default_spawn_times(),
+        stop_schedule: Vec::new(),
+        timepoints: BTreeSet::new(),
     };
 
+    estimate_schedule(map, &mut route)?;
+
+    map.bus_routes.push(route);
+    Ok(())
+}
+
+/// Fills out a route's schedule by assuming vehicles drive between stops with no traffic, and
+/// picks some timepoints. Fails if the route isn't connected.
+pub(crate) fn estimate_schedule(map: &Map, route: &mut BusRoute) -> Result<()> {
     let mut debug_route = "All parts of the route:".to_string();
     debug_route = format!("{}\nStart at {}", debug_route, route.start);
     for (idx, bs) in route.stops.iter().enumerate() {
@@ -167,8 +172,10 @@ fn make_route(
         debug_route = format!("{}\nEnd at {}", debug_route, l);
     }
 
-    // Make sure the route is connected
-    for req in route.all_steps(map) {
+    // Make sure the route is connected, and estimate the schedule with no traffic
+    route.stop_schedule.clear();
+    let mut scheduled = Duration::ZERO;
+    for (idx, req) in route.all_steps(map).into_iter().enumerate() {
         if req.start.lane() == req.end.lane() && req.start.dist_along() > req.end.dist_along() {
             bail!(
                 "Two stops seemingly out of order somewhere on {}",
@@ -176,21 +183,329 @@ fn make_route(
             );
         }
 
-        if let Err(err) = map.pathfind(req.clone()) {
-            bail!(
-                "No path between stop on {} and {}: {}. {}",
+        match map.pathfind(req.clone()) {
+            Ok(path) => {
+                // The last step might go from the last stop to a border
+                if idx < route.stops.len() {
+                    if idx > 0 {
+                        scheduled += SCHEDULED_DWELL_TIME;
+                    }
+                    scheduled += path.estimate_duration(map, route.route_type, None);
+                    route.stop_schedule.push(scheduled);
+                }
+            }
+            Err(err) => {
+                bail!(
+                    "No path between stop on {} and {}: {}. {}",
+                    map.get_parent(req.start.lane()).orig_id,
+                    map.get_parent(req.end.lane()).orig_id,
+                    err,
+                    debug_route
+                );
+            }
+        }
+    }
+    // Like a printed timetable, only some stops are timepoints
+    route.timepoints = (0..route.stops.len())
+        .step_by(TIMEPOINT_EVERY_N_STOPS)
+        .collect();
+    Ok(())
+}
+
+/// A transit route from outside OpenStreetMap, like a GTFS feed. The stops are just points, not
+/// matched to the map yet.
+pub struct ScheduledRoute {
+    pub full_name: String,
+    pub short_name: String,
+    /// Identifies the route in the original data
+    pub gtfs_trip_marker: Option<String>,
+    /// If not, light rail
+    pub is_bus: bool,
+    /// The name and location of every stop, in order
+    pub stops: Vec<(String, Pt2D)>,
+    /// The path vehicles follow, if known. This decides which side of the road each stop is on.
+    pub shape: Option<Vec<Pt2D>>,
+    /// When each vehicle of the day departs the first stop
+    pub departures: Vec<Time>,
+    /// For each stop, how long after departing the first stop a vehicle is scheduled to arrive
+    pub stop_offsets: Vec<Duration>,
+    /// Indices into stops where vehicles may be held. If empty, some are picked automatically.
+    pub timepoints: BTreeSet<usize>,
+}
+
+/// Matches routes from some other source to the map and adds them, keeping their real schedules.
+/// If `replace_existing`, every route and stop from OpenStreetMap is removed first. Returns how
+/// many routes were added.
+pub fn add_scheduled_routes(
+    map: &mut Map,
+    routes: Vec<ScheduledRoute>,
+    replace_existing: bool,
+    timer: &mut Timer,
+) -> usize {
+    if replace_existing {
+        map.bus_routes.clear();
+        map.bus_stops.clear();
+        for l in map.lanes.values_mut() {
+            l.bus_stops.clear();
+        }
+    }
+    let mut pt_to_stop: BTreeMap<(Position, Position), BusStopID> = map
+        .bus_stops
+        .values()
+        .map(|bs| ((bs.sidewalk_pos, bs.driving_pos), bs.id))
+        .collect();
+
+    // There's no relation in OSM, so use a negative placeholder. Importing more than once must not
+    // reuse one, or edits to a route could apply to another.
+    let first_placeholder = map
+        .bus_routes
+        .iter()
+        .map(|br| br.osm_rel_id.0)
+        .min()
+        .unwrap_or(0)
+        .min(0)
+        - 1;
+
+    let mut added = 0;
+    timer.start_iter("match scheduled transit routes", routes.len());
+    for (idx, r) in routes.into_iter().enumerate() {
+        timer.next();
+        let name = r.full_name.clone();
+        let osm_rel_id = osm::RelationID(first_placeholder - idx as i64);
+        match make_scheduled_route(map, r, osm_rel_id, &mut pt_to_stop) {
+            Ok(()) => {
+                added += 1;
+            }
+            Err(err) => {
+                warn!("Skipping route {}: {}", name, err);
+            }
+        }
+    }
+    // Skipped routes may have left behind some stops
+    remove_orphaned_stops(map);
+    added
+}
+
+fn make_scheduled_route(
+    map: &mut Map,
+    r: ScheduledRoute,
+    osm_rel_id: osm::RelationID,
+    pt_to_stop: &mut BTreeMap<(Position, Position), BusStopID>,
+) -> Result<()> {
+    if r.stops.len() < 2 {
+        bail!("only {} stops", r.stops.len());
+    }
+    if r.departures.is_empty() {
+        bail!("no departures");
+    }
+    let route_type = if r.is_bus {
+        PathConstraints::Bus
+    } else {
+        PathConstraints::Train
+    };
+    let shape = r.shape.and_then(|pts| PolyLine::deduping_new(pts).ok());
+
+    let mut stops = Vec::new();
+    for (stop_idx, (name, pt)) in r.stops.iter().enumerate() {
+        // Which way is the vehicle heading here?
+        let heading = if let Some(ref shape) = shape {
+            shape
+                .dist_along_of_point(shape.project_pt(*pt))
+                .map(|(_, angle)| angle)
+        } else {
+            None
+        };
+        let heading = heading.unwrap_or_else(|| {
+            if stop_idx == r.stops.len() - 1 {
+                r.stops[stop_idx - 1].1.angle_to(*pt)
+            } else {
+                pt.angle_to(r.stops[stop_idx + 1].1)
+            }
+        });
+        let (sidewalk_pos, driving_pos) = match_scheduled_stop(map, route_type, *pt, heading)
+            .map_err(|err| anyhow!("couldn't match stop {}: {}", name, err))?;
+        let id = get_or_create_stop(map, pt_to_stop, name, sidewalk_pos, driving_pos, !r.is_bus);
+        // Two stops close together might match the same place
+        if stops.last() == Some(&id) {
+            bail!("{} matched to the same place as the previous stop", name);
+        }
+        stops.push(id);
+    }
+
+    let start = pick_start_lane(map.get_bs(stops[0]).driving_pos, route_type, map)?;
+    let mut route = BusRoute {
+        id: BusRouteID(map.bus_routes.len()),
+        full_name: r.full_name,
+        short_name: r.short_name,
+        osm_rel_id,
+        gtfs_trip_marker: r.gtfs_trip_marker,
+        stops,
+        route_type,
+        start,
+        end_border: None,
+        spawn_times: Vec::new(),
+        orig_spawn_times: Vec::new(),
+        stop_schedule: Vec::new(),
+        timepoints: r.timepoints,
+    };
+
+    // Make sure the route is connected, and follows the shape. Vehicles spawn a bit before the
+    // first stop, so account for that time too.
+    let mut lead_time = Duration::ZERO;
+    for (step_idx, req) in route.all_steps(map).into_iter().enumerate() {
+        let path = map.pathfind(req.clone()).map_err(|err| {
+            anyhow!(
+                "no path between stop on {} and {}: {}",
                 map.get_parent(req.start.lane()).orig_id,
                 map.get_parent(req.end.lane()).orig_id,
-                err,
-                debug_route
-            );
+                err
+            )
+        })?;
+        if step_idx == 0 {
+            lead_time = path.estimate_duration(map, route_type, None);
+        } else if let Some(ref shape) = shape {
+            let pt1 = req.start.pt(map);
+            let pt2 = req.end.pt(map);
+            if let (Some((dist1, _)), Some((dist2, _))) = (
+                shape.dist_along_of_point(shape.project_pt(pt1)),
+                shape.dist_along_of_point(shape.project_pt(pt2)),
+            ) {
+                let expected = dist2 - dist1;
+                if path.total_length() > SHAPE_DETOUR_FACTOR * expected + SHAPE_DETOUR_SLACK {
+                    warn!(
+                        "{} goes {} between {} and {}, but the shape only goes {}",
+                        route.full_name,
+                        path.total_length(),
+                        pt1,
+                        pt2,
+                        expected
+                    );
+                }
+            }
         }
     }
 
+    route.stop_schedule = r
+        .stop_offsets
+        .into_iter()
+        .map(|offset| lead_time + offset)
+        .collect();
+    if route.stop_schedule.len() != route.stops.len() {
+        bail!(
+            "{} stops, but {} scheduled arrivals",
+            route.stops.len(),
+            route.stop_schedule.len()
+        );
+    }
+    let mut spawn_times: Vec<Time> = r
+        .departures
+        .into_iter()
+        .filter(|t| *t >= Time::START_OF_DAY + lead_time)
+        .map(|t| t - lead_time)
+        .collect();
+    spawn_times.sort();
+    spawn_times.dedup();
+    if spawn_times.is_empty() {
+        bail!("every departure is too early in the day");
+    }
+    route.orig_spawn_times = spawn_times.clone();
+    route.spawn_times = spawn_times;
+    if route.timepoints.is_empty() {
+        route.timepoints = (0..route.stops.len())
+            .step_by(TIMEPOINT_EVERY_N_STOPS)
+            .collect();
+    }
+
     map.bus_routes.push(route);
     Ok(())
 }
 
+/// Finds a lane the vehicle can use near the stop, heading roughly the same way. Returns
+/// (sidewalk, driving) positions.
+fn match_scheduled_stop(
+    map: &Map,
+    route_type: PathConstraints,
+    pt: Pt2D,
+    heading: Angle,
+) -> Result<(Position, Position)> {
+    let mut best: Option<(Distance, LaneID, Pt2D)> = None;
+    for l in map.all_lanes().values() {
+        if !route_type.can_use(l, map) {
+            continue;
+        }
+        let proj = l.lane_center_pts.project_pt(pt);
+        let dist = proj.dist_to(pt);
+        if dist > MAX_STOP_DIST || best.map(|(d, _, _)| dist >= d).unwrap_or(false) {
+            continue;
+        }
+        if let Some((_, angle)) = l.lane_center_pts.dist_along_of_point(proj) {
+            if angle.approx_eq(heading, 60.0) {
+                best = Some((dist, l.id, proj));
+            }
+        }
+    }
+    let (_, l, proj) =
+        best.ok_or_else(|| anyhow!("no lane heading the right way within {}", MAX_STOP_DIST))?;
+    let lane = map.get_l(l);
+    let mut driving_pos = Position::new(l, lane.dist_along_of_point(proj).unwrap());
+
+    let sidewalk = map
+        .get_parent(l)
+        .find_closest_lane(l, |l| PathConstraints::Pedestrian.can_use(l, map), map)
+        .ok_or_else(|| anyhow!("driving {} to sidewalk failed", l))?;
+    let sidewalk_pos = driving_pos.equiv_pos(sidewalk, map);
+
+    // Don't stop right where vehicles spawn at a border
+    if map.get_i(lane.src_i).is_incoming_border() {
+        driving_pos = driving_pos
+            .min_dist(Distance::meters(1.0), map)
+            .ok_or_else(|| anyhow!("too close to start of a border {}", l))?;
+    }
+    Ok((sidewalk_pos, driving_pos))
+}
+
+/// Create a new bus stop if needed.
+fn get_or_create_stop(
+    map: &mut Map,
+    pt_to_stop: &mut BTreeMap<(Position, Position), BusStopID>,
+    name: &str,
+    sidewalk_pos: Position,
+    driving_pos: Position,
+    is_train_stop: bool,
+) -> BusStopID {
+    if let Some(id) = pt_to_stop.get(&(sidewalk_pos, driving_pos)) {
+        return *id;
+    }
+    // Some stops may have been removed, so don't just count the ones left
+    let id = BusStopID {
+        sidewalk: sidewalk_pos.lane(),
+        idx: map
+            .get_l(sidewalk_pos.lane())
+            .bus_stops
+            .iter()
+            .map(|bs| bs.idx + 1)
+            .max()
+            .unwrap_or(0),
+    };
+    pt_to_stop.insert((sidewalk_pos, driving_pos), id);
+    map.lanes
+        .get_mut(&sidewalk_pos.lane())
+        .unwrap()
+        .bus_stops
+        .insert(id);
+    map.bus_stops.insert(
+        id,
+        BusStop {
+            id,
+            name: name.to_string(),
+            driving_pos,
+            sidewalk_pos,
+            is_train_stop,
+        },
+    );
+    id
+}
+
 struct Matcher {
     // TODO Eventually, maybe also map to a station building too
     sidewalk_pts: HashMap<HashablePt2D, Position>,
@@ -8,15 +8,16 @@ use serde::{Deserialize, Serialize};
 
 use abstio::{CityName, MapName};
 use abstutil::{Tags, Timer};
-use geom::{Bounds, Distance, GPSBounds, Polygon, Pt2D, Ring, Time};
+use geom::{Bounds, Distance, Duration, GPSBounds, Polygon, Pt2D, Ring, Time};
 
+use crate::pathfind::{vehicle_cost, zone_cost, TimeDependentPathfinder};
 use crate::raw::{OriginalRoad, RawMap};
 use crate::{
     osm, Area, AreaID, AreaType, Building, BuildingID, BuildingType, BusRoute, BusRouteID, BusStop,
     BusStopID, ControlStopSign, ControlTrafficSignal, DirectedRoadID, Intersection, IntersectionID,
     Lane, LaneID, LaneType, Map, MapEdits, MovementID, OffstreetParking, ParkingLot, ParkingLotID,
-    Path, PathConstraints, PathRequest, Pathfinder, Position, Road, RoadID, RoutingParams, Turn,
-    TurnID, TurnType, Zone,
+    ParkingPlace, ParkingPolicy, Path, PathConstraints, PathRequest, PathV2, Pathfinder, Position,
+    Road, RoadID, RoutingParams, ScheduledRoute, TravelTimeProfile, Turn, TurnID, TurnType, Zone,
 };
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -54,6 +55,16 @@ impl Map {
                     return map;
                 }
                 Err(err) => {
+                    if let Ok(mut map) = Map::load_old_format(path.clone(), timer) {
+                        warn!(
+                            "{} uses an old format. Regenerate it or download new data (cargo \
+                             run --bin updater) to load it faster.",
+                            path
+                        );
+                        map.map_loaded_directly();
+                        return map;
+                    }
+
                     error!("\nError loading {}: {}\n", path, err);
                     if err.to_string().contains("No such file") {
                         error!(
@@ -147,6 +158,8 @@ impl Map {
             bus_routes: Vec::new(),
             areas: Vec::new(),
             parking_lots: Vec::new(),
+            parking_policies: BTreeMap::new(),
+            orig_parking_policies: BTreeMap::new(),
             zones: Vec::new(),
             boundary_polygon: Ring::must_new(vec![
                 Pt2D::new(0.0, 0.0),
@@ -168,6 +181,7 @@ impl Map {
             pathfinder: Pathfinder::Dijkstra,
             pathfinder_dirty: false,
             routing_params: RoutingParams::default(),
+            travel_times: None,
             name: MapName::new("zz", "blank city", "blank"),
             edits: MapEdits::new(),
         }
@@ -201,6 +215,11 @@ impl Map {
         &self.parking_lots
     }
 
+    /// Only places that charge for parking or limit how long anybody stays have a policy.
+    pub fn all_parking_policies(&self) -> &BTreeMap<ParkingPlace, ParkingPolicy> {
+        &self.parking_policies
+    }
+
     pub fn all_zones(&self) -> &Vec<Zone> {
         &self.zones
     }
@@ -289,6 +308,11 @@ impl Map {
         &self.parking_lots[id.0]
     }
 
+    /// Anywhere without a policy is free, with no time limit.
+    pub fn get_parking_policy(&self, place: ParkingPlace) -> Option<&ParkingPolicy> {
+        self.parking_policies.get(&place)
+    }
+
     pub fn get_stop_sign(&self, id: IntersectionID) -> &ControlStopSign {
         &self.stop_signs[&id]
     }
@@ -597,6 +621,72 @@ impl Map {
             .ok_or_else(|| anyhow!("can't fulfill {}", req))?;
         path.into_v1(self)
     }
+    /// Finds a path for a trip starting at some time. If the map has a travel time profile, cars
+    /// avoid roads that're usually congested around then. Otherwise, this is just `pathfind`.
+    pub fn pathfind_at(&self, req: PathRequest, time: Time) -> Result<Path> {
+        assert!(!self.pathfinder_dirty);
+        match self.travel_times {
+            Some(ref pathfinder) if req.constraints == PathConstraints::Car => {
+                let path = pathfinder
+                    .pathfind(req.clone(), time, self)
+                    .ok_or_else(|| anyhow!("can't fulfill {}", req))?;
+                path.into_v1(self)
+            }
+            _ => self.pathfind(req),
+        }
+    }
+    /// Like `pathfind`, but returns the road-based path, which also has a cost.
+    pub fn pathfind_v2(&self, req: PathRequest) -> Result<PathV2> {
+        assert!(!self.pathfinder_dirty);
+        self.pathfinder
+            .pathfind(req.clone(), self)
+            .ok_or_else(|| anyhow!("can't fulfill {}", req))
+    }
+    /// Instead of searching for the best path, follow a particular sequence of roads. They have to
+    /// begin and end on the same roads as the request, and each road must lead to the next. The
+    /// cost is calculated like `pathfind_v2`, ignoring uber-turns, so the two can be compared.
+    pub fn pathfind_along_roads(
+        &self,
+        req: PathRequest,
+        roads: Vec<DirectedRoadID>,
+    ) -> Result<PathV2> {
+        if req.constraints == PathConstraints::Pedestrian {
+            bail!("{} is for a pedestrian, who can't follow roads", req);
+        }
+        if let Some(dr) = roads.iter().find(|dr| self.maybe_get_r(dr.id).is_none()) {
+            bail!("{} doesn't exist", dr.id);
+        }
+        if roads.first() != Some(&self.get_l(req.start.lane()).get_directed_parent())
+            || roads.last() != Some(&self.get_l(req.end.lane()).get_directed_parent())
+        {
+            bail!("the roads don't start and end where {} does", req);
+        }
+        let mut cost = Duration::ZERO;
+        for pair in roads.windows(2) {
+            let mvmnt = self
+                .get_movements_for(pair[0], req.constraints)
+                .into_iter()
+                .find(|mvmnt| mvmnt.to == pair[1])
+                .ok_or_else(|| {
+                    anyhow!(
+                        "a {:?} can't go from {} to {}",
+                        req.constraints,
+                        pair[0],
+                        pair[1]
+                    )
+                })?;
+            // vehicle_cost pretends movements with broken geometry are 1m long. Don't guess here.
+            mvmnt.get(self)?;
+            cost += vehicle_cost(
+                mvmnt.from,
+                mvmnt,
+                req.constraints,
+                self.routing_params(),
+                self,
+            ) + zone_cost(mvmnt, req.constraints, self);
+        }
+        Ok(PathV2::from_roads(roads, req, cost, Vec::new(), self))
+    }
 
     pub fn should_use_transit(
         &self,
@@ -649,6 +739,15 @@ impl Map {
         None
     }
 
+    pub fn find_pl_by_osm_id(&self, id: osm::OsmID) -> Option<ParkingLotID> {
+        for pl in self.all_parking_lots() {
+            if pl.osm_id == id {
+                return Some(pl.id);
+            }
+        }
+        None
+    }
+
     pub fn find_br(&self, id: osm::RelationID) -> Option<BusRouteID> {
         for br in self.all_bus_routes() {
             if br.osm_rel_id == id {
@@ -682,6 +781,18 @@ impl Map {
         self.bus_routes[br.0].spawn_times = times;
     }
 
+    /// Matches transit routes from outside OpenStreetMap, like a GTFS feed, and adds them. If
+    /// `replace_existing`, all routes and stops from OSM are removed first. Returns how many
+    /// routes were added.
+    pub fn hack_add_scheduled_routes(
+        &mut self,
+        routes: Vec<ScheduledRoute>,
+        replace_existing: bool,
+        timer: &mut Timer,
+    ) -> usize {
+        crate::make::add_scheduled_routes(self, routes, replace_existing, timer)
+    }
+
     pub fn hack_add_area(&mut self, area_type: AreaType, polygon: Polygon, osm_tags: Tags) {
         self.areas.push(Area {
             id: AreaID(self.areas.len()),
@@ -747,6 +858,22 @@ impl Map {
         )
     }
 
+    /// Make car routing through `pathfind_at` congestion-aware, using travel times that change
+    /// through the day. This prepares routing for every time bin up-front, so it's slow. This isn't
+    /// saved with the map.
+    pub fn set_travel_time_profile(
+        &mut self,
+        profile: Option<TravelTimeProfile>,
+        timer: &mut Timer,
+    ) {
+        self.travel_times =
+            profile.map(|profile| TimeDependentPathfinder::new(profile, self, timer));
+    }
+
+    pub fn get_travel_time_profile(&self) -> Option<&TravelTimeProfile> {
+        self.travel_times.as_ref().map(|p| p.profile())
+    }
+
     /// Returns the routing params baked into the map.
     // Depending how this works out, we might require everybody to explicitly plumb routing params,
     // in which case it should be easy to look for all places calling this.
@@ -1,12 +1,13 @@
 //! Bus stops and routes.
 // TODO Rename public transit -- these also cover light rail now.
 
+use std::collections::BTreeSet;
 use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_usize, serialize_usize};
-use geom::Time;
+use geom::{Duration, Time};
 
 use crate::{osm, LaneID, Map, PathConstraints, PathRequest, Position};
 
@@ -50,7 +51,7 @@ pub struct BusStop {
     pub is_train_stop: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct BusRoute {
     pub id: BusRouteID,
     pub full_name: String,
@@ -67,6 +68,10 @@ pub struct BusRoute {
     /// Explicitly store whatever the original was, since this can't be reconstructed without side
     /// input.
     pub orig_spawn_times: Vec<Time>,
+    /// For each stop, how long after beginning at start a vehicle is scheduled to arrive there.
+    pub stop_schedule: Vec<Duration>,
+    /// Indices into stops where vehicles may be held to stay on schedule or keep even headways.
+    pub timepoints: BTreeSet<usize>,
 }
 
 impl BusRoute {
@@ -93,6 +98,18 @@ impl BusRoute {
         steps
     }
 
+    /// When the vehicle beginning at some spawn time is scheduled to arrive at one of the stops.
+    pub fn scheduled_arrival(&self, spawn_time: Time, stop_idx: usize) -> Time {
+        spawn_time + self.stop_schedule[stop_idx]
+    }
+
+    /// How long after the previous vehicle the one beginning at some spawn time is scheduled to
+    /// run. None for the first vehicle of the day.
+    pub fn scheduled_headway(&self, spawn_time: Time) -> Option<Duration> {
+        let prev = self.spawn_times.iter().rev().find(|t| **t < spawn_time)?;
+        Some(spawn_time - *prev)
+    }
+
     pub fn plural_noun(&self) -> &'static str {
         if self.route_type == PathConstraints::Bus {
             "buses"
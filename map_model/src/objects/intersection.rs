@@ -9,11 +9,13 @@ use geom::{Distance, Polygon};
 use crate::{osm, DirectedRoadID, LaneID, Map, PathConstraints, Road, RoadID, Turn};
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub struct IntersectionID(
     #[serde(
         serialize_with = "serialize_usize",
         deserialize_with = "deserialize_usize"
     )]
+    #[cfg_attr(feature = "json_schema", schemars(with = "u32"))]
     pub usize,
 );
 
@@ -34,7 +36,7 @@ pub enum IntersectionType {
 /// An intersection connects roads. Most have >2 roads and are controlled by stop signs or traffic
 /// signals. Roads that lead to the boundary of the map end at border intersections, with only that
 /// one road attached.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Intersection {
     pub id: IntersectionID,
     /// This needs to be in clockwise orientation, or later rendering of sidewalk corners breaks.
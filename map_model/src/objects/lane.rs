@@ -21,11 +21,13 @@ pub const SIDEWALK_THICKNESS: Distance = Distance::const_meters(1.5);
 const SHOULDER_THICKNESS: Distance = Distance::const_meters(0.5);
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub struct LaneID(
     #[serde(
         serialize_with = "serialize_usize",
         deserialize_with = "deserialize_usize"
     )]
+    #[cfg_attr(feature = "json_schema", schemars(with = "u32"))]
     pub usize,
 );
 
@@ -128,7 +130,7 @@ impl LaneType {
 }
 
 /// A road segment is broken down into individual lanes, which have a LaneType.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Lane {
     pub id: LaneID,
     pub parent: RoadID,
@@ -0,0 +1,297 @@
+//! On-street parking, parking lots, and public garages can charge for parking and limit how long
+//! anybody stays. Places grouped into the same zone, like a meter district, are reported together.
+//! Anywhere without a policy is free, with no time limit.
+
+use std::fmt;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::Tags;
+use geom::{Duration, Time};
+
+use crate::{BuildingID, ParkingLotID, RoadID};
+
+/// Somewhere with parking that can have its own policy
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ParkingPlace {
+    /// All of the on-street parking along a road
+    Road(RoadID),
+    /// A public garage
+    Building(BuildingID),
+    ParkingLot(ParkingLotID),
+}
+
+impl fmt::Display for ParkingPlace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParkingPlace::Road(r) => write!(f, "on-street parking along {}", r),
+            ParkingPlace::Building(b) => write!(f, "the garage at {}", b),
+            ParkingPlace::ParkingLot(pl) => write!(f, "{}", pl),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParkingPolicy {
+    /// Places in the same zone are reported together
+    pub zone: String,
+    /// Sorted by start time, repeating every day. Before the first rate starts each day, the last
+    /// rate from the day before still applies.
+    pub rates: Vec<ParkingRate>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParkingRate {
+    /// The time of day when this rate begins
+    pub start: Time,
+    /// In dollars
+    pub hourly_price: f64,
+    /// How long anybody may park while this rate applies
+    pub max_stay: Option<Duration>,
+}
+
+impl ParkingPolicy {
+    /// The same price and time limit all day
+    pub fn flat(zone: String, hourly_price: f64, max_stay: Option<Duration>) -> ParkingPolicy {
+        ParkingPolicy {
+            zone,
+            rates: vec![ParkingRate {
+                start: Time::START_OF_DAY,
+                hourly_price,
+                max_stay,
+            }],
+        }
+    }
+
+    /// Sorts the rates by start time. Fails if there aren't any, or if one doesn't start during the
+    /// day.
+    pub fn validate(mut self) -> Result<ParkingPolicy> {
+        if self.rates.is_empty() {
+            bail!("parking policy for zone {} has no rates", self.zone);
+        }
+        let end_of_day = Time::START_OF_DAY + Duration::hours(24);
+        for rate in &self.rates {
+            if rate.start < Time::START_OF_DAY || rate.start >= end_of_day {
+                bail!(
+                    "parking policy for zone {} has a rate starting at {}, outside of the day",
+                    self.zone,
+                    rate.start
+                );
+            }
+        }
+        self.rates.sort_by_key(|rate| rate.start);
+        Ok(self)
+    }
+
+    /// The rate in effect at some time, if there are any
+    pub fn rate_at(&self, time: Time) -> Option<&ParkingRate> {
+        let time_of_day = Time::START_OF_DAY + (time - Time::START_OF_DAY) % Duration::hours(24);
+        self.rates
+            .iter()
+            .rev()
+            .find(|rate| rate.start <= time_of_day)
+            .or_else(|| self.rates.last())
+    }
+
+    /// Splits a stay into the stretches covered by each rate.
+    fn split_stay(&self, start: Time, end: Time) -> Vec<(&ParkingRate, Duration)> {
+        let mut pieces: Vec<(&ParkingRate, Duration)> = Vec::new();
+        let mut time = start;
+        while time < end {
+            let rate = match self.rate_at(time) {
+                Some(rate) => rate,
+                None => {
+                    break;
+                }
+            };
+            let time_of_day =
+                Time::START_OF_DAY + (time - Time::START_OF_DAY) % Duration::hours(24);
+            let until_next = match self.rates.iter().find(|r| r.start > time_of_day) {
+                Some(next) => next.start - time_of_day,
+                None => (self.rates[0].start + Duration::hours(24)) - time_of_day,
+            };
+            let dt = until_next.min(end - time);
+            // The same rate might continue past midnight
+            match pieces.last_mut() {
+                Some((last, sum)) if std::ptr::eq(*last, rate) => {
+                    *sum += dt;
+                }
+                _ => {
+                    pieces.push((rate, dt));
+                }
+            }
+            time += dt;
+        }
+        pieces
+    }
+
+    /// What it costs to park from one time to another, in dollars
+    pub fn price(&self, start: Time, end: Time) -> f64 {
+        self.split_stay(start, end)
+            .into_iter()
+            .map(|(rate, dt)| rate.hourly_price * dt.inner_seconds() / 3600.0)
+            .sum()
+    }
+
+    /// Is somebody allowed to park from one time to another, without going over any time limit?
+    pub fn allows_stay(&self, start: Time, end: Time) -> bool {
+        self.split_stay(start, end)
+            .into_iter()
+            .all(|(rate, dt)| rate.max_stay.map(|max| dt <= max).unwrap_or(true))
+    }
+
+    /// Looks for a price or time limit in OpenStreetMap tags, checking each prefix in order. Prices
+    /// are only understood per hour, like `charge=2.50 USD/hour`.
+    pub(crate) fn from_osm(tags: &Tags, prefixes: &[&str]) -> Option<ParkingPolicy> {
+        let find = |key: &str| {
+            prefixes
+                .iter()
+                .find_map(|prefix| tags.get(&format!("{}{}", prefix, key)))
+        };
+        let max_stay = find("maxstay").and_then(|x| parse_max_stay(x.as_str()));
+        let hourly_price = find("charge").and_then(|x| parse_hourly_price(x.as_str()));
+        if max_stay.is_none() && hourly_price.is_none() {
+            return None;
+        }
+        let zone = tags
+            .get("parking:zone")
+            .or_else(|| tags.get("zone:parking"))
+            .cloned()
+            .unwrap_or_else(|| "default".to_string());
+        Some(ParkingPolicy::flat(
+            zone,
+            hourly_price.unwrap_or(0.0),
+            max_stay,
+        ))
+    }
+}
+
+/// Understands things like "2 hours", "90 minutes", and "1h"
+fn parse_max_stay(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let amount = value[..split].parse::<f64>().ok()?;
+    match value[split..].trim() {
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(Duration::seconds(3600.0 * amount)),
+        "min" | "mins" | "minute" | "minutes" => Some(Duration::seconds(60.0 * amount)),
+        _ => None,
+    }
+}
+
+/// Understands things like "2.50 USD/hour" and "1 EUR/h"
+fn parse_hourly_price(value: &str) -> Option<f64> {
+    let (amount, unit) = value.split_once('/')?;
+    if !matches!(unit.trim(), "h" | "hr" | "hour") {
+        return None;
+    }
+    amount.split_whitespace().find_map(|x| {
+        x.trim_matches(|c: char| !c.is_ascii_digit() && c != '.')
+            .parse::<f64>()
+            .ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hours: usize) -> Time {
+        Time::START_OF_DAY + Duration::hours(hours)
+    }
+
+    /// $2 an hour with a 2 hour limit during the day, and free overnight
+    fn metered() -> ParkingPolicy {
+        ParkingPolicy {
+            zone: "downtown".to_string(),
+            rates: vec![
+                ParkingRate {
+                    start: at(8),
+                    hourly_price: 2.0,
+                    max_stay: Some(Duration::hours(2)),
+                },
+                ParkingRate {
+                    start: at(18),
+                    hourly_price: 0.0,
+                    max_stay: None,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_parse_max_stay() {
+        assert_eq!(parse_max_stay("2 hours"), Some(Duration::hours(2)));
+        assert_eq!(parse_max_stay("90 minutes"), Some(Duration::minutes(90)));
+        assert_eq!(parse_max_stay(" 1h "), Some(Duration::hours(1)));
+        assert_eq!(parse_max_stay("1.5 hrs"), Some(Duration::minutes(90)));
+        assert_eq!(parse_max_stay("15min"), Some(Duration::minutes(15)));
+        assert_eq!(parse_max_stay("2 days"), None);
+        assert_eq!(parse_max_stay("hours"), None);
+        assert_eq!(parse_max_stay("no"), None);
+    }
+
+    #[test]
+    fn test_parse_hourly_price() {
+        assert_eq!(parse_hourly_price("2.50 USD/hour"), Some(2.5));
+        assert_eq!(parse_hourly_price("1 EUR/h"), Some(1.0));
+        assert_eq!(parse_hourly_price("$3/hr"), Some(3.0));
+        assert_eq!(parse_hourly_price("5 USD/day"), None);
+        assert_eq!(parse_hourly_price("5 USD"), None);
+        assert_eq!(parse_hourly_price("yes"), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut policy = metered();
+        policy.rates.reverse();
+        assert_eq!(policy.validate().unwrap(), metered());
+
+        let mut policy = metered();
+        policy.rates.clear();
+        assert!(policy.validate().is_err());
+
+        let mut policy = metered();
+        policy.rates[1].start = at(24);
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn test_split_stay() {
+        let policy = metered();
+        let day = &policy.rates[0];
+        let night = &policy.rates[1];
+        let pieces = policy.split_stay(at(17), at(24 + 9));
+        assert_eq!(pieces.len(), 3);
+        // The overnight rate continues past midnight as one piece
+        assert_eq!(pieces[0], (day, Duration::hours(1)));
+        assert_eq!(pieces[1], (night, Duration::hours(14)));
+        assert_eq!(pieces[2], (day, Duration::hours(1)));
+
+        // Before the first rate starts, the last one from the day before still applies
+        assert_eq!(
+            policy.split_stay(at(7), at(8)),
+            vec![(night, Duration::hours(1))]
+        );
+        assert!(policy.split_stay(at(9), at(9)).is_empty());
+    }
+
+    #[test]
+    fn test_price() {
+        let policy = metered();
+        assert_eq!(policy.price(at(9), at(10)), 2.0);
+        assert_eq!(policy.price(at(17), at(19)), 2.0);
+        assert_eq!(policy.price(at(7), at(9) + Duration::minutes(30)), 3.0);
+        assert_eq!(policy.price(at(17), at(24 + 9)), 4.0);
+        assert_eq!(policy.price(at(20), at(23)), 0.0);
+
+        assert!(policy.allows_stay(at(9), at(11)));
+        assert!(!policy.allows_stay(at(7), at(11)));
+        assert!(policy.allows_stay(at(17), at(24 + 9)));
+
+        let flat = ParkingPolicy::flat("lot".to_string(), 1.5, None);
+        assert_eq!(flat.price(at(10), at(24 + 10)), 36.0);
+    }
+}
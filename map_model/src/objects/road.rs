@@ -15,11 +15,13 @@ use crate::{
 };
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub struct RoadID(
     #[serde(
         serialize_with = "serialize_usize",
         deserialize_with = "deserialize_usize"
     )]
+    #[cfg_attr(feature = "json_schema", schemars(with = "u32"))]
     pub usize,
 );
 
@@ -45,6 +47,7 @@ impl RoadID {
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub enum Direction {
     Fwd,
     Back,
@@ -69,6 +72,7 @@ impl fmt::Display for Direction {
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub struct DirectedRoadID {
     pub id: RoadID,
     pub dir: Direction,
@@ -131,7 +135,7 @@ impl DirectedRoadID {
 }
 
 /// A Road represents a segment between exactly two Intersections. It contains Lanes as children.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Road {
     pub id: RoadID,
     pub osm_tags: Tags,
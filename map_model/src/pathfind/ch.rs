@@ -11,7 +11,7 @@ use crate::pathfind::vehicles::VehiclePathfinder;
 use crate::pathfind::walking::SidewalkPathfinder;
 use crate::{BusRouteID, BusStopID, Map, PathConstraints, PathRequest, PathV2, Position};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ContractionHierarchyPathfinder {
     car_graph: VehiclePathfinder,
     bike_graph: VehiclePathfinder,
@@ -23,17 +23,24 @@ pub struct ContractionHierarchyPathfinder {
 impl ContractionHierarchyPathfinder {
     pub fn new(map: &Map, timer: &mut Timer) -> ContractionHierarchyPathfinder {
         timer.start("prepare pathfinding for cars");
-        let car_graph = VehiclePathfinder::new(map, PathConstraints::Car, None);
+        let car_graph =
+            VehiclePathfinder::new(map, PathConstraints::Car, map.routing_params(), None);
         timer.stop("prepare pathfinding for cars");
 
         // The edge weights for bikes are so different from the driving graph that reusing the node
         // ordering actually hurts!
         timer.start("prepare pathfinding for bikes");
-        let bike_graph = VehiclePathfinder::new(map, PathConstraints::Bike, None);
+        let bike_graph =
+            VehiclePathfinder::new(map, PathConstraints::Bike, map.routing_params(), None);
         timer.stop("prepare pathfinding for bikes");
 
         timer.start("prepare pathfinding for buses");
-        let bus_graph = VehiclePathfinder::new(map, PathConstraints::Bus, Some(&car_graph));
+        let bus_graph = VehiclePathfinder::new(
+            map,
+            PathConstraints::Bus,
+            map.routing_params(),
+            Some(&car_graph),
+        );
         timer.stop("prepare pathfinding for buses");
 
         timer.start("prepare pathfinding for pedestrians");
@@ -65,6 +72,10 @@ impl ContractionHierarchyPathfinder {
         }
     }
 
+    pub(crate) fn car_graph(&self) -> &VehiclePathfinder {
+        &self.car_graph
+    }
+
     pub fn should_use_transit(
         &self,
         map: &Map,
@@ -1,5 +1,7 @@
 //! Everything related to pathfinding through a map for different types of agents.
 
+use std::collections::BTreeMap;
+
 use enumset::EnumSetType;
 use serde::{Deserialize, Serialize};
 
@@ -8,16 +10,19 @@ use geom::Duration;
 pub use self::ch::ContractionHierarchyPathfinder;
 pub use self::dijkstra::{build_graph_for_pedestrians, build_graph_for_vehicles};
 pub use self::pathfinder::Pathfinder;
+pub(crate) use self::travel_times::TimeDependentPathfinder;
+pub use self::travel_times::TravelTimeProfile;
 pub use self::v1::{Path, PathRequest, PathStep};
 pub use self::v2::{PathStepV2, PathV2};
 pub use self::vehicles::vehicle_cost;
 pub use self::walking::WalkingNode;
-use crate::{osm, Lane, LaneID, LaneType, Map, MovementID, TurnType};
+use crate::{osm, DirectedRoadID, Lane, LaneID, LaneType, Map, MovementID, TurnType};
 
 mod ch;
 pub mod dijkstra;
 mod node_map;
 mod pathfinder;
+mod travel_times;
 // TODO tmp
 pub mod uber_turns;
 mod v1;
@@ -157,7 +162,7 @@ pub fn zone_cost(mvmnt: MovementID, constraints: PathConstraints, map: &Map) ->
 /// Tuneable parameters for all types of routing.
 // These will maybe become part of the PathRequest later, but that's an extremely invasive and
 // space-expensive change right now.
-#[derive(PartialEq, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct RoutingParams {
     // For all vehicles. This is added to the cost of a movement as an additional delay.
     pub unprotected_turn_penalty: Duration,
@@ -166,6 +171,15 @@ pub struct RoutingParams {
     pub bike_lane_penalty: f64,
     pub bus_lane_penalty: f64,
     pub driving_lane_penalty: f64,
+    /// For cars. Instead of assuming a road can be crossed at the speed limit, use these times,
+    /// usually measured from a previous simulation. They should include any delay waiting at the
+    /// end of the road to start a turn. This isn't saved with the map.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub road_travel_times: BTreeMap<DirectedRoadID, Duration>,
+    /// For cars. Like `road_travel_times`, but for the time to perform a movement, not counting
+    /// the wait before starting it.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub movement_travel_times: BTreeMap<MovementID, Duration>,
 }
 
 impl RoutingParams {
@@ -177,6 +191,8 @@ impl RoutingParams {
             bike_lane_penalty: 1.0,
             bus_lane_penalty: 1.1,
             driving_lane_penalty: 1.5,
+            road_travel_times: BTreeMap::new(),
+            movement_travel_times: BTreeMap::new(),
         }
     }
 }
@@ -13,7 +13,7 @@ use crate::{BusRouteID, BusStopID, Map, PathRequest, PathV2, Position, RoadID, R
 /// explicitly opt into a slower (but preparation-free) pathfinder that just uses Dijkstra's
 /// maneuever.
 #[allow(clippy::large_enum_variant)]
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum Pathfinder {
     Dijkstra,
     CH(ContractionHierarchyPathfinder),
@@ -0,0 +1,189 @@
+//! Congestion-aware routing for cars, using travel times that change through the day.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap, Timer};
+use geom::{Duration, Time};
+
+use crate::pathfind::dijkstra;
+use crate::pathfind::vehicles::VehiclePathfinder;
+use crate::{
+    DirectedRoadID, Map, MovementID, PathConstraints, PathRequest, PathV2, Pathfinder,
+    RoutingParams,
+};
+
+/// How long vehicles took to cross roads and movements, grouped into time bins through the day.
+/// This is usually recorded from a previous simulation.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TravelTimeProfile {
+    pub bin_width: Duration,
+    /// Per bin, the total time and number of vehicles. Crossing a road includes any delay waiting
+    /// at the end of it to start a movement.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    roads: BTreeMap<DirectedRoadID, Vec<(Duration, usize)>>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    movements: BTreeMap<MovementID, Vec<(Duration, usize)>>,
+}
+
+impl TravelTimeProfile {
+    pub fn new(bin_width: Duration) -> TravelTimeProfile {
+        assert!(bin_width > Duration::ZERO);
+        TravelTimeProfile {
+            bin_width,
+            roads: BTreeMap::new(),
+            movements: BTreeMap::new(),
+        }
+    }
+
+    /// A vehicle entered the road at `start` and took `duration` to reach the end.
+    pub fn record_road(&mut self, dr: DirectedRoadID, start: Time, duration: Duration) {
+        let bin = self.bin(start);
+        record(self.roads.entry(dr).or_insert_with(Vec::new), bin, duration);
+    }
+
+    /// A vehicle started the movement at `start` and took `duration` to finish it.
+    pub fn record_movement(&mut self, mvmnt: MovementID, start: Time, duration: Duration) {
+        let bin = self.bin(start);
+        record(
+            self.movements.entry(mvmnt).or_insert_with(Vec::new),
+            bin,
+            duration,
+        );
+    }
+
+    /// The average time to cross a road, for vehicles entering it around some time. None if
+    /// nobody was observed then.
+    pub fn road_time(&self, dr: DirectedRoadID, time: Time) -> Option<Duration> {
+        average(self.roads.get(&dr)?, self.bin(time))
+    }
+
+    /// The average time to perform a movement, for vehicles starting it around some time. None if
+    /// nobody was observed then.
+    pub fn movement_time(&self, mvmnt: MovementID, time: Time) -> Option<Duration> {
+        average(self.movements.get(&mvmnt)?, self.bin(time))
+    }
+
+    fn bin(&self, time: Time) -> usize {
+        ((time - Time::START_OF_DAY) / self.bin_width).floor() as usize
+    }
+
+    /// Routing parameters using the travel times from one bin, falling back to the usual costs
+    /// when nothing was observed.
+    fn routing_params(&self, base: &RoutingParams, bin: usize) -> RoutingParams {
+        let mut params = base.clone();
+        for (dr, bins) in &self.roads {
+            if let Some(t) = average(bins, bin) {
+                params.road_travel_times.insert(*dr, t);
+            }
+        }
+        for (mvmnt, bins) in &self.movements {
+            if let Some(t) = average(bins, bin) {
+                params.movement_travel_times.insert(*mvmnt, t);
+            }
+        }
+        params
+    }
+}
+
+fn record(bins: &mut Vec<(Duration, usize)>, bin: usize, duration: Duration) {
+    if bins.len() <= bin {
+        bins.resize(bin + 1, (Duration::ZERO, 0));
+    }
+    bins[bin].0 += duration;
+    bins[bin].1 += 1;
+}
+
+fn average(bins: &[(Duration, usize)], bin: usize) -> Option<Duration> {
+    match bins.get(bin) {
+        Some((total, count)) if *count > 0 => Some(*total / (*count as f64)),
+        _ => None,
+    }
+}
+
+/// Routes cars using a `TravelTimeProfile`. A path requested during some time bin uses the travel
+/// times from that bin for the entire route.
+///
+/// fast_paths doesn't support customizable contraction hierarchies, but preparing a hierarchy
+/// with a known node ordering is much faster than from scratch. So when the profile is set, the
+/// map's car hierarchy is recustomized once for every bin with observations. That's one extra
+/// hierarchy in memory per bin. Maps using Dijkstra's algorithm just change the edge weights
+/// directly.
+#[derive(Clone)]
+pub(crate) struct TimeDependentPathfinder {
+    profile: TravelTimeProfile,
+    /// Indexed by bin. Empty for maps using Dijkstra's algorithm.
+    graphs: Vec<VehiclePathfinder>,
+}
+
+impl TimeDependentPathfinder {
+    pub fn new(
+        profile: TravelTimeProfile,
+        map: &Map,
+        timer: &mut Timer,
+    ) -> TimeDependentPathfinder {
+        let mut pathfinder = TimeDependentPathfinder {
+            profile,
+            graphs: Vec::new(),
+        };
+        pathfinder.recalculate(map, timer);
+        pathfinder
+    }
+
+    pub fn profile(&self) -> &TravelTimeProfile {
+        &self.profile
+    }
+
+    /// Recustomizes the car hierarchy for every bin. Call this after the map is edited.
+    pub fn recalculate(&mut self, map: &Map, timer: &mut Timer) {
+        self.graphs.clear();
+        let seed = match map.pathfinder {
+            Pathfinder::Dijkstra => {
+                return;
+            }
+            Pathfinder::CH(ref ch) => ch.car_graph(),
+        };
+        let num_bins = self
+            .profile
+            .roads
+            .values()
+            .chain(self.profile.movements.values())
+            .map(|bins| bins.len())
+            .max()
+            .unwrap_or(0);
+        timer.start_iter("recustomize car routing for each time bin", num_bins);
+        for bin in 0..num_bins {
+            timer.next();
+            let params = self.profile.routing_params(map.routing_params(), bin);
+            self.graphs.push(VehiclePathfinder::new(
+                map,
+                PathConstraints::Car,
+                &params,
+                Some(seed),
+            ));
+        }
+    }
+
+    pub fn pathfind(&self, req: PathRequest, time: Time, map: &Map) -> Option<PathV2> {
+        assert_eq!(req.constraints, PathConstraints::Car);
+        let bin = self.profile.bin(time);
+        match map.pathfinder {
+            Pathfinder::Dijkstra => {
+                let params = self.profile.routing_params(map.routing_params(), bin);
+                dijkstra::pathfind(req, &params, map)
+            }
+            // Nothing was observed past the last bin, so the usual costs apply
+            Pathfinder::CH(_) => match self.graphs.get(bin) {
+                Some(graph) => graph.pathfind(req, map),
+                None => map.pathfinder.pathfind(req, map),
+            },
+        }
+    }
+}
@@ -37,10 +37,24 @@ enum Node {
     UberTurn(usize),
 }
 
+// The per-thread calculators can't be shared, so copies start with none.
+impl Clone for VehiclePathfinder {
+    fn clone(&self) -> VehiclePathfinder {
+        VehiclePathfinder {
+            graph: self.graph.clone(),
+            nodes: self.nodes.clone(),
+            uber_turns: self.uber_turns.clone(),
+            constraints: self.constraints,
+            path_calc: ThreadLocal::new(),
+        }
+    }
+}
+
 impl VehiclePathfinder {
     pub fn new(
         map: &Map,
         constraints: PathConstraints,
+        params: &RoutingParams,
         seed: Option<&VehiclePathfinder>,
     ) -> VehiclePathfinder {
         // Insert every road as a node.
@@ -67,7 +81,7 @@ impl VehiclePathfinder {
             }
         }
 
-        let input_graph = make_input_graph(map, &nodes, &uber_turns, constraints);
+        let input_graph = make_input_graph(map, &nodes, &uber_turns, constraints, params);
 
         // All VehiclePathfinders have the same nodes (roads), so if we're not the first being
         // built, seed from the node ordering.
@@ -133,7 +147,13 @@ impl VehiclePathfinder {
         // the node ordering.
         // TODO Make sure the result of this is deterministic and equivalent to computing from
         // scratch.
-        let input_graph = make_input_graph(map, &self.nodes, &self.uber_turns, self.constraints);
+        let input_graph = make_input_graph(
+            map,
+            &self.nodes,
+            &self.uber_turns,
+            self.constraints,
+            map.routing_params(),
+        );
         let node_ordering = self.graph.get_node_ordering();
         self.graph = fast_paths::prepare_with_order(&input_graph, &node_ordering).unwrap();
     }
@@ -144,6 +164,7 @@ fn make_input_graph(
     nodes: &NodeMap<Node>,
     uber_turns: &[UberTurnV2],
     constraints: PathConstraints,
+    params: &RoutingParams,
 ) -> InputGraph {
     let mut input_graph = InputGraph::new();
 
@@ -178,13 +199,8 @@ fn make_input_graph(
                             from,
                             nodes.get(Node::Road(mvmnt.to)),
                             round(
-                                vehicle_cost(
-                                    mvmnt.from,
-                                    mvmnt,
-                                    constraints,
-                                    map.routing_params(),
-                                    map,
-                                ) + zone_cost(mvmnt, constraints, map),
+                                vehicle_cost(mvmnt.from, mvmnt, constraints, params, map)
+                                    + zone_cost(mvmnt, constraints, map),
                             ),
                         );
                     }
@@ -194,13 +210,8 @@ fn make_input_graph(
 
                         let mut sum_cost = Duration::ZERO;
                         for mvmnt in &ut.path {
-                            sum_cost += vehicle_cost(
-                                mvmnt.from,
-                                *mvmnt,
-                                constraints,
-                                map.routing_params(),
-                                map,
-                            ) + zone_cost(*mvmnt, constraints, map);
+                            sum_cost += vehicle_cost(mvmnt.from, *mvmnt, constraints, params, map)
+                                + zone_cost(*mvmnt, constraints, map);
                         }
                         input_graph.add_edge(
                             from,
@@ -245,10 +256,21 @@ pub fn vehicle_cost(
         PathConstraints::Bike => Some(crate::MAX_BIKE_SPEED),
         PathConstraints::Pedestrian => unreachable!(),
     };
-    let t1 = map.get_r(dr.id).center_pts.length()
-        / Traversable::max_speed_along_road(dr, max_speed, constraints, map);
-    let t2 =
-        mvmnt_length / Traversable::max_speed_along_movement(mvmnt, max_speed, constraints, map);
+    let (measured_road, measured_mvmnt) = if constraints == PathConstraints::Car {
+        (
+            params.road_travel_times.get(&dr).cloned(),
+            params.movement_travel_times.get(&mvmnt).cloned(),
+        )
+    } else {
+        (None, None)
+    };
+    let t1 = measured_road.unwrap_or_else(|| {
+        map.get_r(dr.id).center_pts.length()
+            / Traversable::max_speed_along_road(dr, max_speed, constraints, map)
+    });
+    let t2 = measured_mvmnt.unwrap_or_else(|| {
+        mvmnt_length / Traversable::max_speed_along_movement(mvmnt, max_speed, constraints, map)
+    });
 
     let base = match constraints {
         PathConstraints::Car | PathConstraints::Train => t1 + t2,
@@ -280,7 +302,11 @@ pub fn vehicle_cost(
         PathConstraints::Pedestrian => unreachable!(),
     };
 
-    // Penalize unprotected turns at a stop sign from smaller to larger roads.
+    // Penalize unprotected turns at a stop sign from smaller to larger roads. Measured times
+    // already include waiting for a gap at the end of the road, so don't count that twice.
+    if measured_road.is_some() || measured_mvmnt.is_some() {
+        return base;
+    }
     let unprotected_turn_type = if map.get_config().driving_side == DrivingSide::Right {
         TurnType::Left
     } else {
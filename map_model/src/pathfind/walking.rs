@@ -60,6 +60,18 @@ impl WalkingNode {
     }
 }
 
+// The per-thread calculators can't be shared, so copies start with none.
+impl Clone for SidewalkPathfinder {
+    fn clone(&self) -> SidewalkPathfinder {
+        SidewalkPathfinder {
+            graph: self.graph.clone(),
+            nodes: self.nodes.clone(),
+            use_transit: self.use_transit,
+            path_calc: ThreadLocal::new(),
+        }
+    }
+}
+
 impl SidewalkPathfinder {
     pub fn new(map: &Map, use_transit: bool, bus_graph: &VehiclePathfinder) -> SidewalkPathfinder {
         let mut nodes = NodeMap::new();
@@ -184,6 +184,7 @@ impl PersonFactory {
         let mut output = PersonSpec {
             orig_id: None,
             trips: Vec::new(),
+            behavior: None,
         };
 
         let mut current_location = TripEndpoint::Bldg(person.home);
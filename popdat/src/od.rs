@@ -112,6 +112,7 @@ pub fn disaggregate(
                             desire.mode,
                         ),
                     ],
+                    behavior: None,
                 });
             }
         }
@@ -3,14 +3,16 @@ use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use serde::{Deserialize, Serialize};
 
 use abstutil::Counter;
-use geom::{Duration, Time};
+use geom::{Distance, Duration, Time};
 use map_model::{
-    BusRouteID, BusStopID, CompressedMovementID, IntersectionID, LaneID, Map, MovementID,
-    ParkingLotID, Path, PathRequest, RoadID, Traversable, TurnID, TurnType,
+    BuildingID, BusRoute, BusRouteID, BusStopID, CompressedMovementID, IntersectionID, LaneID, Map,
+    MovementID, ParkingLotID, ParkingPlace, Path, PathRequest, RoadID, Traversable, TurnID,
+    TurnType,
 };
 
 use crate::{
-    AgentID, AgentType, AlertLocation, CarID, Event, ParkingSpot, TripID, TripMode, TripPhaseType,
+    AgentID, AgentType, AlertLocation, CarID, ChargerLocation, Event, ParkingSpot, TripID,
+    TripMode, TripPhaseType,
 };
 
 /// As a simulation runs, different pieces emit Events. The Analytics object listens to these,
@@ -39,6 +41,16 @@ pub struct Analytics {
     /// For each passenger boarding, how long did they wait at the stop?
     pub passengers_boarding: BTreeMap<BusStopID, Vec<(Time, BusRouteID, Duration)>>,
     pub passengers_alighting: BTreeMap<BusStopID, Vec<(Time, BusRouteID)>>,
+    /// Every time somebody couldn't board because the vehicle was full
+    pub passengers_left_behind: BTreeMap<BusStopID, Vec<(Time, BusRouteID)>>,
+    /// Per route, how many passengers are aboard each vehicle as it leaves each stop
+    pub transit_loads: BTreeMap<BusRouteID, Vec<(Time, CarID, BusStopID, usize)>>,
+    /// Per route, every arrival at a stop: actual time, vehicle, stop, and scheduled time
+    pub transit_arrivals: BTreeMap<BusRouteID, Vec<(Time, CarID, BusStopID, Time)>>,
+    /// Every time a vehicle caught up to the one ahead: time, vehicle, route, and stop
+    pub transit_bunching: Vec<(Time, CarID, BusRouteID, BusStopID)>,
+    /// Every time a vehicle was held at a timepoint: time, vehicle, route, stop, and for how long
+    pub transit_holds: Vec<(Time, CarID, BusRouteID, BusStopID, Duration)>,
 
     pub started_trips: BTreeMap<TripID, Time>,
     /// Finish time, ID, mode, trip duration if successful (or None if cancelled)
@@ -58,6 +70,43 @@ pub struct Analytics {
     pub parking_lane_changes: BTreeMap<LaneID, Vec<(Time, bool)>>,
     pub parking_lot_changes: BTreeMap<ParkingLotID, Vec<(Time, bool)>>,
 
+    /// For every delivery: finish time, vehicle, building, the lane blocked while unloading, and
+    /// for how long
+    pub deliveries: Vec<(Time, CarID, BuildingID, LaneID, Duration)>,
+    /// For every finished delivery tour: finish time, vehicle, delay compared to driving with no
+    /// traffic, and total time spent blocking lanes
+    pub delivery_tours: Vec<(Time, CarID, Duration, Duration)>,
+    /// For every ride-hail pickup: time, trip, and how long the person waited
+    pub ride_hail_waits: Vec<(Time, TripID, Duration)>,
+    /// For every ride-hail dropoff: time, trip, and the detour factor -- how far the person rode,
+    /// relative to the most direct route
+    pub ride_hail_detours: Vec<(Time, TripID, f64)>,
+    /// For every ride-hail vehicle: total distance driven, and how much of that was deadheading
+    /// with nobody aboard
+    pub ride_hail_distance: BTreeMap<CarID, (Distance, Distance)>,
+    /// Per bike-share dock, how many vehicles are docked there over time
+    pub dock_occupancy: BTreeMap<BuildingID, Vec<(Time, usize)>>,
+    /// For every bike-share trip that couldn't find a vehicle or a free dock: time, trip, and the
+    /// mode used instead
+    pub bike_share_fallbacks: Vec<(Time, TripID, TripMode)>,
+    /// Per trip driven by an electric vehicle, net kilowatt-hours used so far
+    pub energy_per_trip: BTreeMap<TripID, f64>,
+    /// Per charger, how many ports are in use over time
+    pub charger_utilization: BTreeMap<ChargerLocation, Vec<(Time, usize)>>,
+    /// Per charger, total kilowatt-hours delivered
+    pub energy_charged: BTreeMap<ChargerLocation, f64>,
+    /// For every driver who detoured to a charger: time, trip, and the charger
+    pub charging_detours: Vec<(Time, TripID, ChargerLocation)>,
+    /// Per parking zone, fees collected over time, in dollars
+    pub parking_revenue: BTreeMap<String, Vec<(Time, f64)>>,
+    /// Per parking zone, how many spots with a policy are filled over time
+    pub parking_zone_occupancy: BTreeMap<String, Vec<(Time, usize)>>,
+    /// For every car that stayed past a time limit: when the owner came back, the car, and where
+    pub parking_overstays: Vec<(Time, CarID, ParkingPlace)>,
+    /// For every trip that didn't drive because parking was too expensive or too far from the car:
+    /// time, trip, and the mode used instead
+    pub parking_mode_shifts: Vec<(Time, TripID, TripMode)>,
+
     pub(crate) alerts: Vec<(Time, AlertLocation, String)>,
 
     /// For benchmarking, we may want to disable collecting data.
@@ -86,6 +135,11 @@ impl Analytics {
             bus_arrivals: Vec::new(),
             passengers_boarding: BTreeMap::new(),
             passengers_alighting: BTreeMap::new(),
+            passengers_left_behind: BTreeMap::new(),
+            transit_loads: BTreeMap::new(),
+            transit_arrivals: BTreeMap::new(),
+            transit_bunching: Vec::new(),
+            transit_holds: Vec::new(),
             started_trips: BTreeMap::new(),
             finished_trips: Vec::new(),
             problems_per_trip: BTreeMap::new(),
@@ -93,6 +147,21 @@ impl Analytics {
             intersection_delays: BTreeMap::new(),
             parking_lane_changes: BTreeMap::new(),
             parking_lot_changes: BTreeMap::new(),
+            deliveries: Vec::new(),
+            delivery_tours: Vec::new(),
+            ride_hail_waits: Vec::new(),
+            ride_hail_detours: Vec::new(),
+            ride_hail_distance: BTreeMap::new(),
+            dock_occupancy: BTreeMap::new(),
+            bike_share_fallbacks: Vec::new(),
+            energy_per_trip: BTreeMap::new(),
+            charger_utilization: BTreeMap::new(),
+            energy_charged: BTreeMap::new(),
+            charging_detours: Vec::new(),
+            parking_revenue: BTreeMap::new(),
+            parking_zone_occupancy: BTreeMap::new(),
+            parking_overstays: Vec::new(),
+            parking_mode_shifts: Vec::new(),
             alerts: Vec::new(),
             record_anything,
         }
@@ -172,6 +241,53 @@ impl Analytics {
                 .or_insert_with(Vec::new)
                 .push((time, route));
         }
+        if let Event::PassengerLeftBehind(_, _, route, stop) = ev {
+            self.passengers_left_behind
+                .entry(stop)
+                .or_insert_with(Vec::new)
+                .push((time, route));
+        }
+        if let Event::TransitVehicleLoad {
+            bus,
+            route,
+            departing,
+            passengers,
+            ..
+        } = ev
+        {
+            self.transit_loads
+                .entry(route)
+                .or_insert_with(Vec::new)
+                .push((time, bus, departing, passengers));
+        }
+        if let Event::TransitScheduleAdherence {
+            bus,
+            route,
+            stop,
+            scheduled,
+            ..
+        } = ev
+        {
+            self.transit_arrivals
+                .entry(route)
+                .or_insert_with(Vec::new)
+                .push((time, bus, stop, scheduled));
+        }
+        if let Event::TransitBunching {
+            bus, route, stop, ..
+        } = ev
+        {
+            self.transit_bunching.push((time, bus, route, stop));
+        }
+        if let Event::TransitVehicleHeld {
+            bus,
+            route,
+            stop,
+            duration,
+        } = ev
+        {
+            self.transit_holds.push((time, bus, route, stop, duration));
+        }
 
         // Started trips
         if let Event::TripPhaseStarting(id, _, _, _) = ev {
@@ -249,6 +365,131 @@ impl Analytics {
             }
         }
 
+        // Deliveries
+        if let Event::DeliveryMade(car, b, l, blocked) = ev {
+            self.deliveries.push((time, car, b, l, blocked));
+        }
+        if let Event::DeliveryTourFinished {
+            car,
+            delay,
+            lane_blocked_time,
+            ..
+        } = ev
+        {
+            self.delivery_tours
+                .push((time, car, delay, lane_blocked_time));
+        }
+
+        // Ride-hailing
+        if let Event::RideHailPickup(trip, _, wait) = ev {
+            self.ride_hail_waits.push((time, trip, wait));
+        }
+        if let Event::RideHailDropoff {
+            trip,
+            ride_distance,
+            direct_distance,
+            ..
+        } = ev
+        {
+            if direct_distance > Distance::ZERO {
+                self.ride_hail_detours
+                    .push((time, trip, ride_distance / direct_distance));
+            }
+        }
+        if let Event::RideHailVehicleMoved {
+            car,
+            distance,
+            deadheading,
+        } = ev
+        {
+            let entry = self
+                .ride_hail_distance
+                .entry(car)
+                .or_insert((Distance::ZERO, Distance::ZERO));
+            entry.0 += distance;
+            if deadheading {
+                entry.1 += distance;
+            }
+        }
+
+        // Bike-share
+        if let Event::DockOccupancyChanged { dock, vehicles, .. } = ev {
+            self.dock_occupancy
+                .entry(dock)
+                .or_insert_with(Vec::new)
+                .push((time, vehicles));
+        }
+        if let Event::BikeShareUnavailable(trip, mode) = ev {
+            self.bike_share_fallbacks.push((time, trip, mode));
+        }
+
+        // Electric vehicles
+        if let Event::EnergyUsed {
+            trip: Some(trip),
+            kwh,
+            ..
+        } = ev
+        {
+            *self.energy_per_trip.entry(trip).or_insert(0.0) += kwh;
+        }
+        if let Event::ChargerOccupancyChanged {
+            charger, in_use, ..
+        } = ev
+        {
+            self.charger_utilization
+                .entry(charger)
+                .or_insert_with(Vec::new)
+                .push((time, in_use));
+        }
+        if let Event::BatteryCharged { charger, kwh, .. } = ev {
+            *self.energy_charged.entry(charger).or_insert(0.0) += kwh;
+        }
+        if let Event::ChargingDetour(trip, charger) = ev {
+            self.charging_detours.push((time, trip, charger));
+        }
+
+        // Parking policies
+        if let Event::ParkingFeePaid {
+            car,
+            place,
+            fee,
+            overstayed,
+        } = ev
+        {
+            if let Some(policy) = map.get_parking_policy(place) {
+                self.parking_revenue
+                    .entry(policy.zone.clone())
+                    .or_insert_with(Vec::new)
+                    .push((time, fee));
+            }
+            if overstayed {
+                self.parking_overstays.push((time, car, place));
+            }
+        }
+        let zone_change = match ev {
+            Event::CarReachedParkingSpot(_, spot) => Some((spot, true)),
+            Event::CarLeftParkingSpot(_, spot) => Some((spot, false)),
+            _ => None,
+        };
+        if let Some((spot, filled)) = zone_change {
+            if let Some(policy) = map.get_parking_policy(spot.place(map)) {
+                let pts = self
+                    .parking_zone_occupancy
+                    .entry(policy.zone.clone())
+                    .or_insert_with(Vec::new);
+                let mut cnt = pts.last().map(|(_, cnt)| *cnt).unwrap_or(0);
+                if filled {
+                    cnt += 1;
+                } else {
+                    cnt -= 1;
+                }
+                pts.push((time, cnt));
+            }
+        }
+        if let Event::ParkingTooExpensive(trip, mode) = ev {
+            self.parking_mode_shifts.push((time, trip, mode));
+        }
+
         // Safety metrics
         if let Event::AgentEntersTraversable(a, Some(trip), Traversable::Turn(t), _) = ev {
             if a.to_type() == AgentType::Bike && map.get_i(t.parent).roads.len() > 4 {
@@ -301,6 +542,23 @@ impl Analytics {
         }
     }
 
+    /// Occupancy only changes as cars arrive and leave, so start from the cars already parked in
+    /// each zone.
+    pub fn record_initial_parking(&mut self, time: Time, filled: Vec<ParkingSpot>, map: &Map) {
+        if !self.record_anything {
+            return;
+        }
+        let mut per_zone: BTreeMap<String, usize> = BTreeMap::new();
+        for spot in filled {
+            if let Some(policy) = map.get_parking_policy(spot.place(map)) {
+                *per_zone.entry(policy.zone.clone()).or_insert(0) += 1;
+            }
+        }
+        for (zone, cnt) in per_zone {
+            self.parking_zone_occupancy.insert(zone, vec![(time, cnt)]);
+        }
+    }
+
     pub fn record_demand(&mut self, path: &Path, map: &Map) {
         for step in path.get_steps() {
             if let Traversable::Turn(t) = step.as_traversable() {
@@ -449,6 +707,82 @@ impl Analytics {
         pts
     }
 
+    /// For each stop along a route, the average and maximum number of passengers aboard vehicles
+    /// leaving that stop, until the next one. Stops with no departures yet are None.
+    pub fn transit_load_profile(&self, route: &BusRoute) -> Vec<(BusStopID, Option<(f64, usize)>)> {
+        let mut per_stop: BTreeMap<BusStopID, Vec<usize>> = BTreeMap::new();
+        if let Some(loads) = self.transit_loads.get(&route.id) {
+            for (_, _, stop, passengers) in loads {
+                per_stop
+                    .entry(*stop)
+                    .or_insert_with(Vec::new)
+                    .push(*passengers);
+            }
+        }
+        route
+            .stops
+            .iter()
+            .map(|bs| {
+                let summary = per_stop.get(bs).map(|loads| {
+                    let avg = loads.iter().sum::<usize>() as f64 / loads.len() as f64;
+                    (avg, loads.iter().max().cloned().unwrap())
+                });
+                (*bs, summary)
+            })
+            .collect()
+    }
+
+    /// The fraction of arrivals along a route that were on time -- no more than 1 minute early or
+    /// 5 minutes late, following the Transit Capacity and Quality of Service Manual. None if no
+    /// vehicle has arrived anywhere yet.
+    pub fn on_time_performance(&self, route: BusRouteID) -> Option<f64> {
+        let arrivals = self.transit_arrivals.get(&route)?;
+        if arrivals.is_empty() {
+            return None;
+        }
+        let on_time = arrivals
+            .iter()
+            .filter(|(actual, _, _, scheduled)| {
+                *scheduled - *actual <= Duration::minutes(1)
+                    && *actual - *scheduled <= Duration::minutes(5)
+            })
+            .count();
+        Some((on_time as f64) / (arrivals.len() as f64))
+    }
+
+    /// How evenly spaced vehicles along a route are: the coefficient of variation of headway
+    /// deviations, comparing the gap between consecutive arrivals at each stop to the scheduled
+    /// gap. 0 is perfectly regular; bunching pushes this up. None if no stop has seen two
+    /// vehicles yet.
+    pub fn headway_regularity(&self, route: BusRouteID) -> Option<f64> {
+        let mut per_stop: BTreeMap<BusStopID, Vec<(Time, Time)>> = BTreeMap::new();
+        for (actual, _, stop, scheduled) in self.transit_arrivals.get(&route)? {
+            per_stop
+                .entry(*stop)
+                .or_insert_with(Vec::new)
+                .push((*actual, *scheduled));
+        }
+
+        let mut deviations = Vec::new();
+        let mut total_scheduled = Duration::ZERO;
+        for (_, mut arrivals) in per_stop {
+            arrivals.sort();
+            for pair in arrivals.windows(2) {
+                let actual_headway = pair[1].0 - pair[0].0;
+                let scheduled_headway = pair[1].1 - pair[0].1;
+                deviations.push((actual_headway - scheduled_headway).inner_seconds());
+                total_scheduled += scheduled_headway;
+            }
+        }
+        if deviations.is_empty() || total_scheduled <= Duration::ZERO {
+            return None;
+        }
+        let n = deviations.len() as f64;
+        let mean = deviations.iter().sum::<f64>() / n;
+        let variance = deviations.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+        Some(variance.sqrt() / (total_scheduled.inner_seconds() / n))
+    }
+
     /// Returns the free spots over time
     pub fn parking_lane_availability(
         &self,
@@ -0,0 +1,173 @@
+//! Not everybody drives, cycles, or walks the same way. A `BehaviorProfile` describes one person,
+//! and the simulation mechanics consult it per agent.
+
+use serde::{Deserialize, Serialize};
+
+use geom::{Distance, Duration, Speed};
+
+/// How a person behaves while driving, cycling, or walking. Anything left as None is filled out
+/// randomly, the same way as for people without a profile.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct BehaviorProfile {
+    pub walking_speed: Option<Speed>,
+    /// The speed on flat ground
+    pub cycling_speed: Option<Speed>,
+    /// The length of any car this person uses
+    pub car_length: Option<Distance>,
+    pub vehicle: VehicleBehavior,
+}
+
+impl BehaviorProfile {
+    /// Typical behavior, matching people without a profile.
+    pub fn typical() -> BehaviorProfile {
+        BehaviorProfile {
+            walking_speed: None,
+            cycling_speed: None,
+            car_length: None,
+            vehicle: VehicleBehavior::typical(),
+        }
+    }
+
+    /// Electric assistance means a faster cyclist who barely notices hills.
+    pub fn e_bike() -> BehaviorProfile {
+        BehaviorProfile {
+            cycling_speed: Some(Speed::miles_per_hour(15.0)),
+            vehicle: VehicleBehavior {
+                hill_sensitivity: 0.1,
+                ..VehicleBehavior::typical()
+            },
+            ..BehaviorProfile::typical()
+        }
+    }
+
+    /// Slower walking, and more hesitant about unprotected turns.
+    pub fn elderly() -> BehaviorProfile {
+        BehaviorProfile {
+            walking_speed: Some(Speed::miles_per_hour(1.5)),
+            vehicle: VehicleBehavior {
+                reaction_time: Duration::seconds(1.5),
+                gap_acceptance: GapAcceptance::Cautious,
+                ..VehicleBehavior::typical()
+            },
+            ..BehaviorProfile::typical()
+        }
+    }
+
+    /// A longer, heavier vehicle that's slow to get moving.
+    pub fn delivery_van() -> BehaviorProfile {
+        BehaviorProfile {
+            car_length: Some(Distance::meters(7.5)),
+            vehicle: VehicleBehavior {
+                reaction_time: Duration::seconds(1.0),
+                max_acceleration: Some(1.5),
+                ..VehicleBehavior::typical()
+            },
+            ..BehaviorProfile::typical()
+        }
+    }
+}
+
+/// How somebody drives or cycles a vehicle.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct VehicleBehavior {
+    /// After being stopped, how long until the vehicle starts moving again
+    pub reaction_time: Duration,
+    /// In meters per second squared. None means the vehicle reaches full speed immediately.
+    pub max_acceleration: Option<f64>,
+    pub gap_acceptance: GapAcceptance,
+    /// Only affects bikes. 1.0 means hills slow the rider down as much as usual, and 0.0 means
+    /// hills don't matter at all.
+    pub hill_sensitivity: f64,
+    /// At all speeds (including at rest), the vehicle stays at least this far behind whatever's in
+    /// front of it, measured from its front to the other's back.
+    pub following_distance: Distance,
+    /// The shortest time to move over into an adjacent lane
+    pub lane_change_time: Duration,
+}
+
+impl VehicleBehavior {
+    /// No reaction time, instant acceleration, the usual pauses before unprotected turns, 1m
+    /// behind the vehicle in front, and 1s to change lanes.
+    pub fn typical() -> VehicleBehavior {
+        VehicleBehavior {
+            reaction_time: Duration::ZERO,
+            max_acceleration: None,
+            gap_acceptance: GapAcceptance::Normal,
+            hill_sensitivity: 1.0,
+            following_distance: Distance::meters(1.0),
+            lane_change_time: Duration::seconds(1.0),
+        }
+    }
+
+    /// How long it takes to cover some distance starting from a stop, assuming nothing's in the
+    /// way.
+    pub(crate) fn time_to_cross_from_rest(&self, dist: Distance, speed: Speed) -> Duration {
+        let cruising = match self.max_acceleration {
+            Some(accel) => {
+                let v = speed.inner_meters_per_second();
+                let d = dist.inner_meters();
+                // Do we reach full speed before the end?
+                let dist_to_full_speed = v * v / (2.0 * accel);
+                if d >= dist_to_full_speed {
+                    dist / speed + Duration::seconds(v / (2.0 * accel))
+                } else {
+                    Duration::seconds((2.0 * d / accel).sqrt())
+                }
+            }
+            None => dist / speed,
+        };
+        self.reaction_time + cruising
+    }
+}
+
+/// How big of a gap somebody needs before starting an unprotected turn, at a stop sign or while
+/// yielding at a traffic signal.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum GapAcceptance {
+    Cautious,
+    Normal,
+    /// Doesn't pause at all before going
+    Aggressive,
+}
+
+impl GapAcceptance {
+    /// Scales the usual pause before an unprotected turn.
+    pub(crate) fn pause_factor(self) -> f64 {
+        match self {
+            GapAcceptance::Cautious => 4.0,
+            GapAcceptance::Normal => 1.0,
+            GapAcceptance::Aggressive => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_to_cross_from_rest() {
+        let speed = Speed::meters_per_second(10.0);
+        let typical = VehicleBehavior::typical();
+        assert_eq!(
+            Duration::seconds(10.0),
+            typical.time_to_cross_from_rest(Distance::meters(100.0), speed)
+        );
+
+        // Takes 5s and 25m to reach full speed, then 7.5s to go the remaining 75m
+        let slow = VehicleBehavior {
+            reaction_time: Duration::seconds(1.0),
+            max_acceleration: Some(2.0),
+            ..VehicleBehavior::typical()
+        };
+        assert_eq!(
+            Duration::seconds(13.5),
+            slow.time_to_cross_from_rest(Distance::meters(100.0), speed)
+        );
+        // Never reaches full speed
+        assert_eq!(
+            Duration::seconds(4.0),
+            slow.time_to_cross_from_rest(Distance::meters(9.0), speed)
+        );
+    }
+}
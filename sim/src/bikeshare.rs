@@ -0,0 +1,228 @@
+//! Shared bikes and scooters are rented from one docking station and returned to another. People
+//! walk to a dock near their origin, ride to a dock near their destination, and walk the rest of
+//! the way. When somebody sets off, they reserve a vehicle at the first dock and a free slot at
+//! the second, the same way most bike-share apps let you. If no dock nearby has a vehicle or a
+//! free slot, the trip falls back to another mode.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Speed};
+use map_model::{BuildingID, Map, Position};
+
+use crate::{BikeShareDock, Event, SidewalkSpot, TripID};
+
+/// How far somebody is willing to walk to or from a dock, as the crow flies
+const MAX_WALK_TO_DOCK: Distance = Distance::const_meters(800.0);
+/// Shared scooters are usually governed to this speed
+const MAX_SCOOTER_SPEED: Speed = Speed::const_meters_per_second(6.7);
+
+/// What kind of vehicle a dock holds
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SharedVehicleType {
+    Bike,
+    Scooter,
+}
+
+impl SharedVehicleType {
+    /// Limits how fast the rider can go, if the vehicle is slower than they'd otherwise ride.
+    pub(crate) fn max_speed(self) -> Option<Speed> {
+        match self {
+            SharedVehicleType::Bike => None,
+            SharedVehicleType::Scooter => Some(MAX_SCOOTER_SPEED),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Dock {
+    vehicle_type: SharedVehicleType,
+    capacity: usize,
+    /// Vehicles physically locked in the dock right now
+    docked: usize,
+    /// Docked vehicles that somebody is walking over to unlock
+    reserved_vehicles: usize,
+    /// Free slots that somebody is riding over to fill
+    reserved_slots: usize,
+}
+
+impl Dock {
+    fn available_vehicles(&self) -> usize {
+        self.docked - self.reserved_vehicles
+    }
+
+    fn free_slots(&self) -> usize {
+        self.capacity - self.docked - self.reserved_slots
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Rental {
+    pickup: BuildingID,
+    dropoff: BuildingID,
+    /// Has the vehicle left the first dock yet?
+    unlocked: bool,
+}
+
+/// Tracks how many vehicles are in each dock, and who's reserved what.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct BikeShareSimState {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    docks: BTreeMap<BuildingID, Dock>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    rentals: BTreeMap<TripID, Rental>,
+
+    events: Vec<Event>,
+}
+
+impl BikeShareSimState {
+    pub fn new() -> BikeShareSimState {
+        BikeShareSimState {
+            docks: BTreeMap::new(),
+            rentals: BTreeMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn add_dock(&mut self, spec: &BikeShareDock) {
+        if self.docks.contains_key(&spec.building) {
+            warn!(
+                "{} has more than one bike-share dock; using the last",
+                spec.building
+            );
+        }
+        let docked = spec.vehicles.min(spec.capacity);
+        self.docks.insert(
+            spec.building,
+            Dock {
+                vehicle_type: spec.vehicle_type,
+                capacity: spec.capacity,
+                docked,
+                reserved_vehicles: 0,
+                reserved_slots: 0,
+            },
+        );
+        self.events.push(Event::DockOccupancyChanged {
+            dock: spec.building,
+            vehicles: docked,
+            capacity: spec.capacity,
+        });
+    }
+
+    /// Picks the closest dock to the start with a vehicle available, and the closest dock to the
+    /// goal with a free slot. Doesn't reserve anything yet.
+    pub fn find_docks(
+        &self,
+        start: &SidewalkSpot,
+        goal: &SidewalkSpot,
+        map: &Map,
+    ) -> Result<(BuildingID, BuildingID)> {
+        let pickup = self
+            .closest_dock(start.sidewalk_pos, map, |dock| {
+                dock.available_vehicles() > 0
+            })
+            .ok_or_else(|| anyhow!("no dock near the start has a vehicle available"))?;
+        let dropoff = self
+            .closest_dock(goal.sidewalk_pos, map, |dock| dock.free_slots() > 0)
+            .ok_or_else(|| anyhow!("no dock near the goal has a free slot"))?;
+        if pickup == dropoff {
+            bail!("the same dock, {}, is closest to both ends", pickup);
+        }
+        let spot1 = SidewalkSpot::bike_rack(pickup, map)
+            .ok_or_else(|| anyhow!("can't start riding from the dock at {}", pickup))?;
+        let spot2 = SidewalkSpot::bike_rack(dropoff, map)
+            .ok_or_else(|| anyhow!("can't ride to the dock at {}", dropoff))?;
+        if spot1.sidewalk_pos.lane() == spot2.sidewalk_pos.lane() {
+            bail!(
+                "the docks at {} and {} are on the same sidewalk",
+                pickup,
+                dropoff
+            );
+        }
+        Ok((pickup, dropoff))
+    }
+
+    fn closest_dock<F: Fn(&Dock) -> bool>(
+        &self,
+        pos: Position,
+        map: &Map,
+        usable: F,
+    ) -> Option<BuildingID> {
+        let pt = pos.pt(map);
+        self.docks
+            .iter()
+            .filter(|(_, dock)| usable(dock))
+            .map(|(b, _)| (*b, map.get_b(*b).label_center.dist_to(pt)))
+            .filter(|(_, dist)| *dist <= MAX_WALK_TO_DOCK)
+            .min_by_key(|(_, dist)| *dist)
+            .map(|(b, _)| b)
+    }
+
+    pub fn reserve(&mut self, trip: TripID, pickup: BuildingID, dropoff: BuildingID) {
+        self.docks.get_mut(&pickup).unwrap().reserved_vehicles += 1;
+        self.docks.get_mut(&dropoff).unwrap().reserved_slots += 1;
+        self.rentals.insert(
+            trip,
+            Rental {
+                pickup,
+                dropoff,
+                unlocked: false,
+            },
+        );
+    }
+
+    /// The person reached the first dock and takes their reserved vehicle out.
+    pub fn unlock(&mut self, trip: TripID) -> SharedVehicleType {
+        let rental = self.rentals.get_mut(&trip).unwrap();
+        rental.unlocked = true;
+        let dock = self.docks.get_mut(&rental.pickup).unwrap();
+        dock.docked -= 1;
+        dock.reserved_vehicles -= 1;
+        self.events.push(Event::DockOccupancyChanged {
+            dock: rental.pickup,
+            vehicles: dock.docked,
+            capacity: dock.capacity,
+        });
+        dock.vehicle_type
+    }
+
+    /// The person reached the second dock and locks the vehicle in.
+    pub fn lock(&mut self, trip: TripID) {
+        let rental = self.rentals.remove(&trip).unwrap();
+        let dock = self.docks.get_mut(&rental.dropoff).unwrap();
+        dock.docked += 1;
+        dock.reserved_slots -= 1;
+        self.events.push(Event::DockOccupancyChanged {
+            dock: rental.dropoff,
+            vehicles: dock.docked,
+            capacity: dock.capacity,
+        });
+    }
+
+    /// Releases anything reserved for a cancelled trip. If the vehicle was already unlocked, it's
+    /// gone for the rest of the day.
+    pub fn trip_cancelled(&mut self, trip: TripID) {
+        if let Some(rental) = self.rentals.remove(&trip) {
+            if !rental.unlocked {
+                self.docks
+                    .get_mut(&rental.pickup)
+                    .unwrap()
+                    .reserved_vehicles -= 1;
+            }
+            self.docks.get_mut(&rental.dropoff).unwrap().reserved_slots -= 1;
+        }
+    }
+
+    pub fn collect_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+}
@@ -1,14 +1,41 @@
 //! A simple tool that just runs a simulation for the specified number of hours. Use for profiling
 //! and benchmarking.
+//!
+//! `--record_travel_times=path` also measures how long cars take to cross roads each hour, and
+//! saves that as a `TravelTimeProfile`. Pass it to later runs with `--travel_times=path`.
+//!
+//! `--record_emissions=path` estimates emissions and traffic noise per road and hour, and saves
+//! that as `Emissions`. `--emission_factors=path` loads the lookup table from a JSON or binary
+//! file; otherwise typical factors are used.
+//!
+//! `--record_transit_log=path` logs when every transit vehicle reaches and leaves each stop, and
+//! saves that as a `TransitLog`. Use `transit_to_gtfs` in the importer to export it.
 
 fn main() {
     let mut args = abstutil::CmdArgs::new();
     let interruptible = args.enabled("--interruptible");
     let hours = geom::Duration::hours(args.required("--hours").parse::<usize>().unwrap());
+    let record_travel_times = args.optional("--record_travel_times");
+    let record_emissions = args.optional("--record_emissions");
+    let emission_factors = args.optional("--emission_factors");
+    let record_transit_log = args.optional("--record_transit_log");
     let (mut map, mut sim, _) =
-        sim::SimFlags::from_args(&mut args).load(&mut abstutil::Timer::new("setup"));
+        sim::SimFlags::from_args(&mut args).load_synchronously(&mut abstutil::Timer::new("setup"));
     args.done();
 
+    if record_travel_times.is_some() {
+        sim.record_travel_times(geom::Duration::hours(1));
+    }
+    if record_emissions.is_some() {
+        sim.record_emissions(match emission_factors {
+            Some(path) => abstio::read_object(path, &mut abstutil::Timer::throwaway()).unwrap(),
+            None => sim::EmissionFactors::typical(),
+        });
+    }
+    if record_transit_log.is_some() {
+        sim.record_transit_log();
+    }
+
     if interruptible {
         // Pressing ^C will savestate. This needs a more complex loop to check for the interrupt.
         // This is guarded by the --interruptible flag to keep the benchmarking case simple.
@@ -38,6 +65,12 @@ fn main() {
                 &mut None,
             );
             if sim.time() == goal_time {
+                maybe_save_results(
+                    &sim,
+                    record_travel_times,
+                    record_emissions,
+                    record_transit_log,
+                );
                 return;
             }
         }
@@ -51,5 +84,28 @@ fn main() {
             &mut None,
             &mut abstutil::Timer::new("run simulation"),
         );
+        maybe_save_results(
+            &sim,
+            record_travel_times,
+            record_emissions,
+            record_transit_log,
+        );
+    }
+}
+
+fn maybe_save_results(
+    sim: &sim::Sim,
+    travel_times: Option<String>,
+    emissions: Option<String>,
+    transit_log: Option<String>,
+) {
+    if let Some(path) = travel_times {
+        abstio::write_binary(path, sim.get_recorded_travel_times().unwrap());
+    }
+    if let Some(path) = emissions {
+        abstio::write_binary(path, sim.get_recorded_emissions().unwrap());
+    }
+    if let Some(path) = transit_log {
+        abstio::write_binary(path, sim.get_recorded_transit_log().unwrap());
     }
 }
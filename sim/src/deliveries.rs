@@ -0,0 +1,200 @@
+//! Delivery vehicles follow a tour, stopping at buildings to unload. While unloading, they occupy a
+//! free curbside parking spot if there's one close to the stop, or otherwise double-park and block
+//! the driving lane, like a bus at a stop.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Duration, Time};
+use map_model::{BuildingID, LaneID, Map, PathConstraints, PathRequest, Position};
+
+use crate::sim::Ctx;
+use crate::{
+    AlertLocation, CarID, Command, CreateCar, DeliveryTour, Event, ParkedCar, ParkingSim,
+    ParkingSpot, Router, TripEndpoint, TripMode, Vehicle,
+};
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Tour {
+    spec: DeliveryTour,
+    /// Indexes spec.stops. When this is past the last stop, the vehicle is returning to the depot.
+    next_stop: usize,
+    /// If the vehicle is currently unloading, then the building, the lane being blocked, and
+    /// since when
+    unloading: Option<(BuildingID, LaneID, Time)>,
+    /// How long the tour would take with no traffic: the free-flow time of every leg, plus
+    /// unloading
+    expected_time: Duration,
+    lane_blocked_time: Duration,
+}
+
+/// Manages delivery tours. Each tour has its own vehicle, driven by nobody in the scenario.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct DeliverySimState {
+    /// Tours that haven't finished yet, including ones that haven't started
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    tours: BTreeMap<CarID, Tour>,
+
+    events: Vec<Event>,
+}
+
+impl DeliverySimState {
+    pub fn new() -> DeliverySimState {
+        DeliverySimState {
+            tours: BTreeMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn seed_tour(&mut self, car: CarID, spec: DeliveryTour) {
+        self.tours.insert(
+            car,
+            Tour {
+                spec,
+                next_stop: 0,
+                unloading: None,
+                expected_time: Duration::ZERO,
+                lane_blocked_time: Duration::ZERO,
+            },
+        );
+    }
+
+    /// Returns the router for the first leg, or None if the tour can't happen at all.
+    pub fn start_tour(&mut self, now: Time, car: CarID, map: &Map) -> Option<Router> {
+        let depot = self.tours[&car].spec.depot;
+        match depot.pos(TripMode::Drive, true, map) {
+            Some(start) => self.next_leg(now, car, start, map),
+            None => {
+                self.abandon_tour(car, format!("{} can't start at {:?}", car, depot));
+                None
+            }
+        }
+    }
+
+    /// The vehicle reached the end of a leg. Returns how long to unload there, or None if the tour
+    /// is finished and the vehicle should vanish into the depot.
+    pub fn arrived(&mut self, now: Time, car: CarID, blocking: LaneID) -> Option<Duration> {
+        let tour = self.tours.get_mut(&car).unwrap();
+        if let Some(stop) = tour.spec.stops.get(tour.next_stop) {
+            tour.unloading = Some((stop.building, blocking, now));
+            tour.next_stop += 1;
+            return Some(stop.dwell_time);
+        }
+
+        let tour = self.tours.remove(&car).unwrap();
+        let total_time = now - tour.spec.depart;
+        self.events.push(Event::DeliveryTourFinished {
+            car,
+            total_time,
+            delay: (total_time - tour.expected_time).max(Duration::ZERO),
+            lane_blocked_time: tour.lane_blocked_time,
+        });
+        None
+    }
+
+    /// The vehicle parked at a curbside spot for its stop, after starting to maneuver in at
+    /// `since`. After unloading, it'll unpark and continue.
+    pub fn parked(
+        &mut self,
+        now: Time,
+        since: Time,
+        vehicle: Vehicle,
+        spot: ParkingSpot,
+        ctx: &mut Ctx,
+    ) {
+        let lane = match spot {
+            ParkingSpot::Onstreet(l, _) => l,
+            ParkingSpot::Offstreet(_, _) | ParkingSpot::Lot(_, _) => unreachable!(),
+        };
+        let dwell_time = self.arrived(since, vehicle.id, lane).unwrap();
+        let start = ctx.parking.spot_to_driving_pos(spot, &vehicle, ctx.map);
+        if let Some(router) = self.next_leg(now + dwell_time, vehicle.id, start, ctx.map) {
+            let parked_car = ParkedCar {
+                vehicle,
+                spot,
+                parked_since: now,
+            };
+            ctx.parking.add_parked_car(parked_car.clone());
+            ctx.scheduler.push(
+                now + dwell_time,
+                Command::SpawnCar(
+                    CreateCar {
+                        vehicle: parked_car.vehicle.clone(),
+                        router,
+                        maybe_parked_car: Some(parked_car),
+                        trip_and_person: None,
+                        maybe_route: None,
+                    },
+                    true,
+                ),
+            );
+        } else {
+            ctx.parking.unreserve_spot(vehicle.id);
+        }
+    }
+
+    /// The vehicle is done unloading and has just started moving again.
+    pub fn done_unloading(&mut self, now: Time, car: CarID) {
+        let tour = self.tours.get_mut(&car).unwrap();
+        let (b, lane, since) = tour.unloading.take().unwrap();
+        tour.lane_blocked_time += now - since;
+        self.events
+            .push(Event::DeliveryMade(car, b, lane, now - since));
+    }
+
+    /// Plans the route from somewhere to the next stop, or back to the depot, leaving at `now`. If
+    /// that's impossible, gives up on the tour and returns None.
+    pub fn next_leg(
+        &mut self,
+        now: Time,
+        car: CarID,
+        start: Position,
+        map: &Map,
+    ) -> Option<Router> {
+        match self.plan_next_leg(now, car, start, map) {
+            Ok(router) => Some(router),
+            Err(err) => {
+                self.abandon_tour(car, format!("{} is giving up on its tour: {}", car, err));
+                None
+            }
+        }
+    }
+
+    fn plan_next_leg(
+        &mut self,
+        now: Time,
+        car: CarID,
+        start: Position,
+        map: &Map,
+    ) -> Result<Router> {
+        let tour = self.tours.get_mut(&car).unwrap();
+        let (to, target) = match tour.spec.stops.get(tour.next_stop) {
+            Some(stop) => (TripEndpoint::Bldg(stop.building), Some(stop.building)),
+            None => (tour.spec.depot, None),
+        };
+        let end = to
+            .pos(TripMode::Drive, false, map)
+            .ok_or_else(|| anyhow!("can't drive to {:?}", to))?;
+        let path = map.pathfind_at(PathRequest::vehicle(start, end, PathConstraints::Car), now)?;
+        tour.expected_time += path.estimate_duration(map, PathConstraints::Car, None);
+        if let Some(stop) = tour.spec.stops.get(tour.next_stop) {
+            tour.expected_time += stop.dwell_time;
+        }
+        Ok(Router::deliver(car, path, target))
+    }
+
+    pub fn abandon_tour(&mut self, car: CarID, msg: String) {
+        self.tours.remove(&car);
+        self.events.push(Event::Alert(AlertLocation::Nil, msg));
+    }
+
+    pub fn collect_events(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, Speed, Time};
+use map_model::{LaneID, Map, Position, Traversable};
+
+use crate::{AgentID, CarID, DrivingSimState, Event};
+
+/// A virtual detector somewhere along a lane, emulating an induction loop or a counting station.
+/// It measures vehicles, not pedestrians.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
+pub struct Detector {
+    /// Used when exporting readings
+    pub name: String,
+    /// Where the detector starts
+    pub pos: Position,
+    /// How far the detector extends along the lane. Occupancy counts any time part of a vehicle
+    /// is over it.
+    pub length: Distance,
+    /// Readings are grouped into bins of this duration, starting at midnight.
+    pub bin_width: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
+pub struct DetectorID(pub usize);
+
+/// What a detector measured during one time bin.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
+pub struct DetectorReading {
+    pub start: Time,
+    pub end: Time,
+    /// How many vehicles passed the detector
+    pub count: usize,
+    /// The fraction of the bin, from 0 to 1, that some vehicle was over the detector
+    pub occupancy: f64,
+    /// The average speed of the vehicles passing. None if nobody passed.
+    pub mean_speed: Option<Speed>,
+}
+
+/// Tracks vehicles crossing detectors.
+///
+/// Vehicles don't move at a constant speed in this simulation, and their exact position is only
+/// calculated when needed. So when a vehicle crosses an entire lane containing a detector, it's
+/// treated as if it had driven the lane at its average speed. This is reasonable for counts, but
+/// the exact times and speeds are approximations. Vehicles that start or end their trip in the
+/// middle of the lane aren't counted at all. `is_occupied` is exact, though, so signal control
+/// can rely on it.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct DetectorSimState {
+    detectors: Vec<DetectorState>,
+    per_lane: BTreeMap<LaneID, Vec<DetectorID>>,
+    /// Vehicles currently on a lane with a detector. The time they entered, and their length
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    entered: BTreeMap<CarID, (LaneID, Time, Distance)>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct DetectorState {
+    spec: Detector,
+    bins: Vec<Bin>,
+    last_passage: Option<Time>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Bin {
+    count: usize,
+    occupied: Duration,
+    sum_speeds: f64,
+}
+
+impl DetectorSimState {
+    pub fn new() -> DetectorSimState {
+        DetectorSimState {
+            detectors: Vec::new(),
+            per_lane: BTreeMap::new(),
+            entered: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, spec: Detector, map: &Map) -> Result<DetectorID> {
+        let lane = map.get_l(spec.pos.lane());
+        if !lane.lane_type.is_for_moving_vehicles() {
+            bail!("{} isn't a lane for vehicles", lane.id);
+        }
+        if spec.pos.dist_along() + spec.length > lane.length() {
+            bail!(
+                "Detector {} at {} of length {} doesn't fit on {}",
+                spec.name,
+                spec.pos,
+                spec.length,
+                lane.id
+            );
+        }
+        if spec.bin_width <= Duration::ZERO {
+            bail!("Detector {} needs a positive bin width", spec.name);
+        }
+
+        let id = DetectorID(self.detectors.len());
+        self.per_lane
+            .entry(spec.pos.lane())
+            .or_insert_with(Vec::new)
+            .push(id);
+        self.detectors.push(DetectorState {
+            spec,
+            bins: Vec::new(),
+            last_passage: None,
+        });
+        Ok(id)
+    }
+
+    pub fn handle_event(&mut self, time: Time, ev: &Event, map: &Map, driving: &DrivingSimState) {
+        if self.detectors.is_empty() {
+            return;
+        }
+        match ev {
+            Event::AgentEntersTraversable(AgentID::Car(car), _, on, _) => {
+                // Moving to the next step means the vehicle finished crossing the lane.
+                if let Some((l, entered_at, vehicle_len)) = self.entered.remove(car) {
+                    self.record_passages(l, entered_at, time, vehicle_len, map);
+                }
+                if let Traversable::Lane(l) = on {
+                    if self.per_lane.contains_key(l) {
+                        if let Some(len) = driving.vehicle_length(*car) {
+                            self.entered.insert(*car, (*l, time, len));
+                        }
+                    }
+                }
+            }
+            Event::PersonLeavesMap(_, Some(AgentID::Car(car)), _) => {
+                // Vanishing at a border still means the whole lane was crossed
+                if let Some((l, entered_at, vehicle_len)) = self.entered.remove(car) {
+                    self.record_passages(l, entered_at, time, vehicle_len, map);
+                }
+            }
+            Event::CarReachedParkingSpot(car, _) | Event::BikeStoppedAtSidewalk(car, _) => {
+                self.entered.remove(car);
+            }
+            _ => {}
+        }
+    }
+
+    fn record_passages(
+        &mut self,
+        l: LaneID,
+        entered: Time,
+        left: Time,
+        vehicle_len: Distance,
+        map: &Map,
+    ) {
+        let lane_len = map.get_l(l).length();
+        let dt = left - entered;
+        if dt <= Duration::ZERO || lane_len <= Distance::ZERO {
+            return;
+        }
+        let speed = Speed::from_dist_time(lane_len, dt);
+        for id in &self.per_lane[&l] {
+            let detector = &mut self.detectors[id.0];
+            let bin_width = detector.spec.bin_width;
+            let arrive = entered + dt * (detector.spec.pos.dist_along() / lane_len);
+            let occupied = (detector.spec.length + vehicle_len) / speed;
+
+            let idx = bin_idx(arrive, bin_width);
+            let bin = get_bin(&mut detector.bins, idx);
+            bin.count += 1;
+            bin.sum_speeds += speed.inner_meters_per_second();
+            // Passages aren't necessarily recorded in order
+            detector.last_passage = Some(
+                detector
+                    .last_passage
+                    .map(|t| t.max(arrive))
+                    .unwrap_or(arrive),
+            );
+
+            // Spread the occupied time over as many bins as needed
+            let mut start = arrive;
+            let end = arrive + occupied;
+            while start < end {
+                let idx = bin_idx(start, bin_width);
+                let bin_end = Time::START_OF_DAY + bin_width * (idx + 1) as f64;
+                let piece = std::cmp::min(end, bin_end) - start;
+                get_bin(&mut detector.bins, idx).occupied += piece;
+                start += piece;
+                if piece <= Duration::ZERO {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Queries
+impl DetectorSimState {
+    pub fn all_detectors(&self) -> Vec<(DetectorID, &Detector)> {
+        self.detectors
+            .iter()
+            .enumerate()
+            .map(|(idx, d)| (DetectorID(idx), &d.spec))
+            .collect()
+    }
+
+    pub fn detectors_on(&self, l: LaneID) -> Vec<DetectorID> {
+        self.per_lane.get(&l).cloned().unwrap_or_default()
+    }
+
+    pub fn get(&self, id: DetectorID) -> &Detector {
+        &self.detectors[id.0].spec
+    }
+
+    /// When the last vehicle passed the detector
+    pub fn last_passage(&self, id: DetectorID) -> Option<Time> {
+        self.detectors[id.0].last_passage
+    }
+
+    /// Is some vehicle over the detector right now?
+    pub fn is_occupied(&self, id: DetectorID, now: Time, driving: &DrivingSimState) -> bool {
+        let spec = &self.detectors[id.0].spec;
+        driving.any_vehicle_over(
+            now,
+            spec.pos.lane(),
+            spec.pos.dist_along(),
+            spec.pos.dist_along() + spec.length,
+        )
+    }
+
+    /// All readings from one detector, up to `now`. Bins with no traffic are included.
+    pub fn readings(&self, id: DetectorID, now: Time) -> Vec<DetectorReading> {
+        let detector = &self.detectors[id.0];
+        let bin_width = detector.spec.bin_width;
+        let num_bins = bin_idx(now, bin_width) + 1;
+        let mut results = Vec::new();
+        for idx in 0..num_bins {
+            let start = Time::START_OF_DAY + bin_width * idx as f64;
+            let end = start + bin_width;
+            let (count, occupied, sum_speeds) = match detector.bins.get(idx) {
+                Some(bin) => (bin.count, bin.occupied, bin.sum_speeds),
+                None => (0, Duration::ZERO, 0.0),
+            };
+            // The last bin may be partial
+            let observed = std::cmp::min(end, now) - start;
+            results.push(DetectorReading {
+                start,
+                end,
+                count,
+                occupancy: if observed > Duration::ZERO {
+                    (occupied / observed).min(1.0)
+                } else {
+                    0.0
+                },
+                mean_speed: if count == 0 {
+                    None
+                } else {
+                    Some(Speed::meters_per_second(sum_speeds / count as f64))
+                },
+            });
+        }
+        results
+    }
+
+    /// Every reading from every detector, as CSV
+    pub fn to_csv(&self, now: Time) -> String {
+        // Write the header separately, so it's there even without readings
+        let mut out = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(Vec::new());
+        out.write_record(&[
+            "detector",
+            "lane",
+            "dist_along_meters",
+            "bin_start",
+            "bin_end",
+            "count",
+            "occupancy",
+            "mean_speed_mps",
+        ])
+        .unwrap();
+        for (id, spec) in self.all_detectors() {
+            for reading in self.readings(id, now) {
+                out.serialize(CsvReading {
+                    detector: &spec.name,
+                    lane: spec.pos.lane().0,
+                    dist_along_meters: spec.pos.dist_along().inner_meters(),
+                    bin_start: reading.start.to_string(),
+                    bin_end: reading.end.to_string(),
+                    count: reading.count,
+                    occupancy: reading.occupancy,
+                    mean_speed_mps: reading.mean_speed.map(|s| s.inner_meters_per_second()),
+                })
+                .unwrap();
+            }
+        }
+        String::from_utf8(out.into_inner().unwrap()).unwrap()
+    }
+}
+
+fn bin_idx(t: Time, bin_width: Duration) -> usize {
+    ((t - Time::START_OF_DAY) / bin_width).floor() as usize
+}
+
+fn get_bin(bins: &mut Vec<Bin>, idx: usize) -> &mut Bin {
+    if bins.len() <= idx {
+        bins.resize(idx + 1, Bin::default());
+    }
+    &mut bins[idx]
+}
+
+#[derive(Serialize)]
+struct CsvReading<'a> {
+    detector: &'a str,
+    lane: usize,
+    dist_along_meters: f64,
+    bin_start: String,
+    bin_end: String,
+    count: usize,
+    occupancy: f64,
+    mean_speed_mps: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_escaping() {
+        let mut state = DetectorSimState::new();
+        state.detectors.push(DetectorState {
+            spec: Detector {
+                name: "Main St, \"northbound\"".to_string(),
+                pos: Position::new(LaneID(3), Distance::meters(10.0)),
+                length: Distance::meters(2.0),
+                bin_width: Duration::minutes(15),
+            },
+            bins: vec![Bin {
+                count: 4,
+                occupied: Duration::minutes(3),
+                sum_speeds: 40.0,
+            }],
+            last_passage: None,
+        });
+
+        let now = Time::START_OF_DAY + Duration::minutes(15);
+        let csv = state.to_csv(now);
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let rows: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        // The bin ending exactly now, plus the empty one just starting
+        assert_eq!(rows.len(), 2);
+        assert_eq!(&rows[0][0], "Main St, \"northbound\"");
+        assert_eq!(&rows[0][1], "3");
+        assert_eq!(&rows[0][5], "4");
+        assert!((rows[0][6].parse::<f64>().unwrap() - 0.2).abs() < 1e-6);
+        assert!((rows[0][7].parse::<f64>().unwrap() - 10.0).abs() < 1e-6);
+        assert_eq!(&rows[1][7], "");
+    }
+}
@@ -0,0 +1,384 @@
+//! Estimates pollutants and traffic noise along each road, hour by hour. Every time a vehicle
+//! finishes crossing a lane or turn, its average speed and acceleration over that stretch pick an
+//! emission factor from a lookup table, in the style of COPERT or HBEFA. Noise comes from the
+//! hourly volume, average speed, and share of heavy vehicles along the road.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, Speed, Time};
+use map_model::{Map, RoadID, Traversable};
+
+use crate::{AgentID, CarID, Event, VehicleType};
+
+/// Emission factors per vehicle type, usually loaded from a data file.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct EmissionFactors {
+    pub tables: Vec<EmissionTable>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct EmissionTable {
+    pub vehicle_type: VehicleType,
+    /// The first row covering both the speed and acceleration applies. If none do, the last row
+    /// applies.
+    pub rows: Vec<EmissionFactor>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct EmissionFactor {
+    /// Applies to average speeds up to this, in km/h
+    pub max_speed_kmh: f64,
+    /// Applies to accelerations up to this, in m/s^2
+    pub max_accel: f64,
+    /// Grams of CO2 per kilometer
+    pub co2: f64,
+    /// Grams of nitrogen oxides per kilometer
+    pub nox: f64,
+    /// Grams of particulate matter per kilometer, including brake and tyre wear
+    pub pm: f64,
+}
+
+impl EmissionFactors {
+    /// Rough fleet averages, in the spirit of COPERT's speed curves. Good enough to compare
+    /// scenarios; load locally calibrated factors for anything more serious. Bikes and trains
+    /// don't emit anything here.
+    pub fn typical() -> EmissionFactors {
+        // Gentle and hard acceleration, at each speed
+        fn rows(speeds: Vec<(f64, f64, f64, f64)>) -> Vec<EmissionFactor> {
+            let mut rows = Vec::new();
+            for (max_speed_kmh, co2, nox, pm) in speeds {
+                rows.push(EmissionFactor {
+                    max_speed_kmh,
+                    max_accel: 0.5,
+                    co2,
+                    nox,
+                    pm,
+                });
+                rows.push(EmissionFactor {
+                    max_speed_kmh,
+                    max_accel: f64::MAX,
+                    co2: 1.5 * co2,
+                    nox: 2.0 * nox,
+                    pm: 1.5 * pm,
+                });
+            }
+            rows
+        }
+
+        let car = rows(vec![
+            (20.0, 250.0, 0.06, 0.030),
+            (50.0, 170.0, 0.04, 0.025),
+            (80.0, 140.0, 0.03, 0.020),
+            (f64::MAX, 160.0, 0.04, 0.020),
+        ]);
+        EmissionFactors {
+            tables: vec![
+                EmissionTable {
+                    vehicle_type: VehicleType::Car,
+                    rows: car.clone(),
+                },
+                EmissionTable {
+                    vehicle_type: VehicleType::RideHail,
+                    rows: car,
+                },
+                EmissionTable {
+                    vehicle_type: VehicleType::Delivery,
+                    rows: rows(vec![
+                        (20.0, 350.0, 0.50, 0.050),
+                        (50.0, 250.0, 0.35, 0.040),
+                        (80.0, 210.0, 0.30, 0.035),
+                        (f64::MAX, 230.0, 0.30, 0.035),
+                    ]),
+                },
+                EmissionTable {
+                    vehicle_type: VehicleType::Bus,
+                    rows: rows(vec![
+                        (20.0, 1500.0, 8.0, 0.10),
+                        (50.0, 1100.0, 5.0, 0.07),
+                        (80.0, 900.0, 4.0, 0.05),
+                        (f64::MAX, 900.0, 4.0, 0.05),
+                    ]),
+                },
+            ],
+        }
+    }
+
+    fn lookup(
+        &self,
+        vehicle_type: VehicleType,
+        speed: Speed,
+        accel: f64,
+    ) -> Option<&EmissionFactor> {
+        let table = self
+            .tables
+            .iter()
+            .find(|t| t.vehicle_type == vehicle_type)?;
+        let kmh = speed.inner_meters_per_second() * 3.6;
+        table
+            .rows
+            .iter()
+            .find(|row| kmh <= row.max_speed_kmh && accel <= row.max_accel)
+            .or_else(|| table.rows.last())
+    }
+}
+
+/// Everything estimated along one road during one hour.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RoadEmissions {
+    /// In grams
+    pub co2: f64,
+    pub nox: f64,
+    pub pm: f64,
+    pub vehicles: usize,
+    /// Buses, trains, and delivery vehicles
+    pub heavy_vehicles: usize,
+    /// Summed over every vehicle, to find the average speed
+    pub total_distance: Distance,
+    pub total_time: Duration,
+}
+
+impl RoadEmissions {
+    fn new() -> RoadEmissions {
+        RoadEmissions {
+            co2: 0.0,
+            nox: 0.0,
+            pm: 0.0,
+            vehicles: 0,
+            heavy_vehicles: 0,
+            total_distance: Distance::ZERO,
+            total_time: Duration::ZERO,
+        }
+    }
+
+    pub fn average_speed(&self) -> Option<Speed> {
+        if self.total_time == Duration::ZERO {
+            return None;
+        }
+        Some(Speed::from_dist_time(self.total_distance, self.total_time))
+    }
+
+    /// The hourly traffic noise level 10m from the road, in dB(A), following the basic
+    /// calculation from the UK's Calculation of Road Traffic Noise (CoRTN). This ignores road
+    /// surface, gradient, and anything between the road and the listener.
+    pub fn noise_level(&self) -> Option<f64> {
+        if self.vehicles == 0 {
+            return None;
+        }
+        // The method isn't defined for very slow traffic
+        let kmh = (self.average_speed()?.inner_meters_per_second() * 3.6).max(20.0);
+        let heavy_percent = 100.0 * (self.heavy_vehicles as f64) / (self.vehicles as f64);
+        let basic = 42.2 + 10.0 * (self.vehicles as f64).log10();
+        let speed_correction = 33.0 * (kmh + 40.0 + 500.0 / kmh).log10()
+            + 10.0 * (1.0 + 5.0 * heavy_percent / kmh).log10()
+            - 68.8;
+        Some(basic + speed_correction)
+    }
+}
+
+/// Emissions and noise estimates per road and hour of the day.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Emissions {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pub per_road: BTreeMap<(RoadID, usize), RoadEmissions>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Segment {
+    on: Traversable,
+    entered: Time,
+    /// The average speed over the previous segment, or zero if the vehicle just started
+    prev_speed: Speed,
+}
+
+/// Follows vehicles as they move, accumulating Emissions. Vehicles that start or end their trip
+/// partway along a lane aren't counted there.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct EmissionsRecorder {
+    factors: EmissionFactors,
+    results: Emissions,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    current: BTreeMap<CarID, Segment>,
+    /// Electric vehicles only wear down their brakes and tyres
+    electric: BTreeSet<CarID>,
+}
+
+impl EmissionsRecorder {
+    pub fn new(factors: EmissionFactors) -> EmissionsRecorder {
+        EmissionsRecorder {
+            factors,
+            results: Emissions {
+                per_road: BTreeMap::new(),
+            },
+            current: BTreeMap::new(),
+            electric: BTreeSet::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, time: Time, ev: &Event, map: &Map) {
+        match ev {
+            Event::AgentEntersTraversable(AgentID::Car(car), _, on, _) => {
+                let prev_speed = match self.current.remove(car) {
+                    Some(segment) => self.segment_finished(*car, segment, time, map),
+                    None => Speed::ZERO,
+                };
+                self.current.insert(
+                    *car,
+                    Segment {
+                        on: *on,
+                        entered: time,
+                        prev_speed,
+                    },
+                );
+            }
+            Event::EnergyUsed { car, .. } => {
+                self.electric.insert(*car);
+            }
+            Event::PersonLeavesMap(_, Some(AgentID::Car(car)), _)
+            | Event::CarReachedParkingSpot(car, _) => {
+                self.current.remove(car);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the average speed along the segment.
+    fn segment_finished(&mut self, car: CarID, segment: Segment, now: Time, map: &Map) -> Speed {
+        let dt = now - segment.entered;
+        let dist = segment.on.get_polyline(map).length();
+        if dt == Duration::ZERO {
+            return segment.prev_speed;
+        }
+        let speed = Speed::from_dist_time(dist, dt);
+        let accel = (speed - segment.prev_speed).inner_meters_per_second() / dt.inner_seconds();
+
+        // Turns count towards the road they leave, like the time spent waiting to make them
+        let road = match segment.on {
+            Traversable::Lane(l) => map.get_l(l).parent,
+            Traversable::Turn(t) => map.get_l(t.src).parent,
+        };
+        let entry = self
+            .results
+            .per_road
+            .entry((road, segment.entered.get_hours()))
+            .or_insert_with(RoadEmissions::new);
+        // Only count the vehicle once per road, not for every lane and turn
+        if let Traversable::Lane(_) = segment.on {
+            entry.vehicles += 1;
+            if matches!(
+                car.vehicle_type,
+                VehicleType::Bus | VehicleType::Train | VehicleType::Delivery
+            ) {
+                entry.heavy_vehicles += 1;
+            }
+        }
+        entry.total_distance += dist;
+        entry.total_time += dt;
+
+        if let Some(factor) = self.factors.lookup(car.vehicle_type, speed, accel) {
+            let km = dist.inner_meters() / 1000.0;
+            if !self.electric.contains(&car) {
+                entry.co2 += factor.co2 * km;
+                entry.nox += factor.nox * km;
+            }
+            entry.pm += factor.pm * km;
+        }
+
+        speed
+    }
+
+    pub fn get_results(&self) -> &Emissions {
+        &self.results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kmh(x: f64) -> Speed {
+        Speed::meters_per_second(x / 3.6)
+    }
+
+    #[test]
+    fn test_lookup() {
+        let factors = EmissionFactors::typical();
+        let co2 = |vehicle_type, speed, accel| {
+            factors
+                .lookup(vehicle_type, speed, accel)
+                .map(|factor| factor.co2)
+        };
+
+        assert_eq!(co2(VehicleType::Car, kmh(30.0), 0.0), Some(170.0));
+        assert_eq!(co2(VehicleType::Car, kmh(30.0), 1.0), Some(255.0));
+        // The upper bounds are inclusive
+        assert_eq!(co2(VehicleType::Car, kmh(30.0), 0.5), Some(170.0));
+        assert_eq!(co2(VehicleType::Car, kmh(10.0), 0.0), Some(250.0));
+        assert_eq!(co2(VehicleType::Car, kmh(120.0), 0.0), Some(160.0));
+        // Decelerating counts as gentle
+        assert_eq!(co2(VehicleType::Bus, kmh(60.0), -2.0), Some(900.0));
+        assert_eq!(co2(VehicleType::Bike, kmh(15.0), 0.0), None);
+
+        // Past every row, the last applies
+        let factors = EmissionFactors {
+            tables: vec![EmissionTable {
+                vehicle_type: VehicleType::Car,
+                rows: vec![
+                    EmissionFactor {
+                        max_speed_kmh: 30.0,
+                        max_accel: 1.0,
+                        co2: 200.0,
+                        nox: 0.0,
+                        pm: 0.0,
+                    },
+                    EmissionFactor {
+                        max_speed_kmh: 60.0,
+                        max_accel: 1.0,
+                        co2: 150.0,
+                        nox: 0.0,
+                        pm: 0.0,
+                    },
+                ],
+            }],
+        };
+        let factor = factors.lookup(VehicleType::Car, kmh(90.0), 2.0).unwrap();
+        assert_eq!(factor.co2, 150.0);
+    }
+
+    fn road(vehicles: usize, heavy_vehicles: usize, speed_kmh: f64) -> RoadEmissions {
+        RoadEmissions {
+            vehicles,
+            heavy_vehicles,
+            total_distance: Distance::meters(1000.0 * speed_kmh),
+            total_time: Duration::hours(1),
+            ..RoadEmissions::new()
+        }
+    }
+
+    #[test]
+    fn test_noise_level() {
+        // CoRTN's corrections vanish at 75 km/h with no heavy vehicles, leaving 42.2 + 10 log10(q)
+        let level = road(1000, 0, 75.0).noise_level().unwrap();
+        assert!((level - 72.2).abs() < 0.05, "{}", level);
+
+        // 10% heavy vehicles add 10 log10(1 + 5 * 10 / 75)
+        let level = road(1000, 100, 75.0).noise_level().unwrap();
+        assert!((level - 74.43).abs() < 0.05, "{}", level);
+
+        // Anything slower than 20 km/h is treated as 20 km/h
+        let crawling = road(100, 0, 5.0).noise_level().unwrap();
+        let slow = road(100, 0, 20.0).noise_level().unwrap();
+        assert_eq!(crawling, slow);
+        assert!((slow - 57.07).abs() < 0.05, "{}", slow);
+
+        assert_eq!(RoadEmissions::new().noise_level(), None);
+    }
+}
@@ -0,0 +1,365 @@
+//! Electric vehicles carry a battery that drains as they drive and refills while they're parked at
+//! a charger. Energy use follows a simple physical model: rolling resistance and aerodynamic drag
+//! over the distance covered, plus the work done climbing hills. Going downhill recovers some of
+//! that through regenerative braking.
+//!
+//! Chargers are plugs at some of the parking spots in a building or parking lot. EVs prefer
+//! parking at a free charger whenever there's one where they're looking. Drivers running low
+//! detour to a public charger near their destination, then walk the rest of the way and charge
+//! while they're busy there.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, Pt2D, Time};
+use map_model::{BuildingID, Direction, Map, OffstreetParking, Traversable};
+
+use crate::{
+    CarID, Charger, ChargerLocation, Event, ParkingSim, ParkingSimState, ParkingSpot, Vehicle,
+};
+
+/// Drivers below this fraction of their battery's capacity detour to a charger.
+const WANT_TO_CHARGE: f64 = 0.2;
+/// How far somebody is willing to walk from a charger to their destination, as the crow flies
+const MAX_WALK_FROM_CHARGER: Distance = Distance::const_meters(800.0);
+
+// Physical parameters of a typical passenger EV
+const MASS_KG: f64 = 1800.0;
+const GRAVITY: f64 = 9.81;
+const ROLLING_RESISTANCE: f64 = 0.01;
+/// The drag coefficient times frontal area, in square meters
+const DRAG_AREA: f64 = 0.6;
+const AIR_DENSITY: f64 = 1.2;
+/// How much of the energy drawn from the battery reaches the wheels
+const DRIVETRAIN_EFFICIENCY: f64 = 0.9;
+/// How much of the energy spent going downhill makes it back into the battery
+const REGEN_EFFICIENCY: f64 = 0.6;
+/// Climate control, lights, and everything else, in kilowatts
+const AUXILIARY_POWER_KW: f64 = 0.5;
+const JOULES_PER_KWH: f64 = 3.6e6;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Battery {
+    /// Usable capacity, in kilowatt-hours
+    pub capacity_kwh: f64,
+    /// How much is stored right now, in kilowatt-hours
+    pub charge_kwh: f64,
+}
+
+impl Battery {
+    /// From 0 to 1
+    pub fn fraction_charged(&self) -> f64 {
+        self.charge_kwh / self.capacity_kwh
+    }
+
+    pub(crate) fn wants_to_charge(&self) -> bool {
+        self.fraction_charged() < WANT_TO_CHARGE
+    }
+
+    /// Draws energy from the battery, or stores it if negative. Returns true if this just
+    /// emptied the battery.
+    pub(crate) fn use_energy(&mut self, kwh: f64) -> bool {
+        let was_empty = self.charge_kwh <= 0.0;
+        self.charge_kwh = (self.charge_kwh - kwh).max(0.0).min(self.capacity_kwh);
+        !was_empty && self.charge_kwh <= 0.0
+    }
+}
+
+/// How much energy an EV uses to go some distance along something in some amount of time, in
+/// kilowatt-hours. This is negative if going downhill recovered more than the trip cost.
+pub(crate) fn energy_to_cross(on: Traversable, dist: Distance, dt: Duration, map: &Map) -> f64 {
+    let meters = dist.inner_meters();
+    let seconds = dt.inner_seconds();
+    if meters <= 0.0 || seconds <= 0.0 {
+        return 0.0;
+    }
+    let speed = meters / seconds;
+    let grade = match on {
+        Traversable::Lane(l) => {
+            let dr = map.get_l(l).get_directed_parent();
+            let incline = map.get_r(dr.id).percent_incline;
+            if dr.dir == Direction::Fwd {
+                incline
+            } else {
+                -incline
+            }
+        }
+        // Turns are short; treat them as flat
+        Traversable::Turn(_) => 0.0,
+    };
+
+    let rolling = MASS_KG * GRAVITY * ROLLING_RESISTANCE * meters;
+    let drag = 0.5 * AIR_DENSITY * DRAG_AREA * speed.powi(2) * meters;
+    let climb = MASS_KG * GRAVITY * grade * meters;
+    let at_wheels = rolling + drag + climb;
+    let from_battery = if at_wheels >= 0.0 {
+        at_wheels / DRIVETRAIN_EFFICIENCY
+    } else {
+        at_wheels * REGEN_EFFICIENCY
+    };
+    (from_battery + AUXILIARY_POWER_KW * 1000.0 * seconds) / JOULES_PER_KWH
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ChargerState {
+    /// Per port, in kilowatts
+    power_kw: f64,
+    ports: usize,
+    in_use: usize,
+    /// If anybody can park here, drive here when running low. Also, where's the charger?
+    detour: Option<(BuildingID, Pt2D)>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ChargingSession {
+    charger: ChargerLocation,
+    since: Time,
+}
+
+/// Tracks every charger, and which EVs are plugged in where.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct EnergySimState {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    chargers: BTreeMap<ChargerLocation, ChargerState>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    charging_spots: BTreeMap<ParkingSpot, ChargerLocation>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    sessions: BTreeMap<CarID, ChargingSession>,
+
+    events: Vec<Event>,
+}
+
+impl EnergySimState {
+    pub fn new() -> EnergySimState {
+        EnergySimState {
+            chargers: BTreeMap::new(),
+            charging_spots: BTreeMap::new(),
+            sessions: BTreeMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// The spots are the ones at the charger's location that have a plug.
+    pub fn add_charger(&mut self, spec: &Charger, spots: Vec<ParkingSpot>, map: &Map) {
+        if self.chargers.contains_key(&spec.location) {
+            warn!(
+                "{:?} has more than one charger; using the last",
+                spec.location
+            );
+        }
+        let detour = match spec.location {
+            ChargerLocation::Building(b) => {
+                let bldg = map.get_b(b);
+                match bldg.parking {
+                    OffstreetParking::PublicGarage(_, _)
+                        if bldg.driving_connection(map).is_some() =>
+                    {
+                        Some((b, bldg.label_center))
+                    }
+                    _ => None,
+                }
+            }
+            ChargerLocation::ParkingLot(pl) => {
+                // Drivers look for parking near a building, so aim for whichever building is
+                // closest to the lot's entrance, along the same road.
+                let lot = map.get_pl(pl);
+                map.all_buildings()
+                    .iter()
+                    .filter_map(|b| {
+                        let (pos, _) = b.driving_connection(map)?;
+                        if pos.lane() == lot.driving_pos.lane() {
+                            Some((
+                                b.id,
+                                (pos.dist_along() - lot.driving_pos.dist_along()).abs(),
+                            ))
+                        } else {
+                            None
+                        }
+                    })
+                    .min_by_key(|(_, dist)| *dist)
+                    .map(|(b, _)| (b, lot.polygon.center()))
+            }
+        };
+        let ports = spots.len();
+        for spot in spots {
+            self.charging_spots.insert(spot, spec.location);
+        }
+        self.chargers.insert(
+            spec.location,
+            ChargerState {
+                power_kw: spec.power_kw,
+                ports,
+                in_use: 0,
+                detour,
+            },
+        );
+        self.events.push(Event::ChargerOccupancyChanged {
+            charger: spec.location,
+            in_use: 0,
+            ports,
+        });
+    }
+
+    /// Starts charging EVs that park at a charger.
+    pub fn handle_event(&mut self, now: Time, ev: &Event, parking: &ParkingSimState) {
+        if let Event::CarReachedParkingSpot(car, spot) = ev {
+            if let Some(parked_car) = parking.lookup_parked_car(*car) {
+                self.plug_in(now, &parked_car.vehicle, *spot);
+            }
+        }
+        // If the car was moved without its owner coming back for it, like when a trip is
+        // cancelled, it doesn't get any of the charge.
+        if let Event::CarLeftParkingSpot(car, _) = ev {
+            if let Some(session) = self.sessions.remove(car) {
+                self.free_port(session.charger);
+            }
+        }
+    }
+
+    /// If the vehicle is an EV and the spot has a charger, start charging. Cars seeded at a
+    /// charger at the start of the day don't reach their spot, so they're plugged in directly.
+    pub fn plug_in(&mut self, now: Time, vehicle: &Vehicle, spot: ParkingSpot) {
+        if vehicle.battery.is_none() {
+            return;
+        }
+        let location = match self.charging_spots.get(&spot) {
+            Some(location) => *location,
+            None => {
+                return;
+            }
+        };
+        let charger = self.chargers.get_mut(&location).unwrap();
+        charger.in_use += 1;
+        self.events.push(Event::ChargerOccupancyChanged {
+            charger: location,
+            in_use: charger.in_use,
+            ports: charger.ports,
+        });
+        self.sessions.insert(
+            vehicle.id,
+            ChargingSession {
+                charger: location,
+                since: now,
+            },
+        );
+    }
+
+    /// The driver is about to leave with their EV. If it was charging, top up the battery with
+    /// however much it got since parking.
+    pub fn unplug(&mut self, now: Time, vehicle: &mut Vehicle) {
+        let session = match self.sessions.remove(&vehicle.id) {
+            Some(session) => session,
+            None => {
+                return;
+            }
+        };
+        let power_kw = self.chargers[&session.charger].power_kw;
+        self.free_port(session.charger);
+
+        let battery = vehicle.battery.as_mut().unwrap();
+        let hours = (now - session.since).inner_seconds() / 3600.0;
+        let kwh = (power_kw * hours).min(battery.capacity_kwh - battery.charge_kwh);
+        battery.charge_kwh += kwh;
+        self.events.push(Event::BatteryCharged {
+            car: vehicle.id,
+            charger: session.charger,
+            kwh,
+        });
+    }
+
+    fn free_port(&mut self, location: ChargerLocation) {
+        let charger = self.chargers.get_mut(&location).unwrap();
+        charger.in_use -= 1;
+        self.events.push(Event::ChargerOccupancyChanged {
+            charger: location,
+            in_use: charger.in_use,
+            ports: charger.ports,
+        });
+    }
+
+    /// Finds the closest public charger with a free port within walking distance of the goal.
+    /// Returns the building to park near, and where the charger is.
+    pub fn find_charger_near(
+        &self,
+        goal: BuildingID,
+        map: &Map,
+    ) -> Option<(BuildingID, ChargerLocation)> {
+        let pt = map.get_b(goal).label_center;
+        self.chargers
+            .iter()
+            .filter(|(_, charger)| charger.in_use < charger.ports)
+            .filter_map(|(location, charger)| {
+                let (b, charger_pt) = charger.detour?;
+                Some((b, *location, charger_pt.dist_to(pt)))
+            })
+            .filter(|(_, _, dist)| *dist <= MAX_WALK_FROM_CHARGER)
+            .min_by_key(|(_, _, dist)| *dist)
+            .map(|(b, location, _)| (b, location))
+    }
+
+    pub fn collect_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PersonID, VehicleBehavior, VehicleType};
+
+    #[test]
+    fn test_seeded_car_charges() {
+        let location = ChargerLocation::Building(BuildingID(0));
+        let spot = ParkingSpot::Offstreet(BuildingID(0), 0);
+        let mut energy = EnergySimState::new();
+        energy.chargers.insert(
+            location,
+            ChargerState {
+                power_kw: 7.0,
+                ports: 1,
+                in_use: 0,
+                detour: None,
+            },
+        );
+        energy.charging_spots.insert(spot, location);
+
+        let mut vehicle = Vehicle {
+            id: CarID {
+                id: 0,
+                vehicle_type: VehicleType::Car,
+            },
+            owner: Some(PersonID(0)),
+            vehicle_type: VehicleType::Car,
+            length: Distance::meters(4.5),
+            max_speed: None,
+            behavior: VehicleBehavior::typical(),
+            battery: Some(Battery {
+                capacity_kwh: 60.0,
+                charge_kwh: 10.0,
+            }),
+        };
+        // Seeded at the start of the day, without ever reaching the spot
+        energy.plug_in(Time::START_OF_DAY, &vehicle, spot);
+        assert_eq!(energy.chargers[&location].in_use, 1);
+
+        energy.unplug(Time::START_OF_DAY + Duration::hours(2), &mut vehicle);
+        assert_eq!(energy.chargers[&location].in_use, 0);
+        assert_eq!(vehicle.battery.as_ref().unwrap().charge_kwh, 24.0);
+
+        // Cars without a battery don't take a port
+        vehicle.battery = None;
+        energy.plug_in(Time::START_OF_DAY, &vehicle, spot);
+        assert_eq!(energy.chargers[&location].in_use, 0);
+    }
+}
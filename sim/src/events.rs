@@ -1,12 +1,15 @@
 use serde::{Deserialize, Serialize};
 
-use geom::Duration;
+use geom::{Distance, Duration, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, Path, PathRequest, Traversable,
-    TurnID,
+    BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, ParkingPlace, Path,
+    PathRequest, Traversable, TurnID,
 };
 
-use crate::{AgentID, CarID, ParkingSpot, PedestrianID, PersonID, Problem, TripID, TripMode};
+use crate::{
+    AgentID, CarID, ChargerLocation, ParkingSpot, PedestrianID, PersonID, Problem, TransitCapacity,
+    TripID, TripMode,
+};
 
 /// As a simulation runs, different systems emit Events. This cleanly separates the internal
 /// mechanics of the simulation from consumers that just want to know what's happening.
@@ -26,6 +29,39 @@ pub enum Event {
     /// How long waiting at the stop?
     PassengerBoardsTransit(PersonID, CarID, BusRouteID, BusStopID, Duration),
     PassengerAlightsTransit(PersonID, CarID, BusRouteID, BusStopID),
+    /// A transit vehicle arrived at a stop, maybe earlier or later than scheduled
+    TransitScheduleAdherence {
+        bus: CarID,
+        route: BusRouteID,
+        stop: BusStopID,
+        /// Counting from 0, the position of the stop along the route
+        stop_idx: usize,
+        scheduled: Time,
+    },
+    /// A transit vehicle arrived at a stop much sooner after the previous vehicle than scheduled
+    TransitBunching {
+        bus: CarID,
+        leader: CarID,
+        route: BusRouteID,
+        stop: BusStopID,
+    },
+    /// A transit vehicle waited at a timepoint stop, beyond the time needed for passengers
+    TransitVehicleHeld {
+        bus: CarID,
+        route: BusRouteID,
+        stop: BusStopID,
+        duration: Duration,
+    },
+    /// The vehicle was full, so the passenger keeps waiting for the next one.
+    PassengerLeftBehind(PersonID, CarID, BusRouteID, BusStopID),
+    /// How many passengers are aboard as a transit vehicle leaves a stop
+    TransitVehicleLoad {
+        bus: CarID,
+        route: BusRouteID,
+        departing: BusStopID,
+        passengers: usize,
+        capacity: TransitCapacity,
+    },
 
     PersonEntersBuilding(PersonID, BuildingID),
     PersonLeavesBuilding(PersonID, BuildingID),
@@ -35,8 +71,86 @@ pub enum Event {
 
     PedReachedParkingSpot(PedestrianID, ParkingSpot),
 
+    /// A delivery vehicle finished unloading at a building. Includes the parking or driving lane
+    /// it blocked, and for how long.
+    DeliveryMade(CarID, BuildingID, LaneID, Duration),
+    /// A delivery vehicle made it back to its depot.
+    DeliveryTourFinished {
+        car: CarID,
+        total_time: Duration,
+        /// How much longer the tour took than it would've with no traffic, besides unloading
+        delay: Duration,
+        /// Summed over every stop
+        lane_blocked_time: Duration,
+    },
+
+    /// A ride-hail vehicle picked somebody up at the curb. How long did they wait since
+    /// requesting the ride?
+    RideHailPickup(TripID, CarID, Duration),
+    /// A ride-hail vehicle dropped somebody off at the curb.
+    RideHailDropoff {
+        trip: TripID,
+        car: CarID,
+        /// How far the person actually rode, including detours to serve other riders
+        ride_distance: Distance,
+        /// The most direct route between the pickup and dropoff
+        direct_distance: Distance,
+    },
+    /// A ride-hail vehicle finished driving to its next stop.
+    RideHailVehicleMoved {
+        car: CarID,
+        distance: Distance,
+        /// Nobody was aboard
+        deadheading: bool,
+    },
+
     BikeStoppedAtSidewalk(CarID, LaneID),
 
+    /// Somebody unlocked or returned a shared vehicle, or the dock was just created.
+    DockOccupancyChanged {
+        dock: BuildingID,
+        vehicles: usize,
+        capacity: usize,
+    },
+    /// No dock near the start had a shared vehicle, or no dock near the goal had space, so the
+    /// trip switched to this mode instead.
+    BikeShareUnavailable(TripID, TripMode),
+
+    /// An electric vehicle finished crossing a lane or turn. Negative if it recovered energy going
+    /// downhill.
+    EnergyUsed {
+        car: CarID,
+        trip: Option<TripID>,
+        kwh: f64,
+    },
+    /// An electric vehicle plugged in or left, or the charger was just created.
+    ChargerOccupancyChanged {
+        charger: ChargerLocation,
+        in_use: usize,
+        ports: usize,
+    },
+    /// An electric vehicle left a charger with this much more energy.
+    BatteryCharged {
+        car: CarID,
+        charger: ChargerLocation,
+        kwh: f64,
+    },
+    /// The driver was running low, so they're parking at this charger instead of their
+    /// destination.
+    ChargingDetour(TripID, ChargerLocation),
+
+    /// A driver came back for their car and paid for parking, in dollars. They might've stayed
+    /// longer than allowed.
+    ParkingFeePaid {
+        car: CarID,
+        place: ParkingPlace,
+        fee: f64,
+        overstayed: bool,
+    },
+    /// Parking near the destination costs more than the driver is willing to pay, so the trip
+    /// switched to this mode instead.
+    ParkingTooExpensive(TripID, TripMode),
+
     ProblemEncountered(TripID, Problem),
 
     /// If the agent is a transit vehicle, then include a count of how many passengers are on
@@ -78,6 +192,9 @@ pub enum TripPhaseType {
     WaitingForBus(BusRouteID, BusStopID),
     /// What stop did they board at?
     RidingBus(BusRouteID, BusStopID, CarID),
+    WaitingForRideHail,
+    RidingRideHail(CarID),
+    RidingSharedVehicle,
     Cancelled,
     Finished,
     DelayedStart,
@@ -94,6 +211,9 @@ impl TripPhaseType {
                 format!("Waiting for bus {}", map.get_br(r).full_name)
             }
             TripPhaseType::RidingBus(r, _, _) => format!("Riding bus {}", map.get_br(r).full_name),
+            TripPhaseType::WaitingForRideHail => "Waiting for a ride-hail pickup".to_string(),
+            TripPhaseType::RidingRideHail(car) => format!("Riding in {}", car),
+            TripPhaseType::RidingSharedVehicle => "Riding a shared bike or scooter".to_string(),
             TripPhaseType::Cancelled => "Trip was cancelled due to some bug".to_string(),
             TripPhaseType::Finished => "Trip finished".to_string(),
             TripPhaseType::DelayedStart => "Delayed by a previous trip taking too long".to_string(),
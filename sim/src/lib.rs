@@ -24,8 +24,8 @@ use serde::{Deserialize, Serialize};
 use abstutil::{deserialize_usize, serialize_usize};
 use geom::{Distance, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, ParkingLotID, Path,
-    PathConstraints, Position,
+    BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, ParkingLotID, ParkingPlace,
+    Path, PathConstraints, Position, Traversable,
 };
 
 pub use crate::render::{
@@ -34,40 +34,71 @@ pub use crate::render::{
 };
 
 pub use self::analytics::{Analytics, Problem, SlidingWindow, TripPhase};
+pub use self::behavior::{BehaviorProfile, GapAcceptance, VehicleBehavior};
+pub(crate) use self::bikeshare::BikeShareSimState;
+pub use self::bikeshare::SharedVehicleType;
 pub(crate) use self::cap::CapSimState;
-pub(crate) use self::events::Event;
-pub use self::events::{AlertLocation, TripPhaseType};
+pub(crate) use self::deliveries::DeliverySimState;
+pub(crate) use self::detectors::DetectorSimState;
+pub use self::detectors::{Detector, DetectorID, DetectorReading};
+pub(crate) use self::emissions::EmissionsRecorder;
+pub use self::emissions::{
+    EmissionFactor, EmissionFactors, EmissionTable, Emissions, RoadEmissions,
+};
+pub use self::energy::Battery;
+pub(crate) use self::energy::EnergySimState;
+pub use self::events::{AlertLocation, Event, TripPhaseType};
 pub use self::make::{
-    fork_rng, BorderSpawnOverTime, ExternalPerson, ExternalTrip, ExternalTripEndpoint, IndividTrip,
-    MapBorders, PersonSpec, Scenario, ScenarioGenerator, ScenarioModifier, SimFlags, SpawnOverTime,
-    TripEndpoint, TripPurpose,
+    fork_rng, BikeShareDock, BorderSpawnOverTime, Charger, ChargerLocation, DeliveryStop,
+    DeliveryTour, ElectricVehicles, ExternalPerson, ExternalTrip, ExternalTripEndpoint,
+    IndividTrip, MapBorders, PersonSpec, Scenario, ScenarioGenerator, ScenarioModifier, SimFlags,
+    SpawnOverTime, TripEndpoint, TripPurpose,
 };
 pub(crate) use self::make::{StartTripArgs, TripSpec};
+pub use self::mechanics::{
+    Actuated, DetectorStatus, MaxPressure, MovementState, SignalController, SignalControllerInput,
+    StageDecision,
+};
 pub(crate) use self::mechanics::{
     DrivingSimState, IntersectionSimState, ParkingSim, ParkingSimState, WalkingSimState,
 };
 pub(crate) use self::pandemic::PandemicModel;
 pub(crate) use self::recorder::TrafficRecorder;
+pub(crate) use self::ridehail::RideHailSimState;
+pub use self::ridehail::{Dispatcher, FleetVehicle, NearestIdleVehicle, RideRequest};
 pub(crate) use self::router::{ActionAtEnd, Router};
 pub(crate) use self::scheduler::{Command, Scheduler};
 pub use self::sim::{AgentProperties, AlertHandler, DelayCause, Sim, SimCallback, SimOptions};
 pub(crate) use self::transit::TransitSimState;
+pub use self::transit::{HoldingStrategy, TransitCapacity};
+pub(crate) use self::transit_log::TransitLogRecorder;
+pub use self::transit_log::{StopVisit, TransitLog};
+pub(crate) use self::travel_times::TravelTimeRecorder;
 pub use self::trips::TripMode;
 pub use self::trips::{CommutersVehiclesCounts, Person, PersonState, TripInfo, TripResult};
 pub(crate) use self::trips::{TripLeg, TripManager};
 
 mod analytics;
+mod behavior;
+mod bikeshare;
 mod cap;
+mod deliveries;
+mod detectors;
+mod emissions;
+mod energy;
 mod events;
 mod make;
 mod mechanics;
 mod pandemic;
 mod recorder;
 mod render;
+mod ridehail;
 mod router;
 mod scheduler;
 mod sim;
 mod transit;
+mod transit_log;
+mod travel_times;
 mod trips;
 
 // http://pccsc.net/bicycle-parking-info/ says 68 inches, which is 1.73m
@@ -76,24 +107,23 @@ pub(crate) const MIN_CAR_LENGTH: Distance = Distance::const_meters(4.5);
 pub(crate) const MAX_CAR_LENGTH: Distance = Distance::const_meters(6.5);
 // Note this is more than MAX_CAR_LENGTH
 pub(crate) const BUS_LENGTH: Distance = Distance::const_meters(12.5);
+pub(crate) const DELIVERY_LENGTH: Distance = Distance::const_meters(7.5);
 pub(crate) const LIGHT_RAIL_LENGTH: Distance = Distance::const_meters(60.0);
 
-/// At all speeds (including at rest), cars must be at least this far apart, measured from front of
-/// one car to the back of the other.
-pub(crate) const FOLLOWING_DISTANCE: Distance = Distance::const_meters(1.0);
-
 /// When spawning at borders, start the front of the vehicle this far along and gradually appear.
 /// Getting too close to EPSILON_DIST can lead to get_draw_car having no geometry at all.
 pub(crate) const SPAWN_DIST: Distance = Distance::const_meters(0.05);
 
 // TODO Implement Eq, Hash, Ord manually to guarantee this.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub struct CarID {
     /// The numeric ID must be globally unique, without considering VehicleType.
     #[serde(
         serialize_with = "serialize_usize",
         deserialize_with = "deserialize_usize"
     )]
+    #[cfg_attr(feature = "json_schema", schemars(with = "u32"))]
     pub id: usize,
     /// VehicleType is bundled for convenience; many places need to know this without a lookup.
     pub vehicle_type: VehicleType,
@@ -106,16 +136,20 @@ impl fmt::Display for CarID {
             VehicleType::Bus => write!(f, "Bus #{}", self.id),
             VehicleType::Train => write!(f, "Train #{}", self.id),
             VehicleType::Bike => write!(f, "Bike #{}", self.id),
+            VehicleType::Delivery => write!(f, "Delivery #{}", self.id),
+            VehicleType::RideHail => write!(f, "Ride-hail #{}", self.id),
         }
     }
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub struct PedestrianID(
     #[serde(
         serialize_with = "serialize_usize",
         deserialize_with = "deserialize_usize"
     )]
+    #[cfg_attr(feature = "json_schema", schemars(with = "u32"))]
     pub usize,
 );
 
@@ -126,6 +160,7 @@ impl fmt::Display for PedestrianID {
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub enum AgentID {
     Car(CarID),
     Pedestrian(PedestrianID),
@@ -144,7 +179,7 @@ impl AgentID {
     pub fn to_type(self) -> AgentType {
         match self {
             AgentID::Car(c) => match c.vehicle_type {
-                VehicleType::Car => AgentType::Car,
+                VehicleType::Car | VehicleType::Delivery | VehicleType::RideHail => AgentType::Car,
                 VehicleType::Bike => AgentType::Bike,
                 VehicleType::Bus => AgentType::Bus,
                 VehicleType::Train => AgentType::Train,
@@ -183,6 +218,7 @@ impl fmt::Display for AgentID {
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub enum AgentType {
     Car,
     Bike,
@@ -238,11 +274,13 @@ impl AgentType {
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub struct TripID(
     #[serde(
         serialize_with = "serialize_usize",
         deserialize_with = "deserialize_usize"
     )]
+    #[cfg_attr(feature = "json_schema", schemars(with = "u32"))]
     pub usize,
 );
 
@@ -253,11 +291,13 @@ impl fmt::Display for TripID {
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub struct PersonID(
     #[serde(
         serialize_with = "serialize_usize",
         deserialize_with = "deserialize_usize"
     )]
+    #[cfg_attr(feature = "json_schema", schemars(with = "u32"))]
     pub usize,
 );
 
@@ -282,11 +322,16 @@ pub struct OrigPersonID(
 );
 
 #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub enum VehicleType {
     Car,
     Bus,
     Train,
     Bike,
+    /// A van or truck making a tour of deliveries
+    Delivery,
+    /// Part of an on-demand fleet, picking up and dropping off riders
+    RideHail,
 }
 
 impl fmt::Display for VehicleType {
@@ -296,6 +341,8 @@ impl fmt::Display for VehicleType {
             VehicleType::Bus => write!(f, "bus"),
             VehicleType::Train => write!(f, "train"),
             VehicleType::Bike => write!(f, "bike"),
+            VehicleType::Delivery => write!(f, "delivery"),
+            VehicleType::RideHail => write!(f, "ride-hail vehicle"),
         }
     }
 }
@@ -303,7 +350,9 @@ impl fmt::Display for VehicleType {
 impl VehicleType {
     pub fn to_constraints(self) -> PathConstraints {
         match self {
-            VehicleType::Car => PathConstraints::Car,
+            VehicleType::Car | VehicleType::Delivery | VehicleType::RideHail => {
+                PathConstraints::Car
+            }
             VehicleType::Bus => PathConstraints::Bus,
             VehicleType::Train => PathConstraints::Train,
             VehicleType::Bike => PathConstraints::Bike,
@@ -316,6 +365,8 @@ impl VehicleType {
             VehicleType::Bus => true,
             VehicleType::Train => true,
             VehicleType::Bike => false,
+            VehicleType::Delivery => false,
+            VehicleType::RideHail => false,
         }
     }
 }
@@ -327,6 +378,26 @@ pub struct Vehicle {
     pub vehicle_type: VehicleType,
     pub length: Distance,
     pub max_speed: Option<Speed>,
+    pub behavior: VehicleBehavior,
+    /// Only electric vehicles have one
+    pub battery: Option<Battery>,
+}
+
+impl Vehicle {
+    /// How fast the vehicle can go along something, accounting for how hills affect the rider.
+    pub(crate) fn speed_along(&self, on: Traversable, map: &Map) -> Speed {
+        let speed = on.max_speed_along(self.max_speed, self.vehicle_type.to_constraints(), map);
+        // Hills only slow down bikes, and only along lanes. Anything slower than the speed on flat
+        // ground is because of a hill.
+        if let (Traversable::Lane(_), VehicleType::Bike, Some(flat)) =
+            (on, self.vehicle_type, self.max_speed)
+        {
+            if speed < flat {
+                return flat * (speed / flat).powf(self.behavior.hill_sensitivity);
+            }
+        }
+        speed
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -334,6 +405,8 @@ pub struct VehicleSpec {
     pub vehicle_type: VehicleType,
     pub length: Distance,
     pub max_speed: Option<Speed>,
+    pub behavior: VehicleBehavior,
+    pub battery: Option<Battery>,
 }
 
 impl VehicleSpec {
@@ -345,6 +418,8 @@ impl VehicleSpec {
             vehicle_type: self.vehicle_type,
             length: self.length,
             max_speed: self.max_speed,
+            behavior: self.behavior,
+            battery: self.battery,
         }
     }
 }
@@ -358,6 +433,17 @@ pub enum ParkingSpot {
     Lot(ParkingLotID, usize),
 }
 
+impl ParkingSpot {
+    /// Where the spot's price and time limit come from
+    pub fn place(self, map: &Map) -> ParkingPlace {
+        match self {
+            ParkingSpot::Onstreet(l, _) => ParkingPlace::Road(map.get_l(l).parent),
+            ParkingSpot::Offstreet(b, _) => ParkingPlace::Building(b),
+            ParkingSpot::Lot(pl, _) => ParkingPlace::ParkingLot(pl),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ParkedCar {
     pub vehicle: Vehicle,
@@ -612,7 +698,8 @@ pub(crate) struct CreateCar {
     pub maybe_parked_car: Option<ParkedCar>,
     /// None for buses
     pub trip_and_person: Option<(TripID, PersonID)>,
-    pub maybe_route: Option<BusRouteID>,
+    /// For buses, the route and when the vehicle was scheduled to begin it
+    pub maybe_route: Option<(BusRouteID, Time)>,
 }
 
 impl CreateCar {
@@ -275,6 +275,7 @@ fn create_prole(
             IndividTrip::new(depart_am, TripPurpose::Work, home, work, mode),
             IndividTrip::new(depart_pm, TripPurpose::Home, work, home, mode),
         ],
+        behavior: None,
     })
 }
 
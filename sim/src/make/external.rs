@@ -7,11 +7,14 @@ use serde::Deserialize;
 use geom::{Distance, FindClosest, LonLat, Time};
 use map_model::{IntersectionID, Map, PathConstraints};
 
-use crate::{IndividTrip, PersonSpec, TripEndpoint, TripMode, TripPurpose};
+use crate::{BehaviorProfile, IndividTrip, PersonSpec, TripEndpoint, TripMode, TripPurpose};
 
 #[derive(Deserialize)]
 pub struct ExternalPerson {
     pub trips: Vec<ExternalTrip>,
+    /// How this person walks, cycles, and drives. Optional.
+    #[serde(default)]
+    pub behavior: Option<BehaviorProfile>,
 }
 
 #[derive(Deserialize)]
@@ -41,46 +44,19 @@ impl ExternalPerson {
         input: Vec<ExternalPerson>,
         skip_problems: bool,
     ) -> Result<Vec<PersonSpec>> {
-        let mut closest: FindClosest<TripEndpoint> = FindClosest::new(map.get_bounds());
-        for b in map.all_buildings() {
-            closest.add(TripEndpoint::Bldg(b.id), b.polygon.points());
-        }
-        let borders = MapBorders::new(map);
-
-        let lookup_pt = |endpt, is_origin, mode| match endpt {
-            ExternalTripEndpoint::TripEndpoint(endpt) => Ok(endpt),
-            ExternalTripEndpoint::Position(gps) => {
-                let pt = gps.to_pt(map.get_gps_bounds());
-                if map.get_boundary_polygon().contains_pt(pt) {
-                    match closest.closest_pt(pt, Distance::meters(100.0)) {
-                        Some((x, _)) => Ok(x),
-                        None => Err(anyhow!("No building within 100m of {}", gps)),
-                    }
-                } else {
-                    let (incoming, outgoing) = borders.for_mode(mode);
-                    let candidates = if is_origin { incoming } else { outgoing };
-                    Ok(TripEndpoint::Border(
-                        candidates
-                            .iter()
-                            .min_by_key(|(_, border)| border.fast_dist(gps))
-                            .ok_or_else(|| anyhow!("No border for {}", mode.ongoing_verb()))?
-                            .0,
-                    ))
-                }
-            }
-        };
-
+        let snapper = Snapper::new(map);
         let mut results = Vec::new();
         for person in input {
             let mut spec = PersonSpec {
                 orig_id: None,
                 trips: Vec::new(),
+                behavior: person.behavior,
             };
             for trip in person.trips {
                 spec.trips.push(IndividTrip::new(
                     trip.departure,
                     trip.purpose,
-                    match lookup_pt(trip.origin, true, trip.mode) {
+                    match snapper.lookup(trip.origin, true, trip.mode) {
                         Ok(endpt) => endpt,
                         Err(err) => {
                             if skip_problems {
@@ -91,7 +67,7 @@ impl ExternalPerson {
                             }
                         }
                     },
-                    match lookup_pt(trip.destination, false, trip.mode) {
+                    match snapper.lookup(trip.destination, false, trip.mode) {
                         Ok(endpt) => endpt,
                         Err(err) => {
                             if skip_problems {
@@ -109,6 +85,84 @@ impl ExternalPerson {
         }
         Ok(results)
     }
+
+    /// Like `import`, but snaps everybody independently, returning a result for each person in
+    /// the same order as the input. A person fails if any of their trips can't be snapped.
+    pub fn import_each(map: &Map, input: Vec<ExternalPerson>) -> Vec<Result<PersonSpec>> {
+        let snapper = Snapper::new(map);
+        input
+            .into_iter()
+            .map(|person| {
+                let mut spec = PersonSpec {
+                    orig_id: None,
+                    trips: Vec::new(),
+                    behavior: person.behavior,
+                };
+                for trip in person.trips {
+                    spec.trips.push(IndividTrip::new(
+                        trip.departure,
+                        trip.purpose,
+                        snapper.lookup(trip.origin, true, trip.mode)?,
+                        snapper.lookup(trip.destination, false, trip.mode)?,
+                        trip.mode,
+                    ));
+                }
+                Ok(spec)
+            })
+            .collect()
+    }
+}
+
+/// Matches positions to buildings or borders. Building this is expensive, so do it once per
+/// import.
+struct Snapper<'a> {
+    map: &'a Map,
+    closest: FindClosest<TripEndpoint>,
+    borders: MapBorders,
+}
+
+impl<'a> Snapper<'a> {
+    fn new(map: &'a Map) -> Snapper<'a> {
+        let mut closest: FindClosest<TripEndpoint> = FindClosest::new(map.get_bounds());
+        for b in map.all_buildings() {
+            closest.add(TripEndpoint::Bldg(b.id), b.polygon.points());
+        }
+        Snapper {
+            map,
+            closest,
+            borders: MapBorders::new(map),
+        }
+    }
+
+    fn lookup(
+        &self,
+        endpt: ExternalTripEndpoint,
+        is_origin: bool,
+        mode: TripMode,
+    ) -> Result<TripEndpoint> {
+        match endpt {
+            ExternalTripEndpoint::TripEndpoint(endpt) => Ok(endpt),
+            ExternalTripEndpoint::Position(gps) => {
+                let pt = gps.to_pt(self.map.get_gps_bounds());
+                if self.map.get_boundary_polygon().contains_pt(pt) {
+                    match self.closest.closest_pt(pt, Distance::meters(100.0)) {
+                        Some((x, _)) => Ok(x),
+                        None => Err(anyhow!("No building within 100m of {}", gps)),
+                    }
+                } else {
+                    let (incoming, outgoing) = self.borders.for_mode(mode);
+                    let candidates = if is_origin { incoming } else { outgoing };
+                    Ok(TripEndpoint::Border(
+                        candidates
+                            .iter()
+                            .min_by_key(|(_, border)| border.fast_dist(gps))
+                            .ok_or_else(|| anyhow!("No border for {}", mode.ongoing_verb()))?
+                            .0,
+                    ))
+                }
+            }
+        }
+    }
 }
 
 /// Lists all border intersections of the map, broken down by mode and whether they support
@@ -187,8 +241,13 @@ impl MapBorders {
         &Vec<(IntersectionID, LonLat)>,
     ) {
         match mode {
-            TripMode::Walk | TripMode::Transit => (&self.incoming_walking, &self.outgoing_walking),
-            TripMode::Drive => (&self.incoming_driving, &self.outgoing_driving),
+            // People walk to and from bike-share docks
+            TripMode::Walk | TripMode::Transit | TripMode::BikeShare => {
+                (&self.incoming_walking, &self.outgoing_walking)
+            }
+            TripMode::Drive | TripMode::RideHail => {
+                (&self.incoming_driving, &self.outgoing_driving)
+            }
             TripMode::Bike => (&self.incoming_biking, &self.outgoing_biking),
         }
     }
@@ -172,6 +172,7 @@ impl SpawnOverTime {
                 }),
                 mode,
             )],
+            behavior: None,
         });
     }
 }
@@ -190,6 +191,7 @@ impl BorderSpawnOverTime {
                 }),
                 mode,
             )],
+            behavior: None,
         });
     }
 }
@@ -3,7 +3,7 @@ use rand_xorshift::XorShiftRng;
 
 use abstio::MapName;
 use abstutil::CmdArgs;
-use map_model::{Map, MapEdits};
+use map_model::{Map, MapEdits, TravelTimeProfile};
 
 use crate::{Scenario, ScenarioModifier, Sim, SimOptions};
 
@@ -18,6 +18,9 @@ pub struct SimFlags {
     pub modifiers: Vec<ScenarioModifier>,
     pub rng_seed: u64,
     pub opts: SimOptions,
+    /// A path to a `TravelTimeProfile`. If set, cars avoid roads congested at the time they start
+    /// driving.
+    pub travel_times: Option<String>,
 }
 
 impl SimFlags {
@@ -39,6 +42,7 @@ impl SimFlags {
             modifiers,
             rng_seed,
             opts: SimOptions::from_args(args, rng_seed),
+            travel_times: args.optional("--travel_times"),
         }
     }
 
@@ -49,6 +53,7 @@ impl SimFlags {
             modifiers: Vec::new(),
             rng_seed: SimFlags::RNG_SEED,
             opts: SimOptions::new(run_name),
+            travel_times: None,
         }
     }
 
@@ -58,6 +63,15 @@ impl SimFlags {
 
     /// Loads a map and simulation. Not appropriate for use in the UI or on web.
     pub fn load_synchronously(&self, timer: &mut abstutil::Timer) -> (Map, Sim, XorShiftRng) {
+        let (mut map, sim, rng) = self.load_map_and_sim(timer);
+        if let Some(ref path) = self.travel_times {
+            let profile: TravelTimeProfile = abstio::must_read_object(path.clone(), timer);
+            map.set_travel_time_profile(Some(profile), timer);
+        }
+        (map, sim, rng)
+    }
+
+    fn load_map_and_sim(&self, timer: &mut abstutil::Timer) -> (Map, Sim, XorShiftRng) {
         let mut rng = self.make_rng();
 
         let mut opts = self.opts.clone();
@@ -5,20 +5,24 @@ use anyhow::Result;
 use rand::seq::SliceRandom;
 use rand::{Rng, SeedableRng};
 use rand_xorshift::XorShiftRng;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 use abstio::MapName;
 use abstutil::{prettyprint_usize, Counter, Timer};
-use geom::{Distance, Speed, Time};
-use map_model::{BuildingID, Map, OffstreetParking, RoadID};
+use geom::{Distance, Duration, Speed, Time};
+use map_model::{BuildingID, DirectedRoadID, Map, OffstreetParking, ParkingLotID, RoadID};
 
 use crate::make::fork_rng;
 use crate::{
-    OrigPersonID, ParkingSpot, Sim, StartTripArgs, TripEndpoint, TripInfo, TripMode, Vehicle,
-    VehicleSpec, VehicleType, BIKE_LENGTH, MAX_CAR_LENGTH, MIN_CAR_LENGTH,
+    Battery, BehaviorProfile, OrigPersonID, ParkingSpot, SharedVehicleType, Sim, StartTripArgs,
+    TripEndpoint, TripInfo, TripMode, Vehicle, VehicleBehavior, VehicleSpec, VehicleType,
+    BIKE_LENGTH, MAX_CAR_LENGTH, MIN_CAR_LENGTH,
 };
 
 /// A Scenario describes all the input to a simulation. Usually a scenario covers one day.
+///
+/// The binary format isn't versioned. After adding a field here, regenerate the scenarios by
+/// importing again (data/regen.sh); #[serde(default)] only helps scenarios stored as JSON.
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Scenario {
     pub scenario_name: String,
@@ -27,6 +31,18 @@ pub struct Scenario {
     pub people: Vec<PersonSpec>,
     /// None means seed all buses. Otherwise the route name must be present here.
     pub only_seed_buses: Option<BTreeSet<String>>,
+    #[serde(default)]
+    pub deliveries: Vec<DeliveryTour>,
+    /// Each on-demand vehicle starts the day waiting at one of these buildings.
+    #[serde(default)]
+    pub ride_hail_fleet: Vec<BuildingID>,
+    #[serde(default)]
+    pub bike_share_docks: Vec<BikeShareDock>,
+    /// If present, some people's cars are electric.
+    #[serde(default, deserialize_with = "deserialize_electric_vehicles")]
+    pub electric_vehicles: Option<ElectricVehicles>,
+    #[serde(default)]
+    pub chargers: Vec<Charger>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -37,6 +53,8 @@ pub struct PersonSpec {
     /// trip. In the case of borders, the outbound and inbound border may be different. This means
     /// that there was some sort of "remote" trip happening outside the map that we don't simulate.
     pub trips: Vec<IndividTrip>,
+    /// How this person walks, cycles, and drives. None means typical behavior.
+    pub behavior: Option<BehaviorProfile>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -49,6 +67,10 @@ pub struct IndividTrip {
     pub cancelled: bool,
     /// Did a ScenarioModifier affect this?
     pub modified: bool,
+    /// For driving trips, follow these roads instead of searching for the best path when the car
+    /// starts moving. Dynamic traffic assignment fills this out. If the car winds up starting or
+    /// ending somewhere else, like a different parking spot, this is ignored.
+    pub route: Option<Vec<DirectedRoadID>>,
 }
 
 impl IndividTrip {
@@ -67,10 +89,70 @@ impl IndividTrip {
             purpose,
             cancelled: false,
             modified: false,
+            route: None,
         }
     }
 }
 
+/// A delivery vehicle leaves a depot, stops at a sequence of buildings to unload, then returns to
+/// the depot. Nobody in the scenario drives it.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DeliveryTour {
+    pub depart: Time,
+    /// A building or a border. The vehicle starts and ends the tour here.
+    pub depot: TripEndpoint,
+    pub stops: Vec<DeliveryStop>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DeliveryStop {
+    pub building: BuildingID,
+    /// How long it takes to unload here. The vehicle blocks a parking or driving lane the whole
+    /// time.
+    pub dwell_time: Duration,
+}
+
+/// A docking station for shared bikes or scooters, in front of a building.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct BikeShareDock {
+    pub building: BuildingID,
+    pub vehicle_type: SharedVehicleType,
+    /// How many vehicles fit in the dock
+    pub capacity: usize,
+    /// How many vehicles are docked at the start of the day
+    pub vehicles: usize,
+}
+
+/// Describes which cars in a scenario are electric, and their batteries.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ElectricVehicles {
+    /// The fraction of people's cars that are electric, from 0 to 1
+    pub fraction: f64,
+    /// Usable capacity of each battery, in kilowatt-hours
+    pub capacity_kwh: f64,
+    /// Each battery starts the day charged somewhere in this range, as a fraction of capacity
+    pub initial_charge: (f64, f64),
+}
+
+/// Plugs for electric vehicles at some of the parking spots in one place.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Charger {
+    pub location: ChargerLocation,
+    /// How many parking spots here have a plug. If there are fewer spots than this, all of them
+    /// do.
+    pub ports: usize,
+    /// Per port, in kilowatts
+    pub power_kw: f64,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChargerLocation {
+    /// Only residents can use chargers in private garages, but anybody can use the ones in public
+    /// garages.
+    Building(BuildingID),
+    ParkingLot(ParkingLotID),
+}
+
 /// Lifted from Seattle's Soundcast model, but seems general enough to use anyhere.
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum TripPurpose {
@@ -110,6 +192,59 @@ impl fmt::Display for TripPurpose {
     }
 }
 
+impl ElectricVehicles {
+    /// Fails if the fraction or initial charges aren't between 0 and 1, or the capacity isn't
+    /// positive.
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.fraction) {
+            bail!(
+                "The fraction of electric vehicles, {}, isn't between 0 and 1",
+                self.fraction
+            );
+        }
+        if self.capacity_kwh.is_nan() || self.capacity_kwh <= 0.0 {
+            bail!(
+                "Batteries need a positive capacity, not {} kWh",
+                self.capacity_kwh
+            );
+        }
+        let (low, high) = self.initial_charge;
+        if !(0.0..=1.0).contains(&low) || !(0.0..=1.0).contains(&high) || low > high {
+            bail!(
+                "The initial charge, {} to {}, isn't a range between 0 and 1",
+                low,
+                high
+            );
+        }
+        Ok(())
+    }
+
+    fn rand_battery(&self, rng: &mut XorShiftRng) -> Battery {
+        let (low, high) = self.initial_charge;
+        let fraction = if high > low {
+            rng.gen_range(low..high)
+        } else {
+            low
+        };
+        Battery {
+            capacity_kwh: self.capacity_kwh,
+            charge_kwh: fraction * self.capacity_kwh,
+        }
+    }
+}
+
+/// Rejects invalid settings while loading a scenario, instead of failing partway through
+/// instantiating it.
+fn deserialize_electric_vehicles<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Option<ElectricVehicles>, D::Error> {
+    let ev = <Option<ElectricVehicles>>::deserialize(d)?;
+    if let Some(ref ev) = ev {
+        ev.validate().map_err(serde::de::Error::custom)?;
+    }
+    Ok(ev)
+}
+
 impl Scenario {
     pub fn instantiate(&self, sim: &mut Sim, map: &Map, rng: &mut XorShiftRng, timer: &mut Timer) {
         self.instantiate_without_retries(sim, map, rng, true, timer);
@@ -153,9 +288,22 @@ impl Scenario {
                 panic!("{}", err);
             }
 
-            let (vehicle_specs, cars_initially_parked_at, vehicle_foreach_trip) =
+            let (mut vehicle_specs, cars_initially_parked_at, vehicle_foreach_trip) =
                 p.get_vehicles(rng);
-            let person = sim.new_person(p.orig_id, Scenario::rand_ped_speed(rng), vehicle_specs);
+            if let Some(ref ev) = self.electric_vehicles {
+                for spec in &mut vehicle_specs {
+                    if spec.vehicle_type == VehicleType::Car && rng.gen_bool(ev.fraction) {
+                        spec.battery = Some(ev.rand_battery(rng));
+                    }
+                }
+            }
+            // Always consume the RNG, so people without a profile aren't affected by others having
+            // one
+            let mut ped_speed = Scenario::rand_ped_speed(rng);
+            if let Some(speed) = p.behavior.as_ref().and_then(|b| b.walking_speed) {
+                ped_speed = speed;
+            }
+            let person = sim.new_person(p.orig_id, ped_speed, vehicle_specs);
             for (idx, b) in cars_initially_parked_at {
                 parked_cars.push((person.vehicles[idx].clone(), b));
             }
@@ -169,6 +317,7 @@ impl Scenario {
                         end: trip.destination,
                         purpose: trip.purpose,
                         modified: trip.modified,
+                        route: trip.route.clone(),
                         capped: false,
                         cancellation_reason: if trip.cancelled {
                             Some("cancelled by ScenarioModifier".to_string())
@@ -184,9 +333,25 @@ impl Scenario {
             }
         }
 
+        // Plug in chargers before any cars park there
+        for charger in &self.chargers {
+            sim.seed_charger(charger, map);
+        }
+
         // parked_cars is stable over map edits, so don't fork.
         parked_cars.shuffle(rng);
         seed_parked_cars(parked_cars, sim, map, rng, timer);
+        sim.record_initial_parking(map);
+
+        for tour in &self.deliveries {
+            sim.seed_delivery_tour(tour.clone());
+        }
+        for b in &self.ride_hail_fleet {
+            sim.seed_ride_hail_vehicle(*b, map);
+        }
+        for dock in &self.bike_share_docks {
+            sim.seed_bike_share_dock(dock, map);
+        }
 
         sim.spawn_trips(schedule_trips, map, timer);
         timer.stop(format!("Instantiating {}", self.scenario_name));
@@ -205,6 +370,11 @@ impl Scenario {
             map_name: map.get_name().clone(),
             people: Vec::new(),
             only_seed_buses: Some(BTreeSet::new()),
+            deliveries: Vec::new(),
+            ride_hail_fleet: Vec::new(),
+            bike_share_docks: Vec::new(),
+            electric_vehicles: None,
+            chargers: Vec::new(),
         }
     }
 
@@ -214,6 +384,8 @@ impl Scenario {
             vehicle_type: VehicleType::Car,
             length,
             max_speed: None,
+            behavior: VehicleBehavior::typical(),
+            battery: None,
         }
     }
 
@@ -227,6 +399,8 @@ impl Scenario {
             vehicle_type: VehicleType::Bike,
             length: BIKE_LENGTH,
             max_speed,
+            behavior: VehicleBehavior::typical(),
+            battery: None,
         }
     }
 
@@ -463,11 +637,20 @@ impl PersonSpec {
         // TODO If the trip is cancelled, this should be affected...
         for trip in &self.trips {
             let use_for_trip = match trip.mode {
-                TripMode::Walk | TripMode::Transit => None,
-                TripMode::Bike => {
+                TripMode::Walk | TripMode::Transit | TripMode::RideHail => None,
+                // A shared bike is a different physical vehicle, but the person rides it the same
+                // way as their own.
+                TripMode::Bike | TripMode::BikeShare => {
                     if bike_idx.is_none() {
                         bike_idx = Some(vehicle_specs.len());
-                        vehicle_specs.push(Scenario::rand_bike(rng));
+                        let mut spec = Scenario::rand_bike(rng);
+                        if let Some(ref behavior) = self.behavior {
+                            if let Some(speed) = behavior.cycling_speed {
+                                spec.max_speed = Some(speed);
+                            }
+                            spec.behavior = behavior.vehicle.clone();
+                        }
+                        vehicle_specs.push(spec);
                     }
                     bike_idx
                 }
@@ -487,7 +670,14 @@ impl PersonSpec {
                     } else {
                         // Need a new car, starting in the right spot
                         let idx = vehicle_specs.len();
-                        vehicle_specs.push(Scenario::rand_car(rng));
+                        let mut spec = Scenario::rand_car(rng);
+                        if let Some(ref behavior) = self.behavior {
+                            if let Some(length) = behavior.car_length {
+                                spec.length = length;
+                            }
+                            spec.behavior = behavior.vehicle.clone();
+                        }
+                        vehicle_specs.push(spec);
                         if let Some(b) = need_parked_at {
                             cars_initially_parked_at.push((idx, b));
                         }
@@ -9,7 +9,9 @@ use map_model::{
     BuildingID, BusRouteID, BusStopID, IntersectionID, Map, PathConstraints, PathRequest, Position,
 };
 
-use crate::{CarID, DrivingGoal, SidewalkSpot, TripLeg, TripMode, VehicleType, SPAWN_DIST};
+use crate::{
+    BikeShareSimState, CarID, DrivingGoal, SidewalkSpot, TripLeg, TripMode, VehicleType, SPAWN_DIST,
+};
 
 /// We need to remember a few things from scenario instantiation that're used for starting the
 /// trip.
@@ -57,6 +59,19 @@ pub(crate) enum TripSpec {
         stop1: BusStopID,
         maybe_stop2: Option<BusStopID>,
     },
+    RideHail {
+        start: BuildingID,
+        goal: BuildingID,
+    },
+    UsingBikeShare {
+        start: SidewalkSpot,
+        goal: SidewalkSpot,
+        /// Stands in for whichever shared vehicle the person rents
+        bike: CarID,
+        /// The docks to rent from and return to
+        pickup: BuildingID,
+        dropoff: BuildingID,
+    },
 }
 
 impl TripSpec {
@@ -203,19 +218,37 @@ impl TripSpec {
                     legs = vec![TripLeg::Walk(walk_to), TripLeg::RideBus(*route, None)];
                 }
             }
+            TripSpec::RideHail { goal, .. } => {
+                legs.push(TripLeg::RideHail(*goal));
+            }
+            TripSpec::UsingBikeShare {
+                goal,
+                bike,
+                pickup,
+                dropoff,
+                ..
+            } => {
+                // find_docks already checked for biking connections
+                legs = vec![
+                    TripLeg::Walk(SidewalkSpot::bike_rack(*pickup, map).unwrap()),
+                    TripLeg::Drive(*bike, DrivingGoal::ParkNear(*dropoff)),
+                    TripLeg::Walk(goal.clone()),
+                ];
+            }
         };
 
         (self, legs)
     }
 
     /// Turn an origin/destination pair and mode into a specific plan for instantiating a trip.
-    /// Decisions like how to use public transit happen here.
+    /// Decisions like how to use public transit and which bike-share docks to use happen here.
     pub fn maybe_new(
         from: TripEndpoint,
         to: TripEndpoint,
         mode: TripMode,
         use_vehicle: Option<CarID>,
         retry_if_no_room: bool,
+        bikeshare: &BikeShareSimState,
         map: &Map,
     ) -> Result<TripSpec> {
         Ok(match mode {
@@ -290,6 +323,36 @@ impl TripSpec {
                     TripSpec::JustWalking { start, goal }
                 }
             }
+            // Vehicles pick up and drop off at the curb in front of buildings
+            TripMode::RideHail => match (from, to) {
+                (TripEndpoint::Bldg(start), TripEndpoint::Bldg(goal)) => {
+                    TripSpec::RideHail { start, goal }
+                }
+                _ => bail!("ride-hailing trips must start and end at a building"),
+            },
+            TripMode::BikeShare => {
+                let start = from.start_sidewalk_spot(map)?;
+                let goal = to.end_sidewalk_spot(map)?;
+                match bikeshare.find_docks(&start, &goal, map) {
+                    Ok((pickup, dropoff)) => TripSpec::UsingBikeShare {
+                        start,
+                        goal,
+                        bike: use_vehicle.unwrap(),
+                        pickup,
+                        dropoff,
+                    },
+                    // The caller notices the mode change
+                    Err(_) => TripSpec::maybe_new(
+                        from,
+                        to,
+                        TripMode::Transit,
+                        None,
+                        retry_if_no_room,
+                        bikeshare,
+                        map,
+                    )?,
+                }
+            }
         })
     }
 }
@@ -318,7 +381,10 @@ impl TripEndpoint {
         let end = to.pos(mode, false, map)?;
         Some(match mode {
             TripMode::Walk | TripMode::Transit => PathRequest::walking(start, end),
-            TripMode::Bike => PathRequest::vehicle(start, end, PathConstraints::Bike),
+            TripMode::Bike | TripMode::BikeShare => {
+                PathRequest::vehicle(start, end, PathConstraints::Bike)
+            }
+            TripMode::RideHail => PathRequest::vehicle(start, end, PathConstraints::Car),
             // Only cars leaving from a building might turn out from the driveway in a special way
             TripMode::Drive => {
                 if matches!(from, TripEndpoint::Bldg(_)) {
@@ -368,7 +434,7 @@ impl TripEndpoint {
         }
     }
 
-    fn pos(self, mode: TripMode, from: bool, map: &Map) -> Option<Position> {
+    pub(crate) fn pos(self, mode: TripMode, from: bool, map: &Map) -> Option<Position> {
         match mode {
             TripMode::Walk | TripMode::Transit => (if from {
                 self.start_sidewalk_spot(map)
@@ -377,7 +443,7 @@ impl TripEndpoint {
             })
             .ok()
             .map(|spot| spot.sidewalk_pos),
-            TripMode::Drive | TripMode::Bike => {
+            TripMode::Drive | TripMode::Bike | TripMode::RideHail | TripMode::BikeShare => {
                 if from {
                     match self {
                         // Fall through and use DrivingGoal also to start.
@@ -26,8 +26,9 @@ pub(crate) struct Car {
     /// vehicle.length.
     pub last_steps: VecDeque<Traversable>,
 
-    /// Since lane over-taking isn't implemented yet, a vehicle tends to be stuck behind a slow
-    /// leader for a while. Avoid duplicate events.
+    /// Leaders this vehicle has already tried to overtake. Overtaking isn't always possible, so a
+    /// vehicle may be stuck behind a slow leader for a while. Avoid duplicate events and repeated
+    /// attempts.
     pub wants_to_overtake: BTreeSet<CarID>,
 }
 
@@ -51,15 +52,29 @@ impl Car {
         start_time: Time,
         map: &Map,
     ) -> CarState {
-        let speed = self.router.head().max_speed_along(
-            self.vehicle.max_speed,
-            self.vehicle.vehicle_type.to_constraints(),
-            map,
-        );
-        let dt = (dist_int.end - dist_int.start) / speed;
+        let speed = self.vehicle.speed_along(self.router.head(), map);
+        let dist = dist_int.end - dist_int.start;
+        let dt = if self.stopped_before(start_time) {
+            self.vehicle.behavior.time_to_cross_from_rest(dist, speed)
+        } else {
+            dist / speed
+        };
         CarState::Crossing(TimeInterval::new(start_time, start_time + dt), dist_int)
     }
 
+    /// Is the car currently stopped, and has been since before some time? Used right before
+    /// changing state, to decide if the car has to react and speed up again.
+    fn stopped_before(&self, time: Time) -> bool {
+        match self.state {
+            CarState::Queued { blocked_since, .. }
+            | CarState::WaitingToAdvance { blocked_since } => blocked_since < time,
+            CarState::Unparking(_, _, _) | CarState::IdlingAtStop(_, _) => true,
+            CarState::Crossing(_, _)
+            | CarState::ChangingLanes { .. }
+            | CarState::Parking(_, _, _) => false,
+        }
+    }
+
     pub fn get_draw_car(
         &self,
         front: Distance,
@@ -300,6 +315,7 @@ impl Car {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub(crate) enum CarState {
     Crossing(TimeInterval, DistanceInterval),
+    /// Also used while passing through the oncoming lane, with `from` and `to` the same.
     ChangingLanes {
         from: LaneID,
         to: LaneID,
@@ -311,6 +327,7 @@ pub(crate) enum CarState {
     },
     Queued {
         blocked_since: Time,
+        // If this is an oncoming lane, the car wants to use it to pass their leader.
         want_to_change_lanes: Option<LaneID>,
     },
     WaitingToAdvance {
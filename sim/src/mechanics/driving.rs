@@ -3,22 +3,21 @@ use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_hashmap, serialize_hashmap, FixedMap, IndexableKey};
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{DrivingSide, IntersectionID, LaneID, Map, Path, Position, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{DrivingSide, IntersectionID, LaneID, LaneType, Map, Path, Position, Traversable};
 
+use crate::energy::energy_to_cross;
 use crate::mechanics::car::{Car, CarState};
 use crate::mechanics::queue::{Queue, QueueEntry, Queued};
+use crate::ridehail::NextMove;
 use crate::sim::Ctx;
 use crate::{
-    ActionAtEnd, AgentID, AgentProperties, CarID, Command, CreateCar, DelayCause, DistanceInterval,
-    DrawCarInput, Event, IntersectionSimState, ParkedCar, ParkingSim, ParkingSpot, PersonID,
-    Problem, SimOptions, TimeInterval, TransitSimState, TripID, TripManager, UnzoomedAgent,
-    Vehicle, VehicleType, WalkingSimState, FOLLOWING_DISTANCE,
+    ActionAtEnd, AgentID, AgentProperties, AlertLocation, CarID, Command, CreateCar, DelayCause,
+    DeliverySimState, DistanceInterval, DrawCarInput, Event, IntersectionSimState, ParkedCar,
+    ParkingSim, ParkingSpot, PersonID, Problem, RideHailSimState, Router, SimOptions, TimeInterval,
+    TransitSimState, TripID, TripManager, UnzoomedAgent, Vehicle, VehicleType, WalkingSimState,
 };
 
-const TIME_TO_WAIT_AT_BUS_STOP: Duration = Duration::const_seconds(10.0);
-const TIME_TO_CHANGE_LANES: Duration = Duration::const_seconds(1.0);
-
 // TODO Do something else.
 pub const BLIND_RETRY_TO_CREEP_FORWARDS: Duration = Duration::const_seconds(0.1);
 pub const BLIND_RETRY_TO_REACH_END_DIST: Duration = Duration::const_seconds(5.0);
@@ -45,6 +44,10 @@ pub(crate) struct DrivingSimState {
 
     recalc_lanechanging: bool,
     handle_uber_turns: bool,
+    allow_opposing_lane_passing: bool,
+    /// Cars currently passing through the oncoming lane, and the static blockage they've placed
+    /// there
+    opposing_passes: BTreeMap<CarID, LaneID>,
 
     time_to_unpark_onstreet: Duration,
     time_to_park_onstreet: Duration,
@@ -61,6 +64,8 @@ impl DrivingSimState {
             events: Vec::new(),
             recalc_lanechanging: opts.recalc_lanechanging,
             handle_uber_turns: opts.handle_uber_turns,
+            allow_opposing_lane_passing: opts.allow_opposing_lane_passing,
+            opposing_passes: BTreeMap::new(),
             waiting_to_spawn: BTreeMap::new(),
 
             time_to_unpark_onstreet: Duration::seconds(10.0),
@@ -105,6 +110,7 @@ impl DrivingSimState {
             if let Some(idx) = self.queues[&Traversable::Lane(pos.lane())].can_block_from_driveway(
                 pos,
                 params.vehicle.length,
+                params.vehicle.behavior.following_distance,
                 now,
                 &self.cars,
                 &self.queues,
@@ -126,6 +132,7 @@ impl DrivingSimState {
         if let Some(idx) = self.queues[&Traversable::Lane(first_lane)].get_idx_to_insert_car(
             start_dist,
             params.vehicle.length,
+            params.vehicle.behavior.following_distance,
             now,
             &self.cars,
             &self.queues,
@@ -177,6 +184,7 @@ impl DrivingSimState {
                             car.vehicle.id,
                             start_dist,
                             start_dist - car.vehicle.length,
+                            car.vehicle.behavior.following_distance,
                             idx,
                         );
                 }
@@ -265,6 +273,8 @@ impl DrivingSimState {
         ctx: &mut Ctx,
         trips: &mut TripManager,
         transit: &mut TransitSimState,
+        deliveries: &mut DeliverySimState,
+        ridehail: &mut RideHailSimState,
         walking: &mut WalkingSimState,
     ) {
         let mut need_distances = {
@@ -302,9 +312,9 @@ impl DrivingSimState {
             // checker, temporarily move one of them out of the map.
             let mut car = self.cars.remove(&id).unwrap();
             // Responsibility of update_car_with_distances to manage scheduling stuff!
-            if self
-                .update_car_with_distances(&mut car, &dists, idx, now, ctx, trips, transit, walking)
-            {
+            if self.update_car_with_distances(
+                &mut car, &dists, idx, now, ctx, trips, transit, deliveries, ridehail, walking,
+            ) {
                 self.cars.insert(id, car);
             } else {
                 self.delete_car_internal(&mut car, dists, idx, now, ctx);
@@ -323,7 +333,31 @@ impl DrivingSimState {
         transit: &mut TransitSimState,
     ) -> bool {
         match car.state {
-            CarState::Crossing(_, _) => {
+            CarState::Crossing(time_int, dist_int) => {
+                if let Some(ref mut battery) = car.vehicle.battery {
+                    let kwh = energy_to_cross(
+                        car.router.head(),
+                        dist_int.end - dist_int.start,
+                        time_int.end - time_int.start,
+                        ctx.map,
+                    );
+                    // Keep driving on an empty battery, rather than stranding the car in the
+                    // middle of the road
+                    if battery.use_energy(kwh) {
+                        if let Some((_, person)) = car.trip_and_person {
+                            self.events.push(Event::Alert(
+                                AlertLocation::Person(person),
+                                format!("{} ran out of battery", car.vehicle.id),
+                            ));
+                        }
+                    }
+                    self.events.push(Event::EnergyUsed {
+                        car: car.vehicle.id,
+                        trip: car.trip_and_person.map(|(t, _)| t),
+                        kwh,
+                    });
+                }
+
                 car.state = CarState::Queued {
                     blocked_since: now,
                     want_to_change_lanes: None,
@@ -359,10 +393,17 @@ impl DrivingSimState {
                         ));
                     }
 
-                    if let Some(target_lane) = self.pick_overtaking_lane(car, ctx.map) {
-                        // We need the current position of the car to see if lane-changing is
-                        // actually feasible right now, so record our intention and trigger
-                        // update_car_with_distances.
+                    let target_lane = self.pick_overtaking_lane(car, ctx.map).or_else(|| {
+                        if self.allow_opposing_lane_passing {
+                            self.pick_opposing_lane(car, ctx.map)
+                        } else {
+                            None
+                        }
+                    });
+                    if let Some(target_lane) = target_lane {
+                        // We need the current position of the car to see if lane-changing or
+                        // passing is actually feasible right now, so record our intention and
+                        // trigger update_car_with_distances.
                         car.state = CarState::Queued {
                             blocked_since: now,
                             want_to_change_lanes: Some(target_lane),
@@ -419,11 +460,7 @@ impl DrivingSimState {
                     if !ctx.intersections.maybe_start_turn(
                         AgentID::Car(car.vehicle.id),
                         t,
-                        goto.max_speed_along(
-                            car.vehicle.max_speed,
-                            car.vehicle.vehicle_type.to_constraints(),
-                            ctx.map,
-                        ),
+                        car.vehicle.speed_along(goto, ctx.map),
                         now,
                         ctx.map,
                         ctx.scheduler,
@@ -482,7 +519,7 @@ impl DrivingSimState {
                     car.crossing_state_with_end_dist(
                         DistanceInterval::new_driving(
                             Distance::ZERO,
-                            car.vehicle.length + FOLLOWING_DISTANCE,
+                            car.vehicle.length + car.vehicle.behavior.following_distance,
                         ),
                         now,
                         ctx.map,
@@ -522,6 +559,9 @@ impl DrivingSimState {
                     .get_mut(&Traversable::Lane(from))
                     .unwrap()
                     .clear_dynamic_blockage(car.vehicle.id, idx);
+
+                // If we were passing through the oncoming lane, we're back now
+                self.finish_opposing_pass(car.vehicle.id, now, ctx);
             }
             CarState::Queued { .. } => unreachable!(),
             CarState::Parking(_, _, _) => unreachable!(),
@@ -540,6 +580,8 @@ impl DrivingSimState {
         ctx: &mut Ctx,
         trips: &mut TripManager,
         transit: &mut TransitSimState,
+        deliveries: &mut DeliverySimState,
+        ridehail: &mut RideHailSimState,
         walking: &mut WalkingSimState,
     ) -> bool {
         let our_dist = dists[idx].front;
@@ -556,7 +598,12 @@ impl DrivingSimState {
                 // Two totally different reasons we'll wind up here: we want to lane-change, and
                 // we're on our last step.
                 if let Some(target_lane) = want_to_change_lanes {
-                    self.try_start_lc(car, our_dist, idx, target_lane, now, ctx);
+                    let current_lane = car.router.head().as_lane();
+                    if ctx.map.get_l(target_lane).dir == ctx.map.get_l(current_lane).dir {
+                        self.try_start_lc(car, our_dist, idx, target_lane, now, ctx);
+                    } else {
+                        self.try_start_opposing_pass(car, dists, idx, target_lane, now, ctx);
+                    }
                     return true;
                 }
 
@@ -635,10 +682,12 @@ impl DrivingSimState {
                     }
                     Some(ActionAtEnd::BusAtStop) => {
                         car.total_blocked_time += now - blocked_since;
-                        if transit.bus_arrived_at_stop(now, car.vehicle.id, trips, walking, ctx) {
+                        if let Some(dwell_time) =
+                            transit.bus_arrived_at_stop(now, car.vehicle.id, trips, walking, ctx)
+                        {
                             car.state = CarState::IdlingAtStop(
                                 our_dist,
-                                TimeInterval::new(now, now + TIME_TO_WAIT_AT_BUS_STOP),
+                                TimeInterval::new(now, now + dwell_time),
                             );
                             ctx.scheduler
                                 .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
@@ -648,6 +697,41 @@ impl DrivingSimState {
                             false
                         }
                     }
+                    Some(ActionAtEnd::DeliveryAtStop) => {
+                        car.total_blocked_time += now - blocked_since;
+                        // There's no free curbside spot, so double-park
+                        if let Some(dwell_time) =
+                            deliveries.arrived(now, car.vehicle.id, car.router.head().as_lane())
+                        {
+                            car.state = CarState::IdlingAtStop(
+                                our_dist,
+                                TimeInterval::new(now, now + dwell_time),
+                            );
+                            ctx.scheduler
+                                .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                            true
+                        } else {
+                            // Back at the depot
+                            false
+                        }
+                    }
+                    Some(ActionAtEnd::RideHailAtStop) => {
+                        car.total_blocked_time += now - blocked_since;
+                        let dwell_time = ridehail.arrived(
+                            now,
+                            car.vehicle.id,
+                            car.router.get_path().total_length(),
+                            trips,
+                            ctx,
+                        );
+                        car.state = CarState::IdlingAtStop(
+                            our_dist,
+                            TimeInterval::new(now, now + dwell_time),
+                        );
+                        ctx.scheduler
+                            .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                        true
+                    }
                     None => {
                         ctx.scheduler.push(
                             now + BLIND_RETRY_TO_REACH_END_DIST,
@@ -677,7 +761,11 @@ impl DrivingSimState {
                     }
                 }
             }
-            CarState::Parking(_, spot, _) => {
+            CarState::Parking(_, spot, time_int) => {
+                if car.vehicle.vehicle_type == VehicleType::Delivery {
+                    deliveries.parked(now, time_int.start, car.vehicle.clone(), spot, ctx);
+                    return false;
+                }
                 ctx.parking.add_parked_car(ParkedCar {
                     vehicle: car.vehicle.clone(),
                     spot,
@@ -694,7 +782,34 @@ impl DrivingSimState {
                 false
             }
             CarState::IdlingAtStop(dist, _) => {
-                car.router = transit.bus_departed_from_stop(car.vehicle.id, ctx.map);
+                car.router = if car.vehicle.vehicle_type == VehicleType::Delivery {
+                    deliveries.done_unloading(now, car.vehicle.id);
+                    let pos = Position::new(car.router.head().as_lane(), dist);
+                    deliveries
+                        .next_leg(now, car.vehicle.id, pos, ctx.map)
+                        // If the rest of the tour is impossible, vanish the same way buses do at
+                        // the end of their route
+                        .unwrap_or_else(|| Router::vanish_bus(car.vehicle.id, pos, ctx.map))
+                } else if car.vehicle.vehicle_type == VehicleType::RideHail {
+                    let pos = Position::new(car.router.head().as_lane(), dist);
+                    match ridehail.departing(now, car.vehicle.id, pos, trips, ctx) {
+                        NextMove::Drive(router) => router,
+                        NextMove::StayStopped(dwell_time) => {
+                            car.state = CarState::IdlingAtStop(
+                                dist,
+                                TimeInterval::new(now, now + dwell_time),
+                            );
+                            ctx.scheduler
+                                .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                            return true;
+                        }
+                        NextMove::LeaveMap => {
+                            return false;
+                        }
+                    }
+                } else {
+                    transit.bus_departed_from_stop(now, car.vehicle.id, ctx.map)
+                };
                 self.events
                     .push(Event::PathAmended(car.router.get_path().clone()));
                 car.state = car.crossing_state(dist, now, ctx.map);
@@ -729,7 +844,7 @@ impl DrivingSimState {
             let queue = self.queues.get_mut(&car.router.head()).unwrap();
             // delete_car_internal will call free_reserved_space, so this is necessary to balance
             // that.
-            queue.reserved_length += car.vehicle.length + FOLLOWING_DISTANCE;
+            queue.reserved_length += car.vehicle.length + car.vehicle.behavior.following_distance;
             ctx.intersections.agent_deleted_mid_turn(AgentID::Car(c), t);
 
             // Free any reserved space on the next step.
@@ -743,6 +858,7 @@ impl DrivingSimState {
         if car.router.last_step() {
             ctx.parking.unreserve_spot(c);
         }
+        self.finish_opposing_pass(c, now, ctx);
 
         self.delete_car_internal(&mut car, dists, idx, now, ctx);
         // delete_car_internal cancels UpdateLaggyHead
@@ -889,7 +1005,8 @@ impl DrivingSimState {
         };
 
         // Trim off as many of the oldest last_steps as we've made distance.
-        let mut dist_left_to_cleanup = self.cars[&id].vehicle.length + FOLLOWING_DISTANCE;
+        let vehicle = &self.cars[&id].vehicle;
+        let mut dist_left_to_cleanup = vehicle.length + vehicle.behavior.following_distance;
         dist_left_to_cleanup -= dist_along_last;
         let mut num_to_trim = None;
         for (idx, step) in self.cars[&id].last_steps.iter().enumerate() {
@@ -915,7 +1032,8 @@ impl DrivingSimState {
                     // fine for correctness.
                     DistanceInterval::new_driving(
                         dist_along_last,
-                        self.cars[&id].vehicle.length + FOLLOWING_DISTANCE,
+                        self.cars[&id].vehicle.length
+                            + self.cars[&id].vehicle.behavior.following_distance,
                     ),
                     now,
                     ctx.map,
@@ -1018,8 +1136,8 @@ impl DrivingSimState {
     }
 
     /// If the car wants to over-take somebody, what adjacent lane should they use?
-    /// - The lane must be in the same direction as the current; no support for crossing the road's
-    ///   yellow line yet.
+    /// - The lane must be in the same direction as the current. See `pick_opposing_lane` for
+    ///   crossing the road's yellow line.
     /// - Prefer passing on the left (for DrivingSide::Right)
     /// For now, just pick one candidate lane, even if both might be usable.
     fn pick_overtaking_lane(&self, car: &Car, map: &Map) -> Option<LaneID> {
@@ -1070,6 +1188,37 @@ impl DrivingSimState {
         None
     }
 
+    /// If the car can't overtake using a lane in the same direction, can they pass through the
+    /// oncoming lane instead? Only on roads with exactly one driving lane each way.
+    fn pick_opposing_lane(&self, car: &Car, map: &Map) -> Option<LaneID> {
+        let current_lane = map.get_l(car.router.head().maybe_lane()?);
+        if current_lane.lane_type != LaneType::Driving {
+            return None;
+        }
+        // Don't bother on the last step; the car is about to stop anyway.
+        if car.router.last_step() {
+            return None;
+        }
+        let road = map.get_r(current_lane.parent);
+        let mut ours = 0;
+        let mut opposing = Vec::new();
+        for (l, dir, lt) in road.lanes_ltr() {
+            if lt != LaneType::Driving {
+                continue;
+            }
+            if dir == current_lane.dir {
+                ours += 1;
+            } else {
+                opposing.push(l);
+            }
+        }
+        if ours == 1 && opposing.len() == 1 {
+            Some(opposing[0])
+        } else {
+            None
+        }
+    }
+
     fn try_start_lc(
         &mut self,
         car: &mut Car,
@@ -1107,7 +1256,7 @@ impl DrivingSimState {
 
         // Do we have enough time to finish the lane-change, assuming that we go as fast as
         // possible in the target?
-        let lc_time = TimeInterval::new(now, now + TIME_TO_CHANGE_LANES);
+        let lc_time = TimeInterval::new(now, now + car.vehicle.behavior.lane_change_time);
         if lc_time.end >= new_time.end {
             return;
         }
@@ -1117,6 +1266,7 @@ impl DrivingSimState {
             .get_idx_to_insert_car(
                 front_target_queue,
                 car.vehicle.length,
+                car.vehicle.behavior.following_distance,
                 now,
                 &self.cars,
                 &self.queues,
@@ -1158,6 +1308,195 @@ impl DrivingSimState {
         }
     }
 
+    /// Pass the car's leader by briefly driving in the oncoming lane. The car jumps ahead of its
+    /// leader in the current queue, leaving a dynamic blockage behind, just like lane-changing.
+    /// The oncoming lane must be totally empty, and for the duration of the pass, a static
+    /// blockage there stops anybody new from running into the car.
+    ///
+    /// The car skips over the distance it gains on the leader instantly, so a pass saves a little
+    /// more time than it should.
+    fn try_start_opposing_pass(
+        &mut self,
+        car: &mut Car,
+        dists: &[QueueEntry],
+        idx: usize,
+        opposing_lane: LaneID,
+        now: Time,
+        ctx: &mut Ctx,
+    ) {
+        if !car.last_steps.is_empty() || car.router.last_step() || idx == 0 {
+            return;
+        }
+        let leader = match dists[idx - 1].member {
+            Queued::Vehicle(id) => &self.cars[&id],
+            // Don't pass a lane-changing vehicle or something unparking from a driveway
+            Queued::StaticBlockage { .. } | Queued::DynamicBlockage { .. } => {
+                return;
+            }
+        };
+        let current_lane = car.router.head().as_lane();
+        let their_speed = match leader.state {
+            CarState::Crossing(_, _) | CarState::ChangingLanes { .. } => {
+                leader.vehicle.speed_along(leader.router.head(), ctx.map)
+            }
+            CarState::Parking(_, _, _)
+            | CarState::Unparking(_, _, _)
+            | CarState::IdlingAtStop(_, _) => Speed::ZERO,
+            CarState::Queued { .. } | CarState::WaitingToAdvance { .. } => {
+                return;
+            }
+        };
+        let our_speed = car.vehicle.speed_along(car.router.head(), ctx.map);
+        if our_speed <= their_speed {
+            return;
+        }
+
+        // Where do we wind up? Just in front of the leader, if there's room before anybody else.
+        let our_front = dists[idx].front;
+        // The car we pass follows us afterwards, at its own following distance.
+        let following_dist = car.vehicle.behavior.following_distance;
+        let new_front =
+            dists[idx - 1].front + leader.vehicle.behavior.following_distance + car.vehicle.length;
+        let lane_len = ctx.map.get_l(current_lane).length();
+        if new_front >= lane_len {
+            return;
+        }
+        // We slot in between the leader and whoever's ahead of them, so there has to be a gap
+        // behind that car. If the leader is at the front of the queue, somebody might still be
+        // finishing a turn out of this lane; assume the worst case like get_idx_to_insert_car.
+        let room_until = if idx >= 2 {
+            dists[idx - 2].back - following_dist
+        } else {
+            match self.queues[&car.router.head()].laggy_head {
+                Some(c) => lane_len - self.cars[&c].vehicle.length - following_dist,
+                None => lane_len,
+            }
+        };
+        if new_front > room_until {
+            return;
+        }
+        let idx_ahead = idx - 1;
+
+        // How long are we in the oncoming lane? The pass has to finish before the end of the
+        // lane.
+        let pass_time = ((new_front - our_front) / (our_speed - their_speed))
+            .max(car.vehicle.behavior.lane_change_time);
+        let (new_time, new_dist) = match car.crossing_state_with_end_dist(
+            DistanceInterval::new_driving(new_front, lane_len),
+            now,
+            ctx.map,
+        ) {
+            CarState::Crossing(time, dist) => (time, dist),
+            _ => unreachable!(),
+        };
+        let lc_time = TimeInterval::new(now, now + pass_time);
+        if lc_time.end >= new_time.end {
+            return;
+        }
+
+        // Is the oncoming lane clear, with nobody about to enter it?
+        let opposing = ctx.map.get_l(opposing_lane);
+        if !self.queues[&Traversable::Lane(opposing_lane)]
+            .get_active_cars()
+            .is_empty()
+            || !ctx
+                .intersections
+                .nobody_headed_towards(opposing_lane, opposing.src_i)
+        {
+            return;
+        }
+        // Block the part of the oncoming lane we'll use
+        let block_front = Position::new(current_lane, our_front - car.vehicle.length)
+            .equiv_pos(opposing_lane, ctx.map)
+            .dist_along();
+        let block_back = Position::new(
+            current_lane,
+            (new_front + our_speed * pass_time).min(lane_len),
+        )
+        .equiv_pos(opposing_lane, ctx.map)
+        .dist_along();
+        if block_front <= block_back {
+            return;
+        }
+        let idx_blockage = match self.queues[&Traversable::Lane(opposing_lane)]
+            .can_block_from_driveway(
+                &Position::new(opposing_lane, block_front),
+                block_front - block_back,
+                following_dist,
+                now,
+                &self.cars,
+                &self.queues,
+            ) {
+            Some(i) => i,
+            None => {
+                return;
+            }
+        };
+
+        info!(
+            "{} is passing {} using the oncoming lane {}",
+            car.vehicle.id, leader.vehicle.id, opposing_lane
+        );
+
+        self.queues
+            .get_mut(&Traversable::Lane(opposing_lane))
+            .unwrap()
+            .add_static_blockage(
+                car.vehicle.id,
+                block_front,
+                block_back,
+                following_dist,
+                idx_blockage,
+            );
+        self.opposing_passes.insert(car.vehicle.id, opposing_lane);
+
+        // Leave a dynamic blockage behind, then jump ahead of the leader. Since idx_ahead is
+        // before our old index, replacing ourselves first doesn't change it.
+        let queue = self.queues.get_mut(&car.router.head()).unwrap();
+        queue.replace_car_with_dynamic_blockage(car, idx);
+        queue.insert_car_at_idx(idx_ahead, car);
+
+        car.state = CarState::ChangingLanes {
+            from: current_lane,
+            to: current_lane,
+            new_time,
+            new_dist,
+            lc_time,
+        };
+        ctx.scheduler
+            .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+    }
+
+    /// If the car was passing through the oncoming lane, remove the blockage there.
+    fn finish_opposing_pass(&mut self, car: CarID, now: Time, ctx: &mut Ctx) {
+        let opposing_lane = match self.opposing_passes.remove(&car) {
+            Some(l) => l,
+            None => {
+                return;
+            }
+        };
+        // Live edits might've already deleted the lane or cleared the blockage
+        let dists = match self.queues.get(&Traversable::Lane(opposing_lane)) {
+            Some(queue) => queue.get_car_positions(now, &self.cars, &self.queues),
+            None => {
+                return;
+            }
+        };
+        let idx = match dists.iter().position(
+            |entry| matches!(entry.member, Queued::StaticBlockage { cause, ..} if cause == car),
+        ) {
+            Some(idx) => idx,
+            None => {
+                return;
+            }
+        };
+        self.update_follower(idx, &dists, now, ctx);
+        self.queues
+            .get_mut(&Traversable::Lane(opposing_lane))
+            .unwrap()
+            .clear_static_blockage(car, idx);
+    }
+
     pub fn collect_events(&mut self) -> Vec<Event> {
         std::mem::take(&mut self.events)
     }
@@ -1454,6 +1793,39 @@ impl DrivingSimState {
         affected
     }
 
+    /// Finds delivery vans and ride-hail vehicles, which don't belong to any trip, whose path or
+    /// laggy tail crosses affected parts of the map.
+    pub fn find_tripless_vehicles_affected_by_live_edits(
+        &self,
+        closed_intersections: &HashSet<IntersectionID>,
+        edited_lanes: &BTreeSet<LaneID>,
+    ) -> Vec<CarID> {
+        let affected = |step: &Traversable| match step {
+            Traversable::Lane(l) => edited_lanes.contains(l),
+            Traversable::Turn(t) => {
+                closed_intersections.contains(&t.parent)
+                    || edited_lanes.contains(&t.src)
+                    || edited_lanes.contains(&t.dst)
+            }
+        };
+        self.cars
+            .values()
+            .filter(|car| {
+                matches!(
+                    car.vehicle.vehicle_type,
+                    VehicleType::Delivery | VehicleType::RideHail
+                ) && (car.last_steps.iter().any(affected)
+                    || car
+                        .router
+                        .get_path()
+                        .get_steps()
+                        .iter()
+                        .any(|step| affected(&step.as_traversable())))
+            })
+            .map(|car| car.vehicle.id)
+            .collect()
+    }
+
     pub fn all_waiting_people(&self, now: Time, delays: &mut BTreeMap<PersonID, Duration>) {
         for c in self.cars.values() {
             if let Some((_, person)) = c.trip_and_person {
@@ -1470,6 +1842,39 @@ impl DrivingSimState {
         Some((queue.reserved_length, queue.geom_len))
     }
 
+    /// How many vehicles are currently on a lane, not counting ones whose front has already left.
+    pub fn num_vehicles_on(&self, l: LaneID) -> usize {
+        self.queues
+            .get(&Traversable::Lane(l))
+            .map(|q| q.get_active_cars().len())
+            .unwrap_or(0)
+    }
+
+    /// Is any part of a vehicle over some interval of a lane right now?
+    pub fn any_vehicle_over(&self, now: Time, l: LaneID, start: Distance, end: Distance) -> bool {
+        if let Some(queue) = self.queues.get(&Traversable::Lane(l)) {
+            queue
+                .get_car_positions(now, &self.cars, &self.queues)
+                .into_iter()
+                .any(|entry| {
+                    matches!(entry.member, Queued::Vehicle(_))
+                        && entry.back < end
+                        && entry.front > start
+                })
+        } else {
+            false
+        }
+    }
+
+    pub fn vehicle_length(&self, id: CarID) -> Option<Distance> {
+        let car = self.cars.get(&id)?;
+        if car.vehicle.id == id {
+            Some(car.vehicle.length)
+        } else {
+            None
+        }
+    }
+
     pub fn get_blocked_by_graph(
         &self,
         now: Time,
@@ -1528,9 +1933,24 @@ impl DrivingSimState {
         }
         let leader = &self.cars[&leader];
 
+        // Are they moving slowly, stopped, or also stuck behind someone?
+        match leader.state {
+            // TODO Maybe we want to pass someone queued, if they're too slow to pass their own
+            // leader?
+            CarState::WaitingToAdvance { .. } | CarState::Queued { .. } => {
+                return None;
+            }
+            // Always worth getting around somebody stopped in the lane, like a bus at a stop or
+            // a car parking.
+            CarState::IdlingAtStop(_, _)
+            | CarState::Parking(_, _, _)
+            | CarState::Unparking(_, _, _) => {
+                return Some(leader.vehicle.id);
+            }
+            CarState::Crossing(_, _) | CarState::ChangingLanes { .. } => {}
+        }
+
         // Are we faster than them?
-        // TODO This shouldn't be a blocking check; we also want to pass parking cars and buses
-        // waiting at stops.
         let their_speed = leader.vehicle.max_speed?;
         if car
             .vehicle
@@ -1541,22 +1961,9 @@ impl DrivingSimState {
             return None;
         }
 
-        // Are they moving slowly or also stuck behind someone?
-        match leader.state {
-            // TODO Maybe we want to pass someone queued, if they're too slow to pass their own
-            // leader?
-            CarState::WaitingToAdvance { .. } | CarState::Queued { .. } => {
-                return None;
-            }
-            // In all other cases, we may want to pass them.
-            _ => {}
-        }
-
-        // TODO Check if there's room in front of them to pass. Handle passing two bikes?
-        // TODO Check remaining distance before next intersection
-        // TODO Check relative speed difference, and time to pass them
-        // TODO Eventually, check if there's room to do the maneuever (lane-changing to
-        // the left only, or popping into an oncoming lane!
+        // TODO Handle passing two bikes?
+        // The room in front of them, the remaining distance before the next intersection, and the
+        // time to pass are checked when actually trying to change lanes or pass.
 
         Some(leader.vehicle.id)
     }
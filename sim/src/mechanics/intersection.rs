@@ -12,7 +12,9 @@ use map_model::{
 use crate::mechanics::car::{Car, CarState};
 use crate::mechanics::Queue;
 use crate::{
-    AgentID, AlertLocation, CarID, Command, DelayCause, Event, Scheduler, SimOptions, Speed,
+    AgentID, AlertLocation, CarID, Command, DelayCause, DetectorSimState, DetectorStatus,
+    DrivingSimState, Event, MovementState, Scheduler, SignalController, SignalControllerInput,
+    SimOptions, Speed, StageDecision,
 };
 
 const WAIT_AT_STOP_SIGN: Duration = Duration::const_seconds(0.5);
@@ -45,6 +47,10 @@ pub(crate) struct IntersectionSimState {
     total_repeat_requests: usize,
     not_allowed_requests: usize,
     blocked_by_someone_requests: usize,
+
+    // Replaces the default behavior of some traffic signals. Not part of savestates.
+    #[serde(skip_serializing, skip_deserializing)]
+    signal_controllers: BTreeMap<IntersectionID, Box<dyn SignalController>>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -102,6 +108,8 @@ impl IntersectionSimState {
             total_repeat_requests: 0,
             not_allowed_requests: 0,
             blocked_by_someone_requests: 0,
+
+            signal_controllers: opts.signal_controllers.clone(),
         };
         if sim.disable_turn_conflicts {
             sim.use_freeform_policy_everywhere = true;
@@ -271,14 +279,22 @@ impl IntersectionSimState {
         }
     }
 
-    /// This is only triggered for traffic signals.
+    /// This is only triggered for traffic signals. If this returns true, a `SignalController`
+    /// deferred its decision, so halt the simulation.
     pub fn update_intersection(
         &mut self,
         now: Time,
         id: IntersectionID,
         map: &Map,
         scheduler: &mut Scheduler,
-    ) {
+        driving: &DrivingSimState,
+        detectors: &DetectorSimState,
+    ) -> bool {
+        if self.signal_controllers.contains_key(&id) {
+            return self
+                .update_controlled_intersection(now, id, map, scheduler, driving, detectors);
+        }
+
         // trivial function that advances the signal stage and returns duration
         fn advance(
             signal_state: &mut SignalState,
@@ -362,6 +378,125 @@ impl IntersectionSimState {
         signal_state.stage_ends_at = now + duration;
         scheduler.push(signal_state.stage_ends_at, Command::UpdateIntersection(id));
         self.wakeup_waiting(now, id, scheduler, map);
+        false
+    }
+
+    fn update_controlled_intersection(
+        &mut self,
+        now: Time,
+        id: IntersectionID,
+        map: &Map,
+        scheduler: &mut Scheduler,
+        driving: &DrivingSimState,
+        detectors: &DetectorSimState,
+    ) -> bool {
+        let state = &self.state[&id];
+        let signal = map.get_traffic_signal(id);
+        let signal_state = state.signal.as_ref().unwrap();
+        assert_eq!(now, signal_state.stage_ends_at);
+
+        let mut movements = BTreeMap::new();
+        for m in signal.movements.values() {
+            let mut upstream = BTreeSet::new();
+            let mut downstream = BTreeSet::new();
+            for t in &m.members {
+                upstream.insert(t.src);
+                downstream.insert(t.dst);
+            }
+            movements.insert(
+                m.id,
+                MovementState {
+                    queue_length: upstream.iter().map(|l| driving.num_vehicles_on(*l)).sum(),
+                    downstream_queue_length: downstream
+                        .into_iter()
+                        .map(|l| driving.num_vehicles_on(l))
+                        .sum(),
+                    waiting: Vec::new(),
+                    detectors: upstream
+                        .into_iter()
+                        .flat_map(|l| detectors.detectors_on(l))
+                        .map(|d| DetectorStatus {
+                            id: d,
+                            occupied: detectors.is_occupied(d, now, driving),
+                            last_passage: detectors.last_passage(d),
+                        })
+                        .collect(),
+                },
+            );
+        }
+        for (req, (started_waiting, _)) in &state.waiting {
+            if let Some(m) = map.get_movement(req.turn) {
+                movements
+                    .get_mut(&m)
+                    .unwrap()
+                    .waiting
+                    .push((req.agent, *started_waiting));
+            }
+        }
+
+        let input = SignalControllerInput {
+            now,
+            id,
+            signal,
+            current_stage: signal_state.current_stage,
+            movements,
+        };
+        let decision = self
+            .signal_controllers
+            .get_mut(&id)
+            .unwrap()
+            .next_stage(&input);
+
+        let signal_state = self.state.get_mut(&id).unwrap().signal.as_mut().unwrap();
+        let (stage, duration) = match decision {
+            StageDecision::Stage(stage, duration) => (stage, duration),
+            StageDecision::Defer => {
+                // Ask again as soon as the simulation resumes
+                scheduler.push(now, Command::UpdateIntersection(id));
+                return true;
+            }
+        };
+        if stage >= signal.stages.len() {
+            self.events.push(Event::Alert(
+                AlertLocation::Intersection(id),
+                format!(
+                    "Signal controller chose stage {}, but there are only {}. Keeping the \
+                     current stage.",
+                    stage,
+                    signal.stages.len()
+                ),
+            ));
+        } else {
+            signal_state.current_stage = stage;
+        }
+        // Don't let a controller stall the simulation by asking again immediately
+        let duration = std::cmp::max(Duration::const_seconds(1.0), duration);
+        signal_state.extensions_count = 0;
+        signal_state.stage_ends_at = now + duration;
+        scheduler.push(signal_state.stage_ends_at, Command::UpdateIntersection(id));
+        self.wakeup_waiting(now, id, scheduler, map);
+        false
+    }
+
+    /// Replaces the default behavior of a traffic signal, starting at the next stage boundary.
+    /// Passing None restores the default.
+    pub fn set_signal_controller(
+        &mut self,
+        id: IntersectionID,
+        controller: Option<Box<dyn SignalController>>,
+    ) {
+        if let Some(controller) = controller {
+            self.signal_controllers.insert(id, controller);
+        } else {
+            self.signal_controllers.remove(&id);
+        }
+    }
+
+    pub fn get_signal_controller_mut(
+        &mut self,
+        id: IntersectionID,
+    ) -> Option<&mut Box<dyn SignalController>> {
+        self.signal_controllers.get_mut(&id)
     }
 
     /// For cars: The head car calls this when they're at the end of the lane WaitingToAdvance. If
@@ -412,6 +547,11 @@ impl IntersectionSimState {
             map.get_t(req.turn).turn_type == TurnType::SharedSidewalkCorner;
 
         let readonly_pair = maybe_cars_and_queues.as_ref().map(|(_, c, q)| (*c, &**q));
+        // Some drivers pause longer than others before an unprotected turn
+        let pause_factor = maybe_cars_and_queues
+            .as_ref()
+            .map(|(car, _, _)| car.vehicle.behavior.gap_acceptance.pause_factor())
+            .unwrap_or(1.0);
         let started_uber_turn = |state: &Self, car: &Car| {
             state.handle_uber_turns && car.router.get_path().currently_inside_ut().is_some()
         };
@@ -432,7 +572,9 @@ impl IntersectionSimState {
             // TODO: Consider reenabling alert
             if let Some(signal) = map.maybe_get_traffic_signal(turn.parent) {
                 // Don't pass in the scheduler, aka, don't pause before yielding.
-                if !self.traffic_signal_policy(&req, map, signal, speed, now, None) && false {
+                if !self.traffic_signal_policy(&req, map, signal, speed, pause_factor, now, None)
+                    && false
+                {
                     self.events.push(Event::Alert(
                         AlertLocation::Intersection(req.turn.parent),
                         format!("Running a red light inside an uber-turn: {:?}", req),
@@ -445,9 +587,9 @@ impl IntersectionSimState {
             // If we made it this far, we don't conflict with an accepted turn
             true
         } else if let Some(signal) = map.maybe_get_traffic_signal(turn.parent) {
-            self.traffic_signal_policy(&req, map, signal, speed, now, Some(scheduler))
+            self.traffic_signal_policy(&req, map, signal, speed, pause_factor, now, Some(scheduler))
         } else if let Some(sign) = map.maybe_get_stop_sign(turn.parent) {
-            self.stop_sign_policy(&req, map, sign, now, scheduler)
+            self.stop_sign_policy(&req, map, sign, pause_factor, now, scheduler)
         } else {
             unreachable!()
         };
@@ -804,6 +946,7 @@ impl IntersectionSimState {
         req: &Request,
         map: &Map,
         sign: &ControlStopSign,
+        pause_factor: f64,
         now: Time,
         scheduler: &mut Scheduler,
     ) -> bool {
@@ -811,13 +954,11 @@ impl IntersectionSimState {
         assert!(our_priority != TurnPriority::Banned);
         let (our_time, _) = self.state[&req.turn.parent].waiting[req];
 
-        if our_priority == TurnPriority::Yield && now < our_time + WAIT_AT_STOP_SIGN {
+        let pause = pause_factor * WAIT_AT_STOP_SIGN;
+        if our_priority == TurnPriority::Yield && now < our_time + pause {
             // Since we have "ownership" of scheduling for req.agent, don't need to use
             // scheduler.update.
-            scheduler.push(
-                our_time + WAIT_AT_STOP_SIGN,
-                Command::update_agent(req.agent),
-            );
+            scheduler.push(our_time + pause, Command::update_agent(req.agent));
             return false;
         }
 
@@ -846,6 +987,7 @@ impl IntersectionSimState {
         map: &Map,
         signal: &ControlTrafficSignal,
         speed: Speed,
+        pause_factor: f64,
         now: Time,
         scheduler: Option<&mut Scheduler>,
     ) -> bool {
@@ -864,16 +1006,12 @@ impl IntersectionSimState {
             return false;
         }
 
-        if our_priority == TurnPriority::Yield
-            && now < our_time + WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL
-        {
+        let pause = pause_factor * WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL;
+        if our_priority == TurnPriority::Yield && now < our_time + pause {
             // Since we have "ownership" of scheduling for req.agent, don't need to use
             // scheduler.update.
             if let Some(s) = scheduler {
-                s.push(
-                    our_time + WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL,
-                    Command::update_agent(req.agent),
-                );
+                s.push(our_time + pause, Command::update_agent(req.agent));
             }
             return false;
         }
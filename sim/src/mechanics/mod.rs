@@ -2,6 +2,10 @@ pub(crate) use self::driving::DrivingSimState;
 pub(crate) use self::intersection::IntersectionSimState;
 pub(crate) use self::parking::{ParkingSim, ParkingSimState};
 pub(crate) use self::queue::Queue;
+pub use self::signal_controller::{
+    Actuated, DetectorStatus, MaxPressure, MovementState, SignalController, SignalControllerInput,
+    StageDecision,
+};
 pub(crate) use self::walking::WalkingSimState;
 
 mod car;
@@ -9,4 +13,5 @@ mod driving;
 mod intersection;
 mod parking;
 mod queue;
+mod signal_controller;
 mod walking;
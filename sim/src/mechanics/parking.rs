@@ -8,13 +8,20 @@ use serde::{Deserialize, Serialize};
 use abstutil::{
     deserialize_btreemap, deserialize_multimap, serialize_btreemap, serialize_multimap, MultiMap,
 };
-use geom::{Distance, PolyLine, Pt2D};
+use geom::{Distance, PolyLine, Pt2D, Time};
 use map_model::{
-    BuildingID, Lane, LaneID, LaneType, Map, OffstreetParking, ParkingLotID, PathConstraints,
-    PathStep, Position, Traversable, TurnID,
+    BuildingID, Lane, LaneID, LaneType, Map, OffstreetParking, ParkingLotID, ParkingPlace,
+    PathConstraints, PathStep, Position, Traversable, TurnID,
 };
 
-use crate::{CarID, CarStatus, DrawCarInput, Event, ParkedCar, ParkingSpot, PersonID, Vehicle};
+use crate::{
+    CarID, CarStatus, ChargerLocation, DrawCarInput, Event, ParkedCar, ParkingSpot, PersonID,
+    Vehicle,
+};
+
+/// Drivers would walk this much farther to save a dollar on parking, counting the walk there and
+/// back again.
+const WALK_TO_SAVE_A_DOLLAR: Distance = Distance::const_meters(150.0);
 
 /// Manages the state of parked cars. There are two implementations:
 /// - NormalParkingSimState allows only one vehicle per ParkingSpot defined in the map
@@ -74,6 +81,30 @@ pub trait ParkingSim {
     fn collect_events(&mut self) -> Vec<Event>;
     fn all_parked_car_positions(&self, map: &Map) -> Vec<(Position, PersonID)>;
     fn bldg_to_parked_cars(&self, b: BuildingID) -> Vec<CarID>;
+    /// Puts a plug in the first few spots of a building or parking lot. Returns the spots that
+    /// got one.
+    fn add_charger(&mut self, location: ChargerLocation, ports: usize) -> Vec<ParkingSpot>;
+    /// The driver of this car expects to arrive and leave again around these times. Drivers with
+    /// plans avoid spots with too short a time limit, and weigh what each spot costs for the stay
+    /// against how far they'd have to walk.
+    fn plan_stay(&mut self, car: CarID, arrive: Time, leave: Time);
+    /// How much the price of a spot puts off this vehicle's driver, expressed as how much farther
+    /// they'd walk to avoid paying it. Zero for free spots, or if the driver has no plans.
+    fn price_as_walking_distance(
+        &self,
+        spot: ParkingSpot,
+        vehicle: &Vehicle,
+        map: &Map,
+    ) -> Distance;
+    /// The cheapest price for a stay somewhere along the road in front of a building, ignoring
+    /// whether there's a free spot right now. None if there's nowhere to park for that long.
+    fn cheapest_parking_near(
+        &self,
+        b: BuildingID,
+        arrive: Time,
+        leave: Time,
+        map: &Map,
+    ) -> Option<f64>;
 }
 
 #[enum_dispatch]
@@ -146,6 +177,16 @@ pub struct NormalParkingSimState {
     )]
     driving_to_lots: MultiMap<LaneID, ParkingLotID>,
 
+    // Spots with a plug for electric vehicles
+    charging_spots: BTreeSet<ParkingSpot>,
+
+    // When drivers heading somewhere expect to arrive and leave again
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    planned_stays: BTreeMap<CarID, (Time, Time)>,
+
     events: Vec<Event>,
 }
 
@@ -163,6 +204,10 @@ impl NormalParkingSimState {
             num_spots_per_lot: BTreeMap::new(),
             driving_to_lots: MultiMap::new(),
 
+            charging_spots: BTreeSet::new(),
+
+            planned_stays: BTreeMap::new(),
+
             events: Vec::new(),
         };
         for l in map.all_lanes().values() {
@@ -289,6 +334,7 @@ impl ParkingSim for NormalParkingSimState {
 
     fn unreserve_spot(&mut self, car: CarID) {
         self.reserved_spots.retain(|_, c| car != *c);
+        self.planned_stays.remove(&car);
     }
 
     fn remove_parked_car(&mut self, p: ParkedCar) {
@@ -307,6 +353,7 @@ impl ParkingSim for NormalParkingSimState {
             .push(Event::CarReachedParkingSpot(p.vehicle.id, p.spot));
 
         assert_eq!(self.reserved_spots.remove(&p.spot), Some(p.vehicle.id));
+        self.planned_stays.remove(&p.vehicle.id);
 
         assert!(!self.occupants.contains_key(&p.spot));
         self.occupants.insert(p.spot, p.vehicle.id);
@@ -470,6 +517,24 @@ impl ParkingSim for NormalParkingSimState {
             }
         }
 
+        // Nobody plans on overstaying a time limit
+        if let Some((arrive, leave)) = self.planned_stays.get(&vehicle.id) {
+            candidates.retain(|spot| {
+                map.get_parking_policy(spot.place(map))
+                    .map(|policy| policy.allows_stay(*arrive, *leave))
+                    .unwrap_or(true)
+            });
+        }
+
+        // Electric vehicles would rather plug in, if they can
+        if vehicle.battery.is_some()
+            && candidates
+                .iter()
+                .any(|spot| self.charging_spots.contains(spot))
+        {
+            candidates.retain(|spot| self.charging_spots.contains(spot));
+        }
+
         candidates
             .into_iter()
             .map(|spot| (spot, self.spot_to_driving_pos(spot, vehicle, map)))
@@ -549,9 +614,10 @@ impl ParkingSim for NormalParkingSimState {
         let mut backrefs: HashMap<LaneID, TurnID> = HashMap::new();
         // Don't travel far.
         // This is a max-heap, so negate all distances. Tie breaker is lane ID, arbitrary but
-        // deterministic.
-        let mut queue: BinaryHeap<(Distance, LaneID)> = BinaryHeap::new();
-        queue.push((Distance::ZERO, start));
+        // deterministic. Priced spots get queued up as well, in case there's something cheaper
+        // only a little farther away.
+        let mut queue: BinaryHeap<(Distance, LaneID, Option<ParkingSpot>)> = BinaryHeap::new();
+        queue.push((Distance::ZERO, start, None));
 
         // We need a source of randomness between different cars, but it needs to be deterministic
         // across repeated runs of the exact same simulation. This also shouldn't be the same
@@ -560,7 +626,12 @@ impl ParkingSim for NormalParkingSimState {
         let mut rng = XorShiftRng::seed_from_u64((vehicle.id.id + start.0) as u64);
 
         while !queue.is_empty() {
-            let (dist_so_far, current) = queue.pop().unwrap();
+            let (dist_so_far, current, priced_spot) = queue.pop().unwrap();
+            if let Some(spot) = priced_spot {
+                // Nothing cheaper turned up before the price caught up with the walk
+                let pos = self.spot_to_driving_pos(spot, vehicle, map);
+                return Some((backtrack(start, current, &backrefs), spot, pos));
+            }
             // If the current lane has a spot open, we wouldn't be asking. This can happen if a spot
             // opens up on the 'start' lane, but behind the car.
             if current != start {
@@ -569,22 +640,15 @@ impl ParkingSim for NormalParkingSimState {
                 if let Some((spot, pos)) = self
                     .get_all_free_spots(Position::start(current), vehicle, target, map)
                     .into_iter()
-                    .min_by_key(|(_, pos)| pos.dist_along())
+                    .min_by_key(|(spot, pos)| {
+                        pos.dist_along() + self.price_as_walking_distance(*spot, vehicle, map)
+                    })
                 {
-                    let mut steps = vec![PathStep::Lane(current)];
-                    let mut current = current;
-                    loop {
-                        if current == start {
-                            // Don't include PathStep::Lane(start)
-                            steps.pop();
-                            steps.reverse();
-                            return Some((steps, spot, pos));
-                        }
-                        let turn = backrefs[&current];
-                        steps.push(PathStep::Turn(turn));
-                        steps.push(PathStep::Lane(turn.src));
-                        current = turn.src;
+                    let price = self.price_as_walking_distance(spot, vehicle, map);
+                    if price == Distance::ZERO {
+                        return Some((backtrack(start, current, &backrefs), spot, pos));
                     }
+                    queue.push((dist_so_far - price, current, Some(spot)));
                 }
             }
             for turn in map.get_turns_for(current, PathConstraints::Car) {
@@ -600,7 +664,7 @@ impl ParkingSim for NormalParkingSimState {
                     let jitter = rng.gen_range(0.1..0.9);
                     e.insert(turn.id);
                     // Remember, keep things negative
-                    queue.push((dist_so_far - jitter * dist_this_step, turn.id.dst));
+                    queue.push((dist_so_far - jitter * dist_this_step, turn.id.dst, None));
                 }
             }
         }
@@ -615,12 +679,8 @@ impl ParkingSim for NormalParkingSimState {
     fn all_parked_car_positions(&self, map: &Map) -> Vec<(Position, PersonID)> {
         self.parked_cars
             .values()
-            .map(|p| {
-                (
-                    self.spot_to_sidewalk_pos(p.spot, map),
-                    p.vehicle.owner.unwrap(),
-                )
-            })
+            // Delivery vehicles unloading at the curb don't belong to anybody
+            .filter_map(|p| Some((self.spot_to_sidewalk_pos(p.spot, map), p.vehicle.owner?)))
             .collect()
     }
 
@@ -634,6 +694,90 @@ impl ParkingSim for NormalParkingSimState {
         }
         cars
     }
+
+    fn add_charger(&mut self, location: ChargerLocation, ports: usize) -> Vec<ParkingSpot> {
+        let spots: Vec<ParkingSpot> = match location {
+            ChargerLocation::Building(b) => {
+                let num_spots = self.num_spots_per_offstreet.get(&b).cloned().unwrap_or(0);
+                (0..num_spots.min(ports))
+                    .map(|idx| ParkingSpot::Offstreet(b, idx))
+                    .collect()
+            }
+            ChargerLocation::ParkingLot(pl) => {
+                let num_spots = self.num_spots_per_lot.get(&pl).cloned().unwrap_or(0);
+                (0..num_spots.min(ports))
+                    .map(|idx| ParkingSpot::Lot(pl, idx))
+                    .collect()
+            }
+        };
+        self.charging_spots.extend(spots.iter().cloned());
+        spots
+    }
+
+    fn plan_stay(&mut self, car: CarID, arrive: Time, leave: Time) {
+        self.planned_stays.insert(car, (arrive, leave));
+    }
+
+    fn price_as_walking_distance(
+        &self,
+        spot: ParkingSpot,
+        vehicle: &Vehicle,
+        map: &Map,
+    ) -> Distance {
+        let (arrive, leave) = match self.planned_stays.get(&vehicle.id) {
+            Some(stay) => *stay,
+            None => {
+                return Distance::ZERO;
+            }
+        };
+        map.get_parking_policy(spot.place(map))
+            .map(|policy| WALK_TO_SAVE_A_DOLLAR * policy.price(arrive, leave))
+            .unwrap_or(Distance::ZERO)
+    }
+
+    fn cheapest_parking_near(
+        &self,
+        b: BuildingID,
+        arrive: Time,
+        leave: Time,
+        map: &Map,
+    ) -> Option<f64> {
+        let lane = map.find_driving_lane_near_building(b);
+        let mut places = Vec::new();
+        for l in self.driving_to_parking_lanes.get(lane) {
+            places.push(ParkingPlace::Road(map.get_l(*l).parent));
+        }
+        for (bldg, _) in self.driving_to_offstreet.get(lane) {
+            match map.get_b(*bldg).parking {
+                OffstreetParking::PublicGarage(_, _) => {
+                    places.push(ParkingPlace::Building(*bldg));
+                }
+                OffstreetParking::Private(_, _) => {
+                    // Parking at the destination itself is always free
+                    if *bldg == b {
+                        return Some(0.0);
+                    }
+                }
+            }
+        }
+        for pl in self.driving_to_lots.get(lane) {
+            places.push(ParkingPlace::ParkingLot(*pl));
+        }
+
+        places
+            .into_iter()
+            .filter_map(|place| match map.get_parking_policy(place) {
+                Some(policy) => {
+                    if policy.allows_stay(arrive, leave) {
+                        Some(policy.price(arrive, leave))
+                    } else {
+                        None
+                    }
+                }
+                None => Some(0.0),
+            })
+            .min_by(|a, b| a.partial_cmp(b).unwrap())
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -1029,12 +1173,8 @@ impl ParkingSim for InfiniteParkingSimState {
     fn all_parked_car_positions(&self, map: &Map) -> Vec<(Position, PersonID)> {
         self.parked_cars
             .values()
-            .map(|p| {
-                (
-                    self.spot_to_sidewalk_pos(p.spot, map),
-                    p.vehicle.owner.unwrap(),
-                )
-            })
+            // Delivery vehicles unloading at the curb don't belong to anybody
+            .filter_map(|p| Some((self.spot_to_sidewalk_pos(p.spot, map), p.vehicle.owner?)))
             .collect()
     }
 
@@ -1050,4 +1190,38 @@ impl ParkingSim for InfiniteParkingSimState {
         }
         cars
     }
+
+    fn add_charger(&mut self, _: ChargerLocation, _: usize) -> Vec<ParkingSpot> {
+        // Spots here come and go as cars need them, so there's nowhere to put a plug
+        Vec::new()
+    }
+
+    fn plan_stay(&mut self, _: CarID, _: Time, _: Time) {}
+
+    fn price_as_walking_distance(&self, _: ParkingSpot, _: &Vehicle, _: &Map) -> Distance {
+        // Every building has free parking for everybody
+        Distance::ZERO
+    }
+
+    fn cheapest_parking_near(&self, _: BuildingID, _: Time, _: Time, _: &Map) -> Option<f64> {
+        None
+    }
+}
+
+/// Walks back from a lane found while searching for parking to the start.
+fn backtrack(start: LaneID, end: LaneID, backrefs: &HashMap<LaneID, TurnID>) -> Vec<PathStep> {
+    let mut steps = vec![PathStep::Lane(end)];
+    let mut current = end;
+    loop {
+        if current == start {
+            // Don't include PathStep::Lane(start)
+            steps.pop();
+            steps.reverse();
+            return steps;
+        }
+        let turn = backrefs[&current];
+        steps.push(PathStep::Turn(turn));
+        steps.push(PathStep::Lane(turn.src));
+        current = turn.src;
+    }
 }
@@ -7,7 +7,7 @@ use geom::{Distance, Time};
 use map_model::{Map, Position, Traversable};
 
 use crate::mechanics::car::{Car, CarState};
-use crate::{CarID, VehicleType, FOLLOWING_DISTANCE};
+use crate::{CarID, VehicleType};
 
 /// A Queue of vehicles on a single lane or turn. This is where
 /// https://a-b-street.github.io/docs/tech/trafficsim/discrete_event.html#exact-positions is
@@ -36,7 +36,7 @@ pub(crate) struct Queue {
 
     /// How long the lane or turn physically is.
     pub geom_len: Distance,
-    /// When a car's turn is accepted, reserve the vehicle length + FOLLOWING_DISTANCE for the
+    /// When a car's turn is accepted, reserve the vehicle length + following distance for the
     /// target lane. When the car completely leaves (stops being the laggy_head), free up that
     /// space. To prevent blocking the box for possibly scary amounts of time, allocate some of
     /// this length first. This is unused for turns themselves. This value can exceed geom_len
@@ -55,21 +55,36 @@ pub enum Queued {
         cause: CarID,
         front: Distance,
         back: Distance,
+        /// The following distance of the vehicle causing this
+        following_dist: Distance,
     },
     /// This follows whatever's in front of it
     DynamicBlockage {
         /// This vehicle is in the middle of changing lanes
         cause: CarID,
         vehicle_len: Distance,
+        /// The following distance of the vehicle causing this
+        following_dist: Distance,
     },
 }
 
+impl Queued {
+    /// How far this stays behind whatever's in front of it
+    fn following_dist(&self, cars: &FixedMap<CarID, Car>) -> Distance {
+        match self {
+            Queued::Vehicle(id) => cars[id].vehicle.behavior.following_distance,
+            Queued::StaticBlockage { following_dist, .. }
+            | Queued::DynamicBlockage { following_dist, .. } => *following_dist,
+        }
+    }
+}
+
 /// The exact position of something in a `Queue` at some time
 #[derive(Clone, Debug)]
 pub struct QueueEntry {
     pub member: Queued,
     pub front: Distance,
-    /// Not incuding FOLLOWING_DISTANCE
+    /// Not including the following distance
     pub back: Distance,
 }
 
@@ -129,12 +144,13 @@ impl Queue {
         // starting the loop, handling the laggy head case.
         let mut previous: Option<QueueEntry> = None;
         for queued in self.members.iter().cloned() {
+            let following_dist = queued.following_dist(cars);
             let bound = match previous {
-                Some(entry) => entry.back - FOLLOWING_DISTANCE,
+                Some(entry) => entry.back - following_dist,
                 None => match self.laggy_head {
                     Some(id) => {
                         // The simple but broken version:
-                        //self.geom_len - cars[&id].vehicle.length - FOLLOWING_DISTANCE
+                        //self.geom_len - cars[&id].vehicle.length - following_dist
 
                         // The expensive case. We need to figure out exactly where the laggy head
                         // is on their queue.
@@ -150,7 +166,7 @@ impl Queue {
                             // 1) Hope that the last person in this queue isn't bounded by the
                             //    agent in front of them yet. geom_len
                             // 2) Assume the leader has advanced minimally into the next lane.
-                            //    geom_len - laggy head's length - FOLLOWING_DISTANCE.
+                            //    geom_len - laggy head's length - following_dist.
                             //
                             // For now, optimistically assume 1. If we're wrong, consequences could
                             // be queue spillover (we're too optimistic about the number of
@@ -180,12 +196,10 @@ impl Queue {
                             }
                             // They might actually be out of the way, but laggy_head hasn't been
                             // updated yet.
-                            if dist_away_from_this_queue
-                                < leader.vehicle.length + FOLLOWING_DISTANCE
-                            {
+                            if dist_away_from_this_queue < leader.vehicle.length + following_dist {
                                 self.geom_len
                                     - (cars[&id].vehicle.length - dist_away_from_this_queue)
-                                    - FOLLOWING_DISTANCE
+                                    - following_dist
                             } else {
                                 self.geom_len
                             }
@@ -294,6 +308,7 @@ impl Queue {
         &self,
         start_dist: Distance,
         vehicle_len: Distance,
+        following_dist: Distance,
         now: Time,
         cars: &FixedMap<CarID, Car>,
         queues: &HashMap<Traversable, Queue>,
@@ -321,18 +336,20 @@ impl Queue {
                 // TODO We can be more precise! We already call get_car_positions, and that
                 // calculates exactly where the laggy head is. We just need to plumb that bound
                 // back here.
-                if self.geom_len - cars[&c].vehicle.length - FOLLOWING_DISTANCE < start_dist {
+                if self.geom_len - cars[&c].vehicle.length - following_dist < start_dist {
                     return None;
                 }
             }
         }
 
         // Are we too close to the leader?
-        if idx != 0 && dists[idx - 1].back - FOLLOWING_DISTANCE < start_dist {
+        if idx != 0 && dists[idx - 1].back - following_dist < start_dist {
             return None;
         }
         // Or the follower?
-        if idx != dists.len() && start_dist - vehicle_len - FOLLOWING_DISTANCE < dists[idx].front {
+        if idx != dists.len()
+            && start_dist - vehicle_len - dists[idx].member.following_dist(cars) < dists[idx].front
+        {
             return None;
         }
 
@@ -343,7 +360,7 @@ impl Queue {
     /// -- the same index and immediately after passing that query.
     pub fn insert_car_at_idx(&mut self, idx: usize, car: &Car) {
         self.members.insert(idx, Queued::Vehicle(car.vehicle.id));
-        self.reserved_length += car.vehicle.length + FOLLOWING_DISTANCE;
+        self.reserved_length += car.vehicle.length + car.vehicle.behavior.following_distance;
     }
 
     /// Record that a car has entered a queue at the end. It's assumed that try_to_reserve_entry
@@ -376,12 +393,12 @@ impl Queue {
         // won't allow more cars to start a turn towards it, but if force_entry is true, then we'll
         // allow it.
 
-        // Sometimes a car + FOLLOWING_DISTANCE might be longer than the geom_len entirely. In that
+        // Sometimes a car + following distance might be longer than the geom_len entirely. In that
         // case, it just means the car won't totally fit on the queue at once, which is fine.
         // Reserve the normal amount of space; the next car trying to enter will get rejected.
         // Also allow this don't-block-the-box prevention to be disabled.
         if self.room_for_car(car) || force_entry {
-            self.reserved_length += car.vehicle.length + FOLLOWING_DISTANCE;
+            self.reserved_length += car.vehicle.length + car.vehicle.behavior.following_distance;
             return true;
         }
         false
@@ -396,12 +413,13 @@ impl Queue {
     /// Can a car start a turn for this queue?
     pub fn room_for_car(&self, car: &Car) -> bool {
         self.reserved_length == Distance::ZERO
-            || self.reserved_length + car.vehicle.length + FOLLOWING_DISTANCE < self.geom_len
+            || self.reserved_length + car.vehicle.length + car.vehicle.behavior.following_distance
+                < self.geom_len
     }
 
     /// Once a car has fully exited a queue, free up the space it was reserving.
     pub fn free_reserved_space(&mut self, car: &Car) {
-        self.reserved_length -= car.vehicle.length + FOLLOWING_DISTANCE;
+        self.reserved_length -= car.vehicle.length + car.vehicle.behavior.following_distance;
         assert!(
             self.reserved_length >= Distance::ZERO,
             "invalid reserved length: {:?}, car: {:?}",
@@ -463,23 +481,36 @@ impl Queue {
         cause: CarID,
         front: Distance,
         back: Distance,
+        following_dist: Distance,
         idx: usize,
     ) {
         assert!(front > back);
         let vehicle_len = front - back;
-        self.members
-            .insert(idx, Queued::StaticBlockage { cause, front, back });
-        self.reserved_length += vehicle_len + FOLLOWING_DISTANCE;
+        self.members.insert(
+            idx,
+            Queued::StaticBlockage {
+                cause,
+                front,
+                back,
+                following_dist,
+            },
+        );
+        self.reserved_length += vehicle_len + following_dist;
     }
 
     /// Record that a car is no longer blocking a static portion of the queue.
     pub fn clear_static_blockage(&mut self, caused_by: CarID, idx: usize) {
         let blockage = self.members.remove(idx).unwrap();
         match blockage {
-            Queued::StaticBlockage { front, back, cause } => {
+            Queued::StaticBlockage {
+                front,
+                back,
+                cause,
+                following_dist,
+            } => {
                 assert_eq!(caused_by, cause);
                 let vehicle_len = front - back;
-                self.reserved_length -= vehicle_len + FOLLOWING_DISTANCE;
+                self.reserved_length -= vehicle_len + following_dist;
             }
             _ => unreachable!(),
         }
@@ -493,18 +524,23 @@ impl Queue {
             Queued::DynamicBlockage {
                 cause: car.vehicle.id,
                 vehicle_len: car.vehicle.length,
+                following_dist: car.vehicle.behavior.following_distance,
             },
         );
-        // We don't need to touch reserved_length -- it's still vehicle_len + FOLLOWING_DISTANCE
+        // We don't need to touch reserved_length -- it's still vehicle_len + following_dist
     }
 
     /// Record that a car is no longer blocking a dynamic portion of the queue.
     pub fn clear_dynamic_blockage(&mut self, caused_by: CarID, idx: usize) {
         let blockage = self.members.remove(idx).unwrap();
         match blockage {
-            Queued::DynamicBlockage { cause, vehicle_len } => {
+            Queued::DynamicBlockage {
+                cause,
+                vehicle_len,
+                following_dist,
+            } => {
                 assert_eq!(caused_by, cause);
-                self.reserved_length -= vehicle_len + FOLLOWING_DISTANCE;
+                self.reserved_length -= vehicle_len + following_dist;
             }
             _ => unreachable!(),
         }
@@ -516,11 +552,19 @@ impl Queue {
         &self,
         pos: &Position,
         vehicle_len: Distance,
+        following_dist: Distance,
         now: Time,
         cars: &FixedMap<CarID, Car>,
         queues: &HashMap<Traversable, Queue>,
     ) -> Option<usize> {
-        self.get_idx_to_insert_car(pos.dist_along(), vehicle_len, now, cars, queues)
+        self.get_idx_to_insert_car(
+            pos.dist_along(),
+            vehicle_len,
+            following_dist,
+            now,
+            cars,
+            queues,
+        )
     }
 
     /// Get all cars in the queue, not including the laggy head or blockages.
@@ -557,7 +601,7 @@ fn validate_positions(
     id: Traversable,
 ) {
     for pair in dists.windows(2) {
-        if pair[0].back - FOLLOWING_DISTANCE < pair[1].front {
+        if pair[0].back - pair[1].member.following_dist(cars) < pair[1].front {
             dump_cars(dists, cars, id, now);
             panic!(
                 "get_car_positions wound up with bad positioning: {} then {}\n{:?}",
@@ -608,7 +652,9 @@ fn dump_cars(dists: &[QueueEntry], cars: &FixedMap<CarID, Car>, id: Traversable,
             Queued::StaticBlockage { cause, .. } => {
                 println!("  Static blockage by {}", cause);
             }
-            Queued::DynamicBlockage { cause, vehicle_len } => {
+            Queued::DynamicBlockage {
+                cause, vehicle_len, ..
+            } => {
                 println!("  Dynamic blockage of length {} by {}", vehicle_len, cause);
             }
         }
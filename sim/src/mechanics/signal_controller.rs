@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+
+use geom::{Duration, Time};
+use map_model::{ControlTrafficSignal, IntersectionID, MovementID, TurnPriority};
+
+use crate::{AgentID, DetectorID};
+
+/// Decides how a traffic signal changes stages. By default, signals follow the `StageType` of
+/// each stage in their `ControlTrafficSignal`. Register a controller for some intersections
+/// through `SimOptions::signal_controllers` or `Sim::set_signal_controller` to override that.
+///
+/// Controllers aren't part of savestates. After loading one, register them again.
+pub trait SignalController: downcast_rs::Downcast + Send + Sync {
+    /// Called every time the current stage's duration elapses.
+    fn next_stage(&mut self, input: &SignalControllerInput) -> StageDecision;
+
+    fn clone_box(&self) -> Box<dyn SignalController>;
+}
+downcast_rs::impl_downcast!(SignalController);
+
+impl Clone for Box<dyn SignalController> {
+    fn clone(&self) -> Box<dyn SignalController> {
+        self.clone_box()
+    }
+}
+
+/// Everything a `SignalController` can use to make a decision.
+pub struct SignalControllerInput<'a> {
+    pub now: Time,
+    pub id: IntersectionID,
+    pub signal: &'a ControlTrafficSignal,
+    /// The index of the stage just ending
+    pub current_stage: usize,
+    /// Every movement of the signal, including crosswalks
+    pub movements: BTreeMap<MovementID, MovementState>,
+}
+
+/// The current demand for one movement through a traffic signal.
+pub struct MovementState {
+    /// How many vehicles are on the lanes leading to this movement. Lanes feeding more than one
+    /// movement count towards each of them.
+    pub queue_length: usize,
+    /// How many vehicles are on the lanes this movement leads to.
+    pub downstream_queue_length: usize,
+    /// Agents at the front of the queue waiting to start this movement, and since when.
+    pub waiting: Vec<(AgentID, Time)>,
+    /// Detectors on the lanes leading to this movement
+    pub detectors: Vec<DetectorStatus>,
+}
+
+pub struct DetectorStatus {
+    pub id: DetectorID,
+    /// Is some vehicle over the detector right now?
+    pub occupied: bool,
+    /// When the last vehicle passed the detector
+    pub last_passage: Option<Time>,
+}
+
+pub enum StageDecision {
+    /// Switch to this stage (or stay in the current one) and ask again after this duration.
+    Stage(usize, Duration),
+    /// The controller can't decide yet. The current stage keeps running, the simulation halts,
+    /// and the controller is asked again when the simulation next advances. This lets something
+    /// outside the simulation make the choice.
+    Defer,
+}
+
+/// A simple max-pressure controller. At every decision, it picks the stage whose protected
+/// movements have the most vehicles waiting upstream relative to the space taken downstream, and
+/// runs it for a fixed amount of time.
+#[derive(Clone)]
+pub struct MaxPressure {
+    /// How long to run each chosen stage before deciding again
+    pub decision_interval: Duration,
+}
+
+impl SignalController for MaxPressure {
+    fn next_stage(&mut self, input: &SignalControllerInput) -> StageDecision {
+        let pressure = |idx: usize| -> isize {
+            input
+                .movements
+                .iter()
+                .filter(|(id, _)| {
+                    input.signal.stages[idx].get_priority_of_movement(**id)
+                        == TurnPriority::Protected
+                })
+                .map(|(_, m)| m.queue_length as isize - m.downstream_queue_length as isize)
+                .sum()
+        };
+        // Ties go to the current stage, to avoid needless switching
+        let mut best = input.current_stage;
+        let mut best_pressure = pressure(best);
+        for idx in 0..input.signal.stages.len() {
+            let p = pressure(idx);
+            if p > best_pressure {
+                best = idx;
+                best_pressure = p;
+            }
+        }
+        StageDecision::Stage(best, self.decision_interval)
+    }
+
+    fn clone_box(&self) -> Box<dyn SignalController> {
+        Box::new(self.clone())
+    }
+}
+
+/// Actuated control using detectors. Each stage runs for at least `min_green`, then keeps
+/// extending while vehicles are detected on an approach with protected movements, until no vehicle
+/// has been seen for `gap` or the stage has lasted `max_green`. Stages always go in order.
+#[derive(Clone)]
+pub struct Actuated {
+    pub min_green: Duration,
+    pub max_green: Duration,
+    pub gap: Duration,
+    stage_started: Option<Time>,
+}
+
+impl Actuated {
+    pub fn new(min_green: Duration, max_green: Duration, gap: Duration) -> Actuated {
+        Actuated {
+            min_green,
+            max_green,
+            gap,
+            stage_started: None,
+        }
+    }
+}
+
+impl SignalController for Actuated {
+    fn next_stage(&mut self, input: &SignalControllerInput) -> StageDecision {
+        // If the controller was just installed, start measuring the current stage from now
+        let started = *self.stage_started.get_or_insert(input.now);
+        let stage = &input.signal.stages[input.current_stage];
+        let demand = input
+            .movements
+            .iter()
+            .filter(|(id, _)| stage.get_priority_of_movement(**id) == TurnPriority::Protected)
+            .flat_map(|(_, m)| &m.detectors)
+            .any(|d| {
+                d.occupied
+                    || d.last_passage
+                        .map(|t| input.now - t < self.gap)
+                        .unwrap_or(false)
+            });
+        if demand && input.now - started + self.gap <= self.max_green {
+            return StageDecision::Stage(input.current_stage, self.gap);
+        }
+
+        self.stage_started = Some(input.now);
+        StageDecision::Stage(
+            (input.current_stage + 1) % input.signal.stages.len(),
+            self.min_green,
+        )
+    }
+
+    fn clone_box(&self) -> Box<dyn SignalController> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use map_model::{DirectedRoadID, Direction, RoadID, Stage};
+
+    #[test]
+    fn test_actuated_max_green() {
+        let movement = MovementID {
+            from: DirectedRoadID {
+                id: RoadID(0),
+                dir: Direction::Fwd,
+            },
+            to: DirectedRoadID {
+                id: RoadID(1),
+                dir: Direction::Fwd,
+            },
+            parent: IntersectionID(0),
+            crosswalk: false,
+        };
+        let mut stage1 = Stage::new();
+        stage1.protected_movements.insert(movement);
+        let signal = ControlTrafficSignal {
+            id: IntersectionID(0),
+            stages: vec![stage1, Stage::new()],
+            offset: Duration::ZERO,
+            movements: BTreeMap::new(),
+        };
+
+        let mut controller = Actuated::new(
+            Duration::seconds(5.0),
+            Duration::seconds(30.0),
+            Duration::seconds(3.0),
+        );
+        // Some vehicle is always over the detector. The controller is installed partway through
+        // the day, in the middle of the first stage.
+        let mut now = Time::START_OF_DAY + Duration::hours(1);
+        let installed = now;
+        loop {
+            let mut movements = BTreeMap::new();
+            movements.insert(
+                movement,
+                MovementState {
+                    queue_length: 1,
+                    downstream_queue_length: 0,
+                    waiting: Vec::new(),
+                    detectors: vec![DetectorStatus {
+                        id: DetectorID(0),
+                        occupied: true,
+                        last_passage: Some(now),
+                    }],
+                },
+            );
+            let input = SignalControllerInput {
+                now,
+                id: IntersectionID(0),
+                signal: &signal,
+                current_stage: 0,
+                movements,
+            };
+            match controller.next_stage(&input) {
+                StageDecision::Stage(0, dt) => {
+                    now += dt;
+                    assert!(now - installed <= Duration::seconds(30.0));
+                }
+                StageDecision::Stage(1, dt) => {
+                    assert_eq!(dt, Duration::seconds(5.0));
+                    break;
+                }
+                _ => unreachable!(),
+            }
+        }
+        assert_eq!(now - installed, Duration::seconds(30.0));
+    }
+}
@@ -71,6 +71,7 @@ impl TrafficRecorder {
             people.push(PersonSpec {
                 orig_id: None,
                 trips: vec![trip],
+                behavior: None,
             });
         }
         Scenario {
@@ -78,6 +79,11 @@ impl TrafficRecorder {
             map_name: map.get_name().clone(),
             people,
             only_seed_buses: None,
+            deliveries: Vec::new(),
+            ride_hail_fleet: Vec::new(),
+            bike_share_docks: Vec::new(),
+            electric_vehicles: None,
+            chargers: Vec::new(),
         }
         .save();
     }
@@ -0,0 +1,542 @@
+//! On-demand vehicles pick people up at the curb in front of a building and drop them off at the
+//! curb in front of their destination. A `Dispatcher` decides which vehicle serves each request.
+//! Vehicles with nothing to do wait off-street wherever they made their last stop, then deadhead
+//! from there to their next pickup.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, Time};
+use map_model::{BuildingID, Map, PathConstraints, PathRequest, Position};
+
+use crate::sim::Ctx;
+use crate::{
+    AlertLocation, CarID, Command, CreateCar, Event, PersonID, Router, TripEndpoint, TripID,
+    TripManager, TripMode, Vehicle,
+};
+
+/// How long a vehicle stops in the driving lane to let people in or out
+const TIME_TO_STOP_AT_CURB: Duration = Duration::const_seconds(30.0);
+/// If no vehicle has been assigned by this long after somebody asks for a ride, they give up
+const MAX_WAIT_FOR_ASSIGNMENT: Duration = Duration::const_seconds(30.0 * 60.0);
+
+/// Somebody waiting for a ride.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RideRequest {
+    pub trip: TripID,
+    pub person: PersonID,
+    pub requested_at: Time,
+    /// Where the vehicle stops to pick the person up
+    pub pickup: Position,
+    /// Where the vehicle stops to drop the person off
+    pub dropoff: Position,
+}
+
+/// What a `Dispatcher` knows about one vehicle in the fleet.
+pub struct FleetVehicle {
+    pub id: CarID,
+    /// If the vehicle is idle, where it's waiting. Otherwise, where it'll make its next stop.
+    pub pos: Position,
+    pub idle: bool,
+    /// Pickups and dropoffs the vehicle still has to make
+    pub remaining_stops: usize,
+    /// How many people are aboard right now
+    pub riders: usize,
+}
+
+/// Decides which vehicle serves each ride request. The simulation asks when somebody first
+/// requests a ride, and asks again about everybody still waiting whenever a vehicle runs out of
+/// work. Register a dispatcher through `SimOptions::ride_hail_dispatcher` or
+/// `Sim::set_ride_hail_dispatcher`.
+///
+/// Dispatchers aren't part of savestates. After loading one, register it again.
+pub trait Dispatcher: Send + Sync {
+    /// Pick the vehicle to serve a request, or None to leave the person waiting. Assigning a
+    /// vehicle that isn't idle adds the pickup and dropoff to the end of its stops, so riders share
+    /// the vehicle. Picking a vehicle that isn't in the fleet raises an alert and counts as None.
+    /// People left waiting for 30 minutes give up.
+    fn assign(&mut self, request: &RideRequest, fleet: &[FleetVehicle], map: &Map)
+        -> Option<CarID>;
+
+    fn clone_box(&self) -> Box<dyn Dispatcher>;
+}
+
+impl Clone for Box<dyn Dispatcher> {
+    fn clone(&self) -> Box<dyn Dispatcher> {
+        self.clone_box()
+    }
+}
+
+/// Assigns every request to the idle vehicle closest to the pickup, as the crow flies. Riders
+/// never share a vehicle.
+#[derive(Clone)]
+pub struct NearestIdleVehicle;
+
+impl Dispatcher for NearestIdleVehicle {
+    fn assign(
+        &mut self,
+        request: &RideRequest,
+        fleet: &[FleetVehicle],
+        map: &Map,
+    ) -> Option<CarID> {
+        let pickup = request.pickup.pt(map);
+        fleet
+            .iter()
+            .filter(|v| v.idle)
+            .min_by_key(|v| v.pos.pt(map).dist_to(pickup))
+            .map(|v| v.id)
+    }
+
+    fn clone_box(&self) -> Box<dyn Dispatcher> {
+        Box::new(self.clone())
+    }
+}
+
+/// What a ride-hail vehicle does after stopping at the curb
+pub(crate) enum NextMove {
+    Drive(Router),
+    /// The next stop is at the same curb
+    StayStopped(Duration),
+    /// There's nothing else to do, so wait off the map
+    LeaveMap,
+}
+
+fn default_dispatcher() -> Box<dyn Dispatcher> {
+    Box::new(NearestIdleVehicle)
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Ride {
+    request: RideRequest,
+    /// The length of the most direct route from pickup to dropoff
+    direct_distance: Distance,
+    /// How far the person has ridden so far
+    ride_distance: Distance,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+enum Stop {
+    Pickup(TripID),
+    Dropoff(TripID),
+}
+
+impl Stop {
+    fn trip(self) -> TripID {
+        match self {
+            Stop::Pickup(t) | Stop::Dropoff(t) => t,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct FleetVehicleState {
+    vehicle: Vehicle,
+    /// If the vehicle is idle, where it's waiting. Otherwise, where its current leg ends.
+    pos: Position,
+    /// Idle vehicles wait off the map.
+    on_map: bool,
+    stops: VecDeque<Stop>,
+    riders: BTreeSet<TripID>,
+}
+
+/// Manages the ride-hailing fleet and everybody waiting for or riding in one of its vehicles.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct RideHailSimState {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    vehicles: BTreeMap<CarID, FleetVehicleState>,
+    /// Rides that haven't finished yet
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    rides: BTreeMap<TripID, Ride>,
+    /// Requests that no vehicle is serving yet, oldest first
+    unassigned: Vec<TripID>,
+
+    #[serde(skip_serializing, skip_deserializing, default = "default_dispatcher")]
+    dispatcher: Box<dyn Dispatcher>,
+
+    events: Vec<Event>,
+}
+
+impl RideHailSimState {
+    pub fn new(dispatcher: Option<Box<dyn Dispatcher>>) -> RideHailSimState {
+        RideHailSimState {
+            vehicles: BTreeMap::new(),
+            rides: BTreeMap::new(),
+            unassigned: Vec::new(),
+            dispatcher: dispatcher.unwrap_or_else(default_dispatcher),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn set_dispatcher(&mut self, dispatcher: Box<dyn Dispatcher>) {
+        self.dispatcher = dispatcher;
+    }
+
+    /// The vehicle starts out idle, waiting at this position.
+    pub fn add_vehicle(&mut self, vehicle: Vehicle, pos: Position) {
+        self.vehicles.insert(
+            vehicle.id,
+            FleetVehicleState {
+                vehicle,
+                pos,
+                on_map: false,
+                stops: VecDeque::new(),
+                riders: BTreeSet::new(),
+            },
+        );
+    }
+
+    pub fn request_ride(
+        &mut self,
+        now: Time,
+        trip: TripID,
+        person: PersonID,
+        from: BuildingID,
+        to: BuildingID,
+        trips: &mut TripManager,
+        ctx: &mut Ctx,
+    ) {
+        let ride = match self.plan_ride(now, trip, person, from, to, ctx.map) {
+            Ok(ride) => ride,
+            Err(err) => {
+                trips.cancel_ride_hail(now, trip, err.to_string(), ctx);
+                return;
+            }
+        };
+        self.rides.insert(trip, ride);
+        if !self.try_assign(now, trip, trips, ctx) {
+            self.wait_for_vehicle(now, trip, ctx);
+        }
+    }
+
+    /// Nobody's available to pick the person up yet. Later, they might give up.
+    fn wait_for_vehicle(&mut self, now: Time, trip: TripID, ctx: &mut Ctx) {
+        self.unassigned.push(trip);
+        ctx.scheduler
+            .update(now + MAX_WAIT_FOR_ASSIGNMENT, Command::GiveUpOnRide(trip));
+    }
+
+    /// Cancels the trip if nobody's been assigned to pick the person up yet.
+    pub fn give_up(&mut self, now: Time, trip: TripID, trips: &mut TripManager, ctx: &mut Ctx) {
+        if let Some(idx) = self.unassigned.iter().position(|t| *t == trip) {
+            self.unassigned.remove(idx);
+            self.rides.remove(&trip);
+            trips.cancel_ride_hail(
+                now,
+                trip,
+                format!(
+                    "no ride-hail vehicle was assigned within {}",
+                    MAX_WAIT_FOR_ASSIGNMENT
+                ),
+                ctx,
+            );
+        }
+    }
+
+    fn plan_ride(
+        &self,
+        now: Time,
+        trip: TripID,
+        person: PersonID,
+        from: BuildingID,
+        to: BuildingID,
+        map: &Map,
+    ) -> Result<Ride> {
+        if self.vehicles.is_empty() {
+            bail!("there aren't any ride-hail vehicles");
+        }
+        let pickup = TripEndpoint::Bldg(from)
+            .pos(TripMode::Drive, false, map)
+            .ok_or_else(|| anyhow!("no curb to get picked up at {}", from))?;
+        let dropoff = TripEndpoint::Bldg(to)
+            .pos(TripMode::Drive, false, map)
+            .ok_or_else(|| anyhow!("no curb to get dropped off at {}", to))?;
+        if pickup == dropoff {
+            bail!("{} and {} share the same curb", from, to);
+        }
+        let path = map.pathfind_at(
+            PathRequest::vehicle(pickup, dropoff, PathConstraints::Car),
+            now,
+        )?;
+        Ok(Ride {
+            request: RideRequest {
+                trip,
+                person,
+                requested_at: now,
+                pickup,
+                dropoff,
+            },
+            direct_distance: path.total_length(),
+            ride_distance: Distance::ZERO,
+        })
+    }
+
+    /// Asks the dispatcher for a vehicle. Returns false if nothing's assigned yet.
+    fn try_assign(
+        &mut self,
+        now: Time,
+        trip: TripID,
+        trips: &mut TripManager,
+        ctx: &mut Ctx,
+    ) -> bool {
+        let fleet: Vec<FleetVehicle> = self
+            .vehicles
+            .values()
+            .map(|v| FleetVehicle {
+                id: v.vehicle.id,
+                pos: v.pos,
+                idle: v.stops.is_empty(),
+                remaining_stops: v.stops.len(),
+                riders: v.riders.len(),
+            })
+            .collect();
+        let car = match self
+            .dispatcher
+            .assign(&self.rides[&trip].request, &fleet, ctx.map)
+        {
+            Some(car) => car,
+            None => {
+                return false;
+            }
+        };
+
+        let v = match self.vehicles.get_mut(&car) {
+            Some(v) => v,
+            None => {
+                self.events.push(Event::Alert(
+                    AlertLocation::Person(self.rides[&trip].request.person),
+                    format!(
+                        "The dispatcher picked {} for {}, but it isn't in the fleet",
+                        car, trip
+                    ),
+                ));
+                return false;
+            }
+        };
+        let was_idle = v.stops.is_empty();
+        v.stops.push_back(Stop::Pickup(trip));
+        v.stops.push_back(Stop::Dropoff(trip));
+        // If the vehicle is on the map, it's just finished a stop and will plan its next leg soon.
+        if was_idle && !v.on_map {
+            let start = v.pos;
+            self.spawn_vehicle(now, car, start, trips, ctx);
+        }
+        true
+    }
+
+    /// Starts an idle vehicle driving from where it's waiting to its next stop.
+    pub fn spawn_vehicle(
+        &mut self,
+        now: Time,
+        car: CarID,
+        start: Position,
+        trips: &mut TripManager,
+        ctx: &mut Ctx,
+    ) {
+        // Somebody might be waiting right where the vehicle is. Pick them up as it pulls out.
+        if self.next_stop_pos(car) == Some(start) {
+            self.make_stops_here(now, car, trips, ctx);
+        }
+
+        let router = self.next_leg(now, car, start, trips, ctx);
+        let v = self.vehicles.get_mut(&car).unwrap();
+        if let Some(router) = router {
+            v.on_map = true;
+            ctx.scheduler.push(
+                now,
+                Command::SpawnCar(
+                    CreateCar {
+                        vehicle: v.vehicle.clone(),
+                        router,
+                        maybe_parked_car: None,
+                        trip_and_person: None,
+                        maybe_route: None,
+                    },
+                    true,
+                ),
+            );
+        } else {
+            v.on_map = false;
+            v.pos = start;
+        }
+    }
+
+    /// The vehicle reached its next stop, after driving some distance. Returns how long to stay
+    /// stopped at the curb.
+    pub fn arrived(
+        &mut self,
+        now: Time,
+        car: CarID,
+        distance: Distance,
+        trips: &mut TripManager,
+        ctx: &mut Ctx,
+    ) -> Duration {
+        let v = &self.vehicles[&car];
+        self.events.push(Event::RideHailVehicleMoved {
+            car,
+            distance,
+            deadheading: v.riders.is_empty(),
+        });
+        for trip in &v.riders {
+            self.rides.get_mut(trip).unwrap().ride_distance += distance;
+        }
+
+        self.make_stops_here(now, car, trips, ctx);
+        TIME_TO_STOP_AT_CURB
+    }
+
+    /// The vehicle is done stopping at the curb. Decides what it does next.
+    pub fn departing(
+        &mut self,
+        now: Time,
+        car: CarID,
+        pos: Position,
+        trips: &mut TripManager,
+        ctx: &mut Ctx,
+    ) -> NextMove {
+        self.vehicles.get_mut(&car).unwrap().pos = pos;
+        if self.vehicles[&car].stops.is_empty() {
+            // Maybe somebody's been waiting for a free vehicle
+            let waiting = std::mem::take(&mut self.unassigned);
+            for trip in waiting {
+                if !self.try_assign(now, trip, trips, ctx) {
+                    self.unassigned.push(trip);
+                }
+            }
+        }
+
+        if self.next_stop_pos(car) == Some(pos) {
+            self.make_stops_here(now, car, trips, ctx);
+            return NextMove::StayStopped(TIME_TO_STOP_AT_CURB);
+        }
+        match self.next_leg(now, car, pos, trips, ctx) {
+            Some(router) => NextMove::Drive(router),
+            None => {
+                self.vehicles.get_mut(&car).unwrap().on_map = false;
+                NextMove::LeaveMap
+            }
+        }
+    }
+
+    /// Live map edits took the vehicle off the map. Everybody aboard loses their ride, anybody it
+    /// was on its way to pick up asks for another vehicle, and it waits where its current leg
+    /// would've ended.
+    pub fn vehicle_removed(
+        &mut self,
+        now: Time,
+        car: CarID,
+        trips: &mut TripManager,
+        ctx: &mut Ctx,
+    ) {
+        let v = self.vehicles.get_mut(&car).unwrap();
+        v.on_map = false;
+        let riders = std::mem::take(&mut v.riders);
+        let waiting: Vec<TripID> = v
+            .stops
+            .drain(..)
+            .filter_map(|stop| match stop {
+                Stop::Pickup(trip) => Some(trip),
+                Stop::Dropoff(_) => None,
+            })
+            .collect();
+
+        for trip in riders {
+            self.rides.remove(&trip);
+            trips.cancel_ride_hail(now, trip, format!("{} was removed by map edits", car), ctx);
+        }
+        for trip in waiting {
+            if !self.try_assign(now, trip, trips, ctx) {
+                self.wait_for_vehicle(now, trip, ctx);
+            }
+        }
+    }
+
+    fn next_stop_pos(&self, car: CarID) -> Option<Position> {
+        let stop = *self.vehicles[&car].stops.front()?;
+        let ride = &self.rides[&stop.trip()];
+        Some(match stop {
+            Stop::Pickup(_) => ride.request.pickup,
+            Stop::Dropoff(_) => ride.request.dropoff,
+        })
+    }
+
+    /// Lets everybody get in or out at the vehicle's next stop, and any stops right after it at
+    /// the same curb.
+    fn make_stops_here(&mut self, now: Time, car: CarID, trips: &mut TripManager, ctx: &mut Ctx) {
+        let here = self.next_stop_pos(car);
+        while self.next_stop_pos(car).is_some() && self.next_stop_pos(car) == here {
+            let v = self.vehicles.get_mut(&car).unwrap();
+            match v.stops.pop_front().unwrap() {
+                Stop::Pickup(trip) => {
+                    v.riders.insert(trip);
+                    self.events.push(Event::RideHailPickup(
+                        trip,
+                        car,
+                        now - self.rides[&trip].request.requested_at,
+                    ));
+                    trips.ride_hail_picked_up(trip, car);
+                }
+                Stop::Dropoff(trip) => {
+                    v.riders.remove(&trip);
+                    let ride = self.rides.remove(&trip).unwrap();
+                    self.events.push(Event::RideHailDropoff {
+                        trip,
+                        car,
+                        ride_distance: ride.ride_distance,
+                        direct_distance: ride.direct_distance,
+                    });
+                    trips.ride_hail_dropped_off(now, trip, car, ride.ride_distance, ctx);
+                }
+            }
+        }
+    }
+
+    /// Plans the route to the vehicle's next stop. If that's impossible, cancels the trips
+    /// affected and tries the stop after. Returns None if there are no stops left.
+    fn next_leg(
+        &mut self,
+        now: Time,
+        car: CarID,
+        start: Position,
+        trips: &mut TripManager,
+        ctx: &mut Ctx,
+    ) -> Option<Router> {
+        loop {
+            let end = self.next_stop_pos(car)?;
+            let v = self.vehicles.get_mut(&car).unwrap();
+            match ctx
+                .map
+                .pathfind_at(PathRequest::vehicle(start, end, PathConstraints::Car), now)
+            {
+                Ok(path) => {
+                    v.pos = end;
+                    return Some(Router::stop_at_curb(car, path));
+                }
+                Err(err) => {
+                    let trip = v.stops[0].trip();
+                    v.stops.retain(|s| s.trip() != trip);
+                    v.riders.remove(&trip);
+                    self.rides.remove(&trip);
+                    trips.cancel_ride_hail(
+                        now,
+                        trip,
+                        format!("{} can't reach its next stop: {}", car, err),
+                        ctx,
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn collect_events(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+}
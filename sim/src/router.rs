@@ -32,6 +32,8 @@ pub(crate) enum ActionAtEnd {
     GotoLaneEnd,
     StopBiking(SidewalkSpot),
     BusAtStop,
+    DeliveryAtStop,
+    RideHailAtStop,
     GiveUpOnParking,
 }
 
@@ -55,8 +57,23 @@ enum Goal {
     FollowBusRoute {
         end_dist: Distance,
     },
+    /// Head to a delivery stop (or back to the depot, if there's no target). Prefer unloading from
+    /// a free curbside spot near the stop, otherwise double-park at end_dist.
+    Deliver {
+        target: Option<BuildingID>,
+        end_dist: Distance,
+        spot: Option<(ParkingSpot, Distance)>,
+        looked_for_spot: bool,
+    },
+    /// Briefly stop in the driving lane to pick up or drop off a ride-hail passenger
+    StopAtCurb {
+        end_dist: Distance,
+    },
 }
 
+/// Delivery vehicles won't use a curbside spot farther than this from their stop.
+const MAX_CURBSIDE_DIST: Distance = Distance::const_meters(30.0);
+
 impl Router {
     pub fn end_at_border(
         owner: CarID,
@@ -116,6 +133,29 @@ impl Router {
         }
     }
 
+    pub fn deliver(owner: CarID, path: Path, target: Option<BuildingID>) -> Router {
+        Router {
+            goal: Goal::Deliver {
+                target,
+                end_dist: path.get_req().end.dist_along(),
+                spot: None,
+                looked_for_spot: false,
+            },
+            path,
+            owner,
+        }
+    }
+
+    pub fn stop_at_curb(owner: CarID, path: Path) -> Router {
+        Router {
+            goal: Goal::StopAtCurb {
+                end_dist: path.get_req().end.dist_along(),
+            },
+            path,
+            owner,
+        }
+    }
+
     pub fn head(&self) -> Traversable {
         self.path.current_step().as_traversable()
     }
@@ -144,6 +184,8 @@ impl Router {
             } => stuck_end_dist.unwrap_or_else(|| spot.unwrap().1),
             Goal::BikeThenStop { ref goal } => goal.sidewalk_pos.dist_along(),
             Goal::FollowBusRoute { end_dist } => end_dist,
+            Goal::StopAtCurb { end_dist } => end_dist,
+            Goal::Deliver { end_dist, spot, .. } => spot.map(|(_, d)| d).unwrap_or(end_dist),
         }
     }
 
@@ -236,25 +278,28 @@ impl Router {
                         target,
                         map,
                     );
+                    // Drivers trade off walking farther against paying more
+                    let price =
+                        |spot: ParkingSpot| parking.price_as_walking_distance(spot, vehicle, map);
                     let best =
                         if let Some((driving_pos, _)) = map.get_b(target).driving_connection(map) {
                             if driving_pos.lane() == current_lane {
                                 let target_dist = driving_pos.dist_along();
                                 // Closest to the building
-                                candidates
-                                    .into_iter()
-                                    .min_by_key(|(_, pos)| (pos.dist_along() - target_dist).abs())
+                                candidates.into_iter().min_by_key(|(spot, pos)| {
+                                    (pos.dist_along() - target_dist).abs() + price(*spot)
+                                })
                             } else {
                                 // Closest to the road endpoint, I guess
                                 candidates
                                     .into_iter()
-                                    .min_by_key(|(_, pos)| pos.dist_along())
+                                    .min_by_key(|(spot, pos)| pos.dist_along() + price(*spot))
                             }
                         } else {
                             // Closest to the road endpoint, I guess
                             candidates
                                 .into_iter()
-                                .min_by_key(|(_, pos)| pos.dist_along())
+                                .min_by_key(|(spot, pos)| pos.dist_along() + price(*spot))
                         };
                     if let Some((new_spot, new_pos)) = best {
                         if let Some((t, p)) = trip_and_person {
@@ -332,6 +377,55 @@ impl Router {
                     None
                 }
             }
+            Goal::Deliver {
+                target,
+                ref mut end_dist,
+                ref mut spot,
+                ref mut looked_for_spot,
+            } => {
+                if !*looked_for_spot {
+                    *looked_for_spot = true;
+                    if let Some(b) = target {
+                        let current_lane = self.path.current_step().as_lane();
+                        let goal = *end_dist;
+                        *spot = parking
+                            .get_all_free_spots(Position::new(current_lane, front), vehicle, b, map)
+                            .into_iter()
+                            .filter(|(s, pos)| {
+                                matches!(s, ParkingSpot::Onstreet(_, _))
+                                    && (pos.dist_along() - goal).abs() <= MAX_CURBSIDE_DIST
+                            })
+                            .min_by_key(|(_, pos)| (pos.dist_along() - goal).abs())
+                            .map(|(s, pos)| (s, pos.dist_along()));
+                    }
+                }
+
+                if let Some((s, dist)) = *spot {
+                    if parking.is_free(s) {
+                        return if dist == front {
+                            Some(ActionAtEnd::StartParking(s))
+                        } else {
+                            None
+                        };
+                    }
+                    // Somebody else took the spot first, so double-park instead. We might already
+                    // be past the original stop.
+                    *spot = None;
+                    *end_dist = end_dist.max(front);
+                }
+                if *end_dist == front {
+                    Some(ActionAtEnd::DeliveryAtStop)
+                } else {
+                    None
+                }
+            }
+            Goal::StopAtCurb { end_dist } => {
+                if end_dist == front {
+                    Some(ActionAtEnd::RideHailAtStop)
+                } else {
+                    None
+                }
+            }
         }
     }
 
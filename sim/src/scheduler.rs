@@ -27,6 +27,11 @@ pub(crate) enum Command {
     Pandemic(pandemic::Cmd),
     /// The Time is redundant, just used to dedupe commands
     StartBus(BusRouteID, Time),
+    StartDeliveryTour(CarID),
+    /// Somebody starting a ride-hailing trip asks for a vehicle
+    RequestRide(TripID),
+    /// Somebody still waiting for a ride-hailing vehicle to be assigned gives up
+    GiveUpOnRide(TripID),
 }
 
 impl Command {
@@ -50,6 +55,9 @@ impl Command {
             Command::Callback(_) => CommandType::Callback,
             Command::Pandemic(ref p) => CommandType::Pandemic(p.clone()),
             Command::StartBus(r, t) => CommandType::StartBus(*r, *t),
+            Command::StartDeliveryTour(c) => CommandType::StartDeliveryTour(*c),
+            Command::RequestRide(t) => CommandType::RequestRide(*t),
+            Command::GiveUpOnRide(t) => CommandType::GiveUpOnRide(*t),
         }
     }
 
@@ -65,6 +73,9 @@ impl Command {
             Command::Callback(_) => SimpleCommandType::Callback,
             Command::Pandemic(_) => SimpleCommandType::Pandemic,
             Command::StartBus(_, _) => SimpleCommandType::StartBus,
+            Command::StartDeliveryTour(_) => SimpleCommandType::StartDeliveryTour,
+            Command::RequestRide(_) => SimpleCommandType::RequestRide,
+            Command::GiveUpOnRide(_) => SimpleCommandType::GiveUpOnRide,
         }
     }
 }
@@ -81,6 +92,9 @@ enum CommandType {
     Callback,
     Pandemic(pandemic::Cmd),
     StartBus(BusRouteID, Time),
+    StartDeliveryTour(CarID),
+    RequestRide(TripID),
+    GiveUpOnRide(TripID),
 }
 
 /// A more compressed form of CommandType, just used for keeping stats on event processing.
@@ -94,6 +108,9 @@ enum SimpleCommandType {
     Callback,
     Pandemic,
     StartBus,
+    StartDeliveryTour,
+    RequestRide,
+    GiveUpOnRide,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
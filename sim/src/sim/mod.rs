@@ -1,6 +1,6 @@
 // This file has a jumbled mess of queries, setup, and mutating methods.
 
-use std::collections::{BTreeSet, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::panic;
 
 use anyhow::Result;
@@ -13,18 +13,22 @@ use abstio::MapName;
 use abstutil::{prettyprint_usize, serialized_size_bytes, CmdArgs, Timer};
 use geom::{Distance, Duration, Speed, Time};
 use map_model::{
-    BuildingID, BusRoute, IntersectionID, LaneID, Map, ParkingLotID, Path, PathConstraints,
-    PathRequest, Position, Traversable,
+    BuildingID, BusRoute, BusRouteID, IntersectionID, LaneID, Map, ParkingLotID, Path,
+    PathConstraints, PathRequest, Position, TravelTimeProfile, Traversable,
 };
 
 pub use self::queries::{AgentProperties, DelayCause};
 use crate::{
-    AgentID, AlertLocation, Analytics, CapSimState, CarID, Command, CreateCar, DrivingSimState,
-    Event, IntersectionSimState, OrigPersonID, PandemicModel, ParkedCar, ParkingSim,
-    ParkingSimState, ParkingSpot, Person, PersonID, Router, Scheduler, SidewalkPOI, SidewalkSpot,
-    StartTripArgs, TrafficRecorder, TransitSimState, TripID, TripInfo, TripManager, TripPhaseType,
-    Vehicle, VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH, LIGHT_RAIL_LENGTH,
-    MIN_CAR_LENGTH,
+    AgentID, AlertLocation, Analytics, BehaviorProfile, BikeShareDock, BikeShareSimState,
+    CapSimState, CarID, Charger, Command, CreateCar, DeliverySimState, DeliveryTour, Detector,
+    DetectorID, DetectorReading, DetectorSimState, Dispatcher, DrivingSimState, EmissionFactors,
+    Emissions, EmissionsRecorder, EnergySimState, Event, HoldingStrategy, IntersectionSimState,
+    OrigPersonID, PandemicModel, ParkedCar, ParkingSim, ParkingSimState, ParkingSpot, Person,
+    PersonID, RideHailSimState, Router, Scheduler, SidewalkPOI, SidewalkSpot, SignalController,
+    StartTripArgs, TrafficRecorder, TransitCapacity, TransitLog, TransitLogRecorder,
+    TransitSimState, TravelTimeRecorder, TripEndpoint, TripID, TripInfo, TripManager, TripMode,
+    TripPhaseType, Vehicle, VehicleBehavior, VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH,
+    DELIVERY_LENGTH, LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH,
 };
 
 mod queries;
@@ -40,8 +44,13 @@ pub struct Sim {
     walking: WalkingSimState,
     intersections: IntersectionSimState,
     transit: TransitSimState,
+    deliveries: DeliverySimState,
+    ridehail: RideHailSimState,
+    bikeshare: BikeShareSimState,
+    energy: EnergySimState,
     cap: CapSimState,
     trips: TripManager,
+    detectors: DetectorSimState,
     #[serde(skip_serializing, skip_deserializing)]
     pandemic: Option<PandemicModel>,
     scheduler: Scheduler,
@@ -57,9 +66,19 @@ pub struct Sim {
     highlighted_people: Option<BTreeSet<PersonID>>,
 
     analytics: Analytics,
-    // This is created interactively, and there's no reason to preserve one for savestates.
+    // These are created interactively, and there's no reason to preserve them for savestates.
     #[serde(skip_serializing, skip_deserializing)]
     recorder: Option<TrafficRecorder>,
+    #[serde(skip_serializing, skip_deserializing)]
+    travel_times: Option<TravelTimeRecorder>,
+    #[serde(skip_serializing, skip_deserializing)]
+    emissions: Option<EmissionsRecorder>,
+    #[serde(skip_serializing, skip_deserializing)]
+    transit_log: Option<TransitLogRecorder>,
+    // Only used by external consumers that want to see every event, so there's no reason to
+    // preserve it for savestates either.
+    #[serde(skip_serializing, skip_deserializing)]
+    captured_events: Option<Vec<(Time, Event)>>,
 
     #[serde(skip_serializing, skip_deserializing)]
     alerts: AlertHandler,
@@ -69,6 +88,8 @@ pub(crate) struct Ctx<'a> {
     pub parking: &'a mut ParkingSimState,
     pub intersections: &'a mut IntersectionSimState,
     pub cap: &'a mut CapSimState,
+    pub bikeshare: &'a mut BikeShareSimState,
+    pub energy: &'a mut EnergySimState,
     pub scheduler: &'a mut Scheduler,
     pub map: &'a Map,
     /// If present, live map edits are being processed, and the agents specified are in the process
@@ -98,6 +119,9 @@ pub struct SimOptions {
     /// intersections with short roads. "Locks" the entire movement before starting, and ignores
     /// red lights after starting.
     pub handle_uber_turns: bool,
+    /// On roads with only one lane in each direction, let vehicles pass a slower or stopped leader
+    /// by briefly using the oncoming lane, if nobody is there.
+    pub allow_opposing_lane_passing: bool,
     /// Enable an experimental SEIR pandemic model.
     pub enable_pandemic_model: Option<XorShiftRng>,
     /// When a warning is encountered during simulation, specifies how to respond.
@@ -119,6 +143,17 @@ pub struct SimOptions {
     /// Don't collect any analytics. Only useful for benchmarking and debugging gridlock more
     /// quickly.
     pub skip_analytics: bool,
+    /// Replace the default behavior of some traffic signals, normally following the `StageType`
+    /// of each stage, with a custom controller.
+    pub signal_controllers: BTreeMap<IntersectionID, Box<dyn SignalController>>,
+    /// Decides which on-demand vehicle picks up each ride-hailing passenger. None means the
+    /// nearest idle vehicle.
+    pub ride_hail_dispatcher: Option<Box<dyn Dispatcher>>,
+    /// Hold buses and trains at timepoint stops to stay on schedule or keep even headways.
+    pub transit_holding: Option<HoldingStrategy>,
+    /// How many people fit aboard the vehicles of some routes. Other routes use
+    /// `TransitCapacity::typical`.
+    pub transit_capacity: BTreeMap<BusRouteID, TransitCapacity>,
 }
 
 impl std::default::Default for SimOptions {
@@ -138,6 +173,7 @@ impl SimOptions {
             recalc_lanechanging: !args.enabled("--disable_recalc_lc"),
             break_turn_conflict_cycles: !args.enabled("--disable_break_turn_conflict_cycles"),
             handle_uber_turns: !args.enabled("--disable_handle_uber_turns"),
+            allow_opposing_lane_passing: args.enabled("--allow_opposing_lane_passing"),
             enable_pandemic_model: if args.enabled("--pandemic") {
                 Some(XorShiftRng::seed_from_u64(rng_seed))
             } else {
@@ -159,6 +195,16 @@ impl SimOptions {
             delay_trips_instead_of_cancelling: args
                 .optional_parse("--delay_trips_instead_of_cancelling", Duration::parse),
             skip_analytics: args.enabled("--skip_analytics"),
+            signal_controllers: BTreeMap::new(),
+            ride_hail_dispatcher: None,
+            transit_holding: args
+                .optional("--transit_holding")
+                .map(|x| match x.as_ref() {
+                    "schedule" => HoldingStrategy::Schedule,
+                    "headway" => HoldingStrategy::Headway,
+                    _ => panic!("Bad --transit_holding={}. Must be schedule|headway", x),
+                }),
+            transit_capacity: BTreeMap::new(),
         }
     }
 }
@@ -188,6 +234,7 @@ impl SimOptions {
             recalc_lanechanging: true,
             break_turn_conflict_cycles: true,
             handle_uber_turns: true,
+            allow_opposing_lane_passing: false,
             enable_pandemic_model: None,
             alerts: AlertHandler::Print,
             infinite_parking: false,
@@ -195,6 +242,10 @@ impl SimOptions {
             cancel_drivers_delay_threshold: None,
             delay_trips_instead_of_cancelling: None,
             skip_analytics: false,
+            signal_controllers: BTreeMap::new(),
+            ride_hail_dispatcher: None,
+            transit_holding: None,
+            transit_capacity: BTreeMap::new(),
         }
     }
 }
@@ -208,9 +259,14 @@ impl Sim {
             parking: ParkingSimState::new(map, opts.infinite_parking),
             walking: WalkingSimState::new(),
             intersections: IntersectionSimState::new(map, &mut scheduler, &opts),
-            transit: TransitSimState::new(map),
+            transit: TransitSimState::new(map, opts.transit_holding, opts.transit_capacity.clone()),
+            deliveries: DeliverySimState::new(),
+            ridehail: RideHailSimState::new(opts.ride_hail_dispatcher.clone()),
+            bikeshare: BikeShareSimState::new(),
+            energy: EnergySimState::new(),
             cap: CapSimState::new(map, &opts),
             trips: TripManager::new(),
+            detectors: DetectorSimState::new(),
             pandemic: opts.enable_pandemic_model.map(PandemicModel::new),
             scheduler,
             time: Time::START_OF_DAY,
@@ -224,6 +280,10 @@ impl Sim {
 
             analytics: Analytics::new(!opts.skip_analytics),
             recorder: None,
+            travel_times: None,
+            emissions: None,
+            transit_log: None,
+            captured_events: None,
         }
     }
 
@@ -285,6 +345,8 @@ impl Sim {
             vehicle_type: VehicleType::Car,
             length: MIN_CAR_LENGTH,
             max_speed: None,
+            behavior: VehicleBehavior::typical(),
+            battery: None,
         };
         let driving_lane = map.find_driving_lane_near_building(b);
 
@@ -319,6 +381,7 @@ impl Sim {
     }
     pub(crate) fn seed_parked_car(&mut self, vehicle: Vehicle, spot: ParkingSpot) {
         self.parking.reserve_spot(spot, vehicle.id);
+        self.energy.plug_in(self.time, &vehicle, spot);
         self.parking.add_parked_car(ParkedCar {
             vehicle,
             spot,
@@ -326,16 +389,86 @@ impl Sim {
         });
     }
 
+    /// Call after seeding parked cars, so parking analytics count them.
+    pub(crate) fn record_initial_parking(&mut self, map: &Map) {
+        // Infinite parking doesn't track individual spots
+        if self.parking.is_infinite() {
+            return;
+        }
+        let (filled, _) = self.parking.get_all_parking_spots();
+        self.analytics
+            .record_initial_parking(self.time, filled, map);
+    }
+
     pub(crate) fn seed_bus_route(&mut self, route: &BusRoute) {
         for t in &route.spawn_times {
             self.scheduler.push(*t, Command::StartBus(route.id, *t));
         }
     }
 
-    fn start_bus(&mut self, route: &BusRoute, map: &Map) {
-        // Spawn one bus for the first leg.
-        let path = self.transit.create_empty_route(route, map);
+    pub(crate) fn seed_delivery_tour(&mut self, tour: DeliveryTour) {
+        if let TripEndpoint::SuddenlyAppear(_) = tour.depot {
+            warn!("Skipping a delivery tour whose depot isn't a building or border");
+            return;
+        }
+        let car = CarID {
+            id: self.trips.new_car_id(),
+            vehicle_type: VehicleType::Delivery,
+        };
+        self.scheduler
+            .push(tour.depart, Command::StartDeliveryTour(car));
+        self.deliveries.seed_tour(car, tour);
+    }
 
+    pub(crate) fn seed_ride_hail_vehicle(&mut self, b: BuildingID, map: &Map) {
+        let pos = match TripEndpoint::Bldg(b).pos(TripMode::Drive, false, map) {
+            Some(pos) => pos,
+            None => {
+                warn!("Skipping a ride-hail vehicle at {}, which has no curb", b);
+                return;
+            }
+        };
+        let vehicle = VehicleSpec {
+            vehicle_type: VehicleType::RideHail,
+            length: MIN_CAR_LENGTH,
+            max_speed: None,
+            behavior: VehicleBehavior::typical(),
+            battery: None,
+        }
+        .make(
+            CarID {
+                id: self.trips.new_car_id(),
+                vehicle_type: VehicleType::RideHail,
+            },
+            None,
+        );
+        self.ridehail.add_vehicle(vehicle, pos);
+    }
+
+    pub(crate) fn seed_bike_share_dock(&mut self, dock: &BikeShareDock, map: &Map) {
+        if SidewalkSpot::bike_rack(dock.building, map).is_none() {
+            warn!(
+                "Skipping a bike-share dock at {}, which has no biking connection",
+                dock.building
+            );
+            return;
+        }
+        self.bikeshare.add_dock(dock);
+    }
+
+    pub(crate) fn seed_charger(&mut self, charger: &Charger, map: &Map) {
+        let spots = self.parking.add_charger(charger.location, charger.ports);
+        if spots.is_empty() {
+            warn!(
+                "Skipping a charger at {:?}, which has no parking spots",
+                charger.location
+            );
+            return;
+        }
+        self.energy.add_charger(charger, spots, map);
+    }
+
+    fn start_bus(&mut self, route: &BusRoute, scheduled: Time, map: &Map) {
         // For now, no desire for randomness. Caller can pass in list of specs if that ever
         // changes.
         let (vehicle_type, length) = match route.route_type {
@@ -343,10 +476,23 @@ impl Sim {
             PathConstraints::Train => (VehicleType::Train, LIGHT_RAIL_LENGTH),
             _ => unreachable!(),
         };
+        // Spawn one bus for the first leg.
+        let path = match self.transit.create_empty_route(route, vehicle_type, map) {
+            Some(path) => path,
+            None => {
+                warn!(
+                    "Not starting a {} on {}, since it has no capacity",
+                    vehicle_type, route.full_name
+                );
+                return;
+            }
+        };
         let vehicle = VehicleSpec {
             vehicle_type,
             length,
             max_speed: None,
+            behavior: VehicleBehavior::typical(),
+            battery: None,
         }
         .make(
             CarID {
@@ -364,7 +510,37 @@ impl Sim {
                     vehicle,
                     maybe_parked_car: None,
                     trip_and_person: None,
-                    maybe_route: Some(route.id),
+                    maybe_route: Some((route.id, scheduled)),
+                },
+                true,
+            ),
+        );
+    }
+
+    fn start_delivery_tour(&mut self, car: CarID, map: &Map) {
+        let router = match self.deliveries.start_tour(self.time, car, map) {
+            Some(router) => router,
+            None => {
+                return;
+            }
+        };
+        let vehicle = VehicleSpec {
+            vehicle_type: VehicleType::Delivery,
+            length: DELIVERY_LENGTH,
+            max_speed: None,
+            behavior: BehaviorProfile::delivery_van().vehicle,
+            battery: None,
+        }
+        .make(car, None);
+        self.scheduler.push(
+            self.time,
+            Command::SpawnCar(
+                CreateCar {
+                    router,
+                    vehicle,
+                    maybe_parked_car: None,
+                    trip_and_person: None,
+                    maybe_route: None,
                 },
                 true,
             ),
@@ -383,7 +559,7 @@ impl Sim {
 // Running
 impl Sim {
     // Advances time as minimally as possible, also limited by max_dt. Returns true if the callback
-    // said to halt the sim.
+    // or a signal controller said to halt the sim.
     fn minimal_step(
         &mut self,
         map: &Map,
@@ -421,7 +597,7 @@ impl Sim {
         halt
     }
 
-    // If true, halt simulation because the callback said so.
+    // If true, halt simulation because the callback or a signal controller said so.
     fn do_step(
         &mut self,
         map: &Map,
@@ -437,6 +613,8 @@ impl Sim {
             parking: &mut self.parking,
             intersections: &mut self.intersections,
             cap: &mut self.cap,
+            bikeshare: &mut self.bikeshare,
+            energy: &mut self.energy,
             scheduler: &mut self.scheduler,
             map,
             handling_live_edits: None,
@@ -468,14 +646,48 @@ impl Sim {
                         }
                     }
                 }
+                // Live edits might've also evicted the parked car, like a delivery van unloading at
+                // the curb.
+                let evicted = create_car
+                    .maybe_parked_car
+                    .as_ref()
+                    .map(|p| ctx.parking.lookup_parked_car(p.vehicle.id).is_none())
+                    .unwrap_or(false);
+                if evicted {
+                    ok = false;
+                }
                 if !ok {
-                    self.trips.cancel_trip(
-                        self.time,
-                        create_car.trip_and_person.unwrap().0,
-                        "path is no longer valid after map edits".to_string(),
-                        Some(create_car.vehicle),
-                        &mut ctx,
-                    );
+                    if create_car.vehicle.vehicle_type == VehicleType::Delivery {
+                        if let Some(parked_car) = create_car.maybe_parked_car {
+                            if !evicted {
+                                ctx.parking.remove_parked_car(parked_car);
+                            }
+                        }
+                        self.deliveries.abandon_tour(
+                            create_car.vehicle.id,
+                            format!(
+                                "{}'s path is no longer valid after map edits",
+                                create_car.vehicle.id
+                            ),
+                        );
+                    } else if create_car.vehicle.vehicle_type == VehicleType::RideHail {
+                        // Try again from where the vehicle is waiting
+                        self.ridehail.spawn_vehicle(
+                            self.time,
+                            create_car.vehicle.id,
+                            create_car.router.get_path().get_req().start,
+                            &mut self.trips,
+                            &mut ctx,
+                        );
+                    } else {
+                        self.trips.cancel_trip(
+                            self.time,
+                            create_car.trip_and_person.unwrap().0,
+                            "path is no longer valid after map edits".to_string(),
+                            Some(create_car.vehicle),
+                            &mut ctx,
+                        );
+                    }
                 } else {
                     // create_car contains a Path, which is expensive to clone. We need different
                     // parts of create_car after attempting start_car_on_lane.
@@ -530,6 +742,8 @@ impl Sim {
                                 Some(req),
                                 if id.vehicle_type == VehicleType::Car {
                                     TripPhaseType::Driving
+                                } else if self.trips.trip_info(trip).mode == TripMode::BikeShare {
+                                    TripPhaseType::RidingSharedVehicle
                                 } else {
                                     TripPhaseType::Biking
                                 },
@@ -544,9 +758,12 @@ impl Sim {
                                 ));
                             }
                             self.parking.remove_parked_car(parked_car);
+                            if id.vehicle_type == VehicleType::Delivery {
+                                self.deliveries.done_unloading(self.time, id);
+                            }
                         }
-                        if let Some(route) = maybe_route {
-                            self.transit.bus_created(id, route);
+                        if let Some((route, scheduled)) = maybe_route {
+                            self.transit.bus_created(id, route, scheduled);
                         }
                         self.analytics
                             .record_demand(self.driving.get_path(id).unwrap(), map);
@@ -598,6 +815,8 @@ impl Sim {
                     &mut ctx,
                     &mut self.trips,
                     &mut self.transit,
+                    &mut self.deliveries,
+                    &mut self.ridehail,
                     &mut self.walking,
                 );
             }
@@ -614,8 +833,16 @@ impl Sim {
                 );
             }
             Command::UpdateIntersection(i) => {
-                self.intersections
-                    .update_intersection(self.time, i, map, &mut self.scheduler);
+                if self.intersections.update_intersection(
+                    self.time,
+                    i,
+                    map,
+                    &mut self.scheduler,
+                    &self.driving,
+                    &self.detectors,
+                ) {
+                    halt = true;
+                }
             }
             Command::Callback(frequency) => {
                 self.scheduler
@@ -630,8 +857,33 @@ impl Sim {
                     .unwrap()
                     .handle_cmd(self.time, cmd, &mut self.scheduler);
             }
-            Command::StartBus(r, _) => {
-                self.start_bus(map.get_br(r), map);
+            Command::StartBus(r, scheduled) => {
+                self.start_bus(map.get_br(r), scheduled, map);
+            }
+            Command::StartDeliveryTour(car) => {
+                self.start_delivery_tour(car, map);
+            }
+            Command::RequestRide(trip) => {
+                let info = self.trips.trip_info(trip);
+                let person = self.trips.trip_to_person(trip).unwrap();
+                match (info.start, info.end) {
+                    (TripEndpoint::Bldg(from), TripEndpoint::Bldg(to)) => {
+                        self.ridehail.request_ride(
+                            self.time,
+                            trip,
+                            person,
+                            from,
+                            to,
+                            &mut self.trips,
+                            &mut ctx,
+                        );
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            Command::GiveUpOnRide(trip) => {
+                self.ridehail
+                    .give_up(self.time, trip, &mut self.trips, &mut ctx);
             }
         }
 
@@ -644,17 +896,36 @@ impl Sim {
     fn dispatch_events(&mut self, mut events: Vec<Event>, map: &Map) {
         events.extend(self.trips.collect_events());
         events.extend(self.transit.collect_events());
+        events.extend(self.deliveries.collect_events());
+        events.extend(self.ridehail.collect_events());
+        events.extend(self.bikeshare.collect_events());
+        events.extend(self.energy.collect_events());
         events.extend(self.driving.collect_events());
         events.extend(self.walking.collect_events());
         events.extend(self.intersections.collect_events());
         events.extend(self.parking.collect_events());
         for ev in events {
+            self.energy.handle_event(self.time, &ev, &self.parking);
             if let Some(ref mut m) = self.pandemic {
                 m.handle_event(self.time, &ev, &mut self.scheduler);
             }
             if let Some(ref mut r) = self.recorder {
                 r.handle_event(self.time, &ev, map, &self.driving);
             }
+            self.detectors
+                .handle_event(self.time, &ev, map, &self.driving);
+            if let Some(ref mut r) = self.travel_times {
+                r.handle_event(self.time, &ev, map);
+            }
+            if let Some(ref mut r) = self.emissions {
+                r.handle_event(self.time, &ev, map);
+            }
+            if let Some(ref mut r) = self.transit_log {
+                r.handle_event(self.time, &ev);
+            }
+            if let Some(ref mut captured) = self.captured_events {
+                captured.push((self.time, ev.clone()));
+            }
 
             self.analytics.event(ev, self.time, map);
         }
@@ -776,6 +1047,8 @@ impl Sim {
         )
     }
 
+    /// Savestates aren't versioned. Any change to the simulation state, like a new field on
+    /// `Vehicle`, makes old savestates unreadable.
     pub fn save(&mut self) -> String {
         if false {
             println!("sim savestate breakdown:");
@@ -830,6 +1103,26 @@ impl Sim {
     pub fn load_savestate(path: String, timer: &mut Timer) -> Result<Sim> {
         abstio::maybe_read_binary(path, timer)
     }
+
+    /// Like `save`, but just returns the serialized state, instead of writing a file in the
+    /// savestate directory.
+    pub fn save_to_bytes(&self) -> Vec<u8> {
+        abstutil::to_binary(self)
+    }
+
+    /// Restores a simulation from the output of `save_to_bytes`. The map must match the one used
+    /// originally.
+    pub fn load_savestate_from_bytes(bytes: &[u8], map: &Map) -> Result<Sim> {
+        let sim: Sim = abstutil::from_binary(bytes)?;
+        if &sim.map_name != map.get_name() {
+            bail!(
+                "savestate is for {}, but the current map is {}",
+                sim.map_name.describe(),
+                map.get_name().describe()
+            );
+        }
+        Ok(sim)
+    }
 }
 
 // Live edits
@@ -842,11 +1135,21 @@ impl Sim {
     /// Respond to arbitrary map edits without resetting the simulation. Returns the number of
     /// (trips cancelled, parked cars displaced).
     pub fn handle_live_edits(&mut self, map: &Map) -> (usize, usize) {
+        let (affected, num_parked_cars) = self.handle_live_edits_listing_affected(map);
+        (affected.len(), num_parked_cars)
+    }
+
+    /// Like `handle_live_edits`, but returns every (agent, trip) that was cancelled, along with
+    /// the number of parked cars displaced.
+    pub fn handle_live_edits_listing_affected(
+        &mut self,
+        map: &Map,
+    ) -> (BTreeSet<(AgentID, TripID)>, usize) {
         self.edits_name = map.get_edits().edits_name.clone();
 
-        let (affected, num_parked_cars) = self.find_trips_affected_by_live_edits(map);
-        let num_trips_cancelled = affected.len();
-        let affected_agents: BTreeSet<AgentID> = affected.iter().map(|(a, _)| *a).collect();
+        let (affected, tripless, num_parked_cars) = self.find_trips_affected_by_live_edits(map);
+        let mut affected_agents: BTreeSet<AgentID> = affected.iter().map(|(a, _)| *a).collect();
+        affected_agents.extend(tripless.iter().map(|c| AgentID::Car(*c)));
 
         // V1: Just cancel every trip crossing an affected area.
         // (V2 is probably rerouting everyone, only cancelling when that fails)
@@ -855,11 +1158,13 @@ impl Sim {
             parking: &mut self.parking,
             intersections: &mut self.intersections,
             cap: &mut self.cap,
+            bikeshare: &mut self.bikeshare,
+            energy: &mut self.energy,
             scheduler: &mut self.scheduler,
             map,
             handling_live_edits: Some(affected_agents),
         };
-        for (agent, trip) in affected {
+        for (agent, trip) in affected.iter().cloned() {
             match agent {
                 AgentID::Car(car) => {
                     let vehicle = self.driving.delete_car(car, self.time, &mut ctx);
@@ -888,19 +1193,33 @@ impl Sim {
                 AgentID::BusPassenger(_, _) => unreachable!(),
             }
         }
+        for car in tripless {
+            self.driving.delete_car(car, self.time, &mut ctx);
+            if car.vehicle_type == VehicleType::Delivery {
+                self.deliveries.abandon_tour(
+                    car,
+                    format!("{} is giving up on its tour after map edits", car),
+                );
+            } else {
+                self.ridehail
+                    .vehicle_removed(self.time, car, &mut self.trips, &mut ctx);
+            }
+        }
 
         self.driving.handle_live_edits(map);
         self.intersections.handle_live_edits(map);
 
-        (num_trips_cancelled, num_parked_cars)
+        (affected, num_parked_cars)
     }
 
-    /// Returns (trips affected, number of parked cars displaced)
+    /// Returns (trips affected, vehicles without a trip affected, number of parked cars
+    /// displaced)
     fn find_trips_affected_by_live_edits(
         &mut self,
         map: &Map,
-    ) -> (BTreeSet<(AgentID, TripID)>, usize) {
+    ) -> (BTreeSet<(AgentID, TripID)>, BTreeSet<CarID>, usize) {
         let mut affected: BTreeSet<(AgentID, TripID)> = BTreeSet::new();
+        let mut tripless: BTreeSet<CarID> = BTreeSet::new();
 
         // TODO Handle changes to access restrictions
 
@@ -936,6 +1255,10 @@ impl Sim {
                 self.driving
                     .find_vehicles_affected_by_live_edits(&closed_intersections, &edited_lanes),
             );
+            tripless.extend(self.driving.find_tripless_vehicles_affected_by_live_edits(
+                &closed_intersections,
+                &edited_lanes,
+            ));
         }
 
         let num_evicted = {
@@ -944,7 +1267,15 @@ impl Sim {
             affected.extend(self.walking.find_trips_to_parking(evicted_cars));
             for car in cars_parking_in_the_void {
                 let a = AgentID::Car(car);
-                affected.insert((a, self.agent_to_trip(a).unwrap()));
+                match self.agent_to_trip(a) {
+                    Some(trip) => {
+                        affected.insert((a, trip));
+                    }
+                    // A delivery van headed for a curbside spot
+                    None => {
+                        tripless.insert(car);
+                    }
+                }
             }
 
             if !self.parking.is_infinite() {
@@ -957,7 +1288,7 @@ impl Sim {
             num_evicted
         };
 
-        (affected, num_evicted)
+        (affected, tripless, num_evicted)
     }
 }
 
@@ -969,6 +1300,8 @@ impl Sim {
                 parking: &mut self.parking,
                 intersections: &mut self.intersections,
                 cap: &mut self.cap,
+                bikeshare: &mut self.bikeshare,
+                energy: &mut self.energy,
                 scheduler: &mut self.scheduler,
                 map,
                 handling_live_edits: None,
@@ -1026,6 +1359,119 @@ impl Sim {
     pub fn save_recorded_traffic(&mut self, map: &Map) {
         self.recorder.take().unwrap().save(map);
     }
+
+    /// Start measuring how long cars take to cross roads and movements, grouped into bins of some
+    /// duration. Pass the result to `Map::set_travel_time_profile` to make routing in later
+    /// simulations avoid congestion.
+    pub fn record_travel_times(&mut self, bin_width: Duration) {
+        assert!(self.travel_times.is_none());
+        self.travel_times = Some(TravelTimeRecorder::new(bin_width));
+    }
+
+    pub fn get_recorded_travel_times(&self) -> Option<&TravelTimeProfile> {
+        Some(self.travel_times.as_ref()?.get_profile())
+    }
+
+    /// Start estimating emissions and traffic noise along every road, using these factors.
+    pub fn record_emissions(&mut self, factors: EmissionFactors) {
+        assert!(self.emissions.is_none());
+        self.emissions = Some(EmissionsRecorder::new(factors));
+    }
+
+    pub fn get_recorded_emissions(&self) -> Option<&Emissions> {
+        Some(self.emissions.as_ref()?.get_results())
+    }
+
+    /// Start logging when every transit vehicle reaches and leaves each stop.
+    pub fn record_transit_log(&mut self) {
+        assert!(self.transit_log.is_none());
+        self.transit_log = Some(TransitLogRecorder::new());
+    }
+
+    pub fn get_recorded_transit_log(&self) -> Option<&TransitLog> {
+        Some(self.transit_log.as_ref()?.get_results())
+    }
+}
+
+// Capturing events
+impl Sim {
+    /// Start buffering every event the simulation produces, so an external consumer can see
+    /// everything that happens between calls to `drain_captured_events`. Does nothing if capturing
+    /// is already enabled.
+    pub fn capture_events(&mut self) {
+        if self.captured_events.is_none() {
+            self.captured_events = Some(Vec::new());
+        }
+    }
+
+    /// Returns all events captured since the last call, in the order they happened. Empty if
+    /// `capture_events` was never called.
+    pub fn drain_captured_events(&mut self) -> Vec<(Time, Event)> {
+        self.captured_events
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
+
+// Controlling traffic signals
+impl Sim {
+    /// Replaces the default behavior of a traffic signal, starting at its next stage boundary.
+    /// Passing None restores the default.
+    pub fn set_signal_controller(
+        &mut self,
+        i: IntersectionID,
+        controller: Option<Box<dyn SignalController>>,
+        map: &Map,
+    ) -> Result<()> {
+        if controller.is_some() && !map.get_i(i).is_traffic_signal() {
+            bail!("{} isn't a traffic signal", i);
+        }
+        self.intersections.set_signal_controller(i, controller);
+        Ok(())
+    }
+
+    /// Use this with `downcast_mut` to communicate with a specific controller.
+    pub fn get_signal_controller_mut(
+        &mut self,
+        i: IntersectionID,
+    ) -> Option<&mut Box<dyn SignalController>> {
+        self.intersections.get_signal_controller_mut(i)
+    }
+}
+
+// Ride-hailing
+impl Sim {
+    /// Replaces how on-demand vehicles are assigned to riders, starting with the next request.
+    pub fn set_ride_hail_dispatcher(&mut self, dispatcher: Box<dyn Dispatcher>) {
+        self.ridehail.set_dispatcher(dispatcher);
+    }
+}
+
+// Detectors
+impl Sim {
+    /// Starts measuring vehicles passing some point. The detector only sees traffic from now on.
+    pub fn add_detector(&mut self, detector: Detector, map: &Map) -> Result<DetectorID> {
+        self.detectors.add(detector, map)
+    }
+
+    pub fn all_detectors(&self) -> Vec<(DetectorID, &Detector)> {
+        self.detectors.all_detectors()
+    }
+
+    pub fn get_detector_readings(&self, id: DetectorID) -> Vec<DetectorReading> {
+        self.detectors.readings(id, self.time)
+    }
+
+    /// Is some vehicle over the detector right now?
+    pub fn is_detector_occupied(&self, id: DetectorID) -> bool {
+        self.detectors.is_occupied(id, self.time, &self.driving)
+    }
+
+    /// Every reading from every detector so far, as CSV
+    pub fn export_detector_readings(&self) -> String {
+        self.detectors.to_csv(self.time)
+    }
 }
 
 // Managing highlighted people
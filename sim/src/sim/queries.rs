@@ -1,7 +1,7 @@
 //! All sorts of read-only queries about a simulation
 
 use anyhow::Result;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 
 use abstutil::Counter;
@@ -15,8 +15,8 @@ use crate::analytics::SlidingWindow;
 use crate::{
     AgentID, AgentType, Analytics, CarID, CommutersVehiclesCounts, DrawCarInput, DrawPedCrowdInput,
     DrawPedestrianInput, OrigPersonID, PandemicModel, ParkedCar, ParkingSim, PedestrianID, Person,
-    PersonID, PersonState, Scenario, Sim, TripEndpoint, TripID, TripInfo, TripMode, TripResult,
-    UnzoomedAgent, VehicleType,
+    PersonID, PersonState, Scenario, Sim, TransitCapacity, TripEndpoint, TripID, TripInfo,
+    TripMode, TripResult, UnzoomedAgent, VehicleType,
 };
 
 // TODO Many of these just delegate to an inner piece. This is unorganized and hard to maintain.
@@ -92,6 +92,11 @@ impl Sim {
         self.transit.get_passengers(car).len()
     }
 
+    /// How many people fit aboard a bus or train
+    pub fn transit_capacity(&self, car: CarID) -> TransitCapacity {
+        self.transit.get_capacity(car)
+    }
+
     pub fn bus_route_id(&self, maybe_bus: CarID) -> Option<BusRouteID> {
         if maybe_bus.vehicle_type == VehicleType::Bus
             || maybe_bus.vehicle_type == VehicleType::Train
@@ -196,6 +201,8 @@ impl Sim {
             VehicleType::Bike,
             VehicleType::Bus,
             VehicleType::Train,
+            VehicleType::Delivery,
+            VehicleType::RideHail,
         ] {
             let id = CarID {
                 id: idx,
@@ -428,9 +435,9 @@ impl Sim {
                 let max_speed = match info.mode {
                     TripMode::Walk | TripMode::Transit => Some(person.ped_speed),
                     // TODO We should really search the vehicles and grab it from there
-                    TripMode::Drive => None,
+                    TripMode::Drive | TripMode::RideHail => None,
                     // Assume just one bike
-                    TripMode::Bike => {
+                    TripMode::Bike | TripMode::BikeShare => {
                         person
                             .vehicles
                             .iter()
@@ -532,7 +539,8 @@ pub struct AgentProperties {
 
 /// Why is an agent delayed? If there are multiple reasons, arbitrarily pick one -- ie, somebody
 /// could be blocked by two conflicting turns.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub enum DelayCause {
     /// Queued behind someone, or someone's doing a conflicting turn, or someone's eating up space
     /// in a target queue
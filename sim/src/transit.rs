@@ -3,7 +3,7 @@ use std::collections::{BTreeMap, BTreeSet};
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::Time;
+use geom::{Duration, Time};
 use map_model::{BusRoute, BusRouteID, BusStopID, Map, Path, PathRequest, Position};
 
 use crate::sim::Ctx;
@@ -15,6 +15,64 @@ use crate::{
 // These index stops along a route, not stops along a single sidewalk.
 type StopIdx = usize;
 
+/// Opening and closing the doors, and pulling in and out of the stop
+const DWELL_TIME_OVERHEAD: Duration = Duration::const_seconds(6.0);
+const TIME_TO_BOARD: Duration = Duration::const_seconds(2.5);
+const TIME_TO_ALIGHT: Duration = Duration::const_seconds(1.5);
+/// Squeezing past people standing in the aisle slows everybody down
+const CROWDED_SLOWDOWN: f64 = 1.5;
+/// A vehicle arriving less than this fraction of the scheduled headway after the previous one is
+/// bunched up behind it.
+const BUNCHING_RATIO: f64 = 0.25;
+/// Headway-based holding keeps vehicles at least this fraction of the scheduled headway apart.
+const HEADWAY_HOLD_RATIO: f64 = 0.8;
+/// Never hold a vehicle longer than this at one stop
+const MAX_HOLD: Duration = Duration::const_seconds(300.0);
+
+/// How to hold transit vehicles at timepoint stops
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum HoldingStrategy {
+    /// Vehicles running early wait until their scheduled time.
+    Schedule,
+    /// Vehicles wait until enough time has passed since the previous vehicle left, relative to
+    /// the scheduled headway. This evens out gaps between vehicles instead of chasing the
+    /// timetable.
+    Headway,
+}
+
+/// How many people fit aboard a transit vehicle.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct TransitCapacity {
+    pub seated: usize,
+    pub standing: usize,
+}
+
+impl TransitCapacity {
+    /// A standard 12m bus, or a 60m two-car light rail train. None for anything else.
+    pub fn typical(vehicle_type: VehicleType) -> Option<TransitCapacity> {
+        match vehicle_type {
+            VehicleType::Bus => Some(TransitCapacity {
+                seated: 35,
+                standing: 45,
+            }),
+            VehicleType::Train => Some(TransitCapacity {
+                seated: 130,
+                standing: 270,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn total(self) -> usize {
+        self.seated + self.standing
+    }
+
+    /// Is anybody aboard forced to stand?
+    pub fn is_crowded(self, passengers: usize) -> bool {
+        passengers > self.seated
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct Stop {
     id: BusStopID,
@@ -25,9 +83,15 @@ struct Stop {
 #[derive(Serialize, Deserialize, Clone)]
 struct Route {
     stops: Vec<Stop>,
+    /// Every vehicle on the route is the same size
+    capacity: TransitCapacity,
     start: Path,
     end_at_border: Option<Path>,
     active_vehicles: BTreeSet<CarID>,
+    /// Per stop, the most recent vehicle to arrive, when, and when it was scheduled to arrive
+    last_arrivals: Vec<Option<(CarID, Time, Time)>>,
+    /// Per stop, when the most recent vehicle left
+    last_departures: Vec<Option<Time>>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -36,6 +100,9 @@ struct Bus {
     route: BusRouteID,
     /// Where does each passenger want to deboard?
     passengers: Vec<(PersonID, Option<BusStopID>)>,
+    capacity: TransitCapacity,
+    /// When the vehicle was scheduled to begin the route
+    scheduled_start: Time,
     state: BusState,
 }
 
@@ -67,12 +134,22 @@ pub(crate) struct TransitSimState {
         deserialize_with = "deserialize_btreemap"
     )]
     peds_waiting: BTreeMap<BusStopID, Vec<(PedestrianID, BusRouteID, Option<BusStopID>, Time)>>,
+    holding: Option<HoldingStrategy>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    capacities: BTreeMap<BusRouteID, TransitCapacity>,
 
     events: Vec<Event>,
 }
 
 impl TransitSimState {
-    pub fn new(map: &Map) -> TransitSimState {
+    pub fn new(
+        map: &Map,
+        holding: Option<HoldingStrategy>,
+        capacities: BTreeMap<BusRouteID, TransitCapacity>,
+    ) -> TransitSimState {
         // Keep this filled out always so get_passengers can return &Vec without a hassle
         let mut peds_waiting = BTreeMap::new();
         for bs in map.all_bus_stops().keys() {
@@ -83,12 +160,25 @@ impl TransitSimState {
             buses: BTreeMap::new(),
             routes: BTreeMap::new(),
             peds_waiting,
+            holding,
+            capacities,
             events: Vec::new(),
         }
     }
 
-    /// Returns the path for the first leg.
-    pub fn create_empty_route(&mut self, bus_route: &BusRoute, map: &Map) -> Path {
+    /// Returns the path for the first leg, or None if there's no capacity for this route or kind
+    /// of vehicle.
+    pub fn create_empty_route(
+        &mut self,
+        bus_route: &BusRoute,
+        vehicle_type: VehicleType,
+        map: &Map,
+    ) -> Option<Path> {
+        let capacity = self
+            .capacities
+            .get(&bus_route.id)
+            .cloned()
+            .or_else(|| TransitCapacity::typical(vehicle_type))?;
         self.routes.entry(bus_route.id).or_insert_with(|| {
             assert!(bus_route.stops.len() > 1);
             let mut stops = Vec::new();
@@ -144,16 +234,19 @@ impl TransitSimState {
             };
             Route {
                 active_vehicles: BTreeSet::new(),
+                last_arrivals: vec![None; stops.len()],
+                last_departures: vec![None; stops.len()],
                 stops,
+                capacity,
                 start,
                 end_at_border,
             }
         });
 
-        self.routes[&bus_route.id].start.clone()
+        Some(self.routes[&bus_route.id].start.clone())
     }
 
-    pub fn bus_created(&mut self, bus: CarID, r: BusRouteID) {
+    pub fn bus_created(&mut self, bus: CarID, r: BusRouteID, scheduled_start: Time) {
         let route = self.routes.get_mut(&r).unwrap();
         route.active_vehicles.insert(bus);
         self.buses.insert(
@@ -162,13 +255,15 @@ impl TransitSimState {
                 car: bus,
                 route: r,
                 passengers: Vec::new(),
+                capacity: route.capacity,
+                scheduled_start,
                 state: BusState::DrivingToStop(0),
             },
         );
     }
 
-    /// If true, the bus is idling. If false, the bus actually arrived at a border and should now
-    /// vanish.
+    /// If the bus is idling at the stop, returns how long it waits there, depending on how many
+    /// people get on and off. If None, the bus actually arrived at a border and should now vanish.
     pub fn bus_arrived_at_stop(
         &mut self,
         now: Time,
@@ -176,7 +271,7 @@ impl TransitSimState {
         trips: &mut TripManager,
         walking: &mut WalkingSimState,
         ctx: &mut Ctx,
-    ) -> bool {
+    ) -> Option<Duration> {
         let mut bus = self.buses.get_mut(&id).unwrap();
         match bus.state {
             BusState::DrivingToStop(stop_idx) => {
@@ -185,6 +280,34 @@ impl TransitSimState {
                 self.events
                     .push(Event::BusArrivedAtStop(id, bus.route, stop1));
 
+                let map = ctx.map;
+                let bus_route = map.get_br(bus.route);
+                let scheduled = bus_route.scheduled_arrival(bus.scheduled_start, stop_idx);
+                self.events.push(Event::TransitScheduleAdherence {
+                    bus: id,
+                    route: bus.route,
+                    stop: stop1,
+                    stop_idx,
+                    scheduled,
+                });
+                let route = self.routes.get_mut(&bus.route).unwrap();
+                if let Some((leader, leader_arrived, leader_scheduled)) =
+                    route.last_arrivals[stop_idx]
+                {
+                    let scheduled_headway = scheduled - leader_scheduled;
+                    if scheduled_headway > Duration::ZERO
+                        && now - leader_arrived < BUNCHING_RATIO * scheduled_headway
+                    {
+                        self.events.push(Event::TransitBunching {
+                            bus: id,
+                            leader,
+                            route: bus.route,
+                            stop: stop1,
+                        });
+                    }
+                }
+                route.last_arrivals[stop_idx] = Some((id, now, scheduled));
+
                 // Deboard existing passengers.
                 let mut still_riding = Vec::new();
                 for (person, maybe_stop2) in bus.passengers.drain(..) {
@@ -197,14 +320,28 @@ impl TransitSimState {
                         still_riding.push((person, maybe_stop2));
                     }
                 }
+                let num_alighting = bus.passengers.len() - still_riding.len();
                 bus.passengers = still_riding;
+                let crowded_before = bus.capacity.is_crowded(bus.passengers.len());
 
-                // Board new passengers.
+                // Board new passengers, until the vehicle is full.
                 let mut still_waiting = Vec::new();
+                let mut num_boarding = 0;
                 for (ped, route, maybe_stop2, started_waiting) in
                     self.peds_waiting.remove(&stop1).unwrap()
                 {
-                    if bus.route == route {
+                    if bus.route == route && bus.passengers.len() >= bus.capacity.total() {
+                        if let Some(person) = trips
+                            .agent_to_trip(AgentID::Pedestrian(ped))
+                            .and_then(|trip| trips.trip_to_person(trip))
+                        {
+                            self.events.push(Event::PassengerLeftBehind(
+                                person, bus.car, bus.route, stop1,
+                            ));
+                        }
+                        still_waiting.push((ped, route, maybe_stop2, started_waiting));
+                    } else if bus.route == route {
+                        num_boarding += 1;
                         let (trip, person) = trips.ped_boarded_bus(
                             now,
                             ped,
@@ -244,7 +381,48 @@ impl TransitSimState {
                     }
                 }
                 self.peds_waiting.insert(stop1, still_waiting);
-                true
+
+                let mut dwell_time = DWELL_TIME_OVERHEAD
+                    + (num_boarding as f64) * TIME_TO_BOARD
+                    + (num_alighting as f64) * TIME_TO_ALIGHT;
+                if crowded_before || bus.capacity.is_crowded(bus.passengers.len()) {
+                    dwell_time =
+                        DWELL_TIME_OVERHEAD + CROWDED_SLOWDOWN * (dwell_time - DWELL_TIME_OVERHEAD);
+                }
+
+                // Maybe wait longer at a timepoint
+                if let Some(strategy) = self.holding {
+                    if bus_route.timepoints.contains(&stop_idx) {
+                        let ready = now + dwell_time;
+                        let depart_at = match strategy {
+                            HoldingStrategy::Schedule => Some(scheduled),
+                            HoldingStrategy::Headway => {
+                                match (
+                                    self.routes[&bus.route].last_departures[stop_idx],
+                                    bus_route.scheduled_headway(bus.scheduled_start),
+                                ) {
+                                    (Some(leader_left), Some(headway)) => {
+                                        Some(leader_left + HEADWAY_HOLD_RATIO * headway)
+                                    }
+                                    _ => None,
+                                }
+                            }
+                        };
+                        if let Some(depart_at) = depart_at {
+                            if depart_at > ready {
+                                let hold = (depart_at - ready).min(MAX_HOLD);
+                                self.events.push(Event::TransitVehicleHeld {
+                                    bus: id,
+                                    route: bus.route,
+                                    stop: stop1,
+                                    duration: hold,
+                                });
+                                dwell_time += hold;
+                            }
+                        }
+                    }
+                }
+                Some(dwell_time)
             }
             BusState::DrivingOffMap => {
                 self.routes
@@ -263,21 +441,29 @@ impl TransitSimState {
                     }
                     trips.transit_rider_reached_border(now, person, id, ctx);
                 }
-                false
+                None
             }
             BusState::AtStop(_) | BusState::Done => unreachable!(),
         }
     }
 
-    pub fn bus_departed_from_stop(&mut self, id: CarID, map: &Map) -> Router {
+    pub fn bus_departed_from_stop(&mut self, now: Time, id: CarID, map: &Map) -> Router {
         let mut bus = self.buses.get_mut(&id).unwrap();
         let route = self.routes.get_mut(&bus.route).unwrap();
         match bus.state {
             BusState::DrivingToStop(_) | BusState::DrivingOffMap | BusState::Done => unreachable!(),
             BusState::AtStop(stop_idx) => {
+                route.last_departures[stop_idx] = Some(now);
                 let stop = &route.stops[stop_idx];
                 self.events
                     .push(Event::BusDepartedFromStop(id, bus.route, stop.id));
+                self.events.push(Event::TransitVehicleLoad {
+                    bus: id,
+                    route: bus.route,
+                    departing: stop.id,
+                    passengers: bus.passengers.len(),
+                    capacity: bus.capacity,
+                });
                 if let Some(path) = stop.next_stop.clone() {
                     bus.state = BusState::DrivingToStop(stop_idx + 1);
                     Router::follow_bus_route(id, path)
@@ -316,7 +502,10 @@ impl TransitSimState {
         if let Some(route) = self.routes.get(&route_id) {
             for bus in &route.active_vehicles {
                 if let BusState::AtStop(idx) = self.buses[bus].state {
-                    if route.stops[idx].id == stop1 {
+                    // If it's full, wait for the next one
+                    if route.stops[idx].id == stop1
+                        && self.buses[bus].passengers.len() < self.buses[bus].capacity.total()
+                    {
                         self.buses
                             .get_mut(bus)
                             .unwrap()
@@ -362,6 +551,10 @@ impl TransitSimState {
         &self.buses[&bus].passengers
     }
 
+    pub fn get_capacity(&self, bus: CarID) -> TransitCapacity {
+        self.buses[&bus].capacity
+    }
+
     pub fn bus_route(&self, bus: CarID) -> BusRouteID {
         self.buses[&bus].route
     }
@@ -448,3 +641,24 @@ impl TransitSimState {
         results
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typical_capacity() {
+        let bus = TransitCapacity::typical(VehicleType::Bus).unwrap();
+        assert_eq!(bus.total(), 80);
+        assert!(!bus.is_crowded(35));
+        assert!(bus.is_crowded(36));
+        assert_eq!(
+            TransitCapacity::typical(VehicleType::Train)
+                .unwrap()
+                .total(),
+            400
+        );
+        assert_eq!(TransitCapacity::typical(VehicleType::Car), None);
+        assert_eq!(TransitCapacity::typical(VehicleType::Delivery), None);
+    }
+}
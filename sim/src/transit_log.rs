@@ -0,0 +1,227 @@
+//! Records what transit vehicles actually did during a simulation: when each vehicle reached and
+//! left every stop, and how many people got on and off. This is the simulated equivalent of
+//! automatic vehicle location (AVL) and passenger counter data, and can be exported as a
+//! GTFS-like feed to compare with the planned schedule or other runs.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::Time;
+use map_model::{BusRouteID, BusStopID};
+
+use crate::{CarID, Event};
+
+/// One transit vehicle serving one stop.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct StopVisit {
+    pub vehicle: CarID,
+    pub route: BusRouteID,
+    pub stop: BusStopID,
+    /// Counting from 0, the position of the stop along the route
+    pub stop_sequence: usize,
+    pub arrived: Time,
+    /// None if the simulation ended while the vehicle was still at the stop
+    pub departed: Option<Time>,
+    /// When the schedule said the vehicle should arrive
+    pub scheduled: Time,
+    pub boarded: usize,
+    pub alighted: usize,
+    /// How many passengers were aboard when leaving the stop
+    pub departing_load: Option<usize>,
+}
+
+/// Every stop served by every transit vehicle, in the order the vehicles arrived.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TransitLog {
+    pub visits: Vec<StopVisit>,
+}
+
+/// Follows transit events, accumulating a TransitLog.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct TransitLogRecorder {
+    log: TransitLog,
+    /// For each vehicle, the index into the log of the stop it most recently arrived at
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    current: BTreeMap<CarID, usize>,
+}
+
+impl TransitLogRecorder {
+    pub fn new() -> TransitLogRecorder {
+        TransitLogRecorder {
+            log: TransitLog { visits: Vec::new() },
+            current: BTreeMap::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, time: Time, ev: &Event) {
+        match ev {
+            // Every arrival at a stop produces this, with more detail than BusArrivedAtStop
+            Event::TransitScheduleAdherence {
+                bus,
+                route,
+                stop,
+                stop_idx,
+                scheduled,
+            } => {
+                self.current.insert(*bus, self.log.visits.len());
+                self.log.visits.push(StopVisit {
+                    vehicle: *bus,
+                    route: *route,
+                    stop: *stop,
+                    stop_sequence: *stop_idx,
+                    arrived: time,
+                    departed: None,
+                    scheduled: *scheduled,
+                    boarded: 0,
+                    alighted: 0,
+                    departing_load: None,
+                });
+            }
+            Event::PassengerBoardsTransit(_, bus, _, _, _) => {
+                if let Some(visit) = self.current_visit(*bus) {
+                    visit.boarded += 1;
+                }
+            }
+            Event::PassengerAlightsTransit(_, bus, _, _) => {
+                if let Some(visit) = self.current_visit(*bus) {
+                    visit.alighted += 1;
+                }
+            }
+            Event::BusDepartedFromStop(bus, _, _) => {
+                if let Some(visit) = self.current_visit(*bus) {
+                    visit.departed = Some(time);
+                }
+            }
+            Event::TransitVehicleLoad {
+                bus, passengers, ..
+            } => {
+                if let Some(visit) = self.current_visit(*bus) {
+                    visit.departing_load = Some(*passengers);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn current_visit(&mut self, bus: CarID) -> Option<&mut StopVisit> {
+        let idx = *self.current.get(&bus)?;
+        Some(&mut self.log.visits[idx])
+    }
+
+    pub fn get_results(&self) -> &TransitLog {
+        &self.log
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geom::Duration;
+
+    use super::*;
+    use crate::{PersonID, TransitCapacity, VehicleType};
+
+    fn stop(sidewalk: usize) -> BusStopID {
+        // The fields aren't all public outside of map_model
+        abstutil::from_json(format!("{{\"sidewalk\": {}, \"idx\": 0}}", sidewalk).as_bytes())
+            .unwrap()
+    }
+
+    fn at(seconds: f64) -> Time {
+        Time::START_OF_DAY + Duration::seconds(seconds)
+    }
+
+    #[test]
+    fn test_recorder() {
+        let bus1 = CarID {
+            id: 1,
+            vehicle_type: VehicleType::Bus,
+        };
+        let bus2 = CarID {
+            id: 2,
+            vehicle_type: VehicleType::Bus,
+        };
+        let route = BusRouteID(0);
+        let arrive = |bus, stop_idx, scheduled| Event::TransitScheduleAdherence {
+            bus,
+            route,
+            stop: stop(stop_idx),
+            stop_idx,
+            scheduled: at(scheduled),
+        };
+
+        let mut recorder = TransitLogRecorder::new();
+        for (time, ev) in vec![
+            (10.0, Event::BusArrivedAtStop(bus1, route, stop(0))),
+            (10.0, arrive(bus1, 0, 5.0)),
+            (
+                20.0,
+                Event::PassengerBoardsTransit(
+                    PersonID(0),
+                    bus1,
+                    route,
+                    stop(0),
+                    Duration::seconds(30.0),
+                ),
+            ),
+            // The second vehicle only appears partway along the route
+            (20.0, arrive(bus2, 3, 25.0)),
+            (
+                25.0,
+                Event::PassengerBoardsTransit(PersonID(1), bus1, route, stop(0), Duration::ZERO),
+            ),
+            (
+                25.0,
+                Event::PassengerAlightsTransit(PersonID(2), bus2, route, stop(3)),
+            ),
+            (30.0, Event::BusDepartedFromStop(bus1, route, stop(0))),
+            (
+                30.0,
+                Event::TransitVehicleLoad {
+                    bus: bus1,
+                    route,
+                    departing: stop(0),
+                    passengers: 2,
+                    capacity: TransitCapacity::typical(VehicleType::Bus).unwrap(),
+                },
+            ),
+            (90.0, arrive(bus1, 1, 100.0)),
+        ] {
+            recorder.handle_event(at(time), &ev);
+        }
+
+        let visit = |vehicle, stop_idx, arrived, scheduled| StopVisit {
+            vehicle,
+            route,
+            stop: stop(stop_idx),
+            stop_sequence: stop_idx,
+            arrived: at(arrived),
+            departed: None,
+            scheduled: at(scheduled),
+            boarded: 0,
+            alighted: 0,
+            departing_load: None,
+        };
+        assert_eq!(
+            recorder.get_results().visits,
+            vec![
+                StopVisit {
+                    departed: Some(at(30.0)),
+                    boarded: 2,
+                    departing_load: Some(2),
+                    ..visit(bus1, 0, 10.0, 5.0)
+                },
+                StopVisit {
+                    alighted: 1,
+                    ..visit(bus2, 3, 20.0, 25.0)
+                },
+                // Still at the stop when the simulation ended
+                visit(bus1, 1, 90.0, 100.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Duration, Time};
+use map_model::{Map, TravelTimeProfile, Traversable};
+
+use crate::{AgentID, AgentType, Event};
+
+/// Measures how long cars take to cross each road and movement, so later simulations can route
+/// around congestion. Cars that start or end their trip partway along a road aren't counted for
+/// that road.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct TravelTimeRecorder {
+    profile: TravelTimeProfile,
+    /// Where each car currently is, and when it got there
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    entered: BTreeMap<AgentID, (Traversable, Time)>,
+}
+
+impl TravelTimeRecorder {
+    pub fn new(bin_width: Duration) -> TravelTimeRecorder {
+        TravelTimeRecorder {
+            profile: TravelTimeProfile::new(bin_width),
+            entered: BTreeMap::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, time: Time, ev: &Event, map: &Map) {
+        match ev {
+            Event::AgentEntersTraversable(agent, _, on, _) if agent.to_type() == AgentType::Car => {
+                if let Some((prev, entered)) = self.entered.insert(*agent, (*on, time)) {
+                    match (prev, on) {
+                        // The time spent waiting at the end of the road counts towards the road
+                        (Traversable::Lane(l), Traversable::Turn(_)) => {
+                            self.profile.record_road(
+                                map.get_l(l).get_directed_parent(),
+                                entered,
+                                time - entered,
+                            );
+                        }
+                        (Traversable::Turn(t), Traversable::Lane(_)) => {
+                            self.profile.record_movement(
+                                t.to_movement(map),
+                                entered,
+                                time - entered,
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Event::PersonLeavesMap(_, Some(agent), _) => {
+                self.entered.remove(agent);
+            }
+            Event::CarReachedParkingSpot(car, _) => {
+                self.entered.remove(&AgentID::Car(*car));
+            }
+            _ => {}
+        }
+    }
+
+    pub fn get_profile(&self) -> &TravelTimeProfile {
+        &self.profile
+    }
+}
@@ -1,13 +1,15 @@
 use std::collections::{BTreeMap, VecDeque};
 
 use anyhow::Result;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
 use geom::{Distance, Duration, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, Path, PathConstraints, PathRequest,
-    Position,
+    BuildingID, BusRouteID, BusStopID, DirectedRoadID, IntersectionID, Map, Path, PathConstraints,
+    PathRequest, Position,
 };
 
 use crate::cap::CapResult;
@@ -20,6 +22,9 @@ use crate::{
     WalkingSimState,
 };
 
+/// How long drivers expect to park when they have no more trips planned
+const DEFAULT_STAY: Duration = Duration::const_seconds(4.0 * 3600.0);
+
 /// Manages people, each of which executes some trips through the day. Each trip is further broken
 /// down into legs -- for example, a driving trip might start with somebody walking to their car,
 /// driving somewhere, parking, and then walking to their final destination.
@@ -160,12 +165,13 @@ impl TripManager {
         self.trips[trip.0].started = true;
 
         let info = &self.trips[trip.0].info;
-        let spec = match TripSpec::maybe_new(
+        let mut spec = match TripSpec::maybe_new(
             info.start,
             info.end,
             info.mode,
             args.use_vehicle,
             args.retry_if_no_room,
+            ctx.bikeshare,
             ctx.map,
         ) {
             Ok(spec) => spec,
@@ -174,6 +180,76 @@ impl TripManager {
                 error: error.to_string(),
             },
         };
+        if info.mode == TripMode::BikeShare {
+            // No dock nearby had a vehicle or space, so maybe_new picked something else
+            let fallback = match spec {
+                TripSpec::JustWalking { .. } => Some(TripMode::Walk),
+                TripSpec::UsingTransit { .. } => Some(TripMode::Transit),
+                _ => None,
+            };
+            if let Some(mode) = fallback {
+                self.trips[trip.0].info.mode = mode;
+                self.events.push(Event::BikeShareUnavailable(trip, mode));
+            }
+        }
+
+        // Drivers pick where to park based on how long they'll stay
+        let parks_near = match spec {
+            TripSpec::UsingParkedCar {
+                car,
+                goal: DrivingGoal::ParkNear(b),
+                ..
+            } => Some((car, b)),
+            TripSpec::VehicleAppearing {
+                use_vehicle,
+                goal: DrivingGoal::ParkNear(b),
+                ..
+            } => Some((use_vehicle, b)),
+            _ => None,
+        };
+        if let Some((car, b)) = parks_near {
+            // Until the person's next trip
+            let leave = person
+                .trips
+                .iter()
+                .position(|t| *t == trip)
+                .and_then(|idx| person.trips.get(idx + 1))
+                .map(|t| self.trips[t.0].info.departure)
+                .filter(|t| *t > now)
+                .unwrap_or(now + DEFAULT_STAY);
+
+            let too_expensive = ctx
+                .parking
+                .cheapest_parking_near(b, now, leave, ctx.map)
+                .map(|price| price > willing_to_pay_for_parking(person.id))
+                .unwrap_or(false);
+
+            let mut switched = false;
+            if too_expensive {
+                let info = &self.trips[trip.0].info;
+                if let Ok(alt) = TripSpec::maybe_new(
+                    info.start,
+                    info.end,
+                    TripMode::Transit,
+                    None,
+                    args.retry_if_no_room,
+                    ctx.bikeshare,
+                    ctx.map,
+                ) {
+                    // If there's no useful transit route, just pay
+                    if let TripSpec::UsingTransit { .. } = alt {
+                        spec = alt;
+                        switched = true;
+                        self.trips[trip.0].info.mode = TripMode::Transit;
+                        self.events
+                            .push(Event::ParkingTooExpensive(trip, TripMode::Transit));
+                    }
+                }
+            }
+            if !switched {
+                ctx.parking.plan_stay(car, now, leave);
+            }
+        }
         // to_plan might actually change the TripSpec
         let (spec, legs) = spec.into_plan(ctx.map);
         assert!(self.trips[trip.0].legs.is_empty());
@@ -429,6 +505,76 @@ impl TripManager {
                     }
                 }
             }
+            TripSpec::UsingBikeShare {
+                start,
+                pickup,
+                dropoff,
+                ..
+            } => {
+                assert_eq!(
+                    person.state,
+                    match start.connection {
+                        SidewalkPOI::Building(b) => PersonState::Inside(b),
+                        SidewalkPOI::Border(i) => {
+                            self.events.push(Event::PersonEntersMap(
+                                person.id,
+                                AgentID::Pedestrian(person.ped),
+                                i,
+                            ));
+                            PersonState::OffMap
+                        }
+                        SidewalkPOI::SuddenlyAppear => {
+                            self.events.push(Event::PersonEntersMap(
+                                person.id,
+                                AgentID::Pedestrian(person.ped),
+                                ctx.map.get_l(start.sidewalk_pos.lane()).src_i,
+                            ));
+                            PersonState::OffMap
+                        }
+                        _ => unreachable!(),
+                    }
+                );
+                person.state = PersonState::Trip(trip);
+                ctx.bikeshare.reserve(trip, pickup, dropoff);
+
+                let walk_to = match self.trips[trip.0].legs[0] {
+                    TripLeg::Walk(ref spot) => spot.clone(),
+                    _ => unreachable!(),
+                };
+                let req = PathRequest::walking(start.sidewalk_pos, walk_to.sidewalk_pos);
+                match ctx.map.pathfind(req) {
+                    Ok(path) => {
+                        ctx.scheduler.push(
+                            now,
+                            Command::SpawnPed(CreatePedestrian {
+                                id: person.ped,
+                                speed: person.ped_speed,
+                                start,
+                                goal: walk_to,
+                                path,
+                                trip,
+                                person: person.id,
+                            }),
+                        );
+                    }
+                    Err(err) => {
+                        self.cancel_trip(now, trip, err.to_string(), None, ctx);
+                    }
+                }
+            }
+            TripSpec::RideHail { start, .. } => {
+                assert_eq!(person.state, PersonState::Inside(start));
+                person.state = PersonState::Trip(trip);
+
+                // The person waits inside until a vehicle pulls up to the curb.
+                self.events.push(Event::TripPhaseStarting(
+                    trip,
+                    person.id,
+                    None,
+                    TripPhaseType::WaitingForRideHail,
+                ));
+                ctx.scheduler.push(now, Command::RequestRide(trip));
+            }
         }
     }
 
@@ -515,8 +661,18 @@ impl TripManager {
         trip.total_distance += distance_crossed;
 
         trip.assert_walking_leg(SidewalkSpot::deferred_parking_spot());
-        let parked_car = ctx.parking.get_car_at_spot(spot).unwrap().clone();
-        let drive_to = match trip.legs[0] {
+        let mut parked_car = ctx.parking.get_car_at_spot(spot).unwrap().clone();
+        ctx.energy.unplug(now, &mut parked_car.vehicle);
+        let place = spot.place(ctx.map);
+        if let Some(policy) = ctx.map.get_parking_policy(place) {
+            self.events.push(Event::ParkingFeePaid {
+                car: parked_car.vehicle.id,
+                place,
+                fee: policy.price(parked_car.parked_since, now),
+                overstayed: !policy.allows_stay(parked_car.parked_since, now),
+            });
+        }
+        let mut drive_to = match trip.legs[0] {
             TripLeg::Drive(c, ref to) => {
                 assert_eq!(c, parked_car.vehicle.id);
                 to.clone()
@@ -524,6 +680,26 @@ impl TripManager {
             _ => unreachable!(),
         };
 
+        // Drivers running low on charge park at a charger near their destination instead, then
+        // walk the rest of the way.
+        if let DrivingGoal::ParkNear(b) = drive_to {
+            if parked_car
+                .vehicle
+                .battery
+                .as_ref()
+                .map(|battery| battery.wants_to_charge())
+                .unwrap_or(false)
+            {
+                if let Some((park_near, charger)) = ctx.energy.find_charger_near(b, ctx.map) {
+                    if park_near != b {
+                        drive_to = DrivingGoal::ParkNear(park_near);
+                        trip.legs[0] = TripLeg::Drive(parked_car.vehicle.id, drive_to.clone());
+                        self.events.push(Event::ChargingDetour(trip.id, charger));
+                    }
+                }
+            }
+        }
+
         let base_start =
             ctx.parking
                 .spot_to_driving_pos(parked_car.spot, &parked_car.vehicle, ctx.map);
@@ -633,15 +809,17 @@ impl TripManager {
         };
         match maybe_router {
             Ok(router) => {
+                let mut vehicle = self.people[trip.person.0].get_vehicle(bike);
+                if trip.info.mode == TripMode::BikeShare {
+                    let vehicle_type = ctx.bikeshare.unlock(trip.id);
+                    if let Some(limit) = vehicle_type.max_speed() {
+                        vehicle.max_speed = Some(vehicle.max_speed.map_or(limit, |s| s.min(limit)));
+                    }
+                }
                 ctx.scheduler.push(
                     now,
                     Command::SpawnCar(
-                        CreateCar::for_appearing(
-                            self.people[trip.person.0].get_vehicle(bike),
-                            router,
-                            trip.id,
-                            trip.person,
-                        ),
+                        CreateCar::for_appearing(vehicle, router, trip.id, trip.person),
                         true,
                     ),
                 );
@@ -676,6 +854,9 @@ impl TripManager {
             }
             _ => unreachable!(),
         };
+        if trip.info.mode == TripMode::BikeShare {
+            ctx.bikeshare.lock(trip.id);
+        }
 
         let id = trip.id;
         self.spawn_ped(now, id, bike_rack, ctx);
@@ -807,6 +988,57 @@ impl TripManager {
         self.spawn_ped(now, id, start, ctx);
     }
 
+    /// A ride-hail vehicle just picked up somebody waiting at the curb.
+    pub fn ride_hail_picked_up(&mut self, trip: TripID, car: CarID) {
+        let trip = &self.trips[trip.0];
+        match trip.legs[0] {
+            TripLeg::RideHail(_) => {}
+            _ => unreachable!(),
+        }
+        if let TripEndpoint::Bldg(b) = trip.info.start {
+            self.events
+                .push(Event::PersonLeavesBuilding(trip.person, b));
+        }
+        self.events.push(Event::TripPhaseStarting(
+            trip.id,
+            trip.person,
+            None,
+            TripPhaseType::RidingRideHail(car),
+        ));
+        self.active_trip_mode
+            .insert(AgentID::BusPassenger(trip.person, car), trip.id);
+        self.people[trip.person.0].on_bus = Some(car);
+    }
+
+    pub fn ride_hail_dropped_off(
+        &mut self,
+        now: Time,
+        trip: TripID,
+        car: CarID,
+        distance_crossed: Distance,
+        ctx: &mut Ctx,
+    ) {
+        let trip = &mut self.trips[trip.0];
+        assert_eq!(
+            self.active_trip_mode
+                .remove(&AgentID::BusPassenger(trip.person, car)),
+            Some(trip.id)
+        );
+        trip.total_distance += distance_crossed;
+        let b = match trip.legs.pop_front() {
+            Some(TripLeg::RideHail(b)) => b,
+            _ => unreachable!(),
+        };
+        self.people[trip.person.0].on_bus.take().unwrap();
+
+        self.people[trip.person.0].state = PersonState::Inside(b);
+        self.events
+            .push(Event::PersonEntersBuilding(trip.person, b));
+
+        let id = trip.id;
+        self.trip_finished(now, id, ctx);
+    }
+
     pub fn ped_reached_border(
         &mut self,
         now: Time,
@@ -981,7 +1213,16 @@ impl TripManager {
         req: PathRequest,
         car: CarID,
     ) -> Result<Path> {
-        let path = ctx.map.pathfind(req)?;
+        let pinned = self.trips[trip.0].info.route.clone().and_then(|roads| {
+            ctx.map
+                .pathfind_along_roads(req.clone(), roads)
+                .and_then(|path| path.into_v1(ctx.map))
+                .ok()
+        });
+        let path = match pinned {
+            Some(path) => path,
+            None => ctx.map.pathfind_at(req, now)?,
+        };
         match ctx
             .cap
             .maybe_cap_path(path, now, car, ctx.intersections, ctx.map)
@@ -1027,6 +1268,7 @@ impl TripManager {
         self.events
             .push(Event::TripCancelled(trip.id, trip.info.mode));
         let person = trip.person;
+        ctx.bikeshare.trip_cancelled(id);
 
         // Maintain consistentency for anyone listening to events
         if let PersonState::Inside(b) = self.people[person.0].state {
@@ -1110,6 +1352,15 @@ impl TripManager {
     pub fn trip_abruptly_cancelled(&mut self, trip: TripID, agent: AgentID) {
         assert_eq!(self.active_trip_mode.remove(&agent), Some(trip));
     }
+
+    /// Cancel a ride-hail trip, whether the person is still waiting or already riding.
+    pub fn cancel_ride_hail(&mut self, now: Time, id: TripID, reason: String, ctx: &mut Ctx) {
+        let person = self.trips[id.0].person;
+        if let Some(car) = self.people[person.0].on_bus.take() {
+            self.trip_abruptly_cancelled(id, AgentID::BusPassenger(person, car));
+        }
+        self.cancel_trip(now, id, reason, None, ctx);
+    }
 }
 
 // Queries
@@ -1145,6 +1396,13 @@ impl TripManager {
             TripLeg::Walk(_) => AgentID::Pedestrian(person.ped),
             TripLeg::Drive(c, _) => AgentID::Car(*c),
             TripLeg::RideBus(_, _) => AgentID::BusPassenger(person.id, person.on_bus.unwrap()),
+            TripLeg::RideHail(_) => match person.on_bus {
+                Some(car) => AgentID::BusPassenger(person.id, car),
+                // Still waiting to be picked up
+                None => {
+                    return TripResult::ModeChange;
+                }
+            },
         };
         if self.active_trip_mode.get(&a) == Some(&id) {
             TripResult::Ok(a)
@@ -1204,6 +1462,7 @@ impl TripManager {
             trains,
             bus_riders: 0,
             train_riders: 0,
+            ride_hail_riders: 0,
         };
 
         for a in self.active_trip_mode.keys() {
@@ -1215,7 +1474,10 @@ impl TripManager {
                     VehicleType::Bike => {
                         cnt.cyclists += 1;
                     }
-                    VehicleType::Bus | VehicleType::Train => unreachable!(),
+                    VehicleType::Bus
+                    | VehicleType::Train
+                    | VehicleType::Delivery
+                    | VehicleType::RideHail => unreachable!(),
                 },
                 AgentID::BusPassenger(_, c) => match c.vehicle_type {
                     VehicleType::Bus => {
@@ -1224,7 +1486,12 @@ impl TripManager {
                     VehicleType::Train => {
                         cnt.train_riders += 1;
                     }
-                    VehicleType::Car | VehicleType::Bike => unreachable!(),
+                    VehicleType::RideHail => {
+                        cnt.ride_hail_riders += 1;
+                    }
+                    VehicleType::Car | VehicleType::Bike | VehicleType::Delivery => {
+                        unreachable!()
+                    }
                 },
                 // These're counted separately
                 AgentID::Pedestrian(_) => {}
@@ -1310,6 +1577,9 @@ impl TripManager {
                         // TODO Not true for long. People will be able to spawn at borders already
                         // on a bus.
                         TripMode::Transit => AgentType::Pedestrian,
+                        TripMode::RideHail => AgentType::Car,
+                        // People walk to the first dock
+                        TripMode::BikeShare => AgentType::Pedestrian,
                     };
                     times.push((t.info.departure, agent_type));
                 }
@@ -1331,15 +1601,18 @@ impl TripManager {
                     .iter()
                     .map(|t| {
                         let trip = &self.trips[t.0];
-                        IndividTrip::new(
+                        let mut spec = IndividTrip::new(
                             trip.info.departure,
                             trip.info.purpose,
                             trip.info.start,
                             trip.info.end,
                             trip.info.mode,
-                        )
+                        );
+                        spec.route = trip.info.route.clone();
+                        spec
                     })
                     .collect(),
+                behavior: None,
             });
         }
         scenario
@@ -1369,6 +1642,8 @@ pub struct TripInfo {
     pub purpose: TripPurpose,
     /// Did a ScenarioModifier apply to this?
     pub modified: bool,
+    /// Drive along these roads, if possible, instead of searching for the best path.
+    pub route: Option<Vec<DirectedRoadID>>,
     /// Was this trip affected by a congestion cap?
     pub capped: bool,
     pub cancellation_reason: Option<String>,
@@ -1394,14 +1669,21 @@ pub(crate) enum TripLeg {
     Drive(CarID, DrivingGoal),
     /// Maybe get off at a stop, maybe ride off-map
     RideBus(BusRouteID, Option<BusStopID>),
+    /// Wait at the curb for an on-demand vehicle, then ride it to this building
+    RideHail(BuildingID),
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord)]
+#[cfg_attr(feature = "json_schema", derive(schemars::JsonSchema))]
 pub enum TripMode {
     Walk,
     Bike,
     Transit,
     Drive,
+    /// An on-demand ride in a shared vehicle
+    RideHail,
+    /// Rent a bike or scooter from one dock and return it to another
+    BikeShare,
 }
 
 impl TripMode {
@@ -1411,6 +1693,8 @@ impl TripMode {
             TripMode::Bike,
             TripMode::Transit,
             TripMode::Drive,
+            TripMode::RideHail,
+            TripMode::BikeShare,
         ]
     }
 
@@ -1420,6 +1704,8 @@ impl TripMode {
             TripMode::Bike => "bike",
             TripMode::Transit => "use transit",
             TripMode::Drive => "drive",
+            TripMode::RideHail => "hail a ride",
+            TripMode::BikeShare => "rent a bike",
         }
     }
 
@@ -1430,6 +1716,8 @@ impl TripMode {
             TripMode::Bike => "biking",
             TripMode::Transit => "using transit",
             TripMode::Drive => "driving",
+            TripMode::RideHail => "riding on-demand",
+            TripMode::BikeShare => "riding a shared bike",
         }
     }
 
@@ -1439,16 +1727,18 @@ impl TripMode {
             TripMode::Bike => "Bike",
             TripMode::Transit => "Bus",
             TripMode::Drive => "Car",
+            TripMode::RideHail => "Ride-hail",
+            TripMode::BikeShare => "Bike-share",
         }
     }
 
     pub fn to_constraints(self) -> PathConstraints {
         match self {
             TripMode::Walk => PathConstraints::Pedestrian,
-            TripMode::Bike => PathConstraints::Bike,
+            TripMode::Bike | TripMode::BikeShare => PathConstraints::Bike,
             // TODO WRONG
             TripMode::Transit => PathConstraints::Bus,
-            TripMode::Drive => PathConstraints::Car,
+            TripMode::Drive | TripMode::RideHail => PathConstraints::Car,
         }
     }
 
@@ -1514,6 +1804,13 @@ impl Person {
     }
 }
 
+/// How much somebody will pay for one stay in a parking spot, in dollars. This varies from person
+/// to person, but is the same across repeated runs.
+fn willing_to_pay_for_parking(person: PersonID) -> f64 {
+    let mut rng = XorShiftRng::seed_from_u64(person.0 as u64);
+    rng.gen_range(5.0..30.0)
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub enum PersonState {
     Trip(TripID),
@@ -1536,4 +1833,5 @@ pub struct CommutersVehiclesCounts {
     pub trains: usize,
     pub bus_riders: usize,
     pub train_riders: usize,
+    pub ride_hail_riders: usize,
 }
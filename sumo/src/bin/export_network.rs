@@ -0,0 +1,761 @@
+//! Exports a map as a SUMO .net.xml, so the same network (including any edits) can be simulated
+//! in SUMO. `export_routes` writes demand for the result.
+//!
+//! `--map`: The map to export.
+//! `--edits`: Optionally, the path to edits to apply first.
+//! `--output`: Defaults to the map name, like `montlake.net.xml`.
+//!
+//! Every direction of a road becomes an edge, named the way `EdgeMapping` expects. Lanes keep
+//! their width and geometry, and only allow the vehicle classes matching their type. Intersections
+//! become junctions with the same shape, and turns between vehicle lanes become connections.
+//! Traffic signals keep their stages and offset. SUMO expects a yellow phase before a movement
+//! turns red, so the end of each stage becomes one.
+//!
+//! Sidewalks are exported, but crosswalks and walking areas aren't. To add them and let SUMO
+//! double-check the right-of-way rules, run the result through
+//! `netconvert --sumo-net-file x.net.xml --crossings.guess --walkingareas -o x.net.xml`.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use anyhow::Result;
+
+use abstutil::{escape_xml, prettyprint_usize, CmdArgs, Timer};
+use geom::{Duration, Pt2D};
+use map_model::{
+    osm, ControlTrafficSignal, Direction, Intersection, IntersectionType, LaneID, LaneType, Map,
+    MapEdits, StageType, Turn, TurnPriority, TurnType,
+};
+use sumo::EdgeMapping;
+
+/// Taken from the end of every stage that ends some movement's green
+const YELLOW_DURATION: Duration = Duration::const_seconds(3.0);
+
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let map_path = args.required("--map");
+    let edits = args.optional("--edits");
+    let output = args.optional("--output");
+    args.done();
+
+    let mut timer = Timer::new("export SUMO network");
+    let mut map = Map::load_synchronously(map_path, &mut timer);
+    if let Some(path) = edits {
+        let edits = MapEdits::load(&map, path, &mut timer)?;
+        map.must_apply_edits(edits);
+    }
+    let output = output.unwrap_or_else(|| format!("{}.net.xml", map.get_name().map));
+
+    let network = Network::new(&map);
+    let mut f = BufWriter::new(File::create(&output)?);
+    network.write(&mut f, &map)?;
+    f.flush()?;
+
+    println!(
+        "Wrote {} edges, {} junctions, and {} connections to {}",
+        prettyprint_usize(network.num_edges),
+        prettyprint_usize(map.all_intersections().len()),
+        prettyprint_usize(network.links.values().map(|x| x.len()).sum()),
+        output
+    );
+    Ok(())
+}
+
+struct Network {
+    edges: EdgeMapping,
+    /// Every exported lane, named like `123_0`. SUMO counts lanes from the rightmost one.
+    lanes: BTreeMap<LaneID, String>,
+    /// Per intersection, the turns becoming connections, in link index order
+    links: BTreeMap<usize, Vec<Turn>>,
+    num_edges: usize,
+}
+
+impl Network {
+    fn new(map: &Map) -> Network {
+        let mut network = Network {
+            edges: EdgeMapping::new(map),
+            lanes: BTreeMap::new(),
+            links: BTreeMap::new(),
+            num_edges: 0,
+        };
+
+        for r in map.all_roads() {
+            for dr in r.id.both_directions() {
+                let lanes = edge_lanes(r.lanes_ltr(), dr.dir);
+                if !lanes.is_empty() {
+                    network.num_edges += 1;
+                }
+                let edge = network.edges.edge(dr).unwrap().0.clone();
+                for (idx, l) in lanes.into_iter().enumerate() {
+                    network.lanes.insert(l, format!("{}_{}", edge, idx));
+                }
+            }
+        }
+
+        for i in map.all_intersections() {
+            let mut turns: Vec<Turn> = map
+                .get_turns_in_intersection(i.id)
+                .iter()
+                .filter(|t| {
+                    t.turn_type != TurnType::Crosswalk
+                        && t.turn_type != TurnType::SharedSidewalkCorner
+                        && network.lanes.contains_key(&t.id.src)
+                        && network.lanes.contains_key(&t.id.dst)
+                })
+                .cloned()
+                .collect();
+            // Group connections by the incoming lane, like SUMO does
+            turns.sort_by_key(|t| (t.id.src, t.id.dst));
+            network.links.insert(i.id.0, turns);
+        }
+        network
+    }
+
+    fn write<W: Write>(&self, f: &mut W, map: &Map) -> Result<()> {
+        let bounds = map.get_bounds();
+        let gps_bounds = map.get_gps_bounds();
+        writeln!(f, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(
+            f,
+            "<net version=\"1.6\" junctionCornerDetail=\"5\" limitTurnSpeed=\"5.50\">"
+        )?;
+        writeln!(
+            f,
+            "  <location netOffset=\"0.00,0.00\" convBoundary=\"0.00,0.00,{:.2},{:.2}\" \
+             origBoundary=\"{:.7},{:.7},{:.7},{:.7}\" projParameter=\"!\"/>",
+            bounds.max_x,
+            bounds.max_y,
+            gps_bounds.min_lon,
+            gps_bounds.min_lat,
+            gps_bounds.max_lon,
+            gps_bounds.max_lat
+        )?;
+
+        // Edge types, needed by this crate's own reader
+        let mut types: BTreeMap<String, (usize, f64)> = BTreeMap::new();
+        for r in map.all_roads() {
+            types
+                .entry(highway_type(r.osm_tags.get(osm::HIGHWAY)))
+                .or_insert((
+                    r.get_detailed_rank(),
+                    r.speed_limit.inner_meters_per_second(),
+                ));
+        }
+        for (id, (priority, speed)) in &types {
+            writeln!(
+                f,
+                "  <type id=\"{}\" priority=\"{}\" speed=\"{:.2}\"/>",
+                id, priority, speed
+            )?;
+        }
+
+        // Internal edges, one per connection
+        for (i, turns) in &self.links {
+            for (idx, turn) in turns.iter().enumerate() {
+                writeln!(f, "  <edge id=\":{}_{}\" function=\"internal\">", i, idx)?;
+                writeln!(
+                    f,
+                    "    <lane id=\"{}\" index=\"0\" speed=\"{:.2}\" length=\"{:.2}\" \
+                     shape=\"{}\"/>",
+                    internal_lane(*i, idx),
+                    map.get_parent(turn.id.dst)
+                        .speed_limit
+                        .inner_meters_per_second(),
+                    turn.geom.length().inner_meters().max(0.1),
+                    shape(turn.geom.points(), map)
+                )?;
+                writeln!(f, "  </edge>")?;
+            }
+        }
+
+        // Normal edges
+        for r in map.all_roads() {
+            for dr in r.id.both_directions() {
+                let lanes = edge_lanes(r.lanes_ltr(), dr.dir);
+                if lanes.is_empty() {
+                    continue;
+                }
+                writeln!(
+                    f,
+                    "  <edge id=\"{}\" from=\"{}\" to=\"{}\" priority=\"{}\" type=\"{}\" \
+                     shape=\"{}\">",
+                    escape_xml(&self.edges.edge(dr).unwrap().0),
+                    dr.src_i(map).0,
+                    dr.dst_i(map).0,
+                    r.get_detailed_rank(),
+                    highway_type(r.osm_tags.get(osm::HIGHWAY)),
+                    shape(
+                        match dr.dir {
+                            Direction::Fwd => r.center_pts.clone(),
+                            Direction::Back => r.center_pts.reversed(),
+                        }
+                        .points(),
+                        map
+                    )
+                )?;
+                for (idx, l) in lanes.into_iter().enumerate() {
+                    let lane = map.get_l(l);
+                    writeln!(
+                        f,
+                        "    <lane id=\"{}\" index=\"{}\" allow=\"{}\" speed=\"{:.2}\" \
+                         length=\"{:.2}\" width=\"{:.2}\" shape=\"{}\"/>",
+                        escape_xml(&self.lanes[&l]),
+                        idx,
+                        allow(lane.lane_type).unwrap(),
+                        r.speed_limit.inner_meters_per_second(),
+                        lane.lane_center_pts.length().inner_meters(),
+                        lane.width.inner_meters(),
+                        shape(lane.lane_center_pts.points(), map)
+                    )?;
+                }
+                writeln!(f, "  </edge>")?;
+            }
+        }
+
+        for i in map.all_intersections() {
+            if i.is_traffic_signal() && !self.links[&i.id.0].is_empty() {
+                self.write_signal(f, map.get_traffic_signal(i.id), &self.links[&i.id.0])?;
+            }
+        }
+        for i in map.all_intersections() {
+            self.write_junction(f, i, map)?;
+        }
+        for i in map.all_intersections() {
+            self.write_connections(f, i, map)?;
+        }
+
+        writeln!(f, "</net>")?;
+        Ok(())
+    }
+
+    fn write_signal<W: Write>(
+        &self,
+        f: &mut W,
+        signal: &ControlTrafficSignal,
+        turns: &[Turn],
+    ) -> Result<()> {
+        let actuated = signal
+            .stages
+            .iter()
+            .any(|s| matches!(s.stage_type, StageType::Variable(_, _, _)));
+        writeln!(
+            f,
+            "  <tlLogic id=\"{}\" type=\"{}\" programID=\"0\" offset=\"{:.1}\">",
+            signal.id.0,
+            if actuated { "actuated" } else { "static" },
+            signal.offset.inner_seconds()
+        )?;
+        let states: Vec<String> = signal
+            .stages
+            .iter()
+            .map(|stage| {
+                turns
+                    .iter()
+                    .map(|t| match stage.get_priority_of_turn(t.id, signal) {
+                        TurnPriority::Protected => 'G',
+                        TurnPriority::Yield => 'g',
+                        TurnPriority::Banned => 'r',
+                    })
+                    .collect()
+            })
+            .collect();
+        for (idx, stage) in signal.stages.iter().enumerate() {
+            let state = &states[idx];
+            let yellow = yellow_state(state, &states[(idx + 1) % states.len()]);
+            let green = |duration: Duration| {
+                if yellow.is_some() {
+                    (duration - YELLOW_DURATION).max(Duration::seconds(1.0))
+                } else {
+                    duration
+                }
+            };
+            match stage.stage_type {
+                StageType::Fixed(duration) => {
+                    writeln!(
+                        f,
+                        "    <phase duration=\"{:.1}\" state=\"{}\"/>",
+                        green(duration).inner_seconds(),
+                        state
+                    )?;
+                }
+                StageType::Variable(min, _, additional) => {
+                    writeln!(
+                        f,
+                        "    <phase duration=\"{:.1}\" minDur=\"{:.1}\" maxDur=\"{:.1}\" \
+                         state=\"{}\"/>",
+                        green(min).inner_seconds(),
+                        green(min).inner_seconds(),
+                        green(min + additional).inner_seconds(),
+                        state
+                    )?;
+                }
+            }
+            if let Some(yellow) = yellow {
+                writeln!(
+                    f,
+                    "    <phase duration=\"{:.1}\" state=\"{}\"/>",
+                    YELLOW_DURATION.inner_seconds(),
+                    yellow
+                )?;
+            }
+        }
+        writeln!(f, "  </tlLogic>")?;
+        Ok(())
+    }
+
+    fn write_junction<W: Write>(&self, f: &mut W, i: &Intersection, map: &Map) -> Result<()> {
+        let turns = &self.links[&i.id.0];
+        let junction_type = match i.intersection_type {
+            IntersectionType::TrafficSignal if !turns.is_empty() => "traffic_light",
+            IntersectionType::Border => "dead_end",
+            IntersectionType::StopSign => {
+                let sign = map.get_stop_sign(i.id);
+                if sign.roads.values().all(|r| r.must_stop) {
+                    "allway_stop"
+                } else if sign.roads.values().any(|r| r.must_stop) {
+                    "priority_stop"
+                } else {
+                    "priority"
+                }
+            }
+            _ => "priority",
+        };
+        let inc_lanes: Vec<String> = i
+            .incoming_lanes
+            .iter()
+            .filter_map(|l| self.lanes.get(l).map(|x| escape_xml(x)))
+            .collect();
+        let int_lanes: Vec<String> = (0..turns.len())
+            .map(|idx| internal_lane(i.id.0, idx))
+            .collect();
+        let center = i.polygon.center();
+        writeln!(
+            f,
+            "  <junction id=\"{}\" type=\"{}\" x=\"{:.2}\" y=\"{:.2}\" incLanes=\"{}\" \
+             intLanes=\"{}\" shape=\"{}\">",
+            i.id.0,
+            junction_type,
+            center.x(),
+            flip_y(center, map).1,
+            inc_lanes.join(" "),
+            int_lanes.join(" "),
+            shape(i.polygon.points(), map)
+        )?;
+
+        let ranks: Vec<(usize, usize)> = turns.iter().map(|t| link_rank(t, map)).collect();
+        for (idx, (response, foes)) in requests(&ranks, |idx1, idx2| {
+            turns[idx1].conflicts_with(&turns[idx2])
+        })
+        .into_iter()
+        .enumerate()
+        {
+            writeln!(
+                f,
+                "    <request index=\"{}\" response=\"{}\" foes=\"{}\" cont=\"0\"/>",
+                idx, response, foes
+            )?;
+        }
+        writeln!(f, "  </junction>")?;
+        Ok(())
+    }
+
+    fn write_connections<W: Write>(&self, f: &mut W, i: &Intersection, map: &Map) -> Result<()> {
+        let turns = &self.links[&i.id.0];
+        let signalized = i.is_traffic_signal() && !turns.is_empty();
+        for (idx, turn) in turns.iter().enumerate() {
+            let (from, from_lane) = split_lane(&self.lanes[&turn.id.src]);
+            let (to, to_lane) = split_lane(&self.lanes[&turn.id.dst]);
+            let dir = match turn.turn_type {
+                TurnType::Left => "l",
+                TurnType::Right => "r",
+                TurnType::UTurn => "t",
+                _ => "s",
+            };
+            let state = if signalized {
+                "O"
+            } else if i.is_stop_sign()
+                && map.get_stop_sign(i.id).roads.values().all(|r| r.must_stop)
+            {
+                "="
+            } else if i.is_stop_sign()
+                && map
+                    .get_stop_sign(i.id)
+                    .roads
+                    .get(&map.get_l(turn.id.src).parent)
+                    .map(|r| r.must_stop)
+                    .unwrap_or(false)
+            {
+                "s"
+            } else {
+                "M"
+            };
+            let tl = if signalized {
+                format!(" tl=\"{}\" linkIndex=\"{}\"", i.id.0, idx)
+            } else {
+                String::new()
+            };
+            writeln!(
+                f,
+                "  <connection from=\"{}\" to=\"{}\" fromLane=\"{}\" toLane=\"{}\" via=\"{}\"{} \
+                 dir=\"{}\" state=\"{}\"/>",
+                escape_xml(from),
+                escape_xml(to),
+                from_lane,
+                to_lane,
+                internal_lane(i.id.0, idx),
+                tl,
+                dir,
+                state
+            )?;
+            writeln!(
+                f,
+                "  <connection from=\":{}_{}\" to=\"{}\" fromLane=\"0\" toLane=\"{}\" dir=\"{}\" \
+                 state=\"M\"/>",
+                i.id.0,
+                idx,
+                escape_xml(to),
+                to_lane,
+                dir
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The lanes of one direction of a road that SUMO can use, starting from the rightmost.
+fn edge_lanes(lanes_ltr: Vec<(LaneID, Direction, LaneType)>, dir: Direction) -> Vec<LaneID> {
+    let mut lanes: Vec<LaneID> = lanes_ltr
+        .into_iter()
+        .filter(|(_, d, lt)| *d == dir && allow(*lt).is_some())
+        .map(|(l, _, _)| l)
+        .collect();
+    // lanes_ltr is from the perspective of the forward direction
+    if dir == Direction::Fwd {
+        lanes.reverse();
+    }
+    lanes
+}
+
+/// SUMO vehicle classes that can use each type of lane. None for lanes that aren't exported.
+fn allow(lt: LaneType) -> Option<&'static str> {
+    match lt {
+        LaneType::Driving => Some(
+            "passenger taxi delivery truck bus coach motorcycle moped bicycle emergency \
+             authority",
+        ),
+        LaneType::Bus => Some("bus coach taxi emergency authority bicycle"),
+        LaneType::Biking => Some("bicycle"),
+        LaneType::Sidewalk | LaneType::Shoulder => Some("pedestrian"),
+        LaneType::LightRail => Some("rail_urban"),
+        LaneType::Parking | LaneType::SharedLeftTurn | LaneType::Construction => None,
+    }
+}
+
+/// During the yellow, links about to lose their green show `y`. None if no link does.
+fn yellow_state(state: &str, next_state: &str) -> Option<String> {
+    let mut changed = false;
+    let yellow = state
+        .chars()
+        .zip(next_state.chars())
+        .map(|(current, next)| {
+            if (current == 'G' || current == 'g') && next == 'r' {
+                changed = true;
+                'y'
+            } else {
+                current
+            }
+        })
+        .collect();
+    if changed {
+        Some(yellow)
+    } else {
+        None
+    }
+}
+
+/// For every link, the `response` and `foes` bitsets: which links it yields to, and which it
+/// conflicts with. SUMO bitsets put link 0 at the end of the string.
+fn requests<F: Fn(usize, usize) -> bool>(
+    ranks: &[(usize, usize)],
+    conflicts: F,
+) -> Vec<(String, String)> {
+    (0..ranks.len())
+        .map(|idx1| {
+            let mut response = String::new();
+            let mut foes = String::new();
+            for idx2 in (0..ranks.len()).rev() {
+                let conflict = idx1 != idx2 && conflicts(idx1, idx2);
+                // Ties are broken arbitrarily, so that exactly one of the two yields
+                let yields = conflict && (ranks[idx2], idx2) > (ranks[idx1], idx1);
+                response.push(if yields { '1' } else { '0' });
+                foes.push(if conflict { '1' } else { '0' });
+            }
+            (response, foes)
+        })
+        .collect()
+}
+
+/// Higher ranks go first. Vehicles facing a stop sign yield to everyone else, then straight
+/// movements beat right turns, which beat left turns.
+fn link_rank(turn: &Turn, map: &Map) -> (usize, usize) {
+    let must_stop = map.get_i(turn.id.parent).is_stop_sign()
+        && map
+            .get_stop_sign(turn.id.parent)
+            .roads
+            .get(&map.get_l(turn.id.src).parent)
+            .map(|r| r.must_stop)
+            .unwrap_or(false);
+    let movement = match turn.turn_type {
+        TurnType::Straight => 3,
+        TurnType::Right => 2,
+        TurnType::Left => 1,
+        _ => 0,
+    };
+    (if must_stop { 0 } else { 1 }, movement)
+}
+
+fn highway_type(highway: Option<&String>) -> String {
+    format!(
+        "highway.{}",
+        highway.map(|x| x.as_str()).unwrap_or("unclassified")
+    )
+}
+
+fn internal_lane(i: usize, idx: usize) -> String {
+    format!(":{}_{}_0", i, idx)
+}
+
+/// Splits a lane ID like `123_0` into the edge and index
+fn split_lane(lane: &str) -> (&str, &str) {
+    let idx = lane.rfind('_').unwrap();
+    (&lane[..idx], &lane[idx + 1..])
+}
+
+/// The map's own coordinates have Y pointing down
+fn flip_y(pt: Pt2D, map: &Map) -> (f64, f64) {
+    (pt.x(), map.get_bounds().max_y - pt.y())
+}
+
+fn shape(pts: &[Pt2D], map: &Map) -> String {
+    pts.iter()
+        .map(|pt| {
+            let (x, y) = flip_y(*pt, map);
+            format!("{:.2},{:.2}", x, y)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use abstio::MapName;
+    use abstutil::Tags;
+    use geom::{Distance, GPSBounds, LonLat};
+    use map_model::raw::{OriginalRoad, RawIntersection, RawMap, RawRoad};
+    use map_model::RawToMapOptions;
+
+    use super::*;
+
+    #[test]
+    fn test_edge_lanes() {
+        let lanes_ltr = vec![
+            (LaneID(0), Direction::Back, LaneType::Sidewalk),
+            (LaneID(1), Direction::Back, LaneType::Parking),
+            (LaneID(2), Direction::Back, LaneType::Driving),
+            (LaneID(3), Direction::Fwd, LaneType::SharedLeftTurn),
+            (LaneID(4), Direction::Fwd, LaneType::Driving),
+            (LaneID(5), Direction::Fwd, LaneType::Biking),
+            (LaneID(6), Direction::Fwd, LaneType::Sidewalk),
+        ];
+        // The rightmost lane comes first, and lanes SUMO doesn't model are skipped
+        assert_eq!(
+            edge_lanes(lanes_ltr.clone(), Direction::Fwd),
+            vec![LaneID(6), LaneID(5), LaneID(4)]
+        );
+        assert_eq!(
+            edge_lanes(lanes_ltr, Direction::Back),
+            vec![LaneID(0), LaneID(2)]
+        );
+    }
+
+    #[test]
+    fn test_yellow_state() {
+        assert_eq!(yellow_state("GgrG", "rGGG"), Some("ygrG".to_string()));
+        assert_eq!(yellow_state("Ggr", "GgG"), None);
+    }
+
+    #[test]
+    fn test_requests() {
+        // A straight, left, and right turn. The left turn conflicts with the other two.
+        let ranks = [(1, 3), (1, 1), (1, 2)];
+        let conflicts = |idx1: usize, idx2: usize| idx1 == 1 || idx2 == 1;
+        assert_eq!(
+            requests(&ranks, conflicts),
+            vec![
+                ("000".to_string(), "010".to_string()),
+                ("101".to_string(), "101".to_string()),
+                ("000".to_string(), "010".to_string()),
+            ]
+        );
+
+        // With a tie, exactly one of the two yields
+        assert_eq!(
+            requests(&[(1, 3), (1, 3)], |_, _| true),
+            vec![
+                ("10".to_string(), "10".to_string()),
+                ("00".to_string(), "01".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export_four_way() {
+        let map = four_way();
+        let i = map
+            .all_intersections()
+            .iter()
+            .find(|i| i.is_traffic_signal())
+            .unwrap();
+        let signal = map.get_traffic_signal(i.id);
+        let network = Network::new(&map);
+        let turns = &network.links[&i.id.0];
+        assert!(!turns.is_empty());
+
+        let path = std::env::temp_dir()
+            .join("export_network_four_way.net.xml")
+            .to_str()
+            .unwrap()
+            .to_string();
+        {
+            let mut f = BufWriter::new(File::create(&path).unwrap());
+            network.write(&mut f, &map).unwrap();
+            f.flush().unwrap();
+        }
+
+        // This crate's own reader understands the result
+        let parsed = sumo::Network::load(&path, &mut Timer::throwaway()).unwrap();
+        assert_eq!(parsed.normal_edges.len(), network.num_edges);
+        assert_eq!(parsed.junctions.len(), map.all_intersections().len());
+        assert_eq!(
+            parsed.internal_edges.len(),
+            network.links.values().map(|x| x.len()).sum::<usize>()
+        );
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let doc = roxmltree::Document::parse(&text).unwrap();
+        let id = i.id.0.to_string();
+        let tl_logic = doc
+            .descendants()
+            .find(|n| n.has_tag_name("tlLogic") && n.attribute("id") == Some(id.as_str()))
+            .unwrap();
+        let phases: Vec<&str> = tl_logic
+            .children()
+            .filter(|n| n.has_tag_name("phase"))
+            .map(|n| n.attribute("state").unwrap())
+            .collect();
+        assert!(phases.iter().all(|state| state.len() == turns.len()));
+
+        // Yellow phases only follow green links
+        assert!(phases.iter().any(|state| state.contains('y')));
+        for pair in phases.windows(2) {
+            if pair[1].contains('y') {
+                for (green, yellow) in pair[0].chars().zip(pair[1].chars()) {
+                    assert!(yellow != 'y' || green == 'G' || green == 'g');
+                }
+            }
+        }
+        let greens: Vec<&str> = phases
+            .iter()
+            .filter(|state| !state.contains('y'))
+            .cloned()
+            .collect();
+        assert_eq!(greens.len(), signal.stages.len());
+
+        // Every link index points at the same turn's position in the states
+        let mut link_indices = Vec::new();
+        for conn in doc
+            .descendants()
+            .filter(|n| n.has_tag_name("connection") && n.attribute("tl") == Some(id.as_str()))
+        {
+            let idx: usize = conn.attribute("linkIndex").unwrap().parse().unwrap();
+            let turn = &turns[idx];
+            assert_eq!(
+                format!(
+                    "{}_{}",
+                    conn.attribute("from").unwrap(),
+                    conn.attribute("fromLane").unwrap()
+                ),
+                network.lanes[&turn.id.src]
+            );
+            assert_eq!(
+                format!(
+                    "{}_{}",
+                    conn.attribute("to").unwrap(),
+                    conn.attribute("toLane").unwrap()
+                ),
+                network.lanes[&turn.id.dst]
+            );
+            for (stage, state) in signal.stages.iter().zip(&greens) {
+                let expected = match stage.get_priority_of_turn(turn.id, signal) {
+                    TurnPriority::Protected => 'G',
+                    TurnPriority::Yield => 'g',
+                    TurnPriority::Banned => 'r',
+                };
+                assert_eq!(state.chars().nth(idx), Some(expected));
+            }
+            link_indices.push(idx);
+        }
+        link_indices.sort_unstable();
+        assert_eq!(link_indices, (0..turns.len()).collect::<Vec<_>>());
+    }
+
+    /// A signalized four-way intersection, with every road leading to a border
+    fn four_way() -> Map {
+        let mut raw = RawMap::blank(MapName::new("zz", "test", "four_way"));
+        raw.gps_bounds =
+            GPSBounds::from(vec![LonLat::new(-122.31, 47.6), LonLat::new(-122.3, 47.61)]);
+        let bounds = raw.gps_bounds.to_bounds();
+        raw.boundary_polygon = bounds.get_rectangle();
+        let center = bounds.center();
+        raw.intersections.insert(
+            osm::NodeID(1),
+            RawIntersection {
+                point: center,
+                intersection_type: IntersectionType::TrafficSignal,
+                elevation: Distance::ZERO,
+            },
+        );
+        for &(id, (dx, dy)) in &[
+            (2, (300.0, 0.0)),
+            (3, (-300.0, 0.0)),
+            (4, (0.0, 300.0)),
+            (5, (0.0, -300.0)),
+        ] {
+            let pt = center.offset(dx, dy);
+            raw.intersections.insert(
+                osm::NodeID(id),
+                RawIntersection {
+                    point: pt,
+                    intersection_type: IntersectionType::Border,
+                    elevation: Distance::ZERO,
+                },
+            );
+            let mut osm_tags = Tags::empty();
+            osm_tags.insert(osm::HIGHWAY, "primary");
+            osm_tags.insert("lanes", "2");
+            osm_tags.insert(osm::SIDEWALK, "both");
+            raw.roads.insert(
+                OriginalRoad::new(id, (1, id)),
+                RawRoad {
+                    center_points: vec![center, pt],
+                    osm_tags,
+                    turn_restrictions: Vec::new(),
+                    complicated_turn_restrictions: Vec::new(),
+                    percent_incline: 0.0,
+                },
+            );
+        }
+        Map::create_from_raw(raw, RawToMapOptions::default(), &mut Timer::throwaway())
+    }
+}
@@ -0,0 +1,160 @@
+//! Exports a scenario as a SUMO .rou.xml, to run against a SUMO network generated from the same
+//! map. `import_routes` reads the result back.
+//!
+//! `--map`: The map the scenario is for.
+//! `--scenario`: The scenario to export.
+//! `--output`: Defaults to the scenario name, like `weekday.rou.xml`.
+//!
+//! Every trip becomes its own vehicle or person, since SUMO can't wait until a fixed time between
+//! trips. Driving and cycling trips become vehicles, with the route if the scenario has one;
+//! otherwise SUMO picks the route. Walking, transit, and ride-hailing trips become persons.
+
+#[macro_use]
+extern crate log;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use anyhow::Result;
+
+use abstutil::{prettyprint_usize, CmdArgs, Timer};
+use geom::Time;
+use map_model::{DirectedRoadID, Map, PathConstraints};
+use sim::{IndividTrip, Scenario, TripEndpoint, TripMode};
+use sumo::EdgeMapping;
+
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let map = args.required("--map");
+    let scenario: Scenario =
+        abstio::read_binary(args.required("--scenario"), &mut Timer::throwaway());
+    let output = args
+        .optional("--output")
+        .unwrap_or_else(|| format!("{}.rou.xml", scenario.scenario_name));
+    args.done();
+
+    let mut timer = Timer::new("export SUMO routes");
+    let map = Map::load_synchronously(map, &mut timer);
+    let edges = EdgeMapping::new(&map);
+
+    // SUMO insists on departures being sorted
+    let mut entries: Vec<(Time, String)> = Vec::new();
+    let mut skipped = 0;
+    for (person_idx, person) in scenario.people.iter().enumerate() {
+        for (trip_idx, trip) in person.trips.iter().enumerate() {
+            if trip.cancelled {
+                continue;
+            }
+            let id = format!("{}_{}", person_idx, trip_idx);
+            match export_trip(&id, trip, &edges, &map) {
+                Some(xml) => {
+                    entries.push((trip.depart, xml));
+                }
+                None => {
+                    warn!("Skipping trip {}: no edge for an endpoint", id);
+                    skipped += 1;
+                }
+            }
+        }
+    }
+    entries.sort_by_key(|(time, _)| *time);
+
+    let mut f = BufWriter::new(File::create(&output)?);
+    writeln!(f, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(f, "<routes>")?;
+    writeln!(f, "  <vType id=\"car\" vClass=\"passenger\"/>")?;
+    writeln!(f, "  <vType id=\"bike\" vClass=\"bicycle\"/>")?;
+    for (_, xml) in &entries {
+        write!(f, "{}", xml)?;
+    }
+    writeln!(f, "</routes>")?;
+    f.flush()?;
+
+    println!(
+        "Wrote {} trips to {}. Skipped {}.",
+        prettyprint_usize(entries.len()),
+        output,
+        prettyprint_usize(skipped)
+    );
+    Ok(())
+}
+
+fn export_trip(id: &str, trip: &IndividTrip, edges: &EdgeMapping, map: &Map) -> Option<String> {
+    let depart = trip.depart.inner_seconds();
+    let (constraints, vehicle_type, person_modes) = match trip.mode {
+        TripMode::Drive => (PathConstraints::Car, Some("car"), None),
+        TripMode::Bike => (PathConstraints::Bike, Some("bike"), None),
+        TripMode::Walk => (PathConstraints::Pedestrian, None, None),
+        TripMode::Transit => (PathConstraints::Pedestrian, None, Some("public")),
+        TripMode::RideHail => (PathConstraints::Pedestrian, None, Some("taxi")),
+        TripMode::BikeShare => (PathConstraints::Pedestrian, None, Some("bicycle")),
+    };
+    let from = edges.edge(endpoint_road(trip.origin, true, constraints, map)?)?;
+    let to = edges.edge(endpoint_road(trip.destination, false, constraints, map)?)?;
+
+    if let Some(vehicle_type) = vehicle_type {
+        // Use the exact route if it's known
+        if let Some(route) = trip.route.as_ref().and_then(|route| {
+            route
+                .iter()
+                .map(|dr| edges.edge(*dr).map(|e| e.0.clone()))
+                .collect::<Option<Vec<_>>>()
+        }) {
+            return Some(format!(
+                "  <vehicle id=\"{}\" type=\"{}\" depart=\"{:.1}\">\n    <route \
+                 edges=\"{}\"/>\n  </vehicle>\n",
+                id,
+                vehicle_type,
+                depart,
+                route.join(" ")
+            ));
+        }
+        return Some(format!(
+            "  <trip id=\"{}\" type=\"{}\" depart=\"{:.1}\" from=\"{}\" to=\"{}\"/>\n",
+            id, vehicle_type, depart, from.0, to.0
+        ));
+    }
+
+    let stage = match person_modes {
+        Some(modes) => format!(
+            "<personTrip from=\"{}\" to=\"{}\" modes=\"{}\"/>",
+            from.0, to.0, modes
+        ),
+        None => format!("<walk from=\"{}\" to=\"{}\"/>", from.0, to.0),
+    };
+    Some(format!(
+        "  <person id=\"{}\" depart=\"{:.1}\">\n    {}\n  </person>\n",
+        id, depart, stage
+    ))
+}
+
+/// Picks the road where a trip starts or ends, in a direction usable by the mode.
+fn endpoint_road(
+    endpt: TripEndpoint,
+    is_origin: bool,
+    constraints: PathConstraints,
+    map: &Map,
+) -> Option<DirectedRoadID> {
+    let lane = match endpt {
+        TripEndpoint::Bldg(b) => {
+            let bldg = map.get_b(b);
+            match constraints {
+                PathConstraints::Car => bldg.driving_connection(map)?.0.lane(),
+                PathConstraints::Bike => bldg.biking_connection(map)?.0.lane(),
+                _ => bldg.sidewalk(),
+            }
+        }
+        TripEndpoint::Border(i) => {
+            let i = map.get_i(i);
+            if is_origin {
+                i.get_outgoing_lanes(map, constraints)
+            } else {
+                i.get_incoming_lanes(map, constraints)
+            }
+            .into_iter()
+            .next()?
+        }
+        TripEndpoint::SuddenlyAppear(pos) => pos.lane(),
+    };
+    Some(map.get_l(lane).get_directed_parent())
+}
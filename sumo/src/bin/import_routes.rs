@@ -0,0 +1,395 @@
+//! Imports demand from a SUMO .rou.xml into a scenario.
+//!
+//! `--map`: Either a map converted from the SUMO network by this crate, or a map the SUMO network
+//!          was exported from.
+//! `--input`: The .rou.xml file.
+//! `--scenario_name`: Defaults to the name of the input file.
+//!
+//! Each vehicle becomes a person with one trip, following the vehicle's route if it has one.
+//! Persons keep their whole plan, with a new trip after every stop. Trip endpoints on edges that
+//! start or end at the map's boundary become borders; otherwise they're snapped to the nearest
+//! building. Maps converted from a SUMO network have no buildings, so trips start in the middle of
+//! their first edge instead. The simulation can only end trips at buildings or borders, so trips
+//! ending anywhere else on those maps are skipped. Public transport vehicles are skipped, since
+//! the map has its own transit routes.
+
+#[macro_use]
+extern crate log;
+
+use anyhow::{anyhow, bail, Result};
+
+use abstutil::{prettyprint_usize, CmdArgs, Timer};
+use geom::{Distance, FindClosest, Time};
+use map_model::{BuildingID, DirectedRoadID, Map, PathConstraints, Position};
+use sim::{
+    ExternalPerson, ExternalTrip, ExternalTripEndpoint, IndividTrip, Scenario, TripEndpoint,
+    TripMode, TripPurpose,
+};
+use sumo::{EdgeID, EdgeMapping, Person, PersonStage, Routes, Vehicle, VehicleRoute};
+
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let map = args.required("--map");
+    let input = args.required("--input");
+    let scenario_name = args.optional("--scenario_name").unwrap_or_else(|| {
+        // Double basename because "foo.rou.xml" just becomes "foo.rou"
+        abstutil::basename(abstutil::basename(&input))
+    });
+    args.done();
+
+    let mut timer = Timer::new("import SUMO routes");
+    let routes = Routes::load(&input, &mut timer)?;
+    let map = Map::load_synchronously(map, &mut timer);
+    let endpoints = Endpoints::new(&map);
+
+    let mut s = Scenario::empty(&map, &scenario_name);
+    // Include all buses/trains
+    s.only_seed_buses = None;
+    let mut skipped_transit = 0;
+    let orig_num = routes.vehicles.len() + routes.persons.len();
+    // Describe everybody first, then snap them all to the map at once
+    let mut people = Vec::new();
+    // What to call each person in warnings, and the route to follow, if any
+    let mut details: Vec<(String, Option<Vec<DirectedRoadID>>)> = Vec::new();
+    timer.start_iter("import vehicles", routes.vehicles.len());
+    for vehicle in &routes.vehicles {
+        timer.next();
+        let mode = match vehicle_mode(vehicle, &routes) {
+            Some(mode) => mode,
+            None => {
+                skipped_transit += 1;
+                continue;
+            }
+        };
+        match import_vehicle(vehicle, mode, &endpoints) {
+            Ok((person, route)) => {
+                people.push(person);
+                details.push((format!("vehicle {}", vehicle.id), route));
+            }
+            Err(err) => {
+                warn!("Skipping vehicle {}: {}", vehicle.id, err);
+            }
+        }
+    }
+    timer.start_iter("import persons", routes.persons.len());
+    for person in &routes.persons {
+        timer.next();
+        match import_person(person, &endpoints) {
+            Ok(external) => {
+                people.push(external);
+                details.push((format!("person {}", person.id), None));
+            }
+            Err(err) => {
+                warn!("Skipping person {}: {}", person.id, err);
+            }
+        }
+    }
+
+    timer.start("snap to the map");
+    let mut dropped_routes = 0;
+    for (result, (name, route)) in ExternalPerson::import_each(&map, people)
+        .into_iter()
+        .zip(details)
+    {
+        match result {
+            Ok(mut spec) => {
+                if let Some(roads) = route {
+                    if route_fits(&spec.trips[0], &roads, &map) {
+                        spec.trips[0].route = Some(roads);
+                    } else {
+                        dropped_routes += 1;
+                    }
+                }
+                s.people.push(spec);
+            }
+            Err(err) => {
+                warn!("Skipping {}: {}", name, err);
+            }
+        }
+    }
+    timer.stop("snap to the map");
+    if dropped_routes > 0 {
+        warn!(
+            "{} vehicles don't end on the last edge of their route, so they'll find their own way",
+            prettyprint_usize(dropped_routes)
+        );
+    }
+
+    // Always clean up people with no-op trips (going between the same buildings)
+    s = s.remove_weird_schedules();
+    println!(
+        "Imported {}/{} vehicles and persons. Skipped {} public transport vehicles.",
+        prettyprint_usize(s.people.len()),
+        prettyprint_usize(orig_num),
+        prettyprint_usize(skipped_transit)
+    );
+    s.save();
+
+    Ok(())
+}
+
+/// None for public transport vehicles
+fn vehicle_mode(vehicle: &Vehicle, routes: &Routes) -> Option<TripMode> {
+    if vehicle.line.is_some() {
+        return None;
+    }
+    let class = match vehicle.vehicle_type.as_deref() {
+        Some("DEFAULT_BIKETYPE") => "bicycle",
+        Some("DEFAULT_PEDTYPE") => "pedestrian",
+        Some(id) => routes
+            .vehicle_types
+            .get(id)
+            .map(|t| t.vehicle_class.as_str())
+            .unwrap_or("passenger"),
+        None => "passenger",
+    };
+    match class {
+        "bus" | "coach" | "tram" | "rail_urban" | "rail" | "rail_electric" | "rail_fast" => None,
+        "bicycle" => Some(TripMode::Bike),
+        "pedestrian" => Some(TripMode::Walk),
+        _ => Some(TripMode::Drive),
+    }
+}
+
+/// Also returns the route to follow, if every edge is known
+fn import_vehicle(
+    vehicle: &Vehicle,
+    mode: TripMode,
+    endpoints: &Endpoints,
+) -> Result<(ExternalPerson, Option<Vec<DirectedRoadID>>)> {
+    let route: Option<Vec<DirectedRoadID>> = match (mode, &vehicle.route) {
+        (TripMode::Drive, VehicleRoute::Edges(edges)) => {
+            edges.iter().map(|e| endpoints.edges.road(e)).collect()
+        }
+        _ => None,
+    };
+    let origin = match route {
+        // Start on the route's first road, so the simulation can follow it
+        Some(ref roads) => ExternalTripEndpoint::TripEndpoint(TripEndpoint::SuddenlyAppear(
+            endpoints.appear_on(roads[0], mode)?,
+        )),
+        None => endpoints.endpoint(vehicle.route.from(), true, mode)?,
+    };
+    let trip = ExternalTrip {
+        departure: vehicle.depart,
+        origin,
+        destination: endpoints.endpoint(vehicle.route.to(), false, mode)?,
+        mode,
+        // SUMO doesn't say
+        purpose: TripPurpose::Work,
+    };
+    Ok((
+        ExternalPerson {
+            trips: vec![trip],
+            behavior: None,
+        },
+        route,
+    ))
+}
+
+/// The simulation ignores a route that doesn't start and end where the trip does.
+fn route_fits(trip: &IndividTrip, roads: &[DirectedRoadID], map: &Map) -> bool {
+    let start = match trip.origin {
+        TripEndpoint::SuddenlyAppear(pos) => Some(map.get_l(pos.lane()).get_directed_parent()),
+        TripEndpoint::Border(i) => map.get_i(i).some_outgoing_road(map),
+        TripEndpoint::Bldg(_) => None,
+    };
+    let end = match trip.destination {
+        TripEndpoint::Bldg(b) => Some(
+            map.get_l(map.find_driving_lane_near_building(b))
+                .get_directed_parent(),
+        ),
+        TripEndpoint::Border(i) => map.get_i(i).some_incoming_road(map),
+        TripEndpoint::SuddenlyAppear(_) => None,
+    };
+    start.is_some() && start == roads.first().cloned() && end == roads.last().cloned()
+}
+
+fn import_person(person: &Person, endpoints: &Endpoints) -> Result<ExternalPerson> {
+    let mut trips = Vec::new();
+    // Where the person is
+    let mut at: Option<&EdgeID> = None;
+    // The current trip's start, mode, and where it began
+    let mut current: Option<(Time, TripMode, &EdgeID)> = None;
+    let mut departure = person.depart;
+    for stage in &person.plan {
+        let (from, to, mode) = match stage {
+            PersonStage::Walk { from, to } => (from, to, TripMode::Walk),
+            PersonStage::PersonTrip { from, to, modes } => (from, to, person_trip_mode(modes)),
+            PersonStage::Ride { from, to, lines } => (
+                from,
+                to,
+                if lines.iter().any(|x| x == "taxi") {
+                    TripMode::RideHail
+                } else {
+                    TripMode::Transit
+                },
+            ),
+            PersonStage::Stop { duration, until } => {
+                if let Some((depart, mode, origin)) = current.take() {
+                    trips.push(ExternalTrip {
+                        departure: depart,
+                        origin: endpoints.endpoint(origin, true, mode)?,
+                        destination: endpoints.endpoint(at.unwrap(), false, mode)?,
+                        mode,
+                        purpose: TripPurpose::Work,
+                    });
+                }
+                // How long the previous trip took isn't known, so the next trip might be
+                // scheduled too early. The simulation starts it once the person arrives.
+                departure = match (until, duration) {
+                    (Some(until), _) => *until,
+                    (None, Some(duration)) => departure + *duration,
+                    (None, None) => departure,
+                };
+                continue;
+            }
+        };
+        let from = from
+            .as_ref()
+            .or(at)
+            .ok_or_else(|| anyhow!("first stage doesn't say where it starts"))?;
+        if let Some((_, current_mode, _)) = current.as_mut() {
+            // Transit and anything besides walking win
+            if *current_mode == TripMode::Walk || mode == TripMode::Transit {
+                *current_mode = mode;
+            }
+        } else {
+            current = Some((departure, mode, from));
+        }
+        at = Some(to);
+    }
+    if let Some((depart, mode, origin)) = current {
+        trips.push(ExternalTrip {
+            departure: depart,
+            origin: endpoints.endpoint(origin, true, mode)?,
+            destination: endpoints.endpoint(at.unwrap(), false, mode)?,
+            mode,
+            purpose: TripPurpose::Work,
+        });
+    }
+    if trips.is_empty() {
+        bail!("no trips");
+    }
+    Ok(ExternalPerson {
+        trips,
+        behavior: None,
+    })
+}
+
+fn person_trip_mode(modes: &[String]) -> TripMode {
+    if modes.iter().any(|x| x == "public") {
+        TripMode::Transit
+    } else if modes.iter().any(|x| x == "car") {
+        TripMode::Drive
+    } else if modes.iter().any(|x| x == "bicycle") {
+        TripMode::Bike
+    } else if modes.iter().any(|x| x == "taxi") {
+        TripMode::RideHail
+    } else {
+        TripMode::Walk
+    }
+}
+
+/// Matches SUMO edges to trip endpoints.
+struct Endpoints<'a> {
+    map: &'a Map,
+    edges: EdgeMapping,
+    buildings: FindClosest<BuildingID>,
+}
+
+impl<'a> Endpoints<'a> {
+    fn new(map: &'a Map) -> Endpoints<'a> {
+        let mut buildings = FindClosest::new(map.get_bounds());
+        for b in map.all_buildings() {
+            buildings.add(b.id, b.polygon.points());
+        }
+        Endpoints {
+            map,
+            edges: EdgeMapping::new(map),
+            buildings,
+        }
+    }
+
+    /// Edges touching the map's boundary become borders. Anything else is snapped to a nearby
+    /// building. Without one, trips start in the middle of the edge.
+    fn endpoint(
+        &self,
+        edge: &EdgeID,
+        is_origin: bool,
+        mode: TripMode,
+    ) -> Result<ExternalTripEndpoint> {
+        let dr = self
+            .edges
+            .road(edge)
+            .ok_or_else(|| anyhow!("unknown edge {}", edge.0))?;
+        let i = if is_origin {
+            dr.src_i(self.map)
+        } else {
+            dr.dst_i(self.map)
+        };
+        if self.map.get_i(i).is_border() {
+            return Ok(ExternalTripEndpoint::TripEndpoint(TripEndpoint::Border(i)));
+        }
+        let pt = self.map.get_r(dr.id).center_pts.middle();
+        // The same limit ExternalPerson uses to snap positions
+        if is_origin
+            && self
+                .buildings
+                .closest_pt(pt, Distance::meters(100.0))
+                .is_none()
+        {
+            return Ok(ExternalTripEndpoint::TripEndpoint(
+                TripEndpoint::SuddenlyAppear(self.appear_on(dr, mode)?),
+            ));
+        }
+        Ok(ExternalTripEndpoint::Position(
+            pt.to_gps(self.map.get_gps_bounds()),
+        ))
+    }
+
+    /// The middle of some lane on the road that the mode can use
+    fn appear_on(&self, dr: DirectedRoadID, mode: TripMode) -> Result<Position> {
+        let constraints = mode.to_constraints();
+        let lane = if constraints == PathConstraints::Pedestrian {
+            // A sidewalk on either side works
+            self.map
+                .get_r(dr.id)
+                .all_lanes()
+                .into_iter()
+                .find(|l| constraints.can_use(self.map.get_l(*l), self.map))
+        } else {
+            dr.lanes(constraints, self.map).pop()
+        }
+        .ok_or_else(|| anyhow!("can't start {} on {}", mode.ongoing_verb(), dr))?;
+        Ok(Position::new(lane, self.map.get_l(lane).length() / 2.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_person_trip_mode() {
+        let modes = |list: &[&str]| -> Vec<String> { list.iter().map(|x| x.to_string()).collect() };
+        assert_eq!(person_trip_mode(&modes(&[])), TripMode::Walk);
+        assert_eq!(person_trip_mode(&modes(&["car"])), TripMode::Drive);
+        assert_eq!(person_trip_mode(&modes(&["bicycle"])), TripMode::Bike);
+        assert_eq!(person_trip_mode(&modes(&["taxi"])), TripMode::RideHail);
+        // Public transport wins, then driving, then cycling
+        assert_eq!(
+            person_trip_mode(&modes(&["bicycle", "car", "public"])),
+            TripMode::Transit
+        );
+        assert_eq!(
+            person_trip_mode(&modes(&["taxi", "bicycle", "car"])),
+            TripMode::Drive
+        );
+        assert_eq!(
+            person_trip_mode(&modes(&["taxi", "bicycle"])),
+            TripMode::Bike
+        );
+        assert_eq!(person_trip_mode(&modes(&["unknown"])), TripMode::Walk);
+    }
+}
@@ -0,0 +1,51 @@
+//! Matches SUMO edges with directed roads in a map.
+
+use std::collections::BTreeMap;
+
+use map_model::{DirectedRoadID, Direction, Map};
+
+use crate::EdgeID;
+
+/// Maps converted from a SUMO network remember the original edge IDs. Anything else is named the
+/// way SUMO's own OSM import does: each road's forward direction is `123`, and the backward
+/// direction is `-123`.
+pub struct EdgeMapping {
+    to_road: BTreeMap<EdgeID, DirectedRoadID>,
+    from_road: BTreeMap<DirectedRoadID, EdgeID>,
+}
+
+impl EdgeMapping {
+    pub fn new(map: &Map) -> EdgeMapping {
+        let converted_from_sumo = map.get_name().city.city == "sumo";
+        let mut mapping = EdgeMapping {
+            to_road: BTreeMap::new(),
+            from_road: BTreeMap::new(),
+        };
+        for r in map.all_roads() {
+            for dr in r.id.both_directions() {
+                let key = match dr.dir {
+                    Direction::Fwd => "id",
+                    Direction::Back => "reverse_id",
+                };
+                let edge = match r.osm_tags.get(key) {
+                    Some(id) if converted_from_sumo => EdgeID(id.clone()),
+                    _ => match dr.dir {
+                        Direction::Fwd => EdgeID(r.id.0.to_string()),
+                        Direction::Back => EdgeID(format!("-{}", r.id.0)),
+                    },
+                };
+                mapping.to_road.insert(edge.clone(), dr);
+                mapping.from_road.insert(dr, edge);
+            }
+        }
+        mapping
+    }
+
+    pub fn road(&self, edge: &EdgeID) -> Option<DirectedRoadID> {
+        self.to_road.get(edge).cloned()
+    }
+
+    pub fn edge(&self, dr: DirectedRoadID) -> Option<&EdgeID> {
+        self.from_road.get(&dr)
+    }
+}
@@ -2,15 +2,21 @@
 
 #[macro_use]
 extern crate anyhow;
+#[macro_use]
+extern crate log;
 
 use std::collections::BTreeMap;
 
 use geom::{Distance, PolyLine, Polygon, Pt2D, Speed};
 
+pub use self::edges::EdgeMapping;
 pub use self::raw::{Connection, Direction, EdgeID, InternalLaneID, LaneID, NodeID};
+pub use self::routes::{Person, PersonStage, Routes, Vehicle, VehicleRoute, VehicleType};
 
+mod edges;
 mod normalize;
 mod raw;
+mod routes;
 
 /// A normalized form of a SUMO
 /// [network](https://sumo.dlr.de/docs/Networks/SUMO_Road_Networks.html). A `raw::Network` is a direct representation of a .net.xml file. That's further simplified to produce this structure, which should be easier to work with. The
@@ -142,6 +142,10 @@ fn convert(orig_path: &str, network: Network) -> Result<Map> {
             lanes_ltr.extend(roads[road_id.0].lanes_ltr.clone());
             // TODO Should we check that the attributes are the same for both directions?
             roads[road_id.0].lanes_ltr = lanes_ltr;
+            // Remember both edges, so demand referring to either can be matched up later
+            roads[road_id.0]
+                .osm_tags
+                .insert("reverse_id", edge.id.0.clone());
         }
     }
 
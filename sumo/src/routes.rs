@@ -0,0 +1,434 @@
+//! Parse SUMO demand from a .rou.xml file. A subset of
+//! <https://sumo.dlr.de/docs/Definition_of_Vehicles,_Vehicle_Types,_and_Routes.html> is
+//! understood: vehicle types, routes, vehicles, trips, flows, and persons with walks, person trips,
+//! rides, and stops. Flows are expanded into individual vehicles.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use abstutil::Timer;
+use geom::{Duration, Time};
+
+use crate::EdgeID;
+
+pub struct Routes {
+    pub vehicle_types: BTreeMap<String, VehicleType>,
+    pub vehicles: Vec<Vehicle>,
+    pub persons: Vec<Person>,
+}
+
+pub struct VehicleType {
+    pub id: String,
+    /// Like `passenger`, `bicycle`, or `bus`
+    pub vehicle_class: String,
+}
+
+pub struct Vehicle {
+    pub id: String,
+    pub vehicle_type: Option<String>,
+    pub depart: Time,
+    pub route: VehicleRoute,
+    /// Public transport vehicles belong to a line
+    pub line: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum VehicleRoute {
+    /// The full route
+    Edges(Vec<EdgeID>),
+    /// Only the endpoints are known
+    Trip { from: EdgeID, to: EdgeID },
+}
+
+impl VehicleRoute {
+    pub fn from(&self) -> &EdgeID {
+        match self {
+            VehicleRoute::Edges(edges) => &edges[0],
+            VehicleRoute::Trip { from, .. } => from,
+        }
+    }
+
+    pub fn to(&self) -> &EdgeID {
+        match self {
+            VehicleRoute::Edges(edges) => edges.last().unwrap(),
+            VehicleRoute::Trip { to, .. } => to,
+        }
+    }
+}
+
+pub struct Person {
+    pub id: String,
+    pub depart: Time,
+    pub plan: Vec<PersonStage>,
+}
+
+pub enum PersonStage {
+    /// If `from` is missing, the stage starts wherever the previous one ended.
+    Walk { from: Option<EdgeID>, to: EdgeID },
+    PersonTrip {
+        from: Option<EdgeID>,
+        to: EdgeID,
+        /// Like `public`, `car`, `bicycle`, or `taxi`. Empty means walking.
+        modes: Vec<String>,
+    },
+    Ride {
+        from: Option<EdgeID>,
+        to: EdgeID,
+        /// Public transport lines or specific vehicles that can be used
+        lines: Vec<String>,
+    },
+    Stop {
+        duration: Option<Duration>,
+        until: Option<Time>,
+    },
+}
+
+impl Routes {
+    pub fn load(path: &str, timer: &mut Timer) -> Result<Routes> {
+        timer.start(format!("read {}", path));
+        let bytes = abstio::slurp_file(path)?;
+        let raw_string = std::str::from_utf8(&bytes)?;
+        let doc = roxmltree::Document::parse(raw_string)?;
+        timer.stop(format!("read {}", path));
+
+        let mut routes = Routes {
+            vehicle_types: BTreeMap::new(),
+            vehicles: Vec::new(),
+            persons: Vec::new(),
+        };
+        // Routes defined separately from vehicles, by ID
+        let mut named_routes: BTreeMap<String, Vec<EdgeID>> = BTreeMap::new();
+        for node in doc.root_element().children().filter(|n| n.is_element()) {
+            let id = node.attribute("id").unwrap_or("").to_string();
+            match node.tag_name().name() {
+                "vType" => {
+                    routes.vehicle_types.insert(
+                        id.clone(),
+                        VehicleType {
+                            id,
+                            vehicle_class: node
+                                .attribute("vClass")
+                                .unwrap_or("passenger")
+                                .to_string(),
+                        },
+                    );
+                }
+                "route" => {
+                    named_routes.insert(id, parse_edges(node.attribute("edges").unwrap_or("")));
+                }
+                "vehicle" | "trip" => match parse_vehicle_route(node, &named_routes) {
+                    Ok(route) => match parse_time(node.attribute("depart")) {
+                        Ok(depart) => {
+                            routes.vehicles.push(Vehicle {
+                                id,
+                                vehicle_type: node.attribute("type").map(|x| x.to_string()),
+                                depart,
+                                route,
+                                line: node.attribute("line").map(|x| x.to_string()),
+                            });
+                        }
+                        Err(err) => {
+                            warn!("Skipping vehicle {}: {}", id, err);
+                        }
+                    },
+                    Err(err) => {
+                        warn!("Skipping vehicle {}: {}", id, err);
+                    }
+                },
+                "flow" => match parse_flow(node, &named_routes) {
+                    Ok(vehicles) => {
+                        routes.vehicles.extend(vehicles);
+                    }
+                    Err(err) => {
+                        warn!("Skipping flow {}: {}", id, err);
+                    }
+                },
+                "person" => match parse_person(node) {
+                    Ok(person) => {
+                        routes.persons.push(person);
+                    }
+                    Err(err) => {
+                        warn!("Skipping person {}: {}", id, err);
+                    }
+                },
+                other => {
+                    warn!("Ignoring {} {}", other, id);
+                }
+            }
+        }
+        Ok(routes)
+    }
+}
+
+fn parse_edges(raw: &str) -> Vec<EdgeID> {
+    raw.split_whitespace()
+        .map(|x| EdgeID(x.to_string()))
+        .collect()
+}
+
+/// SUMO times are seconds, or something like 07:30:00. Special values like `triggered` aren't
+/// supported.
+fn parse_time(raw: Option<&str>) -> Result<Time> {
+    match raw {
+        Some(x) => Time::parse(x),
+        None => bail!("no time"),
+    }
+}
+
+/// Usually seconds, but maybe something like 00:05:00
+fn parse_duration(raw: &str) -> Result<Duration> {
+    if raw.contains(':') {
+        Duration::parse(raw)
+    } else {
+        Ok(Duration::seconds(raw.parse::<f64>()?))
+    }
+}
+
+fn parse_vehicle_route(
+    node: roxmltree::Node,
+    named_routes: &BTreeMap<String, Vec<EdgeID>>,
+) -> Result<VehicleRoute> {
+    let edges = if let Some(route) = node.children().find(|n| n.has_tag_name("route")) {
+        parse_edges(route.attribute("edges").unwrap_or(""))
+    } else if let Some(id) = node.attribute("route") {
+        named_routes
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("unknown route {}", id))?
+    } else {
+        match (node.attribute("from"), node.attribute("to")) {
+            (Some(from), Some(to)) => {
+                return Ok(VehicleRoute::Trip {
+                    from: EdgeID(from.to_string()),
+                    to: EdgeID(to.to_string()),
+                });
+            }
+            _ => bail!("no route, or from and to edges"),
+        }
+    };
+    if edges.is_empty() {
+        bail!("empty route");
+    }
+    Ok(VehicleRoute::Edges(edges))
+}
+
+fn parse_flow(
+    node: roxmltree::Node,
+    named_routes: &BTreeMap<String, Vec<EdgeID>>,
+) -> Result<Vec<Vehicle>> {
+    let id = node.attribute("id").unwrap_or("");
+    let begin = match node.attribute("begin") {
+        Some(x) => Time::parse(x)?,
+        None => Time::START_OF_DAY,
+    };
+    let end = match node.attribute("end") {
+        Some(x) => Time::parse(x)?,
+        None => Time::START_OF_DAY + Duration::hours(24),
+    };
+    // With a rate, this caps how many vehicles depart. Alone, it spreads them evenly.
+    let number = node
+        .attribute("number")
+        .map(|x| x.parse::<usize>())
+        .transpose()?;
+    let spacing = if let Some(x) = node.attribute("vehsPerHour") {
+        Duration::hours(1) / x.parse::<f64>()?
+    } else if let Some(x) = node.attribute("period") {
+        parse_duration(x)?
+    } else if let Some(x) = node.attribute("probability") {
+        // Use the expected spacing, rather than flipping a coin every second
+        Duration::seconds(1.0 / x.parse::<f64>()?)
+    } else if let Some(n) = number {
+        (end - begin) / (n.max(1) as f64)
+    } else {
+        bail!("no number, vehsPerHour, period, or probability");
+    };
+    if spacing <= Duration::ZERO {
+        bail!("vehicles never depart");
+    }
+
+    let route = parse_vehicle_route(node, named_routes)?;
+    let mut vehicles = Vec::new();
+    let mut depart = begin;
+    while depart < end && number != Some(vehicles.len()) {
+        vehicles.push(Vehicle {
+            id: format!("{}.{}", id, vehicles.len()),
+            vehicle_type: node.attribute("type").map(|x| x.to_string()),
+            depart,
+            route: route.clone(),
+            line: node.attribute("line").map(|x| x.to_string()),
+        });
+        depart += spacing;
+    }
+    Ok(vehicles)
+}
+
+fn parse_person(node: roxmltree::Node) -> Result<Person> {
+    let mut plan = Vec::new();
+    for stage in node.children().filter(|n| n.is_element()) {
+        let from = stage.attribute("from").map(|x| EdgeID(x.to_string()));
+        let to = stage.attribute("to").map(|x| EdgeID(x.to_string()));
+        let list = |key| {
+            stage
+                .attribute(key)
+                .unwrap_or("")
+                .split_whitespace()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+        };
+        plan.push(match stage.tag_name().name() {
+            "walk" => {
+                // A walk can list every edge instead
+                let edges = parse_edges(stage.attribute("edges").unwrap_or(""));
+                match (from, to) {
+                    (from, Some(to)) => PersonStage::Walk { from, to },
+                    (_, None) if !edges.is_empty() => PersonStage::Walk {
+                        from: Some(edges[0].clone()),
+                        to: edges.last().unwrap().clone(),
+                    },
+                    _ => bail!("walk without a destination"),
+                }
+            }
+            "personTrip" => PersonStage::PersonTrip {
+                from,
+                to: to.ok_or_else(|| anyhow!("personTrip without a destination"))?,
+                modes: list("modes"),
+            },
+            "ride" => PersonStage::Ride {
+                from,
+                to: to.ok_or_else(|| anyhow!("ride without a destination"))?,
+                lines: list("lines"),
+            },
+            "stop" => PersonStage::Stop {
+                duration: stage
+                    .attribute("duration")
+                    .map(parse_duration)
+                    .transpose()?,
+                until: stage.attribute("until").map(Time::parse).transpose()?,
+            },
+            other => bail!("unsupported {}", other),
+        });
+    }
+    Ok(Person {
+        id: node.attribute("id").unwrap_or("").to_string(),
+        depart: parse_time(node.attribute("depart"))?,
+        plan,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow(xml: &str) -> Result<Vec<Vehicle>> {
+        let doc = roxmltree::Document::parse(xml)?;
+        let mut named_routes = BTreeMap::new();
+        named_routes.insert(
+            "r".to_string(),
+            vec![EdgeID("a".to_string()), EdgeID("b".to_string())],
+        );
+        parse_flow(doc.root_element(), &named_routes)
+    }
+
+    fn departures(vehicles: &[Vehicle]) -> Vec<f64> {
+        vehicles.iter().map(|v| v.depart.inner_seconds()).collect()
+    }
+
+    #[test]
+    fn test_parse_flow() {
+        // The period sets the spacing, and the number caps it
+        let vehicles =
+            flow(r#"<flow id="f" route="r" begin="0" number="10" period="60"/>"#).unwrap();
+        assert_eq!(vehicles.len(), 10);
+        assert_eq!(vehicles[0].id, "f.0");
+        assert_eq!(vehicles[1].depart.inner_seconds(), 60.0);
+        assert_eq!(vehicles[9].depart.inner_seconds(), 540.0);
+
+        assert_eq!(
+            departures(
+                &flow(r#"<flow id="f" route="r" begin="100" end="400" vehsPerHour="36"/>"#)
+                    .unwrap()
+            ),
+            vec![100.0, 200.0, 300.0]
+        );
+
+        // Only a number spreads the vehicles between begin and end
+        assert_eq!(
+            departures(
+                &flow(r#"<flow id="f" route="r" begin="0" end="300" number="3"/>"#).unwrap()
+            ),
+            vec![0.0, 100.0, 200.0]
+        );
+
+        // A rate without a number runs until the end
+        assert_eq!(
+            flow(r#"<flow id="f" route="r" begin="0" end="10" probability="0.5"/>"#)
+                .unwrap()
+                .len(),
+            5
+        );
+
+        let vehicles =
+            flow(r#"<flow id="f" from="x" to="y" begin="0" end="1" period="1"/>"#).unwrap();
+        assert!(matches!(
+            &vehicles[0].route,
+            VehicleRoute::Trip { from, to } if from.0 == "x" && to.0 == "y"
+        ));
+
+        assert!(flow(r#"<flow id="f" route="r" begin="0" end="60"/>"#).is_err());
+        assert!(flow(r#"<flow id="f" route="r" period="0"/>"#).is_err());
+        assert!(flow(r#"<flow id="f" route="unknown" period="60"/>"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_person() {
+        let doc = roxmltree::Document::parse(
+            r#"<person id="p" depart="07:30:00">
+                <walk edges="a b c"/>
+                <personTrip to="d" modes="car public"/>
+                <stop duration="60" until="09:00:00"/>
+                <ride from="d" to="e" lines="taxi"/>
+            </person>"#,
+        )
+        .unwrap();
+        let person = parse_person(doc.root_element()).unwrap();
+        assert_eq!(person.id, "p");
+        assert_eq!(
+            person.depart,
+            Time::START_OF_DAY + Duration::hours(7) + Duration::minutes(30)
+        );
+        assert_eq!(person.plan.len(), 4);
+        assert!(matches!(
+            &person.plan[0],
+            PersonStage::Walk { from: Some(from), to } if from.0 == "a" && to.0 == "c"
+        ));
+        assert!(matches!(
+            &person.plan[1],
+            PersonStage::PersonTrip { from: None, to, modes }
+                if to.0 == "d" && *modes == ["car", "public"]
+        ));
+        assert!(matches!(
+            &person.plan[2],
+            PersonStage::Stop { duration: Some(duration), until: Some(until) }
+                if *duration == Duration::minutes(1)
+                    && *until == Time::START_OF_DAY + Duration::hours(9)
+        ));
+        assert!(matches!(
+            &person.plan[3],
+            PersonStage::Ride { from: Some(from), to, lines }
+                if from.0 == "d" && to.0 == "e" && *lines == ["taxi"]
+        ));
+
+        for bad in [
+            r#"<person id="p"><walk to="a"/></person>"#,
+            r#"<person id="p" depart="0"><walk/></person>"#,
+            r#"<person id="p" depart="0"><ride from="a"/></person>"#,
+            r#"<person id="p" depart="0"><tranship to="a"/></person>"#,
+        ]
+        .iter()
+        {
+            let doc = roxmltree::Document::parse(bad).unwrap();
+            assert!(parse_person(doc.root_element()).is_err());
+        }
+    }
+}
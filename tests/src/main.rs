@@ -9,8 +9,11 @@ use rand::seq::SliceRandom;
 use abstio::{CityName, MapName};
 use abstutil::Timer;
 use geom::{Distance, Duration, Time};
-use map_model::{IntersectionID, Map};
-use sim::{IndividTrip, PersonSpec, Scenario, TripEndpoint, TripMode, TripPurpose};
+use map_model::{Building, IntersectionID, LaneType, Map};
+use sim::{
+    CarID, DeliveryStop, DeliveryTour, IndividTrip, ParkingSpot, PersonSpec, Scenario,
+    TripEndpoint, TripMode, TripPurpose, VehicleType,
+};
 
 fn main() -> Result<()> {
     test_lane_changing(&import_map(abstio::path(
@@ -18,6 +21,7 @@ fn main() -> Result<()> {
     )))?;
     test_map_importer()?;
     check_proposals()?;
+    test_live_edits_with_delivery_van()?;
     smoke_test()?;
     Ok(())
 }
@@ -206,6 +210,7 @@ fn test_lane_changing(map: &Map) -> Result<()> {
                     TripMode::Bike
                 },
             )],
+            behavior: None,
         });
     }
     // Enable to manually watch the scenario
@@ -236,3 +241,97 @@ fn test_lane_changing(map: &Map) -> Result<()> {
 
     Ok(())
 }
+
+/// Send a delivery van to a building with street parking, then remove the parking lane while the
+/// van is unloading there. The van should give up on its tour without breaking the simulation.
+fn test_live_edits_with_delivery_van() -> Result<()> {
+    let mut timer = Timer::new("test live edits with a delivery van");
+    let name = MapName::seattle("montlake");
+    if !abstio::file_exists(name.path()) {
+        println!(
+            "Skipping the delivery van test; {} isn't imported",
+            name.describe()
+        );
+        return Ok(());
+    }
+    let mut map = Map::load_synchronously(name.path(), &mut timer);
+
+    let has_parking = |b: &Building| {
+        map.get_parent(b.sidewalk())
+            .lanes_ltr()
+            .into_iter()
+            .any(|(_, _, lt)| lt == LaneType::Parking)
+    };
+    let can_drive = |b: &Building, is_start| {
+        TripEndpoint::Bldg(b.id)
+            .pos(TripMode::Drive, is_start, &map)
+            .is_some()
+    };
+    let stop = map
+        .all_buildings()
+        .iter()
+        .find(|b| has_parking(b) && can_drive(b, false))
+        .unwrap()
+        .id;
+    let depot = map
+        .all_buildings()
+        .iter()
+        .find(|b| !has_parking(b) && can_drive(b, true))
+        .unwrap()
+        .id;
+
+    let mut scenario = Scenario::empty(&map, "delivery_van");
+    scenario.deliveries.push(DeliveryTour {
+        depart: Time::START_OF_DAY,
+        depot: TripEndpoint::Bldg(depot),
+        stops: vec![DeliveryStop {
+            building: stop,
+            dwell_time: Duration::hours(1),
+        }],
+    });
+    let mut opts = sim::SimOptions::new("test_live_edits_with_delivery_van");
+    opts.alerts = sim::AlertHandler::Silence;
+    let mut sim = sim::Sim::new(&map, opts);
+    let mut rng = sim::SimFlags::for_test("test_live_edits_with_delivery_van").make_rng();
+    scenario.instantiate(&mut sim, &map, &mut rng, &mut timer);
+
+    // Nobody else is in the scenario, so the van gets the first ID
+    let van = CarID {
+        id: 0,
+        vehicle_type: VehicleType::Delivery,
+    };
+    let spot = loop {
+        if let Some(parked_car) = sim.lookup_parked_car(van) {
+            break parked_car.spot;
+        }
+        if sim.time() > Time::START_OF_DAY + Duration::hours(1) {
+            panic!("{} never parked to unload at {}", van, stop);
+        }
+        sim.tiny_step(&map, &mut None);
+    };
+    let lane = match spot {
+        ParkingSpot::Onstreet(l, _) => l,
+        ParkingSpot::Offstreet(_, _) | ParkingSpot::Lot(_, _) => unreachable!(),
+    };
+
+    let mut edits = map.get_edits().clone();
+    edits
+        .commands
+        .push(map.edit_road_cmd(map.get_parent(lane).id, |new| {
+            for spec in &mut new.lanes_ltr {
+                if spec.lt == LaneType::Parking {
+                    spec.lt = LaneType::Driving;
+                }
+            }
+        }));
+    map.must_apply_edits(edits);
+    map.recalculate_pathfinding_after_edits(&mut timer);
+    sim.handle_live_edits(&map);
+    assert!(sim.lookup_parked_car(van).is_none());
+
+    // When the van would've finished unloading, it must not try to leave the missing spot
+    sim.timed_step(&map, Duration::hours(2), &mut None, &mut timer);
+    assert!(sim.lookup_car_id(van.id).is_none());
+
+    Ok(())
+}